@@ -9,10 +9,31 @@ import {IFlashLoanSimpleReceiver} from "@aave-v3/misc/flashloan/interfaces/IFlas
 import {FlashLoanSimpleReceiverBase} from "@aave-v3/misc/flashloan/base/FlashLoanSimpleReceiverBase.sol";
 import {IPoolAddressesProvider} from "@aave-v3/interfaces/IPoolAddressesProvider.sol";
 import {ISwapRouter} from "@uniswap-v3-periphery/interfaces/ISwapRouter.sol";
+import {IVault} from "@balancer-labs/v3-interfaces/contracts/vault/IVault.sol";
+
+/// @dev subset of Morpho Blue's `IMorpho`
+interface IMorphoFlashLoan {
+    function flashLoan(address token, uint256 assets, bytes calldata data) external;
+}
 
 contract Liquidatoor is FlashLoanSimpleReceiverBase, Ownable {
     ISwapRouter public immutable swapRouter;
 
+    // lender of the flashloan in progress, callbacks from anyone else are rejected
+    address private _lender;
+
+    struct LiquidationParams {
+        address debtAsset;
+        address collateralAsset;
+        address user;
+        uint256 debtToCover;
+        // uniswap v3 path from the collateral to the debt asset, i.e
+        // `collateral | fee | (token | fee)* | debt` packed. Unused when both are the same asset.
+        bytes path;
+        // least the liquidation may bring back in debt asset, flashloan repayment and profit included
+        uint256 minAmountOut;
+    }
+
     receive() external payable {}
 
     constructor(
@@ -52,90 +73,178 @@ contract Liquidatoor is FlashLoanSimpleReceiverBase, Ownable {
         address initiator,
         bytes calldata params
     ) external override returns (bool) {
-        (address collateral_asset, address user, uint24 fee) = abi.decode(
-            params,
-            (address, address, uint24)
+        require(
+            msg.sender == address(POOL) && initiator == address(this),
+            "unauthorized flashloan"
         );
+        LiquidationParams memory p = abi.decode(params, (LiquidationParams));
 
-        IERC20(asset).approve(address(POOL), amount);
-        uint256 collateralBalance = IERC20(collateral_asset).balanceOf(
-            address(this)
-        );
+        uint256 totalRepayment = amount + premium;
+        _liquidateAndSwap(p, amount, totalRepayment);
+
+        // Approve the pool to pull the flash loan repayment
+        IERC20(asset).approve(address(POOL), totalRepayment);
+
+        return true;
+    }
+
+    /*
+     * @notice Called back by the Balancer vault once unlocked, borrows from it and settles the debt.
+     */
+    function receiveBalancerFlashLoan(LiquidationParams calldata p) external {
+        require(msg.sender == _lender, "unauthorized flashloan");
+        IVault vault = IVault(msg.sender);
+        IERC20 debt = IERC20(p.debtAsset);
 
+        vault.sendTo(debt, address(this), p.debtToCover);
+        // no fee, the vault only has to get its tokens back
+        _liquidateAndSwap(p, p.debtToCover, p.debtToCover);
+
+        debt.transfer(address(vault), p.debtToCover);
+        vault.settle(debt, p.debtToCover);
+    }
+
+    /*
+     * @notice Called by Morpho Blue after sending the flashloan, which it pulls back afterwards.
+     */
+    function onMorphoFlashLoan(uint256 assets, bytes calldata data) external {
+        require(msg.sender == _lender, "unauthorized flashloan");
+        LiquidationParams memory p = abi.decode(data, (LiquidationParams));
+
+        // no fee either
+        _liquidateAndSwap(p, assets, assets);
+
+        IERC20(p.debtAsset).approve(msg.sender, assets);
+    }
+
+    /*
+     * @notice Liquidates `p.user` with the borrowed debt asset and swaps the seized collateral back.
+     * @param borrowed The flashloaned amount, already in the contract.
+     * @param repayment What the lender is owed once done.
+     */
+    function _liquidateAndSwap(
+        LiquidationParams memory p,
+        uint256 borrowed,
+        uint256 repayment
+    ) internal {
+        IERC20 debt = IERC20(p.debtAsset);
+        // profits of previous liquidations aren't proceeds of this one
+        uint256 held = debt.balanceOf(address(this)) - borrowed;
+
+        debt.approve(address(POOL), p.debtToCover);
         POOL.liquidationCall(
-            collateral_asset,
-            asset, // debt asset
-            user,
-            amount,
+            p.collateralAsset,
+            p.debtAsset,
+            p.user,
+            p.debtToCover,
             false
         );
 
-        if (collateral_asset != asset) {
-            collateralBalance = IERC20(collateral_asset).balanceOf(
+        if (p.collateralAsset != p.debtAsset) {
+            uint256 collateralBalance = IERC20(p.collateralAsset).balanceOf(
                 address(this)
             );
 
             // approve the Uniswap router to spend the collateral.
-            IERC20(collateral_asset).approve(
+            IERC20(p.collateralAsset).approve(
                 address(swapRouter),
                 collateralBalance
             );
-            IERC20(collateral_asset).allowance(
-                address(this),
-                address(swapRouter)
-            );
 
-            // setup swap parameters
-            ISwapRouter.ExactInputSingleParams memory swapParams = ISwapRouter
-                .ExactInputSingleParams({
-                    tokenIn: collateral_asset, // ETH
-                    tokenOut: asset, // USDT
-                    fee: fee,
+            // setup swap parameters, possibly hopping through other tokens
+            ISwapRouter.ExactInputParams memory swapParams = ISwapRouter
+                .ExactInputParams({
+                    path: p.path,
                     recipient: address(this),
                     deadline: block.timestamp + 10,
                     amountIn: collateralBalance,
-                    amountOutMinimum: 0,
-                    sqrtPriceLimitX96: 0
+                    amountOutMinimum: p.minAmountOut
                 });
 
-            swapRouter.exactInputSingle(swapParams);
+            swapRouter.exactInput(swapParams);
         }
 
-        uint256 totalRepayment = amount + premium;
-        // Approve the pool to pull the flash loan repayment
-        IERC20(asset).approve(address(POOL), totalRepayment);
-
-        return true;
+        // whatever's left of the loan plus what the collateral was swapped for
+        uint256 proceeds = debt.balanceOf(address(this)) - held;
+        require(
+            proceeds >= repayment && proceeds >= p.minAmountOut,
+            "insufficient proceeds"
+        );
     }
 
     /*
      * @notice Initiates a flashloan to liquidate an undercollateralized position.
-     * @param asset The debt asset to be repaid through the flashloan.
-     * @param collateral The collateral asset to be liquidated.
-     * @param userToLiquidate The address of the user to liquidate.
-     * @param amount The amount to liquidate.
+     * @param params The debt asset to flashloan and repay, the collateral to seize, the user to
+     * liquidate, the amount to repay and how to swap the collateral back.
      */
-    function liquidatoor(
-        address debt_asset,
-        address collateral_asset,
-        address userToLiquidate,
-        uint256 amount,
-        uint24 fee
-    ) external onlyOwner {
-        // pack collateral and user for use in executeOperation
-        bytes memory params = abi.encode(
-            collateral_asset,
-            userToLiquidate,
-            fee
-        );
-
-        // Initiate the flashLoan with the updated parameters
+    function liquidatoor(LiquidationParams calldata params) external onlyOwner {
+        // Initiate the flashLoan, the params are handed back to executeOperation
         POOL.flashLoanSimple(
             address(this),
-            debt_asset,
-            amount,
-            params,
+            params.debtAsset,
+            params.debtToCover,
+            abi.encode(params),
             0
         );
     }
+
+    /*
+     * @notice Liquidates out of the contract's own balance of the debt asset and keeps the seized
+     * collateral as aTokens, i.e neither flashloan nor swap.
+     * @param params `path` is unused, `minAmountOut` is the least aTokens to receive.
+     */
+    function liquidatoorHold(
+        LiquidationParams calldata params
+    ) external onlyOwner {
+        IERC20 aToken = IERC20(
+            POOL.getReserveData(params.collateralAsset).aTokenAddress
+        );
+        uint256 held = aToken.balanceOf(address(this));
+
+        IERC20(params.debtAsset).approve(address(POOL), params.debtToCover);
+        POOL.liquidationCall(
+            params.collateralAsset,
+            params.debtAsset,
+            params.user,
+            params.debtToCover,
+            true
+        );
+
+        require(
+            aToken.balanceOf(address(this)) - held >= params.minAmountOut,
+            "insufficient proceeds"
+        );
+    }
+
+    /*
+     * @notice Same as `liquidatoor`, flashloaning from Balancer's vault, free of fees.
+     * @param vault The Balancer V3 vault.
+     */
+    function liquidatoorBalancer(
+        address vault,
+        LiquidationParams calldata params
+    ) external onlyOwner {
+        _lender = vault;
+        IVault(vault).unlock(
+            abi.encodeCall(this.receiveBalancerFlashLoan, (params))
+        );
+        _lender = address(0);
+    }
+
+    /*
+     * @notice Same as `liquidatoor`, flashloaning from Morpho Blue, free of fees.
+     * @param morpho The Morpho Blue singleton.
+     */
+    function liquidatoorMorpho(
+        address morpho,
+        LiquidationParams calldata params
+    ) external onlyOwner {
+        _lender = morpho;
+        IMorphoFlashLoan(morpho).flashLoan(
+            params.debtAsset,
+            params.debtToCover,
+            abi.encode(params)
+        );
+        _lender = address(0);
+    }
 }
@@ -0,0 +1,22 @@
+-- reserve flags as reported by `UiPoolDataProviderV3.getReservesData`, kept in sync with the
+-- `PoolConfigurator` events. Used to skip liquidations which are bound to revert.
+ALTER TABLE aavev3_reserves
+    ADD COLUMN is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    ADD COLUMN is_frozen BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN is_paused BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN is_siloed_borrowing BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN borrowing_enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    ADD COLUMN usage_as_collateral_enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    ADD COLUMN borrowable_in_isolation BOOLEAN NOT NULL DEFAULT FALSE,
+    -- expressed in USD, 0 means the reserve isn't an isolated asset
+    ADD COLUMN debt_ceiling DOUBLE PRECISION NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS aavev3_skipped_liquidations (
+    id SERIAL PRIMARY KEY,
+    protocol_details_id INTEGER REFERENCES protocols_details (id),
+    user_address CHAR(42) NOT NULL,
+    collateral_asset CHAR(42) NOT NULL REFERENCES aavev3_reserves (reserve),
+    debt_asset CHAR(42) NOT NULL REFERENCES aavev3_reserves (reserve),
+    reason VARCHAR(64) NOT NULL,
+    timestamp TIMESTAMP NOT NULL DEFAULT (NOW () AT TIME ZONE 'UTC')
+);
@@ -0,0 +1,2 @@
+-- whether the user has the reserve enabled as collateral, see `ReserveUsedAsCollateralEnabled/Disabled`
+ALTER TABLE aavev3_positions ADD COLUMN collateral_enabled BOOLEAN NOT NULL DEFAULT TRUE;
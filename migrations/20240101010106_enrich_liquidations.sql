@@ -0,0 +1,18 @@
+-- everything `LiquidationCall` carries, along with where it happened and what it was worth
+ALTER TABLE aavev3_liquidations
+    ADD COLUMN liquidator_address CHAR(42),
+    ADD COLUMN debt_to_cover NUMERIC(78, 0),
+    ADD COLUMN liquidated_collateral_amount NUMERIC(78, 0),
+    ADD COLUMN receive_a_token BOOLEAN,
+    ADD COLUMN block_number BIGINT,
+    ADD COLUMN tx_hash CHAR(66),
+    ADD COLUMN log_index INTEGER,
+    -- effective gas price paid by the liquidator, in gwei
+    ADD COLUMN gas_price DOUBLE PRECISION,
+    ADD COLUMN debt_to_cover_usd DOUBLE PRECISION,
+    ADD COLUMN liquidated_collateral_usd DOUBLE PRECISION,
+    -- the liquidator was our bot
+    ADD COLUMN ours BOOLEAN NOT NULL DEFAULT FALSE,
+    -- we had a pending attempt for that user, but someone else got there first
+    ADD COLUMN missed BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD CONSTRAINT aavev3_liquidations_tx_hash_log_index_key UNIQUE (tx_hash, log_index);
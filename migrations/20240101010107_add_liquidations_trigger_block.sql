@@ -0,0 +1,5 @@
+-- block of the last `AnswerUpdated` per reserve, i.e what may have triggered a liquidation
+ALTER TABLE aavev3_reserves_stats ADD COLUMN price_updated_block BIGINT;
+
+-- latest oracle update (collateral or debt) at or before the liquidation's block
+ALTER TABLE aavev3_liquidations ADD COLUMN trigger_block_number BIGINT;
@@ -0,0 +1,63 @@
+-- Zero-fee flashloan lenders, used instead of the Aave pool when they hold enough of the debt asset
+INSERT INTO
+    protocols (id, name, kind)
+VALUES
+    ('balancer_v3', 'Balancer V3', 'dex'),
+    ('morpho_blue', 'Morpho Blue', 'lending');
+
+-------------- ethereum --------------
+WITH
+    inserted_protocol AS (
+        INSERT INTO
+            protocols_details (
+                protocol_id,
+                network_id,
+                deployed_block,
+                deployed_at
+            )
+        VALUES
+            ('balancer_v3', 'ethereum', null, null) RETURNING id
+    )
+INSERT INTO
+    protocols_contracts (protocol_details_id, name, address)
+SELECT
+    id,
+    name,
+    address
+FROM
+    inserted_protocol,
+    (
+        VALUES
+            (
+                'Vault',
+                '0xbA1333333333a1BA1108E8412f11850A5C319bA9'
+            )
+    ) AS contracts (name, address);
+
+WITH
+    inserted_protocol AS (
+        INSERT INTO
+            protocols_details (
+                protocol_id,
+                network_id,
+                deployed_block,
+                deployed_at
+            )
+        VALUES
+            ('morpho_blue', 'ethereum', null, null) RETURNING id
+    )
+INSERT INTO
+    protocols_contracts (protocol_details_id, name, address)
+SELECT
+    id,
+    name,
+    address
+FROM
+    inserted_protocol,
+    (
+        VALUES
+            (
+                'MorphoBlue',
+                '0xBBBBBbbBBb9cC5e90e3b3Af64bdAF62C37EEFFCb'
+            )
+    ) AS contracts (name, address);
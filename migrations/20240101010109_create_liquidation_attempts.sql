@@ -0,0 +1,39 @@
+-- every liquidation the executor went for, along with what it knew then and what came out of it
+CREATE TABLE IF NOT EXISTS liquidation_attempts (
+    id SERIAL PRIMARY KEY,
+    protocol_details_id INTEGER REFERENCES protocols_details (id),
+    user_address CHAR(42) NOT NULL,
+    collateral_asset CHAR(42) NOT NULL REFERENCES aavev3_reserves (reserve),
+    debt_asset CHAR(42) NOT NULL REFERENCES aavev3_reserves (reserve),
+    debt_to_cover NUMERIC(78, 0) NOT NULL,
+    -- swap_and_repay, same_asset or receive_atoken
+    strategy VARCHAR(32) NOT NULL,
+    -- NULL when repaid out of the bot's own balance
+    flashloan_source VARCHAR(32),
+    -- uniswap_v3 fee tiers of the swap path, one per hop
+    fee_tiers INTEGER[] NOT NULL,
+    swap_path VARCHAR(1024) NOT NULL,
+    min_amount_out NUMERIC(78, 0) NOT NULL,
+    -- at decision time
+    health_factor DOUBLE PRECISION NOT NULL,
+    estimated_profit_usd DOUBLE PRECISION NOT NULL,
+    -- gas params, fees in gwei, NULL unless the transaction was sent
+    gas_limit BIGINT,
+    max_fee_per_gas DOUBLE PRECISION,
+    max_priority_fee_per_gas DOUBLE PRECISION,
+    gas_used BIGINT,
+    gas_price DOUBLE PRECISION,
+    tx_hash CHAR(66),
+    block_number BIGINT,
+    status VARCHAR(16) NOT NULL CHECK (
+        status IN (
+            'simulated',
+            'reverted',
+            'mined_success',
+            'mined_failed',
+            'lost_race'
+        )
+    ),
+    revert_reason TEXT,
+    timestamp TIMESTAMP NOT NULL DEFAULT (NOW () AT TIME ZONE 'UTC')
+);
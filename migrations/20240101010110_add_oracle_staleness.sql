@@ -0,0 +1,11 @@
+-- per feed overrides of `--oracle-heartbeat-secs` / `--oracle-deviation-bps`, Chainlink doesn't
+-- expose either on-chain. See https://data.chain.link for each feed's parameters
+ALTER TABLE aavev3_reserves
+    ADD COLUMN oracle_heartbeat_secs INTEGER CHECK (oracle_heartbeat_secs > 0),
+    ADD COLUMN oracle_deviation_bps INTEGER CHECK (oracle_deviation_bps >= 0);
+
+-- set by the oracle monitor while the reserve's aggregator is overdue, cleared by its next
+-- `AnswerUpdated`
+ALTER TABLE aavev3_reserves_stats
+    ADD COLUMN price_stale BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN price_stale_since TIMESTAMP;
@@ -0,0 +1,30 @@
+-- the fanatic's complete in-memory state, replaced as a whole by every snapshot. Restored on
+-- startup, the logs emitted since `block_number` are then backfilled
+CREATE TABLE IF NOT EXISTS fanatic_snapshots (
+    protocol_details_id INTEGER PRIMARY KEY REFERENCES protocols_details (id),
+    block_number BIGINT NOT NULL,
+    taken_at TIMESTAMP NOT NULL DEFAULT (NOW () AT TIME ZONE 'UTC')
+);
+
+CREATE TABLE IF NOT EXISTS fanatic_snapshot_users (
+    protocol_details_id INTEGER NOT NULL REFERENCES fanatic_snapshots (protocol_details_id) ON DELETE CASCADE,
+    user_address CHAR(42) NOT NULL,
+    health_factor DOUBLE PRECISION NOT NULL,
+    -- UTC EPOCH timestamp
+    last_update BIGINT NOT NULL,
+    PRIMARY KEY (protocol_details_id, user_address)
+);
+
+-- balances are scaled, i.e to be multiplied by the reserve's liquidity/variable borrow index. NULL
+-- when the user's known to be in the reserve but hasn't been evaluated since the bot started
+CREATE TABLE IF NOT EXISTS fanatic_snapshot_positions (
+    protocol_details_id INTEGER NOT NULL,
+    user_address CHAR(42) NOT NULL,
+    reserve CHAR(42) NOT NULL,
+    scaled_a_token_balance NUMERIC(78, 0),
+    scaled_variable_debt NUMERIC(78, 0),
+    usage_as_collateral_enabled BOOLEAN,
+    PRIMARY KEY (protocol_details_id, user_address, reserve),
+    FOREIGN KEY (protocol_details_id, user_address)
+        REFERENCES fanatic_snapshot_users (protocol_details_id, user_address) ON DELETE CASCADE
+);
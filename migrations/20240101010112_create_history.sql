@@ -0,0 +1,44 @@
+-- append-only counterparts of `aavev3_users_stats` and `aavev3_reserves_stats`, which only keep
+-- the latest values. Rows past `--history-downsample-after` are thinned out to one per
+-- `--history-downsample-bucket`, and dropped past `--history-retention`
+
+-- health factor of a user as evaluated by the fanatic, per block
+CREATE TABLE IF NOT EXISTS aavev3_health_factors_history (
+    protocol_details_id INTEGER NOT NULL REFERENCES protocols_details (id),
+    user_address CHAR(42) NOT NULL,
+    block_number BIGINT NOT NULL,
+    health_factor DOUBLE PRECISION NOT NULL,
+    recorded_at TIMESTAMP NOT NULL DEFAULT (NOW () AT TIME ZONE 'UTC'),
+    PRIMARY KEY (protocol_details_id, user_address, block_number)
+);
+
+-- price of a reserve, per `AnswerUpdated` of its aggregator
+CREATE TABLE IF NOT EXISTS aavev3_prices_history (
+    protocol_details_id INTEGER NOT NULL REFERENCES protocols_details (id),
+    reserve CHAR(42) NOT NULL,
+    block_number BIGINT NOT NULL,
+    price_usd DOUBLE PRECISION NOT NULL,
+    recorded_at TIMESTAMP NOT NULL DEFAULT (NOW () AT TIME ZONE 'UTC'),
+    PRIMARY KEY (protocol_details_id, reserve, block_number)
+);
+
+-- rates and indices of a reserve, per `ReserveDataUpdated`
+CREATE TABLE IF NOT EXISTS aavev3_reserves_stats_history (
+    protocol_details_id INTEGER NOT NULL REFERENCES protocols_details (id),
+    reserve CHAR(42) NOT NULL,
+    block_number BIGINT NOT NULL,
+    liquidity_rate DOUBLE PRECISION NOT NULL,
+    variable_borrow_rate DOUBLE PRECISION NOT NULL,
+    liquidity_index DOUBLE PRECISION NOT NULL,
+    variable_borrow_index DOUBLE PRECISION NOT NULL,
+    recorded_at TIMESTAMP NOT NULL DEFAULT (NOW () AT TIME ZONE 'UTC'),
+    PRIMARY KEY (protocol_details_id, reserve, block_number)
+);
+
+-- retention and downsampling go by age
+CREATE INDEX IF NOT EXISTS aavev3_health_factors_history_recorded_at_idx
+    ON aavev3_health_factors_history (recorded_at);
+CREATE INDEX IF NOT EXISTS aavev3_prices_history_recorded_at_idx
+    ON aavev3_prices_history (recorded_at);
+CREATE INDEX IF NOT EXISTS aavev3_reserves_stats_history_recorded_at_idx
+    ON aavev3_reserves_stats_history (recorded_at);
@@ -0,0 +1,106 @@
+-- users, reserves and their positions/stats are keyed by (protocol_details_id, address) rather than
+-- the address alone, which collides across forks (e.g aave and spark) and networks.
+-- erc20_details is keyed by (network_id, address) only, a token may share its address across
+-- networks
+
+-- foreign keys on the old keys
+ALTER TABLE aavev3_users_stats DROP CONSTRAINT aavev3_users_stats_user_address_fkey;
+ALTER TABLE aavev3_positions DROP CONSTRAINT aavev3_positions_user_address_fkey;
+ALTER TABLE aavev3_positions DROP CONSTRAINT aavev3_positions_reserve_fkey;
+ALTER TABLE aavev3_reserves_stats DROP CONSTRAINT aavev3_reserves_stats_reserve_fkey;
+ALTER TABLE aavev3_reserves DROP CONSTRAINT aavev3_reserves_reserve_fkey;
+ALTER TABLE aavev3_liquidations
+    DROP CONSTRAINT aavev3_liquidations_collateral_asset_fkey,
+    DROP CONSTRAINT aavev3_liquidations_debt_asset_fkey;
+ALTER TABLE aavev3_skipped_liquidations
+    DROP CONSTRAINT aavev3_skipped_liquidations_collateral_asset_fkey,
+    DROP CONSTRAINT aavev3_skipped_liquidations_debt_asset_fkey;
+ALTER TABLE liquidation_attempts
+    DROP CONSTRAINT liquidation_attempts_collateral_asset_fkey,
+    DROP CONSTRAINT liquidation_attempts_debt_asset_fkey;
+
+ALTER TABLE erc20_details DROP CONSTRAINT erc20_details_address_key;
+
+-- the rows' protocol, up to now only known to the users and the reserves
+ALTER TABLE aavev3_users_stats ADD COLUMN protocol_details_id INTEGER;
+ALTER TABLE aavev3_reserves_stats ADD COLUMN protocol_details_id INTEGER;
+ALTER TABLE aavev3_positions ADD COLUMN protocol_details_id INTEGER;
+
+UPDATE aavev3_users_stats us
+SET protocol_details_id = u.protocol_details_id
+FROM aavev3_users u
+WHERE u.address = us.user_address;
+
+UPDATE aavev3_reserves_stats rs
+SET protocol_details_id = r.protocol_details_id
+FROM aavev3_reserves r
+WHERE r.reserve = rs.reserve;
+
+UPDATE aavev3_positions p
+SET protocol_details_id = u.protocol_details_id
+FROM aavev3_users u
+WHERE u.address = p.user_address;
+
+-- users never attributed to a protocol, along with whatever's left unattributed. The bot writes
+-- them back as it evaluates them
+DELETE FROM aavev3_users_stats WHERE protocol_details_id IS NULL;
+DELETE FROM aavev3_reserves_stats WHERE protocol_details_id IS NULL;
+DELETE FROM aavev3_positions WHERE protocol_details_id IS NULL;
+DELETE FROM aavev3_users WHERE protocol_details_id IS NULL;
+
+-- positions in a reserve of another protocol than the user's
+DELETE FROM aavev3_positions p
+WHERE NOT EXISTS (
+    SELECT 1
+    FROM aavev3_reserves r
+    WHERE r.protocol_details_id = p.protocol_details_id AND r.reserve = p.reserve
+);
+
+ALTER TABLE aavev3_users
+    ALTER COLUMN protocol_details_id SET NOT NULL,
+    DROP CONSTRAINT aavev3_users_pkey,
+    ADD PRIMARY KEY (protocol_details_id, address);
+
+ALTER TABLE aavev3_reserves
+    DROP CONSTRAINT aavev3_reserves_pkey,
+    ADD PRIMARY KEY (protocol_details_id, reserve);
+
+ALTER TABLE aavev3_users_stats
+    ALTER COLUMN protocol_details_id SET NOT NULL,
+    DROP CONSTRAINT aavev3_users_stats_pkey,
+    ADD PRIMARY KEY (protocol_details_id, user_address),
+    ADD FOREIGN KEY (protocol_details_id, user_address)
+        REFERENCES aavev3_users (protocol_details_id, address);
+
+ALTER TABLE aavev3_reserves_stats
+    ALTER COLUMN protocol_details_id SET NOT NULL,
+    DROP CONSTRAINT aavev3_reserves_stats_pkey,
+    ADD PRIMARY KEY (protocol_details_id, reserve),
+    ADD FOREIGN KEY (protocol_details_id, reserve)
+        REFERENCES aavev3_reserves (protocol_details_id, reserve);
+
+ALTER TABLE aavev3_positions
+    ALTER COLUMN protocol_details_id SET NOT NULL,
+    DROP CONSTRAINT aavev3_positions_user_address_reserve_key,
+    ADD UNIQUE (protocol_details_id, user_address, reserve),
+    ADD FOREIGN KEY (protocol_details_id, user_address)
+        REFERENCES aavev3_users (protocol_details_id, address),
+    ADD FOREIGN KEY (protocol_details_id, reserve)
+        REFERENCES aavev3_reserves (protocol_details_id, reserve);
+
+-- the liquidations' assets, only checked when the protocol is known
+ALTER TABLE aavev3_liquidations
+    ADD FOREIGN KEY (protocol_details_id, collateral_asset)
+        REFERENCES aavev3_reserves (protocol_details_id, reserve),
+    ADD FOREIGN KEY (protocol_details_id, debt_asset)
+        REFERENCES aavev3_reserves (protocol_details_id, reserve);
+ALTER TABLE aavev3_skipped_liquidations
+    ADD FOREIGN KEY (protocol_details_id, collateral_asset)
+        REFERENCES aavev3_reserves (protocol_details_id, reserve),
+    ADD FOREIGN KEY (protocol_details_id, debt_asset)
+        REFERENCES aavev3_reserves (protocol_details_id, reserve);
+ALTER TABLE liquidation_attempts
+    ADD FOREIGN KEY (protocol_details_id, collateral_asset)
+        REFERENCES aavev3_reserves (protocol_details_id, reserve),
+    ADD FOREIGN KEY (protocol_details_id, debt_asset)
+        REFERENCES aavev3_reserves (protocol_details_id, reserve);
@@ -1,19 +1,235 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 
-use crate::configs::DatabaseConfig;
+use crate::{args::HistoryArgs, configs::DatabaseConfig, error::LiquidatorError, metrics::METRICS};
 use actix::prelude::*;
-use sqlx::{PgPool, Row};
+use alloy::primitives::Address;
+use sqlx::PgPool;
+use tokio::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+use super::{
+    messages::{
+        database::{prune_history, upsert_users_data, UserWrite},
+        watchdog::Restarted,
+    },
+    watchdog::catch_panics,
+    Watchdog,
+};
+
+// users' writes are buffered for at most this long
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+// or until this many users are waiting
+pub const FLUSH_MAX_USERS: usize = 500;
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
 
 pub struct Database {
     pub pool: Arc<PgPool>,
+    pub users: UsersBuffer,
+    pub history: Option<HistoryArgs>,
 }
 
 impl Actor for Database {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(FLUSH_INTERVAL, |actor, ctx| actor.spawn_flush(ctx));
+
+        if let Some(history) = self.history.clone() {
+            ctx.run_interval(PRUNE_INTERVAL, move |actor, ctx| {
+                let pool = actor.pool.clone();
+                let history = history.clone();
+
+                let fut = async move {
+                    match prune_history(
+                        &pool,
+                        history.history_retention,
+                        history.history_downsample_after,
+                        history.history_downsample_bucket,
+                    )
+                    .await
+                    {
+                        Ok(deleted) => info!(deleted, "pruned history"),
+                        Err(e) => error!("Failed to prune history: {}", e),
+                    }
+                    Ok(())
+                };
+
+                ctx.spawn(catch_panics(fut).into_actor(actor).map(|res, _, ctx| {
+                    if let Err(e) = res {
+                        error!("History pruning failed: {:?}", e);
+                        ctx.stop();
+                    }
+                }));
+            });
+        }
+    }
+}
+
+// the pool reconnects on its own, and `users` survives the restart
+impl Supervised for Database {
+    fn restarting(&mut self, _: &mut Self::Context) {
+        warn!("[restarting] Database");
+        Watchdog::from_registry().do_send(Restarted { actor: "database" });
+    }
 }
 
 impl Database {
     pub async fn new(config: DatabaseConfig) -> Database {
-        Database { pool: config.pool }
+        Database {
+            pool: config.pool,
+            users: UsersBuffer::default(),
+            history: config.history,
+        }
+    }
+
+    pub(crate) fn spawn_flush(&self, ctx: &mut Context<Self>) {
+        let pool = self.pool.clone();
+        let users = self.users.clone();
+
+        let fut = async move {
+            if let Err(e) = users.flush(&pool).await {
+                error!("Failed to flush users: {}", e);
+            }
+            Ok(())
+        };
+
+        ctx.spawn(catch_panics(fut).into_actor(self).map(|res, _, ctx| {
+            if let Err(e) = res {
+                error!("Users flush failed: {:?}", e);
+                ctx.stop();
+            }
+        }));
+    }
+}
+
+/// Write-behind buffer of the users' data, keeping only the latest write of each user until it's
+/// flushed.
+#[derive(Clone, Debug, Default)]
+pub struct UsersBuffer {
+    // keyed by `UserWrite::key`
+    pending: Arc<Mutex<HashMap<(i32, Address), UserWrite>>>,
+    // one flush at a time, so that an older batch never lands after a newer one
+    flushing: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl UsersBuffer {
+    /// Buffers `writes`, replacing the users' pending ones. Returns how many users are pending.
+    pub fn push(&self, writes: Vec<UserWrite>) -> usize {
+        let mut pending = self.pending.lock().unwrap();
+        for write in writes {
+            pending.insert(write.key(), write);
+        }
+        pending.len()
+    }
+
+    /// Puts back a batch which failed to be written, unless the users were written again since.
+    pub fn requeue(&self, batch: Vec<UserWrite>) {
+        let mut pending = self.pending.lock().unwrap();
+        for write in batch {
+            pending.entry(write.key()).or_insert(write);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn take(&self) -> Vec<UserWrite> {
+        std::mem::take(&mut *self.pending.lock().unwrap())
+            .into_values()
+            .collect()
+    }
+
+    /// Writes the pending users, returns how many were written.
+    pub async fn flush(&self, pool: &PgPool) -> Result<usize, LiquidatorError> {
+        let _flushing = self.flushing.lock().await;
+
+        let batch = self.take();
+        if batch.is_empty() {
+            return Ok(0);
+        }
+
+        let start = Instant::now();
+        let res = upsert_users_data(pool, &batch).await;
+        record_flush(batch.len(), start.elapsed(), res.is_ok());
+
+        match res {
+            Ok(()) => Ok(batch.len()),
+            Err(e) => {
+                // retried with the next flush, anything else would fail again
+                if e.is_transient() {
+                    self.requeue(batch);
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+fn record_flush(batch_size: usize, elapsed: Duration, ok: bool) {
+    METRICS.set_gauge(
+        "db_flush_duration_seconds",
+        "Time taken by the last flush of the users' writes",
+        &[],
+        elapsed.as_secs_f64(),
+    );
+    METRICS.set_gauge(
+        "db_flush_batch_size",
+        "Users written by the last flush",
+        &[],
+        batch_size as f64,
+    );
+    METRICS.inc_counter(
+        "db_flushes_total",
+        "Flushes of the users' writes",
+        &[("result", if ok { "ok" } else { "error" }.to_string())],
+        1.0,
+    );
+    if ok {
+        METRICS.inc_counter(
+            "db_flushed_users_total",
+            "Users written by the flushes",
+            &[],
+            batch_size as f64,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(user: u8, health_factor: f64) -> UserWrite {
+        UserWrite {
+            address: Address::with_last_byte(user),
+            protocol_details_id: 1,
+            health_factor,
+            positions: vec![],
+        }
+    }
+
+    #[test]
+    fn latest_write_of_each_user_wins() {
+        let users = UsersBuffer::default();
+
+        assert_eq!(users.push(vec![write(1, 1.5), write(2, 1.2)]), 2);
+        assert_eq!(users.push(vec![write(1, 0.9)]), 2);
+
+        // a failed batch doesn't override the writes buffered while it was in flight
+        let batch = users.take();
+        assert!(users.is_empty());
+        users.push(vec![write(2, 1.1)]);
+        users.requeue(batch);
+
+        let mut pending = users.take();
+        pending.sort_by_key(|write| write.address);
+        assert_eq!(pending, vec![write(1, 0.9), write(2, 1.1)]);
     }
 }
@@ -1,25 +1,47 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use crate::client::{send_liquidation, LendingProtocolClient, Liquidation, LiquidationReceipt};
 use crate::contracts;
-use crate::utils::{find_most_liquid_uniswap_pool, health_factor, user_liquidation_data};
+use crate::flashloan::{Flashloan, FlashloanLenders};
+use crate::metrics::METRICS;
+use crate::revert::{revert_reason, LiquidationError, RetryPolicy};
+use crate::router::{intermediate_tokens, Route, Router, UniswapV3};
+use crate::strategy::{most_profitable, Candidate, Strategy};
+use crate::utils::{health_factor, norm, user_liquidation_data};
+use crate::validator::{validate_liquidation, SkipReason};
 use actix::prelude::*;
 use alloy::{
-    primitives::{Address, Uint},
+    primitives::{Address, Bytes, U256},
     providers::Provider,
 };
+use tokio::{sync::mpsc::UnboundedSender, time::Duration};
 
 use tracing::{error, info, warn};
 
-use super::messages::executor::LiquidationRequest;
+use super::messages::executor::{LiquidationRequest, SetLiquidationsAllowed, SimulatedLiquidation};
 use super::messages::fanatic::SendExecutorAddr;
+use super::messages::watchdog::Restarted;
 use super::Database;
 use super::Fanatic;
+use super::{watchdog::catch_panics, Watchdog};
 use crate::{
     actors::messages::{
-        database,
+        database::{self, AttemptStatus, Reserve},
         fanatic::{FailedLiquidation, SuccessfulLiquidation},
     },
     configs::ExecutorConfig,
+    error::LiquidatorError,
 };
 
+// liquidations reverting for lack of proceeds are retried this many times in a row at most
+const MAX_RETRIES: u32 = 3;
+const BLOCK_POLL_INTERVAL: Duration = Duration::from_secs(1);
+// the sentinel is polled this often while liquidations are allowed, every block otherwise
+const SENTINEL_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
 // executes the liquidations by triggering the liquidator contract
 #[derive(Debug, Clone)]
 pub struct Executor<P: Provider + Unpin + Clone + 'static> {
@@ -27,169 +49,1397 @@ pub struct Executor<P: Provider + Unpin + Clone + 'static> {
 
     pub db_addr: Addr<Database>,
     pub fanatic_addr: Addr<Fanatic<P>>,
-    pub provider_addr: Address,
+    pub client: Arc<dyn LendingProtocolClient>,
+    pub protocol_details_id: i32,
 
     // deployed liquidator bot
     pub bot_contract:
         contracts::liquidator::LiquidatoorContract::LiquidatoorContractInstance<(), P>,
 
-    // aave_v3
-    pub pool_contract: contracts::aave_v3::PoolContract::PoolContractInstance<(), P>,
-    pub datap_contract:
-        contracts::aave_v3::DataProviderContract::DataProviderContractInstance<(), P>,
+    // swaps of the seized collateral back to the debt asset
+    pub router: Router,
+
+    pub flashloan_lenders: FlashloanLenders,
+    pub slippage_bps: u32,
+    pub min_profit_usd: f64,
+    // collaterals whose aToken we'd rather keep than swap, see `Strategy::ReceiveAToken`
+    pub hold_atokens: Vec<Address>,
 
-    // uniswap_v3
-    pub factory_contract: contracts::uniswap_v3::FactoryContract::FactoryContractInstance<(), P>,
-    pub quoter_contract: contracts::uniswap_v3::QuoterContract::QuoterContractInstance<(), P>,
+    // (collateral, debt) pairs whose liquidations are bound to revert, see `RetryPolicy::Blacklist`
+    pub blacklist: Arc<Mutex<HashSet<(Address, Address)>>>,
+    // user => liquidations retried in a row
+    pub retries: Arc<Mutex<HashMap<Address, u32>>>,
+
+    // set while the `PriceOracleSentinel` disallows liquidations, requests wait in `queue`
+    pub paused: bool,
+    pub queue: VecDeque<LiquidationRequest>,
+
+    // backtesting, see `ExecutorConfig::simulations`
+    pub simulations: Option<UnboundedSender<SimulatedLiquidation>>,
 }
 
 impl<P: Provider + Unpin + Clone + 'static> Actor for Executor<P> {
     type Context = Context<Self>;
 
-    // notify `fanatic` of our address
+    // notify `fanatic` of our address, again when restarted
     fn started(&mut self, ctx: &mut Self::Context) {
         self.fanatic_addr.do_send(SendExecutorAddr(ctx.address()));
+
+        // the fork only moves when told to
+        if self.simulations.is_none() {
+            self.check_sentinel(ctx);
+        }
+    }
+}
+
+impl<P: Provider + Unpin + Clone + 'static> Supervised for Executor<P> {
+    fn restarting(&mut self, _: &mut Self::Context) {
+        warn!("[restarting] Executor");
+        Watchdog::from_registry().do_send(Restarted { actor: "executor" });
     }
 }
 
 impl<P: Provider + Unpin + Clone + 'static> Executor<P> {
     pub async fn new(config: ExecutorConfig<P>) -> eyre::Result<Executor<P>> {
-        let target_network = config.target.split('-').next().unwrap();
-        let uniswap_target = format!("{}-uniswap_v3", target_network);
-
-        let aave_contracts = config
+        let protocol_details_id = config
             .db_addr
-            .send(database::GetProtocolContracts(config.target))
-            .await??;
-        let uniswap_contracts = config
-            .db_addr
-            .send(database::GetProtocolContracts(uniswap_target))
+            .send(database::GetProtocolDetailsId(config.target.clone()))
             .await??;
+        let (router, flashloan_lenders) =
+            router_and_lenders(&config.provider, &config.db_addr, &config.target).await?;
 
-        let (
-            bot_contract,
-            factory_contract,
-            quoter_contract,
-            pool_contract,
-            datap_contract,
-            provider_addr,
-        ) = match (
-            uniswap_contracts.get("UniswapV3Factory"),
-            uniswap_contracts.get("QuoterV2"),
-            aave_contracts.get("Pool"),
-            aave_contracts.get("UiPoolDataProviderV3"),
-            aave_contracts.get("PoolAddressesProvider"),
-        ) {
-            (
-                Some(factory_addr),
-                Some(quoter_addr),
-                Some(pool_addr),
-                Some(datap_addr),
-                Some(provider_addr),
-            ) => (
-                contracts::liquidator::LiquidatoorContract::new(
-                    config.bot_addr,
-                    config.provider.clone(),
-                ),
-                contracts::uniswap_v3::FactoryContract::new(*factory_addr, config.provider.clone()),
-                contracts::uniswap_v3::QuoterContract::new(*quoter_addr, config.provider.clone()),
-                contracts::aave_v3::PoolContract::new(*pool_addr, config.provider.clone()),
-                contracts::aave_v3::DataProviderContract::new(*datap_addr, config.provider.clone()),
-                *provider_addr,
-            ),
-            _ => return Err(eyre::eyre!("Missing required contract addresses")),
-        };
+        let bot_contract = contracts::liquidator::LiquidatoorContract::new(
+            config.bot_addr,
+            config.provider.clone(),
+        );
 
         Ok(Executor {
             provider: config.provider,
 
             db_addr: config.db_addr,
             fanatic_addr: config.fanatic_addr,
-            provider_addr,
+            client: config.client,
+            protocol_details_id,
 
             bot_contract,
-            pool_contract,
-            datap_contract,
-            factory_contract,
-            quoter_contract,
+            router,
+
+            flashloan_lenders,
+            slippage_bps: config.slippage_bps,
+            min_profit_usd: config.min_profit_usd,
+            hold_atokens: config.hold_atokens,
+
+            blacklist: Arc::new(Mutex::new(HashSet::new())),
+            retries: Arc::new(Mutex::new(HashMap::new())),
+
+            paused: false,
+            queue: VecDeque::new(),
+
+            simulations: config.simulations,
         })
     }
+
+    /// Polls the `PriceOracleSentinel`, every block while liquidations are paused so that they
+    /// resume as soon as `isLiquidationAllowed()` does.
+    fn check_sentinel(&mut self, ctx: &mut Context<Self>) {
+        let client = self.client.clone();
+
+        ctx.spawn(async move { client.sentinel().await }.into_actor(self).map(
+            |res, actor, ctx| {
+                match res {
+                    Ok(Some(status)) if !status.liquidation_allowed => {
+                        if !actor.paused {
+                            warn!(
+                                sentinel = ?status.sentinel,
+                                sequencer_up = status.sequencer_up,
+                                resumes_at = ?status.resumes_at(),
+                                "liquidations disallowed by the price oracle sentinel"
+                            );
+                        }
+                        actor.set_paused(true, ctx);
+                    }
+                    Ok(_) => actor.set_paused(false, ctx),
+                    Err(e) => warn!(error = ?e, "Failed to check the price oracle sentinel"),
+                }
+
+                let interval = if actor.paused {
+                    BLOCK_POLL_INTERVAL
+                } else {
+                    SENTINEL_CHECK_INTERVAL
+                };
+                ctx.run_later(interval, |actor, ctx| actor.check_sentinel(ctx));
+            },
+        ));
+    }
+
+    fn set_paused(&mut self, paused: bool, ctx: &mut Context<Self>) {
+        if self.paused == paused {
+            return;
+        }
+        self.paused = paused;
+
+        if paused {
+            warn!(queued = self.queue.len(), "pausing liquidations");
+        } else {
+            info!(queued = self.queue.len(), "resuming liquidations");
+            for msg in self.queue.drain(..) {
+                ctx.notify(msg);
+            }
+        }
+
+        METRICS.set_gauge(
+            "liquidations_paused",
+            "1 while the price oracle sentinel disallows liquidations",
+            &[],
+            if paused { 1.0 } else { 0.0 },
+        );
+    }
+}
+
+impl<P: Provider + Unpin + Clone + 'static> Handler<SetLiquidationsAllowed> for Executor<P> {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetLiquidationsAllowed, ctx: &mut Context<Self>) -> Self::Result {
+        self.set_paused(!msg.allowed, ctx);
+    }
 }
 
 impl<P: Provider + Unpin + Clone + 'static> Handler<LiquidationRequest> for Executor<P> {
-    type Result = ResponseFuture<eyre::Result<()>>;
+    type Result = ResponseActFuture<Self, eyre::Result<()>>;
+
+    fn handle(&mut self, msg: LiquidationRequest, ctx: &mut Self::Context) -> Self::Result {
+        if self.paused {
+            info!(user = ?msg.user_address, "liquidations paused, queueing");
+            enqueue(&mut self.queue, msg);
+            return Box::pin(fut::ready(Ok(())));
+        }
 
-    fn handle(&mut self, msg: LiquidationRequest, _ctx: &mut Self::Context) -> Self::Result {
+        let addr = ctx.address();
         let db_addr = self.db_addr.clone();
         let fanatic_addr = self.fanatic_addr.clone();
         let provider = self.provider.clone();
-        let provider_addr = self.provider_addr;
+        let client = self.client.clone();
+        let protocol_details_id = self.protocol_details_id;
         let bot_contract = self.bot_contract.clone();
-        let pool_contract = self.pool_contract.clone();
-        let datap_contract = self.datap_contract.clone();
-        let factory_contract = self.factory_contract.clone();
+        let router = self.router.clone();
+        let flashloan_lenders = self.flashloan_lenders.clone();
+        let (slippage_bps, min_profit_usd) = (self.slippage_bps, self.min_profit_usd);
+        let hold_atokens = self.hold_atokens.clone();
+        let (blacklist, retries) = (self.blacklist.clone(), self.retries.clone());
+        let simulations = self.simulations.clone();
 
         let fut = async move {
-            let health_factor = health_factor(&pool_contract, msg.user_address).await;
-
-            if let Some(hf) = health_factor {
-                info!(user = ?msg.user_address, health_factor = ?hf, "user is undercollateralized");
-
-                let indices = db_addr
-                    .send(database::GetReservesLiquidityIndices(format!(
-                        "{}-{}",
-                        msg.network, msg.protocol
-                    )))
-                    .await??;
-
-                let (debt_asset, collateral_asset, debt_to_cover) = user_liquidation_data(
-                    &datap_contract,
-                    provider_addr,
-                    msg.user_address,
-                    &indices,
-                )
-                .await?;
-
-                let (_pool_addr, fee) = find_most_liquid_uniswap_pool(
-                    &provider,
-                    &factory_contract,
+            let target = format!("{}-{}", msg.network, msg.protocol);
+            let indices = db_addr
+                .send(database::GetReservesLiquidityIndices(target.clone()))
+                .await??;
+            let reserves = db_addr.send(database::GetReserves(target)).await??;
+
+            match liquidation_decision(client.as_ref(), msg.user_address, &indices, &reserves)
+                .await?
+            {
+                Decision::Healthy => Ok(()),
+                Decision::Skip {
+                    collateral_asset,
+                    debt_asset,
+                    reason,
+                } => {
+                    warn!(user = ?msg.user_address, ?collateral_asset, ?debt_asset, %reason, "skipping liquidation");
+                    db_addr
+                        .send(database::InsertSkippedLiquidation {
+                            protocol_details_id,
+                            user_address: msg.user_address,
+                            collateral_asset,
+                            debt_asset,
+                            reason: reason.to_string(),
+                        })
+                        .await??;
+                    Ok(())
+                }
+                Decision::Liquidate {
                     collateral_asset,
                     debt_asset,
-                )
-                .await?;
+                    debt_to_cover,
+                    collateral_balance,
+                    health_factor: hf,
+                } => {
+                    if blacklist
+                        .lock()
+                        .unwrap()
+                        .contains(&(collateral_asset, debt_asset))
+                    {
+                        let reason = SkipReason::Blacklisted;
+                        warn!(user = ?msg.user_address, ?collateral_asset, ?debt_asset, %reason, "skipping liquidation");
+                        db_addr
+                            .send(database::InsertSkippedLiquidation {
+                                protocol_details_id,
+                                user_address: msg.user_address,
+                                collateral_asset,
+                                debt_asset,
+                                reason: reason.to_string(),
+                            })
+                            .await??;
+                        return Ok(());
+                    }
+
+                    let collateral_reserve =
+                        reserves.iter().find(|r| r.reserve == collateral_asset);
+                    let debt_reserve = reserves.iter().find(|r| r.reserve == debt_asset);
+
+                    let (Some(collateral_reserve), Some(debt_reserve)) =
+                        (collateral_reserve, debt_reserve)
+                    else {
+                        return Err(LiquidatorError::StaleState(format!(
+                            "Unknown reserves {} / {}, can't size the swap",
+                            collateral_asset, debt_asset
+                        ))
+                        .into());
+                    };
+
+                    let plan = plan_liquidation(
+                        &provider,
+                        client.as_ref(),
+                        &router,
+                        &flashloan_lenders,
+                        *bot_contract.address(),
+                        collateral_reserve,
+                        debt_reserve,
+                        debt_to_cover,
+                        collateral_balance,
+                        &hold_atokens,
+                        slippage_bps,
+                        min_profit_usd,
+                    )
+                    .await?;
+                    let candidate = match plan.candidate() {
+                        Ok(candidate) => candidate.clone(),
+                        Err(reason) => {
+                            warn!(user = ?msg.user_address, ?collateral_asset, ?debt_asset, quoted = ?plan.route.map(|r| r.amount_out), %reason, "skipping liquidation");
+                            db_addr
+                                .send(database::InsertSkippedLiquidation {
+                                    protocol_details_id,
+                                    user_address: msg.user_address,
+                                    collateral_asset,
+                                    debt_asset,
+                                    reason: reason.to_string(),
+                                })
+                                .await??;
+                            return Ok(());
+                        }
+                    };
+                    let estimated_profit_usd = candidate.expected_profit_usd;
 
-                match bot_contract
-                    .liquidatoor(
+                    let liquidation = Liquidation {
                         debt_asset,
                         collateral_asset,
-                        msg.user_address,
+                        user: msg.user_address,
                         debt_to_cover,
-                        Uint::from(fee),
-                    )
-                    .call()
-                    .await
-                {
-                    Ok(_) => {
+                        path: candidate.path,
+                        min_amount_out: candidate.min_amount_out,
+                        flashloan: candidate.flashloan,
+                    };
+                    info!(user = ?msg.user_address, strategy = %candidate.strategy, flashloan = ?candidate.flashloan.map(|f| f.source), estimated_profit_usd, "liquidating");
+
+                    let simulating = simulations.is_some();
+                    let sent = if let Some(simulations) = simulations {
+                        let (simulated, sent) = simulate_liquidation(
+                            &provider,
+                            &bot_contract,
+                            &liquidation,
+                            candidate.strategy,
+                            Some(debt_reserve),
+                            estimated_profit_usd,
+                        )
+                        .await?;
+                        info!(user = ?msg.user_address, success = simulated.success, profit_usd = simulated.profit_usd, "simulated liquidation");
+
+                        let _ = simulations.send(simulated);
+                        sent
+                    } else {
+                        client.liquidate(liquidation.clone()).await
+                    };
+
+                    let liquidated = sent.as_ref().is_ok_and(|receipt| receipt.success);
+                    // someone else may have gotten there first
+                    let still_liquidatable = liquidated
+                        || match health_factor(client.as_ref(), msg.user_address).await {
+                            Ok(hf) => hf.is_some_and(|hf| hf < 1.0),
+                            // can't tell, don't blame it on someone else
+                            Err(e) => {
+                                warn!(user = ?msg.user_address, "failed to fetch health factor: {}", e);
+                                true
+                            }
+                        };
+                    let status = attempt_status(simulating, &sent, still_liquidatable);
+                    let (receipt, revert_reason, failure) = match sent {
+                        Ok(receipt) => (Some(receipt), None, None),
+                        Err(e) => {
+                            let revert_reason = revert_reason(&e).unwrap_or(e.to_string());
+                            let failure = LiquidatorError::from_liquidation_report(e);
+                            error!(user = ?msg.user_address, "Liquidation failed: {}", failure);
+                            (None, Some(revert_reason), Some(failure))
+                        }
+                    };
+                    let error = match &failure {
+                        Some(LiquidatorError::ContractRevert(e)) => Some(e.clone()),
+                        _ => None,
+                    };
+
+                    db_addr
+                        .send(database::InsertLiquidationAttempt {
+                            protocol_details_id,
+                            liquidation,
+                            strategy: candidate.strategy,
+                            health_factor: hf,
+                            estimated_profit_usd,
+                            status,
+                            receipt,
+                            revert_reason,
+                        })
+                        .await??;
+
+                    if liquidated {
                         fanatic_addr
                             .send(SuccessfulLiquidation {
                                 user_addr: msg.user_address,
                             })
-                            .await?;
-                    }
-                    Err(e) => {
+                            .await??;
+                    } else {
                         fanatic_addr
                             .send(FailedLiquidation {
                                 user_addr: msg.user_address,
                             })
-                            .await?;
-                        error!("Liquidation failed: {}", e);
+                            .await??;
+                    }
+
+                    // retried once the sentinel allows liquidations again, not on the next block
+                    if !simulating
+                        && error == Some(LiquidationError::PriceOracleSentinelCheckFailed)
+                    {
+                        retries.lock().unwrap().remove(&msg.user_address);
+                        addr.do_send(SetLiquidationsAllowed { allowed: false });
+                        addr.do_send(msg);
+                        return Ok(());
+                    }
+
+                    let policy = error
+                        .as_ref()
+                        .map_or(RetryPolicy::Drop, LiquidationError::retry_policy);
+                    match policy {
+                        RetryPolicy::Blacklist => {
+                            warn!(?collateral_asset, ?debt_asset, ?error, "blacklisting pair");
+                            blacklist
+                                .lock()
+                                .unwrap()
+                                .insert((collateral_asset, debt_asset));
+                        }
+                        // the fork only moves when told to
+                        RetryPolicy::RetryNextBlock if !simulating => {
+                            let retried = {
+                                let mut retries = retries.lock().unwrap();
+                                let retried = retries.entry(msg.user_address).or_default();
+                                *retried += 1;
+                                *retried
+                            };
+                            if retried <= MAX_RETRIES {
+                                info!(user = ?msg.user_address, retried, ?error, "retrying liquidation next block");
+                                let block = provider.get_block_number().await?;
+                                actix::spawn(async move {
+                                    match next_block(&provider, block).await {
+                                        Ok(()) => addr.do_send(msg),
+                                        Err(e) => {
+                                            error!("Failed to wait for the next block: {}", e)
+                                        }
+                                    }
+                                });
+                                return Ok(());
+                            }
+                        }
+                        _ => {}
+                    }
+                    retries.lock().unwrap().remove(&msg.user_address);
+                    match failure {
+                        Some(failure) => Err(failure.into()),
+                        None => Ok(()),
                     }
                 }
             }
+        };
+
+        // a panic stops the executor, its supervisor restarts it
+        Box::pin(
+            catch_panics(async move { Ok(fut.await) })
+                .into_actor(self)
+                .map(|res, _, ctx| {
+                    res.unwrap_or_else(|e| {
+                        error!("Liquidation request failed: {:?}", e);
+                        ctx.stop();
+                        Err(e)
+                    })
+                }),
+        )
+    }
+}
+
+/// Swap router and flashloan lenders of the target's network, as registered in the database.
+pub async fn router_and_lenders<P: Provider + Unpin + Clone + 'static>(
+    provider: &P,
+    db_addr: &Addr<Database>,
+    target: &str,
+) -> eyre::Result<(Router, FlashloanLenders)> {
+    let (target_network, _) = database::split_target(target)?;
+    let uniswap_target = format!("{}-uniswap_v3", target_network);
+    let balancer_target = format!("{}-balancer_v3", target_network);
+    let morpho_target = format!("{}-morpho_blue", target_network);
+
+    let uniswap_contracts = db_addr
+        .send(database::GetProtocolContracts(uniswap_target))
+        .await??;
+
+    let uniswap_v3 = match (
+        uniswap_contracts.get("UniswapV3Factory"),
+        uniswap_contracts.get("QuoterV2"),
+    ) {
+        (Some(factory_addr), Some(quoter_addr)) => UniswapV3::new(
+            provider.clone(),
+            *factory_addr,
+            *quoter_addr,
+            intermediate_tokens(&target_network),
+        ),
+        _ => return Err(eyre::eyre!("Missing required contract addresses")),
+    };
+
+    let aave_pool = db_addr
+        .send(database::GetProtocolContracts(target.to_string()))
+        .await??
+        .get("Pool")
+        .copied()
+        .ok_or(eyre::eyre!("Missing required contract addresses"))?;
+    // zero-fee lenders, not necessarily deployed on every network
+    let balancer_vault = db_addr
+        .send(database::GetProtocolContracts(balancer_target))
+        .await??
+        .get("Vault")
+        .copied();
+    let morpho = db_addr
+        .send(database::GetProtocolContracts(morpho_target))
+        .await??
+        .get("MorphoBlue")
+        .copied();
+
+    Ok((
+        Router::new(vec![Arc::new(uniswap_v3)]),
+        FlashloanLenders {
+            aave_pool,
+            balancer_vault,
+            morpho,
+        },
+    ))
+}
+
+/// Queues a request while liquidations are paused, a user being re-evaluated from scratch when
+/// they resume there's no point queueing them twice.
+pub fn enqueue(queue: &mut VecDeque<LiquidationRequest>, msg: LiquidationRequest) {
+    if !queue.iter().any(|m| m.user_address == msg.user_address) {
+        queue.push_back(msg);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    // not liquidatable, or out of the health factor's sane range
+    Healthy,
+    Skip {
+        collateral_asset: Address,
+        debt_asset: Address,
+        reason: SkipReason,
+    },
+    Liquidate {
+        collateral_asset: Address,
+        debt_asset: Address,
+        debt_to_cover: U256,
+        // the user's, what may be seized at most
+        collateral_balance: U256,
+        // at decision time
+        health_factor: f64,
+    },
+}
+
+/// Decides what to do about `user` given the protocol's state and our view of the reserves.
+/// Reserves we don't know about aren't validated.
+pub async fn liquidation_decision(
+    client: &dyn LendingProtocolClient,
+    user: Address,
+    indices: &HashMap<String, (f64, f64)>,
+    reserves: &[Reserve],
+) -> eyre::Result<Decision> {
+    let hf = match health_factor(client, user).await? {
+        Some(hf) if hf < 1.0 => hf,
+        _ => return Ok(Decision::Healthy),
+    };
+    info!(?user, health_factor = ?hf, "user is undercollateralized");
+
+    let (debt_asset, collateral_asset, debt_to_cover, collateral_balance) =
+        user_liquidation_data(client, user, indices).await?;
+
+    let collateral_reserve = reserves.iter().find(|r| r.reserve == collateral_asset);
+    let debt_reserve = reserves.iter().find(|r| r.reserve == debt_asset);
+    if let (Some(collateral_reserve), Some(debt_reserve)) = (collateral_reserve, debt_reserve) {
+        if let Err(reason) = validate_liquidation(collateral_reserve, debt_reserve) {
+            return Ok(Decision::Skip {
+                collateral_asset,
+                debt_asset,
+                reason,
+            });
+        }
+    }
+
+    Ok(Decision::Liquidate {
+        collateral_asset,
+        debt_asset,
+        debt_to_cover,
+        collateral_balance,
+        health_factor: hf,
+    })
+}
+
+/// How a liquidation would go, see `plan_liquidation`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiquidationPlan {
+    // collateral handed over, bonus included
+    pub seized: U256,
+    // `None` for a same asset liquidation, or when there's no route
+    pub route: Option<Route>,
+    // `None` when no lender can lend the debt to cover
+    pub flashloan: Option<Flashloan>,
+    // `None` when no strategy clears `min_profit_usd`
+    pub candidate: Option<Candidate>,
+}
+
+impl LiquidationPlan {
+    /// The strategy to go with, or why there's none.
+    pub fn candidate(&self) -> Result<&Candidate, SkipReason> {
+        match (&self.candidate, &self.flashloan) {
+            (Some(candidate), _) => Ok(candidate),
+            (None, None) => Err(SkipReason::FlashloanDisabled),
+            (None, Some(_)) => Err(SkipReason::Unprofitable),
+        }
+    }
+}
+
+/// Sizes the seized collateral, quotes its swap back to the debt asset and picks the cheapest
+/// flashloan, then the most profitable strategy.
+#[allow(clippy::too_many_arguments)]
+pub async fn plan_liquidation<P: Provider + Unpin + Clone + 'static>(
+    provider: &P,
+    client: &dyn LendingProtocolClient,
+    router: &Router,
+    flashloan_lenders: &FlashloanLenders,
+    bot_addr: Address,
+    collateral_reserve: &Reserve,
+    debt_reserve: &Reserve,
+    debt_to_cover: U256,
+    collateral_balance: U256,
+    hold_atokens: &[Address],
+    slippage_bps: u32,
+    min_profit_usd: f64,
+) -> eyre::Result<LiquidationPlan> {
+    let (collateral_asset, debt_asset) = (collateral_reserve.reserve, debt_reserve.reserve);
+
+    let seized = seized_collateral(
+        collateral_reserve,
+        debt_reserve,
+        debt_to_cover,
+        collateral_balance,
+        client.liquidation_protocol_fee(collateral_asset).await?,
+    )?;
+    let route = if collateral_asset == debt_asset {
+        None
+    } else {
+        router
+            .best_route(collateral_asset, debt_asset, seized)
+            .await
+            .inspect_err(|e| warn!(?collateral_asset, ?debt_asset, error = ?e, "no swap route"))
+            .ok()
+    };
+    let flashloan = flashloan_lenders
+        .cheapest(
+            provider,
+            debt_asset,
+            debt_to_cover,
+            debt_reserve.flashloan_enabled,
+            client.flashloan_premium().await?,
+        )
+        .await;
+    // only worth knowing when we'd keep the collateral
+    let own_balance = if hold_atokens.contains(&collateral_asset) {
+        contracts::erc20::ERC20Contract::new(debt_asset, provider.clone())
+            .balanceOf(bot_addr)
+            .call()
+            .await?
+            ._0
+    } else {
+        U256::ZERO
+    };
+
+    let candidates = strategy_candidates(
+        collateral_reserve,
+        debt_reserve,
+        debt_to_cover,
+        seized,
+        flashloan.as_ref(),
+        route.as_ref(),
+        own_balance,
+        slippage_bps,
+        min_profit_usd,
+    )?;
+
+    Ok(LiquidationPlan {
+        seized,
+        route,
+        flashloan,
+        candidate: most_profitable(candidates),
+    })
+}
+
+/// Collateral `liquidationCall` hands over for `debt_to_cover`, bonus included, priced off our view
+/// of the reserves. That's what gets swapped back to the debt asset. No more than the user's
+/// `collateral_balance` is seized, and the protocol keeps `protocol_fee` percent of the bonus.
+pub fn seized_collateral(
+    collateral_reserve: &Reserve,
+    debt_reserve: &Reserve,
+    debt_to_cover: U256,
+    collateral_balance: U256,
+    protocol_fee: f64,
+) -> eyre::Result<U256> {
+    if collateral_reserve.stats.price_usd <= 0.0 {
+        return Err(LiquidatorError::StaleState(format!(
+            "No price for collateral {}",
+            collateral_reserve.reserve
+        ))
+        .into());
+    }
+
+    let debt_usd = norm(debt_to_cover, Some(10f64.powi(-debt_reserve.decimals)))?
+        * debt_reserve.stats.price_usd;
+    let bonus = 1.0 + collateral_reserve.liquidation_bonus / 100.0;
+    let collateral = debt_usd / collateral_reserve.stats.price_usd * bonus;
+    let collateral = U256::from((collateral * 10f64.powi(collateral_reserve.decimals)) as u128)
+        .min(collateral_balance);
+
+    // in bps as aave does, i.e `percentDiv`/`percentMul`
+    let bonus_bps = U256::from((bonus * 10_000.0).round() as u128);
+    let bonus_collateral = collateral - collateral * U256::from(10_000) / bonus_bps;
+    let fee =
+        bonus_collateral * U256::from((protocol_fee * 100.0).round() as u128) / U256::from(10_000);
+    Ok(collateral - fee)
+}
+
+/// Least the liquidation may bring back: the quote minus `slippage_bps`, never below the
+/// flashloan's repayment plus `min_profit`. `None` when even the quote falls short of that.
+pub fn min_amount_out(
+    quoted: U256,
+    slippage_bps: u32,
+    repayment: U256,
+    min_profit: U256,
+) -> Option<U256> {
+    let floor = repayment + min_profit;
+    if quoted < floor {
+        return None;
+    }
+
+    let tolerated = quoted * U256::from(10_000 - slippage_bps) / U256::from(10_000);
+    Some(tolerated.max(floor))
+}
+
+/// `usd` worth of the reserve's asset, in its smallest unit.
+pub fn usd_to_amount(reserve: &Reserve, usd: f64) -> eyre::Result<U256> {
+    if usd <= 0.0 {
+        return Ok(U256::ZERO);
+    }
+    if reserve.stats.price_usd <= 0.0 {
+        return Err(
+            LiquidatorError::StaleState(format!("No price for {}", reserve.reserve)).into(),
+        );
+    }
+
+    Ok(U256::from(
+        (usd / reserve.stats.price_usd * 10f64.powi(reserve.decimals)) as u128,
+    ))
+}
+
+/// USD value of `amount` of the reserve's asset.
+fn amount_to_usd(reserve: &Reserve, amount: U256) -> eyre::Result<f64> {
+    Ok(norm(amount, Some(10f64.powi(-reserve.decimals)))? * reserve.stats.price_usd)
+}
+
+/// Strategies clearing `min_profit_usd`, with their expected profit ignoring gas. The swap's is
+/// off `route`'s quote, the others off our view of the prices. Without `flashloan`, only our own
+/// balance can cover the debt.
+#[allow(clippy::too_many_arguments)]
+pub fn strategy_candidates(
+    collateral_reserve: &Reserve,
+    debt_reserve: &Reserve,
+    debt_to_cover: U256,
+    seized: U256,
+    flashloan: Option<&Flashloan>,
+    route: Option<&Route>,
+    own_balance: U256,
+    slippage_bps: u32,
+    min_profit_usd: f64,
+) -> eyre::Result<Vec<Candidate>> {
+    let mut candidates = Vec::new();
+    let min_profit = usd_to_amount(debt_reserve, min_profit_usd)?;
+
+    if let Some(flashloan) = flashloan {
+        let repayment = debt_to_cover + flashloan.fee;
+        if collateral_reserve.reserve == debt_reserve.reserve {
+            if seized >= repayment + min_profit {
+                candidates.push(Candidate {
+                    strategy: Strategy::SameAsset,
+                    path: Bytes::new(),
+                    min_amount_out: repayment + min_profit,
+                    flashloan: Some(*flashloan),
+                    expected_profit_usd: amount_to_usd(debt_reserve, seized - repayment)?,
+                });
+            }
+        } else if let Some(route) = route {
+            if let Some(min_amount_out) =
+                min_amount_out(route.amount_out, slippage_bps, repayment, min_profit)
+            {
+                candidates.push(Candidate {
+                    strategy: Strategy::SwapAndRepay,
+                    path: route.path.clone(),
+                    min_amount_out,
+                    flashloan: Some(*flashloan),
+                    expected_profit_usd: amount_to_usd(debt_reserve, route.amount_out - repayment)?,
+                });
+            }
+        }
+    }
+
+    // no flashloan to repay, what's kept has to be worth the debt covered plus the profit
+    if !own_balance.is_zero() && own_balance >= debt_to_cover {
+        let debt_usd = amount_to_usd(debt_reserve, debt_to_cover)?;
+        let min_atokens = usd_to_amount(collateral_reserve, debt_usd + min_profit_usd)?;
+        if seized >= min_atokens {
+            candidates.push(Candidate {
+                strategy: Strategy::ReceiveAToken,
+                path: Bytes::new(),
+                min_amount_out: min_atokens,
+                flashloan: None,
+                expected_profit_usd: amount_to_usd(collateral_reserve, seized)? - debt_usd,
+            });
+        }
+    }
+
+    Ok(candidates)
+}
+
+/// Sends the liquidation for real, meant for a fork, and measures what the bot's left with.
+async fn simulate_liquidation<P: Provider + Unpin + Clone + 'static>(
+    provider: &P,
+    bot_contract: &contracts::liquidator::LiquidatoorContract::LiquidatoorContractInstance<(), P>,
+    liquidation: &Liquidation,
+    strategy: Strategy,
+    debt_reserve: Option<&Reserve>,
+    estimated_profit_usd: f64,
+) -> eyre::Result<(SimulatedLiquidation, eyre::Result<LiquidationReceipt>)> {
+    let user = liquidation.user;
+    let block_number = provider.get_block_number().await?;
+    let debt_token = contracts::erc20::ERC20Contract::new(liquidation.debt_asset, provider.clone());
+    let balance_before = debt_token
+        .balanceOf(*bot_contract.address())
+        .call()
+        .await?
+        ._0;
+
+    let sent = send_liquidation(provider, bot_contract, liquidation).await;
+    if let Err(e) = &sent {
+        warn!(?user, error = ?e, "simulated liquidation reverted");
+    }
+    let receipt = sent.as_ref().ok();
+
+    let balance_after = debt_token
+        .balanceOf(*bot_contract.address())
+        .call()
+        .await?
+        ._0;
+    let (decimals, price) = debt_reserve.map_or((0, 0.0), |r| (r.decimals, r.stats.price_usd));
+    let scale = Some(10f64.powi(-decimals));
+    let profit = norm(balance_after.saturating_sub(balance_before), scale)?;
+
+    let simulated = SimulatedLiquidation {
+        block_number,
+        user_address: user,
+        collateral_asset: liquidation.collateral_asset,
+        debt_asset: liquidation.debt_asset,
+        debt_to_cover: norm(liquidation.debt_to_cover, scale)?,
+        strategy,
+        flashloan_source: liquidation.flashloan.map(|f| f.source),
+        flashloan_fee: norm(liquidation.flashloan.map_or(U256::ZERO, |f| f.fee), scale)?,
+        success: receipt.is_some_and(|r| r.success),
+        tx_hash: receipt.map(|r| r.tx_hash),
+        gas_used: receipt.map(|r| r.gas_used),
+        profit,
+        profit_usd: profit * price,
+        estimated_profit_usd,
+    };
+    Ok((simulated, sent))
+}
+
+/// Resolves once the chain is past `block`.
+async fn next_block<P: Provider + Unpin + Clone + 'static>(
+    provider: &P,
+    block: u64,
+) -> eyre::Result<()> {
+    while provider.get_block_number().await? <= block {
+        tokio::time::sleep(BLOCK_POLL_INTERVAL).await;
+    }
+    Ok(())
+}
+
+/// `still_liquidatable` tells a failed liquidation from one somebody else beat us to.
+pub fn attempt_status(
+    simulating: bool,
+    sent: &eyre::Result<LiquidationReceipt>,
+    still_liquidatable: bool,
+) -> AttemptStatus {
+    match sent {
+        _ if simulating => AttemptStatus::Simulated,
+        Ok(receipt) if receipt.success => AttemptStatus::MinedSuccess,
+        _ if !still_liquidatable => AttemptStatus::LostRace,
+        Ok(_) => AttemptStatus::MinedFailed,
+        Err(_) => AttemptStatus::Reverted,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::address;
+    use sqlx::types::time::{OffsetDateTime, PrimitiveDateTime};
+
+    use super::*;
+    use crate::{
+        actors::messages::database::{ReserveFlags, ReserveStats},
+        client::{fake::InMemoryClient, UserReserve},
+        flashloan::FlashloanSource,
+        router::encode_uniswap_v3_path,
+    };
+
+    const USER: Address = address!("0x1111111111111111111111111111111111111111");
+    const WETH: Address = address!("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+    const USDC: Address = address!("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+    const VAULT: Address = address!("0xbA1333333333a1BA1108E8412f11850A5C319bA9");
 
-            Ok(())
+    fn reserve(address: Address) -> Reserve {
+        let now = OffsetDateTime::now_utc();
+        Reserve {
+            reserve: address,
+            protocol_details_id: 1,
+            liquidation_threshold: 80.0,
+            liquidation_bonus: 5.0,
+            flashloan_enabled: true,
+            oracle_addr: Address::ZERO,
+            aggregator_addr: None,
+            oracle_heartbeat_secs: None,
+            oracle_deviation_bps: None,
+            symbol: String::new(),
+            decimals: 18,
+            flags: ReserveFlags {
+                is_active: true,
+                is_frozen: false,
+                is_paused: false,
+                is_siloed_borrowing: false,
+                borrowing_enabled: true,
+                usage_as_collateral_enabled: true,
+                borrowable_in_isolation: false,
+                debt_ceiling: 0.0,
+            },
+            stats: ReserveStats {
+                liquidity_index: 1.0,
+                liquidity_rate: 0.0,
+                variable_borrow_rate: 0.0,
+                variable_borrow_index: 1.0,
+                price_usd: 1.0,
+                price_stale: false,
+                updated_at: PrimitiveDateTime::new(now.date(), now.time()),
+            },
+        }
+    }
+
+    /// WETH supplied as collateral, USDC borrowed.
+    fn client_with_position(health_factor: f64, collateral_enabled: bool) -> InMemoryClient {
+        let client = InMemoryClient::default();
+        client.set_health_factor(USER, health_factor);
+        client.set_user_reserves(
+            USER,
+            vec![
+                UserReserve {
+                    underlying_asset: WETH,
+                    scaled_a_token_balance: U256::from(10u64.pow(18)),
+                    scaled_variable_debt: U256::ZERO,
+                    usage_as_collateral_enabled: collateral_enabled,
+                },
+                UserReserve {
+                    underlying_asset: USDC,
+                    scaled_a_token_balance: U256::ZERO,
+                    scaled_variable_debt: U256::from(2_000_000_000u64),
+                    usage_as_collateral_enabled: false,
+                },
+            ],
+        );
+        client
+    }
+
+    #[tokio::test]
+    async fn healthy_user_is_left_alone() {
+        let client = client_with_position(1.2, true);
+        let decision = liquidation_decision(&client, USER, &HashMap::new(), &[])
+            .await
+            .unwrap();
+
+        assert_eq!(decision, Decision::Healthy);
+    }
+
+    #[tokio::test]
+    async fn user_without_debt_is_left_alone() {
+        let client = InMemoryClient::default();
+        let decision = liquidation_decision(&client, USER, &HashMap::new(), &[])
+            .await
+            .unwrap();
+
+        assert_eq!(decision, Decision::Healthy);
+    }
+
+    #[tokio::test]
+    async fn undercollateralized_user_is_liquidated_up_to_the_close_factor() {
+        let client = client_with_position(0.98, true);
+        // the variable borrow index grew the debt by 10%
+        let indices = HashMap::from([(USDC.to_string(), (1.0, 1.1))]);
+        let decision =
+            liquidation_decision(&client, USER, &indices, &[reserve(WETH), reserve(USDC)])
+                .await
+                .unwrap();
+
+        assert_eq!(
+            decision,
+            Decision::Liquidate {
+                collateral_asset: WETH,
+                debt_asset: USDC,
+                debt_to_cover: U256::from(1_100_000_000u64),
+                collateral_balance: U256::from(10u64.pow(18)),
+                health_factor: 0.98,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn paused_reserve_is_skipped() {
+        let client = client_with_position(0.98, true);
+        let mut weth = reserve(WETH);
+        weth.flags.is_paused = true;
+
+        let decision = liquidation_decision(&client, USER, &HashMap::new(), &[weth, reserve(USDC)])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            decision,
+            Decision::Skip {
+                collateral_asset: WETH,
+                debt_asset: USDC,
+                reason: SkipReason::CollateralReservePaused,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn reserve_with_a_stale_price_is_skipped() {
+        let client = client_with_position(0.98, true);
+        let mut usdc = reserve(USDC);
+        usdc.stats.price_stale = true;
+
+        let decision = liquidation_decision(&client, USER, &HashMap::new(), &[reserve(WETH), usdc])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            decision,
+            Decision::Skip {
+                collateral_asset: WETH,
+                debt_asset: USDC,
+                reason: SkipReason::StalePrice,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn collateral_disabled_by_the_user_cant_be_seized() {
+        let client = client_with_position(0.98, false);
+        let decision = liquidation_decision(&client, USER, &HashMap::new(), &[]).await;
+
+        assert!(decision.is_err());
+    }
+
+    #[tokio::test]
+    async fn liquidations_reach_the_client() {
+        let client = client_with_position(0.98, true);
+        let Decision::Liquidate {
+            collateral_asset,
+            debt_asset,
+            debt_to_cover,
+            ..
+        } = liquidation_decision(&client, USER, &HashMap::new(), &[])
+            .await
+            .unwrap()
+        else {
+            panic!("expected a liquidation");
+        };
+
+        let liquidation = Liquidation {
+            debt_asset,
+            collateral_asset,
+            user: USER,
+            debt_to_cover,
+            path: encode_uniswap_v3_path(&[WETH, USDC], &[500]),
+            min_amount_out: debt_to_cover,
+            flashloan: Some(Flashloan {
+                source: FlashloanSource::Balancer,
+                lender: VAULT,
+                fee: U256::ZERO,
+            }),
+        };
+        client.liquidate(liquidation.clone()).await.unwrap();
+        assert_eq!(client.liquidations(), vec![liquidation]);
+
+        *client.revert_liquidations.lock().unwrap() = true;
+        assert!(client
+            .liquidate(Liquidation {
+                path: encode_uniswap_v3_path(&[WETH, USDC], &[3000]),
+                ..client.liquidations()[0].clone()
+            })
+            .await
+            .is_err());
+        assert_eq!(client.liquidations().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn attempts_are_told_apart_by_their_outcome() {
+        let client = client_with_position(0.98, true);
+        let liquidation = Liquidation {
+            debt_asset: USDC,
+            collateral_asset: WETH,
+            user: USER,
+            debt_to_cover: U256::from(1_000u64),
+            path: encode_uniswap_v3_path(&[WETH, USDC], &[500]),
+            min_amount_out: U256::from(1_000u64),
+            flashloan: None,
+        };
+        let mined = client.liquidate(liquidation.clone()).await;
+        let failed: eyre::Result<_> = Ok(LiquidationReceipt {
+            success: false,
+            ..mined.as_ref().unwrap().clone()
+        });
+        *client.revert_liquidations.lock().unwrap() = true;
+        let reverted = client.liquidate(liquidation).await;
+
+        assert_eq!(
+            attempt_status(true, &reverted, true),
+            AttemptStatus::Simulated
+        );
+        assert_eq!(
+            attempt_status(false, &mined, false),
+            AttemptStatus::MinedSuccess
+        );
+        assert_eq!(
+            attempt_status(false, &failed, true),
+            AttemptStatus::MinedFailed
+        );
+        assert_eq!(
+            attempt_status(false, &reverted, true),
+            AttemptStatus::Reverted
+        );
+        assert_eq!(
+            attempt_status(false, &failed, false),
+            AttemptStatus::LostRace
+        );
+        assert_eq!(
+            attempt_status(false, &reverted, false),
+            AttemptStatus::LostRace
+        );
+    }
+
+    /// 1000 USDC covered against WETH at $2000, seizing 0.525 WETH with the 5% bonus.
+    fn weth_usdc() -> (Reserve, Reserve, U256, U256) {
+        let mut weth = reserve(WETH);
+        weth.stats.price_usd = 2000.0;
+        let mut usdc = reserve(USDC);
+        usdc.decimals = 6;
+        (
+            weth,
+            usdc,
+            U256::from(1_000_000_000u64),
+            U256::from(525_000_000_000_000_000u64),
+        )
+    }
+
+    fn aave_flashloan(fee: u64) -> Flashloan {
+        Flashloan {
+            source: FlashloanSource::Aave,
+            lender: Address::ZERO,
+            fee: U256::from(fee),
+        }
+    }
+
+    fn route(amount_out: u64) -> Route {
+        Route {
+            dex: "uniswap_v3",
+            tokens: vec![WETH, USDC],
+            path: encode_uniswap_v3_path(&[WETH, USDC], &[500]),
+            amount_out: U256::from(amount_out),
+        }
+    }
+
+    #[test]
+    fn swap_is_valued_off_the_quote_net_of_the_flashloan_fee() {
+        let (weth, usdc, debt_to_cover, seized) = weth_usdc();
+        // the swap loses $10 of the $1050 seized, aave's premium is 0.5 USDC
+        let candidates = strategy_candidates(
+            &weth,
+            &usdc,
+            debt_to_cover,
+            seized,
+            Some(&aave_flashloan(500_000)),
+            Some(&route(1_040_000_000)),
+            U256::ZERO,
+            50,
+            0.0,
+        )
+        .unwrap();
+
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].strategy, Strategy::SwapAndRepay);
+        assert_eq!(candidates[0].min_amount_out, U256::from(1_034_800_000u64));
+        assert!((candidates[0].expected_profit_usd - 39.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn holding_the_atoken_skips_the_swap_loss() {
+        let (weth, usdc, debt_to_cover, seized) = weth_usdc();
+        let own_balance = U256::from(2_000_000_000u64);
+
+        let candidates = strategy_candidates(
+            &weth,
+            &usdc,
+            debt_to_cover,
+            seized,
+            Some(&aave_flashloan(500_000)),
+            Some(&route(1_040_000_000)),
+            own_balance,
+            50,
+            0.0,
+        )
+        .unwrap();
+        let best = most_profitable(candidates).unwrap();
+        assert_eq!(best.strategy, Strategy::ReceiveAToken);
+        assert_eq!(best.flashloan, None);
+        assert!((best.expected_profit_usd - 50.0).abs() < 1e-9);
+
+        // $45 is out of the swap's reach, not of the aToken's
+        let candidates = strategy_candidates(
+            &weth,
+            &usdc,
+            debt_to_cover,
+            seized,
+            Some(&aave_flashloan(500_000)),
+            Some(&route(1_040_000_000)),
+            own_balance,
+            50,
+            45.0,
+        )
+        .unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].strategy, Strategy::ReceiveAToken);
+        // $1045 worth of WETH
+        let min_atokens = norm(candidates[0].min_amount_out, Some(1e-18)).unwrap();
+        assert!((min_atokens - 0.5225).abs() < 1e-12);
+    }
+
+    #[test]
+    fn without_a_lender_only_our_own_balance_covers_the_debt() {
+        let (weth, usdc, debt_to_cover, seized) = weth_usdc();
+
+        let candidates = strategy_candidates(
+            &weth,
+            &usdc,
+            debt_to_cover,
+            seized,
+            None,
+            Some(&route(1_040_000_000)),
+            U256::from(2_000_000_000u64),
+            50,
+            0.0,
+        )
+        .unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].strategy, Strategy::ReceiveAToken);
+
+        let plan = |candidate, flashloan| LiquidationPlan {
+            seized,
+            route: None,
+            flashloan,
+            candidate,
         };
+        assert_eq!(
+            plan(None, None).candidate(),
+            Err(SkipReason::FlashloanDisabled)
+        );
+        assert_eq!(
+            plan(None, Some(aave_flashloan(500_000))).candidate(),
+            Err(SkipReason::Unprofitable)
+        );
+        assert!(plan(candidates.into_iter().next(), None)
+            .candidate()
+            .is_ok());
+    }
+
+    #[test]
+    fn same_asset_repays_with_the_seized_collateral() {
+        let mut weth = reserve(WETH);
+        weth.stats.price_usd = 2000.0;
+        let debt_to_cover = U256::from(10u64.pow(18));
+        let seized = U256::from(1_050_000_000_000_000_000u64);
+
+        let candidates = strategy_candidates(
+            &weth,
+            &weth,
+            debt_to_cover,
+            seized,
+            Some(&aave_flashloan(0)),
+            None,
+            U256::ZERO,
+            50,
+            0.0,
+        )
+        .unwrap();
+
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].strategy, Strategy::SameAsset);
+        assert!(candidates[0].path.is_empty());
+        assert_eq!(candidates[0].min_amount_out, debt_to_cover);
+        assert!((candidates[0].expected_profit_usd - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn seized_collateral_includes_the_bonus() {
+        let mut weth = reserve(WETH);
+        weth.stats.price_usd = 2000.0;
+        let mut usdc = reserve(USDC);
+        usdc.decimals = 6;
+
+        // 1000 USDC worth of WETH, plus 5%
+        let seized =
+            seized_collateral(&weth, &usdc, U256::from(1_000_000_000u64), U256::MAX, 0.0).unwrap();
+        assert_eq!(seized, U256::from(525_000_000_000_000_000u64));
+
+        weth.stats.price_usd = 0.0;
+        assert!(
+            seized_collateral(&weth, &usdc, U256::from(1_000_000_000u64), U256::MAX, 0.0).is_err()
+        );
+    }
+
+    #[test]
+    fn seized_collateral_is_capped_at_the_users_balance() {
+        let mut weth = reserve(WETH);
+        weth.stats.price_usd = 2000.0;
+        let mut usdc = reserve(USDC);
+        usdc.decimals = 6;
+        let balance = U256::from(300_000_000_000_000_000u64);
+
+        let seized =
+            seized_collateral(&weth, &usdc, U256::from(1_000_000_000u64), balance, 0.0).unwrap();
+        assert_eq!(seized, balance);
+    }
+
+    #[test]
+    fn protocol_keeps_its_share_of_the_bonus() {
+        let mut weth = reserve(WETH);
+        weth.stats.price_usd = 2000.0;
+        let mut usdc = reserve(USDC);
+        usdc.decimals = 6;
+
+        // 10% of the 0.025 WETH bonus
+        let seized =
+            seized_collateral(&weth, &usdc, U256::from(1_000_000_000u64), U256::MAX, 10.0).unwrap();
+        assert_eq!(seized, U256::from(522_500_000_000_000_000u64));
+
+        // off the bonus of what's left to seize, i.e 0.0105 of 1.05 WETH
+        let balance = U256::from(1_050_000_000_000_000_000u64);
+        let seized =
+            seized_collateral(&weth, &usdc, U256::from(10_000_000_000u64), balance, 20.0).unwrap();
+        assert_eq!(seized, U256::from(1_040_000_000_000_000_000u64));
+    }
+
+    #[test]
+    fn min_amount_out_tolerates_slippage_down_to_the_repayment() {
+        let repayment = U256::from(1_000_500u64);
+
+        // 1% off 1_100_000 leaves room above the repayment
+        assert_eq!(
+            min_amount_out(U256::from(1_100_000u64), 100, repayment, U256::ZERO),
+            Some(U256::from(1_089_000u64))
+        );
+        // 10% off would eat into the flashloan, the floor wins
+        assert_eq!(
+            min_amount_out(
+                U256::from(1_100_000u64),
+                1_000,
+                repayment,
+                U256::from(10_000)
+            ),
+            Some(U256::from(1_010_500u64))
+        );
+        // not even the quote makes the minimum profit
+        assert_eq!(
+            min_amount_out(U256::from(1_005_000u64), 50, repayment, U256::from(10_000)),
+            None
+        );
+        // the whole quote may slip
+        assert_eq!(
+            min_amount_out(U256::from(1_100_000u64), 10_000, repayment, U256::ZERO),
+            Some(repayment)
+        );
+    }
+
+    #[test]
+    fn min_profit_is_priced_in_the_debt_asset() {
+        let mut weth = reserve(WETH);
+        weth.stats.price_usd = 2000.0;
+
+        assert_eq!(
+            usd_to_amount(&weth, 10.0).unwrap(),
+            U256::from(5_000_000_000_000_000u64)
+        );
+        assert_eq!(usd_to_amount(&weth, 0.0).unwrap(), U256::ZERO);
+    }
+
+    #[test]
+    fn paused_requests_are_queued_once_per_user() {
+        let request = |user| LiquidationRequest {
+            user_address: user,
+            network: "arbitrum".to_string(),
+            protocol: "aave_v3".to_string(),
+        };
+        let mut queue = VecDeque::new();
+
+        enqueue(&mut queue, request(USER));
+        enqueue(&mut queue, request(WETH));
+        enqueue(&mut queue, request(USER));
 
-        Box::pin(fut)
+        assert_eq!(
+            queue.iter().map(|m| m.user_address).collect::<Vec<_>>(),
+            vec![USER, WETH]
+        );
     }
 }
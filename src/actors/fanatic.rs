@@ -2,34 +2,44 @@ use std::{collections::HashMap, sync::Arc};
 
 use crate::actors::messages::executor::LiquidationRequest;
 use crate::actors::Database;
+use crate::client::LendingProtocolClient;
 use crate::contracts;
-use crate::utils::{health_factor, norm, user_positions};
+use crate::utils::{health_factor, user_positions};
 use actix::prelude::*;
-use alloy::{primitives::Address, providers::Provider};
+use alloy::{
+    primitives::{Address, U256},
+    providers::Provider,
+};
 use sqlx::types::time::OffsetDateTime;
 use tokio::{sync::Mutex, time::Duration};
 use tracing::{error, info, warn};
 
 use super::messages::fanatic::SendExecutorAddr;
+use super::messages::fanatic::{FailedLiquidation, SuccessfulLiquidation};
 use super::Executor;
-use super::{
-    follower::oracle_price,
-    messages::fanatic::{FailedLiquidation, SuccessfulLiquidation},
-};
 use crate::{
     actors::{
         messages::{
             database,
             fanatic::{DoSmthWithLiquidationCall, UpdateReservePrice, UpdateReserveUser},
-            follower::{SendFanaticAddr, StartListeningForEvents, StartListeningForOraclePrices},
+            follower::{
+                Backfill, GetLastBlock, SendFanaticAddr, StartListeningForEvents,
+                StartListeningForOraclePrices,
+            },
+            watchdog::Restarted,
         },
-        Follower,
+        watchdog::catch_panics,
+        Follower, Watchdog,
     },
     configs::FanaticConfig,
-    consts::RAY,
+    error::LiquidatorError,
     run::Shutdown,
 };
 
+// a `LiquidationCall` by someone else within this window after our own request counts as missed
+const MISSED_ATTEMPT_WINDOW_SECS: i64 = 120;
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
 #[derive(Debug, Clone)]
 pub struct Fanatic<P: Provider + Unpin + Clone + 'static> {
     provider: P,
@@ -38,16 +48,20 @@ pub struct Fanatic<P: Provider + Unpin + Clone + 'static> {
     follower_addr: Addr<Follower<P>>,
     executor_addr: Option<Addr<Executor<P>>>,
 
-    pool_contract: contracts::aave_v3::PoolContract::PoolContractInstance<(), P>,
-    datap_contract: contracts::aave_v3::DataProviderContract::DataProviderContractInstance<(), P>,
-    addressp_contract:
-        contracts::aave_v3::AddressProviderContract::AddressProviderContractInstance<(), P>,
+    client: Arc<dyn LendingProtocolClient>,
 
     users: Arc<Mutex<HashMap<Address, database::UserData>>>,
     reserves: Arc<Mutex<HashMap<Address, database::ReserveData>>>,
+    // user => UTC EPOCH timestamp of our last liquidation request
+    attempts: Arc<Mutex<HashMap<Address, i64>>>,
 
+    bot_addr: Address,
     target: String,
     protocol_details_id: i32,
+
+    snapshots: bool,
+    // block of the restored snapshot, logs are backfilled from there once started
+    backfill_from: Option<u64>,
 }
 
 impl<P: Provider + Unpin + Clone + 'static> Actor for Fanatic<P> {
@@ -56,23 +70,37 @@ impl<P: Provider + Unpin + Clone + 'static> Actor for Fanatic<P> {
     fn started(&mut self, ctx: &mut Self::Context) {
         let addr = ctx.address();
         let follower_addr = self.follower_addr.clone();
+        let backfill_from = self.backfill_from.take();
 
+        // re-run on restarts, `follower` replaces its subscriptions
         let fut = async move {
-            follower_addr.send(SendFanaticAddr(addr)).await.unwrap();
-            follower_addr
-                .send(StartListeningForOraclePrices)
-                .await
-                .unwrap();
-            follower_addr.send(StartListeningForEvents).await.unwrap();
+            follower_addr.send(SendFanaticAddr(addr)).await?;
+            follower_addr.send(StartListeningForOraclePrices).await?;
+            follower_addr.send(StartListeningForEvents).await?;
+
+            // catch up on what was missed while down, i.e since the snapshot or before a restart
+            let from = match backfill_from {
+                Some(from) => Some(from),
+                None => follower_addr.send(GetLastBlock).await?,
+            };
+            if let Some(from) = from {
+                let to = follower_addr.send(Backfill { from }).await??;
+                info!(from, to, "backfilled");
+            }
+            Ok(())
         };
 
-        ctx.spawn(fut.into_actor(self));
+        ctx.spawn(catch_panics(fut).into_actor(self).map(|res, _, ctx| {
+            if let Err(e) = res {
+                error!("Failed to register with the follower: {:?}", e);
+                ctx.stop();
+            }
+        }));
 
         ctx.run_interval(Duration::from_secs(60), |actor, ctx| {
             let users = actor.users.clone();
             let db_addr = actor.db_addr.clone();
-            let datap_contract = actor.datap_contract.clone();
-            let addressp_address = *actor.addressp_contract.address();
+            let client = actor.client.clone();
             let protocol_details_id = actor.protocol_details_id;
             let target = actor.target.clone();
 
@@ -81,18 +109,45 @@ impl<P: Provider + Unpin + Clone + 'static> Actor for Fanatic<P> {
                     &users,
                     &db_addr,
                     protocol_details_id,
-                    &datap_contract,
-                    &addressp_address,
+                    client.as_ref(),
                     target,
                 )
                 .await
                 {
                     error!("Failed to update recent users: {}", e);
                 }
+                Ok(())
             };
 
-            ctx.spawn(fut.into_actor(actor));
+            ctx.spawn(catch_panics(fut).into_actor(actor).map(|res, _, ctx| {
+                if let Err(e) = res {
+                    error!("Recent users update failed: {:?}", e);
+                    ctx.stop();
+                }
+            }));
         });
+
+        if self.snapshots {
+            ctx.run_interval(SNAPSHOT_INTERVAL, |actor, ctx| {
+                let snapshot = actor.save_snapshot();
+                ctx.spawn(
+                    async move {
+                        if let Err(e) = snapshot.await {
+                            error!("Failed to save snapshot: {}", e);
+                        }
+                    }
+                    .into_actor(actor),
+                );
+            });
+        }
+    }
+}
+
+impl<P: Provider + Unpin + Clone + 'static> Supervised for Fanatic<P> {
+    // in-memory users and reserves survive, `started` registers with the follower again
+    fn restarting(&mut self, _: &mut Self::Context) {
+        warn!("[restarting] Fanatic");
+        Watchdog::from_registry().do_send(Restarted { actor: "fanatic" });
     }
 }
 
@@ -104,14 +159,13 @@ impl<P: Provider + Unpin + Clone + 'static> Handler<SendExecutorAddr<P>> for Fan
     }
 }
 
-async fn update_recent_users<P: Provider + Clone>(
+async fn update_recent_users(
     users: &Arc<Mutex<HashMap<Address, database::UserData>>>,
     db_addr: &Addr<Database>,
     protocol_details_id: i32,
-    datap_contract: &contracts::aave_v3::DataProviderContract::DataProviderContractInstance<(), P>,
-    addressp_address: &Address,
+    client: &dyn LendingProtocolClient,
     target: String,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> eyre::Result<()> {
     let secs = 70;
     let now = OffsetDateTime::now_utc().unix_timestamp();
     let indices = db_addr
@@ -122,27 +176,27 @@ async fn update_recent_users<P: Provider + Clone>(
     let total_users = users_guard.len();
     info!("Starting update for {} total users", total_users);
 
-    let mut recent_updates = 0;
+    let mut writes = Vec::new();
     for (user, data) in users_guard.iter() {
         if now - data.last_update < secs {
-            let user_positions =
-                user_positions(datap_contract, addressp_address, user, &indices).await?;
-            recent_updates += 1;
+            let user_positions = user_positions(client, user, &indices).await?;
 
             info!(
                 "Updating user {} with HF {} (timestamp: {})",
                 user, data.health_factor, data.last_update
             );
-            db_addr
-                .send(database::UpsertUserData {
-                    address: user.to_string(),
-                    health_factor: data.health_factor,
-                    protocol_details_id,
-                    positions: user_positions,
-                })
-                .await??;
+            writes.push(database::UserWrite {
+                address: *user,
+                health_factor: data.health_factor,
+                protocol_details_id,
+                positions: user_positions,
+            });
         }
     }
+    drop(users_guard);
+
+    let recent_updates = writes.len();
+    db_addr.send(database::UpsertUsersData(writes)).await?;
 
     info!(
         "Updated {}/{} users with recent data (<{}s old)",
@@ -151,38 +205,96 @@ async fn update_recent_users<P: Provider + Clone>(
     Ok(())
 }
 
+/// Appends the health factors to the users' history, losing them is no reason to stop evaluating.
+async fn record_health_factors(
+    db_addr: &Addr<Database>,
+    protocol_details_id: i32,
+    block_number: u64,
+    health_factors: Vec<(Address, f64)>,
+) {
+    if health_factors.is_empty() {
+        return;
+    }
+
+    let msg = database::InsertHealthFactors {
+        protocol_details_id,
+        block_number,
+        health_factors,
+    };
+    match db_addr.send(msg).await {
+        Ok(Ok(())) => (),
+        Ok(Err(e)) => warn!(block_number, error = %e, "Failed to record health factors"),
+        Err(e) => warn!(block_number, error = ?e, "Failed to send health factors"),
+    }
+}
+
+async fn request_liquidation<P: Provider + Unpin + Clone + 'static>(
+    executor_addr: Option<&Addr<Executor<P>>>,
+    attempts: &Mutex<HashMap<Address, i64>>,
+    target: &str,
+    user: Address,
+    hf: f64,
+) -> Result<(), LiquidatorError> {
+    // the executor registers itself once started
+    let Some(executor_addr) = executor_addr else {
+        warn!(
+            ?user,
+            "no executor registered yet, dropping liquidation request"
+        );
+        return Ok(());
+    };
+
+    let (network, protocol) = database::split_target(target)?;
+    let payload = LiquidationRequest {
+        user_address: user,
+        network,
+        protocol,
+    };
+
+    attempts
+        .lock()
+        .await
+        .insert(user, OffsetDateTime::now_utc().unix_timestamp());
+    match executor_addr.send(payload).await? {
+        Ok(()) => info!("sent liquidation request for user {} with HF {}", user, hf),
+        Err(e) => warn!("liquidation request for user {} failed: {}", user, e),
+    }
+    Ok(())
+}
+
 impl<P: Provider + Unpin + Clone + 'static> Fanatic<P> {
     pub async fn new(config: FanaticConfig<P>) -> eyre::Result<Fanatic<P>> {
         let protocol_details_id = config
             .db_addr
             .send(database::GetProtocolDetailsId(config.target.clone()))
             .await??;
-        let contracts_addr = config
-            .db_addr
-            .send(database::GetProtocolContracts(config.target.clone()))
-            .await??;
-
-        let (pool_contract, datap_contract, addressp_contract) = match (
-            contracts_addr.get("Pool"),
-            contracts_addr.get("UiPoolDataProviderV3"),
-            contracts_addr.get("PoolAddressesProvider"),
-        ) {
-            (Some(pool_addr), Some(datap_addr), Some(provider_addr)) => (
-                contracts::aave_v3::PoolContract::new(*pool_addr, config.provider.clone()),
-                contracts::aave_v3::DataProviderContract::new(*datap_addr, config.provider.clone()),
-                contracts::aave_v3::AddressProviderContract::new(
-                    *provider_addr,
-                    config.provider.clone(),
-                ),
-            ),
-            _ => return Err(eyre::eyre!("Missing required contract addresses")),
-        };
-
         let (users, prices) = config
             .db_addr
             .send(database::GetReservesUsers(config.target.clone()))
             .await??;
 
+        // postgres' users are only a fallback, they're missing whoever wasn't written yet
+        let snapshot = if config.snapshots {
+            config
+                .db_addr
+                .send(database::GetFanaticSnapshot(protocol_details_id))
+                .await??
+        } else {
+            None
+        };
+        let backfill_from = snapshot.as_ref().map(|snapshot| snapshot.block_number);
+        let (users, prices) = match snapshot {
+            Some(snapshot) => {
+                info!(
+                    block_number = snapshot.block_number,
+                    users = snapshot.users.len(),
+                    "restoring snapshot"
+                );
+                restore(snapshot, prices)
+            }
+            None => (users, prices),
+        };
+
         info!("Reserves Users: {:?}", users);
         info!("Reserves Prices: {:#?}", prices);
 
@@ -191,16 +303,60 @@ impl<P: Provider + Unpin + Clone + 'static> Fanatic<P> {
             db_addr: config.db_addr,
             follower_addr: config.follower_addr,
             executor_addr: None,
-            pool_contract,
-            datap_contract,
-            addressp_contract,
+            client: config.client,
             users: Arc::new(Mutex::new(users)),
             reserves: Arc::new(Mutex::new(prices)),
+            attempts: Arc::new(Mutex::new(HashMap::new())),
+            bot_addr: config.bot_addr,
             target: config.target.clone(),
             protocol_details_id,
+            snapshots: config.snapshots,
+            backfill_from,
         })
     }
 
+    /// Saves the users and their reserves as of the follower's last block, skipped while it
+    /// doesn't have one (backfilling, or nothing received yet) as the previous snapshot is still
+    /// the latest consistent one.
+    fn save_snapshot(&self) -> impl std::future::Future<Output = eyre::Result<()>> {
+        let users = self.users.clone();
+        let reserves = self.reserves.clone();
+        let follower_addr = self.follower_addr.clone();
+        let db_addr = self.db_addr.clone();
+        let protocol_details_id = self.protocol_details_id;
+
+        async move {
+            // before reading the state, which then reflects at least that block
+            let Some(block_number) = follower_addr.send(GetLastBlock).await? else {
+                info!("no processed block to snapshot at yet, skipping");
+                return Ok(());
+            };
+
+            let users = users.lock().await.clone();
+            let reserves_users = reserves
+                .lock()
+                .await
+                .iter()
+                .map(|(reserve, data)| (*reserve, data.users.clone()))
+                .collect();
+            let snapshot = database::FanaticSnapshot {
+                block_number,
+                users,
+                reserves_users,
+            };
+            let saved = snapshot.users.len();
+
+            db_addr
+                .send(database::SaveFanaticSnapshot {
+                    protocol_details_id,
+                    snapshot,
+                })
+                .await??;
+            info!(block_number, users = saved, "saved snapshot");
+            Ok(())
+        }
+    }
+
     pub async fn init(self) -> eyre::Result<Self> {
         self._init_contracts().await?;
         self._init_reserves().await?;
@@ -221,63 +377,47 @@ impl<P: Provider + Unpin + Clone + 'static> Fanatic<P> {
 
         if reserves.is_empty() {
             info!("No reserves found");
-            let reserves = self
-                .datap_contract
-                .getReservesData(*self.addressp_contract.address())
-                .call()
-                .await?;
+            let reserves = self.client.reserves_data().await?;
 
             info!("reserves data: {:#?}", reserves);
             let mut init_reserves = Vec::new();
-            for reserve in reserves._0.iter() {
-                let aggregator_addr = self.aggregator_addr(&reserve.priceOracle).await.ok();
+            for reserve in reserves.iter() {
+                let aggregator_addr = self.aggregator_addr(&reserve.price_oracle).await.ok();
                 info!("aggregator_addr: {:#?}", aggregator_addr);
 
-                let price = oracle_price(&self.provider, reserve.priceOracle).await;
+                let price = self.client.price(reserve.price_oracle).await?;
                 info!("price: {:?}", price);
 
                 init_reserves.push(database::UpsertReserve {
-                    symbol: reserve.symbol.to_string(),
-                    name: reserve.name.to_string(),
-                    decimals: reserve.decimals.to::<u32>() as i32,
-                    address: reserve.underlyingAsset.to_string(),
+                    symbol: reserve.symbol.clone(),
+                    name: reserve.name.clone(),
+                    decimals: reserve.decimals,
+                    address: reserve.underlying_asset.to_string(),
                     protocol_details_id: self.protocol_details_id,
-                    liquidation_threshold: norm(
-                        reserve.reserveLiquidationThreshold,
-                        Some(10.0_f64.powf(-2.0)),
-                    )?,
-                    // https://aave.com/docs/developers/smart-contracts/pool-configurator#only-risk-or-pool-admins-methods-configurereserveascollateral
-                    // All the values are expressed in bps. A value of 10000 results in 100.00%.
-                    // The liquidationBonus is always above 100%.
-                    // A value of 105% means the liquidator will receive a 5% bonus.
-                    liquidation_bonus: if (norm(reserve.reserveLiquidationBonus, None)? - 10_000.0)
-                        / 100.0
-                        > 0.0
-                    {
-                        (norm(reserve.reserveLiquidationBonus, None)? - 10_000.0) / 100.0
-                    } else {
-                        0.0
-                    },
-                    flashloan_enabled: reserve.flashLoanEnabled,
+                    liquidation_threshold: reserve.liquidation_threshold,
+                    liquidation_bonus: reserve.liquidation_bonus,
+                    flashloan_enabled: reserve.flashloan_enabled,
                     // You can choose to store the aggregator address or the original oracle address.
                     // Here we store the original oracle address (as a string) for reference.
-                    oracle_addr: reserve.priceOracle.to_string(),
+                    oracle_addr: reserve.price_oracle.to_string(),
                     aggregator_addr: aggregator_addr.map(|v| v.to_string()),
+                    flags: reserve.flags.clone(),
                     price_usd: price,
                     stats: database::UpsertReserveStats {
-                        reserve: reserve.underlyingAsset.to_string(),
-                        liquidity_rate: norm(reserve.liquidityRate, Some(100.0 / RAY))?,
-                        variable_borrow_rate: norm(reserve.variableBorrowRate, Some(100.0 / RAY))?,
-                        liquidity_index: norm(reserve.liquidityIndex, Some(1.0 / RAY))?,
-                        variable_borrow_index: norm(reserve.variableBorrowIndex, Some(1.0 / RAY))?,
+                        reserve: reserve.underlying_asset.to_string(),
+                        liquidity_rate: reserve.liquidity_rate,
+                        variable_borrow_rate: reserve.variable_borrow_rate,
+                        liquidity_index: reserve.liquidity_index,
+                        variable_borrow_index: reserve.variable_borrow_index,
+                        block_number: None,
                     },
                 });
             }
 
-            let (network, _) = self.target.split_once('-').unwrap();
+            let (network, _) = database::split_target(&self.target)?;
             self.db_addr
                 .send(database::UpsertReserves {
-                    network_id: network.to_string(),
+                    network_id: network,
                     reserves: init_reserves,
                 })
                 .await??;
@@ -335,21 +475,45 @@ impl<P: Provider + Unpin + Clone + 'static> Fanatic<P> {
     }
 }
 
+/// Users and reserves as of `snapshot`, the reserves themselves (and their prices) are as loaded
+/// from postgres.
+pub fn restore(
+    snapshot: database::FanaticSnapshot,
+    mut reserves: HashMap<Address, database::ReserveData>,
+) -> (
+    HashMap<Address, database::UserData>,
+    HashMap<Address, database::ReserveData>,
+) {
+    for (reserve, data) in reserves.iter_mut() {
+        data.users = snapshot
+            .reserves_users
+            .get(reserve)
+            .cloned()
+            .unwrap_or_default();
+    }
+
+    (snapshot.users, reserves)
+}
+
 impl<P: Provider + Unpin + Clone + 'static> Handler<UpdateReservePrice> for Fanatic<P> {
-    type Result = ResponseFuture<()>;
+    type Result = ResponseActFuture<Self, ()>;
 
     fn handle(&mut self, msg: UpdateReservePrice, _ctx: &mut Self::Context) -> Self::Result {
         // TODO: we have to recalculate the new users' health factors
         // we'll initially do a brute force calculation, i.e calculate the new health factors for all users
-        let executor_addr = self.executor_addr.clone().unwrap();
+        let executor_addr = self.executor_addr.clone();
         let reserve_addr = msg.reserve;
         let new_price = msg.new_price;
+        let block_number = msg.block_number;
 
         let target = self.target.clone();
-        let pool_contract = self.pool_contract.clone();
+        let client = self.client.clone();
+        let db_addr = self.db_addr.clone();
+        let protocol_details_id = self.protocol_details_id;
 
         let reserves = self.reserves.clone();
         let users = self.users.clone();
+        let attempts = self.attempts.clone();
 
         let fut = async move {
             // update the reserve price
@@ -378,112 +542,136 @@ impl<P: Provider + Unpin + Clone + 'static> Handler<UpdateReservePrice> for Fana
                     .users
                     .iter()
                     .filter(|user| {
-                        users_lock.get(*user).is_some()
-                            && users_lock.get(*user).unwrap().health_factor < 1.05
+                        users_lock
+                            .get(*user)
+                            .is_some_and(|data| data.health_factor < 1.05)
                     })
                     .collect::<Vec<&Address>>();
 
                 for user in high_prio {
-                    let hf = match health_factor(&pool_contract, *user).await {
-                        Some(hf) => hf,
-                        None => return,
+                    let hf = match health_factor(client.as_ref(), *user).await {
+                        Ok(Some(hf)) => hf,
+                        Ok(None) => continue,
+                        Err(e) => {
+                            error!(?user, "failed to fetch health factor: {}", e);
+                            continue;
+                        }
                     };
 
                     if hf < 1.0 {
-                        let (network, protocol) = target.split_once('-').unwrap();
-                        let (network, protocol) = (network.to_string(), protocol.to_string());
-
-                        let payload = LiquidationRequest {
-                            user_address: *user,
-                            network: network,
-                            protocol: protocol,
-                        };
-
-                        let result = executor_addr.send(payload).await;
-                        match result {
-                            Ok(_) => info!("sent liquidation request for user {}", user),
-                            Err(e) => error!(
+                        if let Err(e) = request_liquidation(
+                            executor_addr.as_ref(),
+                            &attempts,
+                            &target,
+                            *user,
+                            hf,
+                        )
+                        .await
+                        {
+                            error!(
                                 "failed to send liquidation request for user {}: {}",
                                 user, e
-                            ),
+                            );
                         }
 
+                        // positions didn't move, only their value
                         let mut users = users.lock().await;
-                        users.insert(
-                            *user,
-                            database::UserData {
-                                health_factor: hf,
-                                last_update: OffsetDateTime::now_utc().unix_timestamp(),
-                            },
-                        );
+                        let user_data = users.entry(*user).or_default();
+                        user_data.health_factor = hf;
+                        user_data.last_update = OffsetDateTime::now_utc().unix_timestamp();
                     }
                 }
 
+                let mut evaluated = Vec::new();
                 for user in &reserve_data.users {
-                    let hf = match health_factor(&pool_contract, *user).await {
-                        Some(hf) => hf,
-                        None => return,
+                    let hf = match health_factor(client.as_ref(), *user).await {
+                        Ok(Some(hf)) => hf,
+                        Ok(None) => continue,
+                        Err(e) => {
+                            error!(?user, "failed to fetch health factor: {}", e);
+                            continue;
+                        }
                     };
 
                     if hf < 1.0 {
-                        let (network, protocol) = target.split_once('-').unwrap();
-                        let (network, protocol) = (network.to_string(), protocol.to_string());
-
-                        let payload = LiquidationRequest {
-                            user_address: *user,
-                            network: network,
-                            protocol: protocol,
-                        };
-
-                        let result = executor_addr.send(payload).await;
-                        match result {
-                            Ok(_) => info!("sent liquidation request for user {}", user),
-                            Err(e) => error!(
+                        if let Err(e) = request_liquidation(
+                            executor_addr.as_ref(),
+                            &attempts,
+                            &target,
+                            *user,
+                            hf,
+                        )
+                        .await
+                        {
+                            error!(
                                 "failed to send liquidation request for user {}: {}",
                                 user, e
-                            ),
+                            );
                         }
                     }
 
                     if hf < 100.0 {
+                        // positions didn't move, only their value
                         let mut users = users.lock().await;
-                        users.insert(
-                            *user,
-                            database::UserData {
-                                health_factor: hf,
-                                last_update: OffsetDateTime::now_utc().unix_timestamp(),
-                            },
-                        );
+                        let user_data = users.entry(*user).or_default();
+                        user_data.health_factor = hf;
+                        user_data.last_update = OffsetDateTime::now_utc().unix_timestamp();
+                        evaluated.push((*user, hf));
                     }
                 }
+
+                if let Some(block_number) = block_number {
+                    record_health_factors(&db_addr, protocol_details_id, block_number, evaluated)
+                        .await;
+                }
             } else {
                 info!("No users found in reserve");
             }
         };
 
-        Box::pin(fut)
+        Box::pin(
+            catch_panics(async move {
+                fut.await;
+                Ok(())
+            })
+            .into_actor(self)
+            .map(|res, _, ctx| {
+                if let Err(e) = res {
+                    error!("Reserve price update failed: {:?}", e);
+                    ctx.stop();
+                }
+            }),
+        )
     }
 }
 
 impl<P: Provider + Unpin + Clone + 'static> Handler<UpdateReserveUser> for Fanatic<P> {
-    type Result = ResponseFuture<()>;
+    type Result = ResponseActFuture<Self, ()>;
 
     fn handle(&mut self, msg: UpdateReserveUser, _ctx: &mut Self::Context) -> Self::Result {
         let reserve_addr = msg.reserve;
         let user = msg.user_addr;
-        let executor_addr = self.executor_addr.clone().unwrap();
+        let block_number = msg.block_number;
+        let executor_addr = self.executor_addr.clone();
 
         let target = self.target.clone();
+        let db_addr = self.db_addr.clone();
+        let protocol_details_id = self.protocol_details_id;
 
-        let pool_contract = self.pool_contract.clone();
+        let client = self.client.clone();
 
         let reserves = self.reserves.clone();
         let users = self.users.clone();
+        let attempts = self.attempts.clone();
 
         let fut = async move {
-            let hf = match health_factor(&pool_contract, user).await {
-                Some(hf) => hf,
-                None => return,
+            let hf = match health_factor(client.as_ref(), user).await {
+                Ok(Some(hf)) => hf,
+                Ok(None) => return,
+                Err(e) => {
+                    error!(?user, "failed to fetch health factor: {}", e);
+                    return;
+                }
             };
 
             {
@@ -499,44 +687,85 @@ impl<P: Provider + Unpin + Clone + 'static> Handler<UpdateReserveUser> for Fanat
             }
 
             if hf < 1.0 {
-                let (network, protocol) = target.split_once('-').unwrap();
-                let (network, protocol) = (network.to_string(), protocol.to_string());
-
-                let payload = LiquidationRequest {
-                    user_address: user,
-                    network: network,
-                    protocol: protocol,
-                };
-
-                let result = executor_addr.send(payload).await;
-                match result {
-                    Ok(_) => info!("sent liquidation request for user {} with HF {}", user, hf),
-                    Err(e) => error!(
+                if let Err(e) =
+                    request_liquidation(executor_addr.as_ref(), &attempts, &target, user, hf).await
+                {
+                    error!(
                         "failed to send liquidation request for user {}: {}",
                         user, e
-                    ),
+                    );
                 }
             }
 
             // sanity check
             if hf < 100.0 {
-                let mut reserves = reserves.lock().await;
-                if let Some(reserve) = reserves.get_mut(&reserve_addr) {
-                    reserve.users.insert(user);
+                if let Some(block_number) = block_number {
+                    record_health_factors(
+                        &db_addr,
+                        protocol_details_id,
+                        block_number,
+                        vec![(user, hf)],
+                    )
+                    .await;
                 }
 
-                let mut users = users.lock().await;
-                users.insert(
-                    user,
-                    database::UserData {
-                        health_factor: hf,
-                        last_update: OffsetDateTime::now_utc().unix_timestamp(),
-                    },
-                );
+                // collateral is usually supplied before borrowing, i.e while the health factor is
+                // still out of range, so the user is tracked on every reserve they're in
+                let positions = match client.user_reserves(user).await {
+                    Ok(user_reserves) => Some(
+                        user_reserves
+                            .into_iter()
+                            .filter(|r| {
+                                r.scaled_a_token_balance > U256::ZERO
+                                    || r.scaled_variable_debt > U256::ZERO
+                            })
+                            .collect::<Vec<_>>(),
+                    ),
+                    Err(e) => {
+                        warn!(?user, error = ?e, "unable to fetch user reserves");
+                        None
+                    }
+                };
+
+                let user_reserves = {
+                    let mut users = users.lock().await;
+                    let user_data = users.entry(user).or_default();
+                    user_data.health_factor = hf;
+                    user_data.last_update = OffsetDateTime::now_utc().unix_timestamp();
+                    if let Some(positions) = positions {
+                        user_data.positions = positions;
+                    }
+                    user_data
+                        .positions
+                        .iter()
+                        .map(|p| p.underlying_asset)
+                        .chain([reserve_addr])
+                        .collect::<Vec<_>>()
+                };
+
+                // `users` is released first, `UpdateReservePrice` locks `reserves` then `users`
+                let mut reserves = reserves.lock().await;
+                for reserve_addr in user_reserves {
+                    if let Some(reserve) = reserves.get_mut(&reserve_addr) {
+                        reserve.users.insert(user);
+                    }
+                }
             }
         };
 
-        Box::pin(fut)
+        Box::pin(
+            catch_panics(async move {
+                fut.await;
+                Ok(())
+            })
+            .into_actor(self)
+            .map(|res, _, ctx| {
+                if let Err(e) = res {
+                    error!("Reserve user update failed: {:?}", e);
+                    ctx.stop();
+                }
+            }),
+        )
     }
 }
 
@@ -546,12 +775,39 @@ impl<P: Provider + Unpin + Clone + 'static> Handler<DoSmthWithLiquidationCall> f
     fn handle(&mut self, msg: DoSmthWithLiquidationCall, _: &mut Self::Context) -> Self::Result {
         let protocol_details_id = self.protocol_details_id;
         let db_addr = self.db_addr.clone();
+        let provider = self.provider.clone();
+        let attempts = self.attempts.clone();
+        let bot_addr = self.bot_addr;
 
         let fut = async move {
+            let gas_price = match provider.get_transaction_receipt(msg.tx_hash).await {
+                Ok(Some(receipt)) => Some(receipt.effective_gas_price as f64 / 1e9),
+                Ok(None) => None,
+                Err(e) => {
+                    warn!(tx_hash = ?msg.tx_hash, error = ?e, "unable to fetch liquidation receipt");
+                    None
+                }
+            };
+
+            let ours = msg.call.liquidator == bot_addr;
+            let missed = !ours
+                && attempts.lock().await.get(&msg.call.user).is_some_and(|at| {
+                    OffsetDateTime::now_utc().unix_timestamp() - at < MISSED_ATTEMPT_WINDOW_SECS
+                });
+            if missed {
+                warn!(user = ?msg.call.user, liquidator = ?msg.call.liquidator, "missed liquidation");
+            }
+
             db_addr
                 .send(database::InsertLiquidationCall {
-                    call: msg.0,
+                    call: msg.call,
                     protocol_details_id,
+                    block_number: msg.block_number,
+                    tx_hash: msg.tx_hash,
+                    log_index: msg.log_index,
+                    gas_price,
+                    ours,
+                    missed,
                 })
                 .await??;
 
@@ -566,10 +822,10 @@ impl<P: Provider + Unpin + Clone + 'static> Handler<Shutdown> for Fanatic<P> {
     type Result = ResponseFuture<eyre::Result<()>>;
 
     fn handle(&mut self, _: Shutdown, _: &mut Self::Context) -> Self::Result {
+        let snapshot = self.snapshots.then(|| self.save_snapshot());
         let users = self.users.clone();
         let db_addr = self.db_addr.clone();
-        let datap_contract = self.datap_contract.clone();
-        let addressp_address = *self.addressp_contract.address();
+        let client = self.client.clone();
         let protocol_details_id = self.protocol_details_id;
         let target = self.target.clone();
 
@@ -578,15 +834,24 @@ impl<P: Provider + Unpin + Clone + 'static> Handler<Shutdown> for Fanatic<P> {
                 &users,
                 &db_addr,
                 protocol_details_id,
-                &datap_contract,
-                &addressp_address,
+                client.as_ref(),
                 target,
             )
             .await
             {
                 error!("Failed to update recent users: {}", e);
-            } else {
-                info!("[shutdown] upsert all users positions & health factors..done");
+            }
+            // the database's buffer would be lost with the process
+            match db_addr.send(database::FlushUsersData).await? {
+                Ok(()) => info!("[shutdown] upsert all users positions & health factors..done"),
+                Err(e) => error!("Failed to flush users: {}", e),
+            }
+
+            if let Some(snapshot) = snapshot {
+                match snapshot.await {
+                    Ok(()) => info!("[shutdown] snapshot..done"),
+                    Err(e) => error!("Failed to save snapshot: {}", e),
+                }
             }
 
             Ok(())
@@ -1,7 +1,21 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use crate::client::LendingProtocolClient;
 use crate::contracts;
-use crate::utils::norm;
+use crate::utils::{liquidation_bonus, liquidation_threshold, norm};
 use actix::prelude::*;
-use alloy::{primitives::Address, providers::Provider, rpc::types::Filter, sol_types::SolEvent};
+use alloy::{
+    primitives::{Address, B256},
+    providers::Provider,
+    rpc::types::{Filter, Log},
+    sol_types::SolEvent,
+};
 use futures_util::StreamExt;
 use tracing::{error, info, warn};
 
@@ -10,25 +24,49 @@ use crate::{
         messages::{
             database,
             fanatic::{DoSmthWithLiquidationCall, UpdateReservePrice, UpdateReserveUser},
-            follower::{SendFanaticAddr, StartListeningForEvents, StartListeningForOraclePrices},
+            follower::{
+                Backfill, GetLastBlock, GetReplayFilter, ReplayBlock, SendFanaticAddr,
+                StartListeningForEvents, StartListeningForOraclePrices,
+            },
+            watchdog::Restarted,
         },
-        Database, Fanatic,
+        watchdog::catch_panics,
+        Database, Fanatic, Watchdog,
     },
     configs::FollowerConfig,
-    consts::RAY,
+    consts::{DEBT_CEILING_DECIMALS, RAY},
+    error::LiquidatorError,
+    reorg::ReorgTracker,
 };
 
+// confirmed db writes are tried this many times when failing transiently
+const CONFIRMED_WRITE_ATTEMPTS: u32 = 3;
+// blocks per `eth_getLogs` when backfilling
+const BACKFILL_CHUNK_SIZE: u64 = 1_000;
+
 #[derive(Debug, Clone)]
 pub struct Follower<P: Provider + Unpin + Clone + 'static> {
     provider: P,
     filter: Filter,
 
-    provider_addr: Address,
+    // aToken => reserve
+    a_tokens: HashMap<Address, Address>,
+    confirmations: u64,
+    // logs are fed through `ReplayBlock` instead of subscriptions, i.e backtesting
+    replay: bool,
+
     db_addr: Addr<Database>,
     fanatic_addr: Option<Addr<Fanatic<P>>>,
 
-    pool_contract: contracts::aave_v3::PoolContract::PoolContractInstance<(), P>,
-    datap_contract: contracts::aave_v3::DataProviderContract::DataProviderContractInstance<(), P>,
+    client: Arc<dyn LendingProtocolClient>,
+
+    // running subscriptions, re-established when restarted
+    oracle_prices: Option<SpawnHandle>,
+    events: Option<SpawnHandle>,
+
+    // highest block seen by the pool events subscription, 0 until then
+    last_block: Arc<AtomicU64>,
+    backfilling: bool,
 
     target: String,
 }
@@ -41,6 +79,21 @@ impl<P: Provider + Unpin + Clone + 'static> Actor for Follower<P> {
     }
 }
 
+impl<P: Provider + Unpin + Clone + 'static> Supervised for Follower<P> {
+    // the subscriptions died along with the previous context
+    fn restarting(&mut self, ctx: &mut Self::Context) {
+        warn!("[restarting] Follower");
+        Watchdog::from_registry().do_send(Restarted { actor: "follower" });
+
+        if self.oracle_prices.is_some() {
+            self.oracle_prices = Some(self.listen_oracle_prices(ctx));
+        }
+        if self.events.is_some() {
+            self.events = Some(self.listen_events(ctx));
+        }
+    }
+}
+
 impl<P: Provider + Unpin + Clone + 'static> Handler<SendFanaticAddr<P>> for Follower<P> {
     type Result = ();
 
@@ -57,7 +110,15 @@ impl<P: Provider + Unpin + Clone + 'static> Handler<StartListeningForOraclePrice
         _: StartListeningForOraclePrices,
         ctx: &mut Context<Self>,
     ) -> Self::Result {
-        self.listen_oracle_prices(ctx);
+        if self.replay {
+            info!("replay mode, not subscribing to oracle prices");
+            return;
+        }
+        // i.e `fanatic` restarted
+        if let Some(handle) = self.oracle_prices.take() {
+            ctx.cancel_future(handle);
+        }
+        self.oracle_prices = Some(self.listen_oracle_prices(ctx));
     }
 }
 
@@ -65,7 +126,202 @@ impl<P: Provider + Unpin + Clone + 'static> Handler<StartListeningForEvents> for
     type Result = ();
 
     fn handle(&mut self, _: StartListeningForEvents, ctx: &mut Context<Self>) -> Self::Result {
-        self.listen_events(ctx);
+        if self.replay {
+            info!("replay mode, not subscribing to events");
+            return;
+        }
+        if let Some(handle) = self.events.take() {
+            ctx.cancel_future(handle);
+        }
+        self.events = Some(self.listen_events(ctx));
+    }
+}
+
+impl<P: Provider + Unpin + Clone + 'static> Handler<GetReplayFilter> for Follower<P> {
+    type Result = ResponseFuture<eyre::Result<Filter>>;
+
+    fn handle(&mut self, _: GetReplayFilter, _: &mut Context<Self>) -> Self::Result {
+        let filter = self.filter.clone();
+        let db_addr = self.db_addr.clone();
+        let target = self.target.clone();
+
+        let fut = async move {
+            let aggregators = db_addr
+                .send(database::GetAggregatorMapping(target))
+                .await??;
+
+            Ok(replay_filter(&filter, &aggregators))
+        };
+
+        Box::pin(fut)
+    }
+}
+
+impl<P: Provider + Unpin + Clone + 'static> Handler<GetLastBlock> for Follower<P> {
+    type Result = Option<u64>;
+
+    fn handle(&mut self, _: GetLastBlock, _: &mut Context<Self>) -> Self::Result {
+        match self.last_block.load(Ordering::Relaxed) {
+            _ if self.backfilling => None,
+            0 => None,
+            block => Some(block),
+        }
+    }
+}
+
+impl<P: Provider + Unpin + Clone + 'static> Handler<Backfill> for Follower<P> {
+    type Result = ResponseActFuture<Self, eyre::Result<u64>>;
+
+    // the live subscriptions run meanwhile, the blocks both see are processed twice which is
+    // harmless: users are re-evaluated from the tip and writes are idempotent. Unlike live ones,
+    // backfilled writes don't wait for `confirmations`
+    fn handle(&mut self, msg: Backfill, _: &mut Context<Self>) -> Self::Result {
+        let provider = self.provider.clone();
+        let filter = self.filter.clone();
+        let client = self.client.clone();
+        let a_tokens = self.a_tokens.clone();
+        let db_addr = self.db_addr.clone();
+        let fanatic_addr = self.fanatic_addr.clone();
+        let target = self.target.clone();
+        let replay = self.replay;
+
+        self.backfilling = true;
+
+        let fut = async move {
+            if replay {
+                return Err(eyre::eyre!("not backfilling in replay mode"));
+            }
+
+            let aggregators = db_addr
+                .send(database::GetAggregatorMapping(target.clone()))
+                .await??;
+            let filter = replay_filter(&filter, &aggregators);
+
+            // until caught up with the head, which moves along while backfilling
+            let mut from = msg.from;
+            loop {
+                let head = provider.get_block_number().await?;
+                if from > head {
+                    break;
+                }
+
+                while from <= head {
+                    let to = (from + BACKFILL_CHUNK_SIZE - 1).min(head);
+                    let logs = provider
+                        .get_logs(&filter.clone().from_block(from).to_block(to))
+                        .await?;
+                    info!(from, to, logs = logs.len(), "backfilling");
+
+                    let mut blocks: BTreeMap<u64, Vec<Log>> = BTreeMap::new();
+                    for log in logs {
+                        if let Some(number) = log.block_number {
+                            blocks.entry(number).or_default().push(log);
+                        }
+                    }
+                    for (number, mut logs) in blocks {
+                        logs.sort_by_key(|log| log.log_index);
+                        replay_logs(
+                            number,
+                            &logs,
+                            client.as_ref(),
+                            &aggregators,
+                            &a_tokens,
+                            &db_addr,
+                            &fanatic_addr,
+                            &target,
+                        )
+                        .await?;
+                    }
+
+                    from = to + 1;
+                }
+            }
+
+            Ok(from - 1)
+        };
+
+        // a panic stops the follower, its supervisor restarts it
+        Box::pin(
+            catch_panics(async move { Ok(fut.await) })
+                .into_actor(self)
+                .map(|res, actor, ctx| {
+                    actor.backfilling = false;
+                    let res = res.unwrap_or_else(|e| {
+                        error!("Backfill failed: {:?}", e);
+                        ctx.stop();
+                        Err(e)
+                    });
+                    if let Ok(last) = res {
+                        actor.last_block.fetch_max(last, Ordering::Relaxed);
+                    }
+                    res
+                }),
+        )
+    }
+}
+
+impl<P: Provider + Unpin + Clone + 'static> Handler<ReplayBlock> for Follower<P> {
+    type Result = ResponseActFuture<Self, ()>;
+
+    fn handle(&mut self, msg: ReplayBlock, _: &mut Context<Self>) -> Self::Result {
+        let client = self.client.clone();
+        let a_tokens = self.a_tokens.clone();
+        let db_addr = self.db_addr.clone();
+        let fanatic_addr = self.fanatic_addr.clone();
+        let target = self.target.clone();
+        let replay = self.replay;
+
+        let fut = async move {
+            if !replay {
+                error!(
+                    number = msg.number,
+                    "ignoring replayed block, not in replay mode"
+                );
+                return Ok(());
+            }
+
+            let aggregators: eyre::Result<_> = async {
+                Ok(db_addr
+                    .send(database::GetAggregatorMapping(target.clone()))
+                    .await??)
+            }
+            .await;
+            let aggregators = match aggregators {
+                Ok(aggregators) => aggregators,
+                Err(e) => {
+                    error!(number = msg.number, "Failed to fetch aggregators: {}", e);
+                    return Ok(());
+                }
+            };
+
+            if let Err(e) = replay_logs(
+                msg.number,
+                &msg.logs,
+                client.as_ref(),
+                &aggregators,
+                &a_tokens,
+                &db_addr,
+                &fanatic_addr,
+                &target,
+            )
+            .await
+            {
+                error!(number = msg.number, "Failed to replay block: {}", e);
+            }
+            Ok(())
+        };
+
+        // a panic stops the follower, its supervisor restarts it
+        Box::pin(
+            catch_panics::<_, ()>(fut)
+                .into_actor(self)
+                .map(|res, _, ctx| {
+                    if let Err(e) = res {
+                        error!("Replaying block failed: {:?}", e);
+                        ctx.stop();
+                    }
+                }),
+        )
     }
 }
 
@@ -79,33 +335,66 @@ impl<P: Provider + Unpin + Clone + 'static> Follower<P> {
         match (
             contracts.get("Pool"),
             contracts.get("PoolAddressesProvider"),
-            contracts.get("UiPoolDataProviderV3"),
         ) {
-            (Some(pool_addr), Some(provider_addr), Some(datap_addr)) => {
-                let filter = Filter::new().address(*pool_addr).events(vec![
-                    contracts::aave_v3::PoolContract::LiquidationCall::SIGNATURE,
-                    contracts::aave_v3::PoolContract::Supply::SIGNATURE,
-                    contracts::aave_v3::PoolContract::Borrow::SIGNATURE,
-                    contracts::aave_v3::PoolContract::Repay::SIGNATURE,
-                    contracts::aave_v3::PoolContract::Withdraw::SIGNATURE,
-                    contracts::aave_v3::PoolContract::ReserveDataUpdated::SIGNATURE,
-                ]);
-
-                let pool_contract =
-                    contracts::aave_v3::PoolContract::new(*pool_addr, config.provider.clone());
-                let datap_contract = contracts::aave_v3::DataProviderContract::new(
-                    *datap_addr,
+            (Some(pool_addr), Some(provider_addr)) => {
+                // reserve configuration changes (pausing, freezing..) are emitted by the configurator
+                let configurator_addr = contracts::aave_v3::AddressProviderContract::new(
+                    *provider_addr,
                     config.provider.clone(),
-                );
+                )
+                .getPoolConfigurator()
+                .call()
+                .await?
+                ._0;
+
+                // aTokens transfers between users move collateral around without touching the pool
+                let a_tokens: HashMap<Address, Address> = config
+                    .client
+                    .reserves_data()
+                    .await?
+                    .iter()
+                    .map(|r| (r.a_token_address, r.underlying_asset))
+                    .collect();
+
+                let mut addresses = vec![*pool_addr, configurator_addr];
+                addresses.extend(a_tokens.keys());
+
+                let filter = Filter::new()
+                    .address(addresses)
+                    .events(vec![
+                        contracts::aave_v3::PoolContract::LiquidationCall::SIGNATURE,
+                        contracts::aave_v3::PoolContract::Supply::SIGNATURE,
+                        contracts::aave_v3::PoolContract::Borrow::SIGNATURE,
+                        contracts::aave_v3::PoolContract::Repay::SIGNATURE,
+                        contracts::aave_v3::PoolContract::Withdraw::SIGNATURE,
+                        contracts::aave_v3::PoolContract::ReserveDataUpdated::SIGNATURE,
+                        contracts::aave_v3::PoolContract::ReserveUsedAsCollateralEnabled::SIGNATURE,
+                        contracts::aave_v3::PoolContract::ReserveUsedAsCollateralDisabled::SIGNATURE,
+                        contracts::aave_v3::ATokenContract::BalanceTransfer::SIGNATURE,
+                        contracts::aave_v3::PoolConfiguratorContract::ReserveActive::SIGNATURE,
+                        contracts::aave_v3::PoolConfiguratorContract::ReserveFrozen::SIGNATURE,
+                        contracts::aave_v3::PoolConfiguratorContract::ReservePaused::SIGNATURE,
+                        contracts::aave_v3::PoolConfiguratorContract::ReserveBorrowing::SIGNATURE,
+                        contracts::aave_v3::PoolConfiguratorContract::ReserveFlashLoaning::SIGNATURE,
+                        contracts::aave_v3::PoolConfiguratorContract::SiloedBorrowingChanged::SIGNATURE,
+                        contracts::aave_v3::PoolConfiguratorContract::BorrowableInIsolationChanged::SIGNATURE,
+                        contracts::aave_v3::PoolConfiguratorContract::DebtCeilingChanged::SIGNATURE,
+                        contracts::aave_v3::PoolConfiguratorContract::CollateralConfigurationChanged::SIGNATURE,
+                    ]);
 
                 Ok(Self {
                     provider: config.provider,
                     filter,
+                    a_tokens,
+                    confirmations: config.confirmations,
+                    replay: config.replay,
                     db_addr: config.db_addr,
                     fanatic_addr: None,
-                    provider_addr: *provider_addr,
-                    pool_contract,
-                    datap_contract,
+                    client: config.client,
+                    oracle_prices: None,
+                    events: None,
+                    last_block: Arc::new(AtomicU64::new(0)),
+                    backfilling: false,
                     target: config.target.clone(),
                 })
             }
@@ -116,8 +405,10 @@ impl<P: Provider + Unpin + Clone + 'static> Follower<P> {
         }
     }
 
-    fn listen_oracle_prices(&self, ctx: &mut Context<Self>) {
+    /// Stops the actor, for its supervisor to restart it, once the subscription fails.
+    fn listen_oracle_prices(&self, ctx: &mut Context<Self>) -> SpawnHandle {
         let provider = self.provider.clone();
+        let client = self.client.clone();
         let db_addr = self.db_addr.clone();
         let fanatic_addr = self.fanatic_addr.clone();
         let target = self.target.clone();
@@ -126,11 +417,10 @@ impl<P: Provider + Unpin + Clone + 'static> Follower<P> {
             // Get the list of aggregator addresses to monitor.
             let aggregators = db_addr
                 .send(database::GetAggregatorMapping(target.clone()))
-                .await
-                .unwrap();
+                .await??;
             info!(
                 ?aggregators,
-                "listening for oracle price events from aggregators [{}] [aggregator => reserve]",
+                "listening for oracle price events from aggregators [{}] [aggregator => reserves]",
                 aggregators.keys().len()
             );
 
@@ -142,220 +432,669 @@ impl<P: Provider + Unpin + Clone + 'static> Follower<P> {
                     contracts::chainlink::EACAggregatorProxyContract::AnswerUpdated::SIGNATURE,
                 ]);
 
-            let sub = provider.subscribe_logs(&filter).await.unwrap();
+            let sub = provider.subscribe_logs(&filter).await?;
             let mut stream = sub.into_stream();
 
             while let Some(log) = stream.next().await {
-                if let Ok(event) =
-                    contracts::chainlink::EACAggregatorProxyContract::AnswerUpdated::decode_log(
-                        &log.inner, true,
-                    )
-                {
-                    let price = oracle_price(&provider, event.address).await;
-                    info!(aggregator=?event.address, ?price, "new price from aggregator");
-
-                    // Update the DB reserve price, and send a message to calculate the affected
-                    // users' health_factor.
-                    // TODO: perhaps this should be done in the `fanatic` actor
-                    //
-                    // get the reserve from the aggregator => reserve HashMap
-                    if let Some(reserve) = aggregators.get(&event.address) {
-                        db_addr
-                            .send(database::UpdateOraclePrice {
-                                reserve: *reserve,
-                                target: target.clone(),
-                                price,
-                            })
-                            .await
-                            .unwrap()
-                            .unwrap();
-
-                        fanatic_addr
-                            .clone()
-                            .expect("no fanatic_addr found")
-                            .send(UpdateReservePrice {
-                                reserve: *reserve,
-                                new_price: price,
-                            })
-                            .await
-                            .unwrap();
-                    }
-                }
+                process_oracle_log(
+                    &log,
+                    client.as_ref(),
+                    &aggregators,
+                    &db_addr,
+                    &fanatic_addr,
+                    &target,
+                )
+                .await?;
             }
+            Err(eyre::eyre!("oracle price subscription closed"))
         };
 
-        ctx.spawn(fut.into_actor(self));
+        ctx.spawn(
+            catch_panics::<_, ()>(fut)
+                .into_actor(self)
+                .map(|res, _, ctx| {
+                    if let Err(e) = res {
+                        error!("Stopped listening for oracle prices: {:?}", e);
+                        ctx.stop();
+                    }
+                }),
+        )
     }
 
     /// Listen for realtime action happening in the lending pools
     /// ..and act accordingly
-    fn listen_events(&self, ctx: &mut Context<Self>) {
+    ///
+    /// In-memory state follows the tip, db writes are held until they're `confirmations` deep so
+    /// that logs from orphaned blocks never make it to postgres.
+    fn listen_events(&self, ctx: &mut Context<Self>) -> SpawnHandle {
         let provider = self.provider.clone();
         let filter = self.filter.clone();
+        let a_tokens = self.a_tokens.clone();
+        let confirmations = self.confirmations;
+        let last_block = self.last_block.clone();
 
         let db_addr = self.db_addr.clone();
         let fanatic_addr = self.fanatic_addr.clone();
+        let target = self.target.clone();
 
         let fut = async move {
-            let sub = provider.subscribe_logs(&filter).await.unwrap();
+            let sub = provider.subscribe_logs(&filter).await?;
             let mut stream = sub.into_stream();
+            let mut heads = provider.subscribe_blocks().await?.into_stream();
 
-            while let Some(log) = stream.next().await {
-                let signature = log.topic0().unwrap();
-
-                match signature {
-                    hash if *hash
-                        == contracts::aave_v3::PoolContract::LiquidationCall::SIGNATURE_HASH =>
-                    {
-                        if let Ok(event) =
-                            contracts::aave_v3::PoolContract::LiquidationCall::decode_log(
-                                &log.inner, true,
-                            )
-                        {
-                            info!(?event.user, "liquidation_event_handler");
-                            fanatic_addr
-                                .clone()
-                                .expect("no fanatic_addr found")
-                                .send(DoSmthWithLiquidationCall(event.data))
-                                .await
-                                .unwrap()
-                                .unwrap();
-                        }
-                    }
-                    hash if *hash == contracts::aave_v3::PoolContract::Supply::SIGNATURE_HASH => {
-                        if let Ok(event) =
-                            contracts::aave_v3::PoolContract::Supply::decode_log(&log.inner, true)
-                        {
-                            info!(reserve = ?event.reserve, user = ?event.user, amount = ?event.amount, "supply_event_handler");
-                            fanatic_addr
-                                .clone()
-                                .expect("no fanatic_addr found")
-                                .send(UpdateReserveUser {
-                                    reserve: event.reserve,
-                                    user_addr: event.user,
-                                })
-                                .await
-                                .unwrap();
-                        }
-                    }
-                    hash if *hash == contracts::aave_v3::PoolContract::Borrow::SIGNATURE_HASH => {
-                        if let Ok(event) =
-                            contracts::aave_v3::PoolContract::Borrow::decode_log(&log.inner, true)
-                        {
-                            info!(reserve = ?event.reserve, user = ?event.user, amount = ?event.amount, "borrow_event_handler");
-                            fanatic_addr
-                                .clone()
-                                .expect("no fanatic_addr found")
-                                .send(UpdateReserveUser {
-                                    reserve: event.reserve,
-                                    user_addr: event.user,
-                                })
-                                .await
-                                .unwrap();
+            let mut tracker = ReorgTracker::new(confirmations);
+            let mut head = 0;
+
+            loop {
+                let log = tokio::select! {
+                    Some(log) = stream.next() => log,
+                    Some(header) = heads.next() => {
+                        head = head.max(header.number);
+                        last_block.fetch_max(head, Ordering::Relaxed);
+                        for write in tracker.confirmed(head) {
+                            apply_write(write, &db_addr, &fanatic_addr, &target).await;
                         }
+                        continue;
                     }
-                    hash if *hash == contracts::aave_v3::PoolContract::Repay::SIGNATURE_HASH => {
-                        if let Ok(event) =
-                            contracts::aave_v3::PoolContract::Repay::decode_log(&log.inner, true)
-                        {
-                            info!(reserve = ?event.reserve, user = ?event.user, amount = ?event.amount, "repay_event_handler");
-                            fanatic_addr
-                                .clone()
-                                .expect("no fanatic_addr found")
-                                .send(UpdateReserveUser {
-                                    reserve: event.reserve,
-                                    user_addr: event.user,
-                                })
-                                .await
-                                .unwrap();
-                        }
+                    else => return Err(eyre::eyre!("pool events subscription closed")),
+                };
+
+                let (Some(number), Some(block_hash)) = (log.block_number, log.block_hash) else {
+                    warn!(?log, "log without block number/hash");
+                    continue;
+                };
+
+                if log.removed {
+                    let dropped = tracker.remove(number, block_hash);
+                    warn!(
+                        number,
+                        ?block_hash,
+                        dropped = dropped.len(),
+                        "log removed by a reorg"
+                    );
+
+                    // re-derive the affected users from the tip
+                    for (reserve, user) in affected_users(&log, &a_tokens) {
+                        fanatic(&fanatic_addr)?
+                            .send(UpdateReserveUser {
+                                reserve,
+                                user_addr: user,
+                                // as of the new tip, not of the removed log's block
+                                block_number: None,
+                            })
+                            .await?;
                     }
-                    hash if *hash == contracts::aave_v3::PoolContract::Withdraw::SIGNATURE_HASH => {
-                        if let Ok(event) =
-                            contracts::aave_v3::PoolContract::Withdraw::decode_log(&log.inner, true)
-                        {
-                            info!(reserve = ?event.reserve, user = ?event.user, amount = ?event.amount, "withdraw_event_handler");
-                            fanatic_addr
-                                .clone()
-                                .expect("no fanatic_addr found")
-                                .send(UpdateReserveUser {
-                                    reserve: event.reserve,
-                                    user_addr: event.user,
-                                })
-                                .await
-                                .unwrap();
-                        }
+                    continue;
+                }
+
+                let orphaned = tracker.observe(number, block_hash);
+                if !orphaned.is_empty() {
+                    warn!(
+                        number,
+                        ?block_hash,
+                        dropped = orphaned.len(),
+                        "reorg detected, dropping pending writes"
+                    );
+                }
+                head = head.max(number);
+                last_block.fetch_max(head, Ordering::Relaxed);
+
+                process_log(
+                    &log,
+                    number,
+                    block_hash,
+                    &a_tokens,
+                    &fanatic_addr,
+                    &mut tracker,
+                )
+                .await?;
+
+                for write in tracker.confirmed(head) {
+                    apply_write(write, &db_addr, &fanatic_addr, &target).await;
+                }
+            }
+        };
+
+        ctx.spawn(
+            catch_panics::<_, ()>(fut)
+                .into_actor(self)
+                .map(|res, _, ctx| {
+                    if let Err(e) = res {
+                        error!("Stopped listening for pool events: {:?}", e);
+                        ctx.stop();
                     }
-                    hash if *hash
-                        == contracts::aave_v3::PoolContract::ReserveDataUpdated::SIGNATURE_HASH =>
-                    {
-                        if let Ok(event) =
-                            contracts::aave_v3::PoolContract::ReserveDataUpdated::decode_log(
-                                &log.inner, true,
-                            )
-                        {
-                            info!(reserve = ?event.reserve, liq_rate = ?event.liquidityRate,
-                                liq_index = ?event.liquidityIndex, stable_borrow_rate = ?event.stableBorrowRate,
-                                variable_borrow_rate = ?event.variableBorrowRate, "reserve_update_event_handler");
-                            match db_addr
-                                .send(database::UpsertReservesStats(vec![
-                                    database::UpsertReserveStats {
-                                        reserve: event.reserve.to_string(),
-                                        liquidity_rate: norm(
-                                            event.liquidityRate,
-                                            Some(100.0 / RAY),
-                                        )
-                                        .unwrap(),
-                                        variable_borrow_rate: norm(
-                                            event.variableBorrowRate,
-                                            Some(100.0 / RAY),
-                                        )
-                                        .unwrap(),
-                                        liquidity_index: norm(
-                                            event.liquidityIndex,
-                                            Some(1.0 / RAY),
-                                        )
-                                        .unwrap(),
-                                        variable_borrow_index: norm(
-                                            event.variableBorrowIndex,
-                                            Some(1.0 / RAY),
-                                        )
-                                        .unwrap(),
-                                    },
-                                ]))
-                                .await
-                            {
-                                Ok(Ok(_)) => (),
-                                Ok(Err(e)) => {
-                                    error!(?event.reserve, error = ?e, "Failed to update reserve data")
-                                }
-                                Err(e) => {
-                                    error!(?event.reserve, error = ?e, "Failed to send reserve update")
-                                }
-                            }
-                        }
+                }),
+        )
+    }
+}
+
+/// Filter matching the pool's logs along with the aggregators' `AnswerUpdated`.
+fn replay_filter(filter: &Filter, aggregators: &HashMap<Address, Vec<Address>>) -> Filter {
+    let mut addresses: Vec<Address> = filter.address.iter().cloned().collect();
+    addresses.extend(aggregators.keys());
+    let mut events: Vec<B256> = filter.topics[0].iter().cloned().collect();
+    events.push(contracts::chainlink::EACAggregatorProxyContract::AnswerUpdated::SIGNATURE_HASH);
+
+    Filter::new().address(addresses).event_signature(events)
+}
+
+/// Processes a past block's logs, in order. The block is taken as final, writes are applied right
+/// away.
+#[allow(clippy::too_many_arguments)]
+async fn replay_logs<P: Provider + Unpin + Clone + 'static>(
+    number: u64,
+    logs: &[Log],
+    client: &dyn LendingProtocolClient,
+    aggregators: &HashMap<Address, Vec<Address>>,
+    a_tokens: &HashMap<Address, Address>,
+    db_addr: &Addr<Database>,
+    fanatic_addr: &Option<Addr<Fanatic<P>>>,
+    target: &str,
+) -> Result<(), LiquidatorError> {
+    let mut tracker = ReorgTracker::new(0);
+    for log in logs {
+        if aggregators.contains_key(&log.address()) {
+            process_oracle_log(log, client, aggregators, db_addr, fanatic_addr, target).await?;
+            continue;
+        }
+
+        let Some(block_hash) = log.block_hash else {
+            warn!(?log, "log without block hash");
+            continue;
+        };
+        process_log(
+            log,
+            number,
+            block_hash,
+            a_tokens,
+            fanatic_addr,
+            &mut tracker,
+        )
+        .await?;
+    }
+
+    for write in tracker.confirmed(number) {
+        apply_write(write, db_addr, fanatic_addr, target).await;
+    }
+    Ok(())
+}
+
+/// Handles an aggregator's `AnswerUpdated`: refreshes the reserve's price in postgres and has
+/// `fanatic` re-evaluate its users.
+async fn process_oracle_log<P: Provider + Unpin + Clone + 'static>(
+    log: &Log,
+    client: &dyn LendingProtocolClient,
+    aggregators: &HashMap<Address, Vec<Address>>,
+    db_addr: &Addr<Database>,
+    fanatic_addr: &Option<Addr<Fanatic<P>>>,
+    target: &str,
+) -> Result<(), LiquidatorError> {
+    if let Ok(event) = contracts::chainlink::EACAggregatorProxyContract::AnswerUpdated::decode_log(
+        &log.inner, true,
+    ) {
+        let price = match client.price(event.address).await {
+            Ok(price) => price,
+            Err(e) => {
+                error!(aggregator = ?event.address, error = ?e, "unable to fetch price");
+                return Ok(());
+            }
+        };
+        info!(aggregator=?event.address, ?price, "new price from aggregator");
+
+        // Update the DB reserve price, and send a message to calculate the affected
+        // users' health_factor.
+        // TODO: perhaps this should be done in the `fanatic` actor
+        //
+        // get the reserves from the aggregator => reserves HashMap
+        for reserve in aggregators.get(&event.address).into_iter().flatten() {
+            db_addr
+                .send(database::UpdateOraclePrice {
+                    reserve: *reserve,
+                    target: target.to_string(),
+                    price,
+                    block_number: log.block_number,
+                })
+                .await??;
+
+            fanatic(fanatic_addr)?
+                .send(UpdateReservePrice {
+                    reserve: *reserve,
+                    new_price: price,
+                    block_number: log.block_number,
+                })
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Handles a pool, aToken or configurator log: in-memory updates are sent to `fanatic` right away,
+/// db writes are queued on `tracker`.
+async fn process_log<P: Provider + Unpin + Clone + 'static>(
+    log: &Log,
+    number: u64,
+    block_hash: B256,
+    a_tokens: &HashMap<Address, Address>,
+    fanatic_addr: &Option<Addr<Fanatic<P>>>,
+    tracker: &mut ReorgTracker<PendingWrite>,
+) -> Result<(), LiquidatorError> {
+    use contracts::aave_v3::{ATokenContract, PoolContract};
+
+    let Some(signature) = log.topic0() else {
+        warn!(?log, "log without topics");
+        return Ok(());
+    };
+
+    match signature {
+        hash if *hash == PoolContract::LiquidationCall::SIGNATURE_HASH => {
+            if let Ok(event) = PoolContract::LiquidationCall::decode_log(&log.inner, true) {
+                info!(?event.user, liquidator = ?event.liquidator, "liquidation_event_handler");
+                match (log.transaction_hash, log.log_index) {
+                    (Some(tx_hash), Some(log_index)) => tracker.push(
+                        number,
+                        block_hash,
+                        PendingWrite::LiquidationCall(DoSmthWithLiquidationCall {
+                            call: event.data,
+                            block_number: number,
+                            tx_hash,
+                            log_index,
+                        }),
+                    ),
+                    _ => warn!(?log, "liquidation log without tx hash/log index"),
+                }
+            }
+        }
+        hash if *hash == PoolContract::Supply::SIGNATURE_HASH => {
+            if let Ok(event) = PoolContract::Supply::decode_log(&log.inner, true) {
+                info!(
+                    reserve = ?event.reserve, user = ?event.user, amount = ?event.amount,
+                    "supply_event_handler"
+                );
+                fanatic(fanatic_addr)?
+                    .send(UpdateReserveUser {
+                        reserve: event.reserve,
+                        user_addr: event.user,
+                        block_number: Some(number),
+                    })
+                    .await?;
+            }
+        }
+        hash if *hash == PoolContract::Borrow::SIGNATURE_HASH => {
+            if let Ok(event) = PoolContract::Borrow::decode_log(&log.inner, true) {
+                info!(
+                    reserve = ?event.reserve, user = ?event.user, amount = ?event.amount,
+                    "borrow_event_handler"
+                );
+                fanatic(fanatic_addr)?
+                    .send(UpdateReserveUser {
+                        reserve: event.reserve,
+                        user_addr: event.user,
+                        block_number: Some(number),
+                    })
+                    .await?;
+            }
+        }
+        hash if *hash == PoolContract::Repay::SIGNATURE_HASH => {
+            if let Ok(event) = PoolContract::Repay::decode_log(&log.inner, true) {
+                // `repayWithATokens` emits a regular `Repay`, burning the user's aTokens
+                info!(
+                    reserve = ?event.reserve, user = ?event.user, amount = ?event.amount,
+                    use_a_tokens = event.useATokens, "repay_event_handler"
+                );
+                fanatic(fanatic_addr)?
+                    .send(UpdateReserveUser {
+                        reserve: event.reserve,
+                        user_addr: event.user,
+                        block_number: Some(number),
+                    })
+                    .await?;
+            }
+        }
+        hash if *hash == PoolContract::Withdraw::SIGNATURE_HASH => {
+            if let Ok(event) = PoolContract::Withdraw::decode_log(&log.inner, true) {
+                info!(
+                    reserve = ?event.reserve, user = ?event.user, amount = ?event.amount,
+                    "withdraw_event_handler"
+                );
+                fanatic(fanatic_addr)?
+                    .send(UpdateReserveUser {
+                        reserve: event.reserve,
+                        user_addr: event.user,
+                        block_number: Some(number),
+                    })
+                    .await?;
+            }
+        }
+        hash if *hash == PoolContract::ReserveDataUpdated::SIGNATURE_HASH => {
+            if let Ok(event) = PoolContract::ReserveDataUpdated::decode_log(&log.inner, true) {
+                info!(
+                    reserve = ?event.reserve, liq_rate = ?event.liquidityRate,
+                    liq_index = ?event.liquidityIndex, stable_borrow_rate = ?event.stableBorrowRate,
+                    variable_borrow_rate = ?event.variableBorrowRate,
+                    "reserve_update_event_handler"
+                );
+                tracker.push(
+                    number,
+                    block_hash,
+                    PendingWrite::ReserveStats(database::UpsertReserveStats {
+                        reserve: event.reserve.to_string(),
+                        liquidity_rate: norm(event.liquidityRate, Some(100.0 / RAY))
+                            .map_err(decode_error)?,
+                        variable_borrow_rate: norm(event.variableBorrowRate, Some(100.0 / RAY))
+                            .map_err(decode_error)?,
+                        liquidity_index: norm(event.liquidityIndex, Some(1.0 / RAY))
+                            .map_err(decode_error)?,
+                        variable_borrow_index: norm(event.variableBorrowIndex, Some(1.0 / RAY))
+                            .map_err(decode_error)?,
+                        block_number: Some(number),
+                    }),
+                );
+            }
+        }
+        hash if *hash == PoolContract::ReserveUsedAsCollateralEnabled::SIGNATURE_HASH
+            || *hash == PoolContract::ReserveUsedAsCollateralDisabled::SIGNATURE_HASH =>
+        {
+            let enabled = *hash == PoolContract::ReserveUsedAsCollateralEnabled::SIGNATURE_HASH;
+            // both events share the same (reserve, user) layout
+            if let Ok(event) =
+                PoolContract::ReserveUsedAsCollateralEnabled::decode_log(&log.inner, false)
+            {
+                info!(
+                    reserve = ?event.reserve, user = ?event.user, enabled,
+                    "collateral_toggle_event_handler"
+                );
+                tracker.push(
+                    number,
+                    block_hash,
+                    PendingWrite::UserCollateral {
+                        user: event.user,
+                        reserve: event.reserve,
+                        enabled,
+                    },
+                );
+                fanatic(fanatic_addr)?
+                    .send(UpdateReserveUser {
+                        reserve: event.reserve,
+                        user_addr: event.user,
+                        block_number: Some(number),
+                    })
+                    .await?;
+            }
+        }
+        hash if *hash == ATokenContract::BalanceTransfer::SIGNATURE_HASH => {
+            if let (Ok(event), Some(reserve)) = (
+                ATokenContract::BalanceTransfer::decode_log(&log.inner, true),
+                a_tokens.get(&log.address()),
+            ) {
+                info!(
+                    ?reserve, from = ?event.from, to = ?event.to, value = ?event.value,
+                    "balance_transfer_event_handler"
+                );
+                for user in [event.from, event.to] {
+                    if user == Address::ZERO {
+                        continue;
                     }
-                    _ => warn!(?signature, ?log, "unknown event"),
+                    fanatic(fanatic_addr)?
+                        .send(UpdateReserveUser {
+                            reserve: *reserve,
+                            user_addr: user,
+                            block_number: Some(number),
+                        })
+                        .await?;
                 }
             }
+        }
+        _ => match reserve_flag_update(log) {
+            Some((reserve, update)) => {
+                info!(?reserve, ?update, "reserve_configuration_event_handler");
+                tracker.push(
+                    number,
+                    block_hash,
+                    PendingWrite::ReserveFlag { reserve, update },
+                );
+            }
+            None => warn!(?signature, ?log, "unknown event"),
+        },
+    }
+    Ok(())
+}
+
+/// `fanatic` registers itself once started.
+fn fanatic<P: Provider + Unpin + Clone + 'static>(
+    fanatic_addr: &Option<Addr<Fanatic<P>>>,
+) -> Result<&Addr<Fanatic<P>>, LiquidatorError> {
+    fanatic_addr
+        .as_ref()
+        .ok_or(LiquidatorError::Config("no fanatic_addr found".to_string()))
+}
+
+fn decode_error(e: eyre::Report) -> LiquidatorError {
+    LiquidatorError::Decode(e.to_string())
+}
+
+/// Db writes derived from the pool's logs, applied once their block is deep enough.
+enum PendingWrite {
+    LiquidationCall(DoSmthWithLiquidationCall),
+    ReserveStats(database::UpsertReserveStats),
+    ReserveFlag {
+        reserve: Address,
+        update: database::ReserveFlagUpdate,
+    },
+    UserCollateral {
+        user: Address,
+        reserve: Address,
+        enabled: bool,
+    },
+}
+
+async fn apply_write<P: Provider + Unpin + Clone + 'static>(
+    write: PendingWrite,
+    db_addr: &Addr<Database>,
+    fanatic_addr: &Option<Addr<Fanatic<P>>>,
+    target: &str,
+) {
+    if let PendingWrite::LiquidationCall(call) = write {
+        let Some(fanatic_addr) = fanatic_addr else {
+            error!("no fanatic_addr found, dropping confirmed liquidation call");
+            return;
         };
+        match fanatic_addr.send(call).await {
+            Ok(Ok(_)) => (),
+            Ok(Err(e)) => error!(error = ?e, "Failed to apply confirmed write"),
+            Err(e) => error!(error = ?e, "Failed to send confirmed write"),
+        }
+        return;
+    }
+
+    // the write is lost otherwise, give transient failures another chance
+    for attempt in 1..=CONFIRMED_WRITE_ATTEMPTS {
+        match send_write(&write, db_addr, target).await {
+            Ok(Ok(_)) => return,
+            Ok(Err(e)) if e.is_transient() && attempt < CONFIRMED_WRITE_ATTEMPTS => {
+                warn!(error = %e, attempt, "Failed to apply confirmed write, retrying")
+            }
+            Ok(Err(e)) => return error!(error = %e, "Failed to apply confirmed write"),
+            Err(e) => return error!(error = ?e, "Failed to send confirmed write"),
+        }
+    }
+}
+
+async fn send_write(
+    write: &PendingWrite,
+    db_addr: &Addr<Database>,
+    target: &str,
+) -> Result<Result<(), LiquidatorError>, MailboxError> {
+    match write {
+        // goes through `fanatic`, see `apply_write`
+        PendingWrite::LiquidationCall(_) => Ok(Ok(())),
+        PendingWrite::ReserveStats(stats) => {
+            db_addr
+                .send(database::UpsertReservesStats {
+                    target: target.to_string(),
+                    reserves: vec![stats.clone()],
+                })
+                .await
+        }
+        PendingWrite::ReserveFlag { reserve, update } => {
+            db_addr
+                .send(database::UpdateReserveFlag {
+                    target: target.to_string(),
+                    reserve: *reserve,
+                    update: update.clone(),
+                })
+                .await
+        }
+        PendingWrite::UserCollateral {
+            user,
+            reserve,
+            enabled,
+        } => {
+            db_addr
+                .send(database::UpdateUserCollateral {
+                    target: target.to_string(),
+                    user_address: *user,
+                    reserve: *reserve,
+                    enabled: *enabled,
+                })
+                .await
+        }
+    }
+}
+
+/// (reserve, user) pairs whose state depends on `log`.
+fn affected_users(log: &Log, a_tokens: &HashMap<Address, Address>) -> Vec<(Address, Address)> {
+    use contracts::aave_v3::{ATokenContract, PoolContract};
+
+    let Some(signature) = log.topic0() else {
+        return Vec::new();
+    };
+    let inner = &log.inner;
+
+    match *signature {
+        PoolContract::LiquidationCall::SIGNATURE_HASH => {
+            PoolContract::LiquidationCall::decode_log(inner, true)
+                .map(|e| vec![(e.collateralAsset, e.user), (e.debtAsset, e.user)])
+                .unwrap_or_default()
+        }
+        PoolContract::Supply::SIGNATURE_HASH => PoolContract::Supply::decode_log(inner, true)
+            .map(|e| vec![(e.reserve, e.user)])
+            .unwrap_or_default(),
+        PoolContract::Borrow::SIGNATURE_HASH => PoolContract::Borrow::decode_log(inner, true)
+            .map(|e| vec![(e.reserve, e.user)])
+            .unwrap_or_default(),
+        PoolContract::Repay::SIGNATURE_HASH => PoolContract::Repay::decode_log(inner, true)
+            .map(|e| vec![(e.reserve, e.user)])
+            .unwrap_or_default(),
+        PoolContract::Withdraw::SIGNATURE_HASH => PoolContract::Withdraw::decode_log(inner, true)
+            .map(|e| vec![(e.reserve, e.user)])
+            .unwrap_or_default(),
+        PoolContract::ReserveUsedAsCollateralEnabled::SIGNATURE_HASH
+        | PoolContract::ReserveUsedAsCollateralDisabled::SIGNATURE_HASH => {
+            PoolContract::ReserveUsedAsCollateralEnabled::decode_log(inner, false)
+                .map(|e| vec![(e.reserve, e.user)])
+                .unwrap_or_default()
+        }
+        ATokenContract::BalanceTransfer::SIGNATURE_HASH => {
+            match (
+                ATokenContract::BalanceTransfer::decode_log(inner, true),
+                a_tokens.get(&log.address()),
+            ) {
+                (Ok(e), Some(reserve)) => [e.from, e.to]
+                    .into_iter()
+                    .filter(|u| *u != Address::ZERO)
+                    .map(|u| (*reserve, u))
+                    .collect(),
+                _ => Vec::new(),
+            }
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Decodes the `PoolConfigurator` events we keep track of into the matching reserve flag update.
+fn reserve_flag_update(log: &Log) -> Option<(Address, database::ReserveFlagUpdate)> {
+    use contracts::aave_v3::PoolConfiguratorContract as Configurator;
+    use database::ReserveFlagUpdate;
 
-        ctx.spawn(fut.into_actor(self));
+    let signature = *log.topic0()?;
+    let inner = &log.inner;
+
+    match signature {
+        Configurator::ReserveActive::SIGNATURE_HASH => {
+            let event = Configurator::ReserveActive::decode_log(inner, true).ok()?;
+            Some((event.asset, ReserveFlagUpdate::Active(event.active)))
+        }
+        Configurator::ReserveFrozen::SIGNATURE_HASH => {
+            let event = Configurator::ReserveFrozen::decode_log(inner, true).ok()?;
+            Some((event.asset, ReserveFlagUpdate::Frozen(event.frozen)))
+        }
+        Configurator::ReservePaused::SIGNATURE_HASH => {
+            let event = Configurator::ReservePaused::decode_log(inner, true).ok()?;
+            Some((event.asset, ReserveFlagUpdate::Paused(event.paused)))
+        }
+        Configurator::ReserveBorrowing::SIGNATURE_HASH => {
+            let event = Configurator::ReserveBorrowing::decode_log(inner, true).ok()?;
+            Some((
+                event.asset,
+                ReserveFlagUpdate::BorrowingEnabled(event.enabled),
+            ))
+        }
+        Configurator::ReserveFlashLoaning::SIGNATURE_HASH => {
+            let event = Configurator::ReserveFlashLoaning::decode_log(inner, true).ok()?;
+            Some((
+                event.asset,
+                ReserveFlagUpdate::FlashloanEnabled(event.enabled),
+            ))
+        }
+        Configurator::SiloedBorrowingChanged::SIGNATURE_HASH => {
+            let event = Configurator::SiloedBorrowingChanged::decode_log(inner, true).ok()?;
+            Some((
+                event.asset,
+                ReserveFlagUpdate::SiloedBorrowing(event.newState),
+            ))
+        }
+        Configurator::BorrowableInIsolationChanged::SIGNATURE_HASH => {
+            let event = Configurator::BorrowableInIsolationChanged::decode_log(inner, true).ok()?;
+            Some((
+                event.asset,
+                ReserveFlagUpdate::BorrowableInIsolation(event.borrowable),
+            ))
+        }
+        Configurator::DebtCeilingChanged::SIGNATURE_HASH => {
+            let event = Configurator::DebtCeilingChanged::decode_log(inner, true).ok()?;
+            let debt_ceiling = norm(
+                event.newDebtCeiling,
+                Some(10.0_f64.powi(-DEBT_CEILING_DECIMALS)),
+            )
+            .ok()?;
+            Some((event.asset, ReserveFlagUpdate::DebtCeiling(debt_ceiling)))
+        }
+        Configurator::CollateralConfigurationChanged::SIGNATURE_HASH => {
+            let event =
+                Configurator::CollateralConfigurationChanged::decode_log(inner, true).ok()?;
+            Some((
+                event.asset,
+                ReserveFlagUpdate::CollateralConfiguration {
+                    liquidation_threshold: liquidation_threshold(event.liquidationThreshold)
+                        .ok()?,
+                    liquidation_bonus: liquidation_bonus(event.liquidationBonus).ok()?,
+                },
+            ))
+        }
+        _ => None,
     }
 }
 
-pub async fn oracle_price<P: Provider + Clone>(provider: P, addr: Address) -> f64 {
+pub async fn oracle_price<P: Provider + Clone>(provider: P, addr: Address) -> eyre::Result<f64> {
     let contract = contracts::chainlink::OffchainAggregatorContract::new(addr, &provider);
 
     let latest_answer = contract
         .latestAnswer()
         .call()
-        .await
-        .unwrap()
+        .await?
         ._0
         .to_string()
-        .parse::<f64>()
-        .unwrap();
+        .parse::<f64>()?;
 
     // Try to get decimals using CLRatePriceCapAdapter, if it fails, use CLSynchronicityPriceAdapterPegToBase.
     let decimals = match contract.decimals().call().await {
@@ -366,9 +1105,9 @@ pub async fn oracle_price<P: Provider + Clone>(provider: P, addr: Address) -> f6
                 contracts::chainlink::CLSynchronicityPriceAdapterPegToBaseContract::new(
                     addr, &provider,
                 );
-            synch_adapter.DECIMALS().call().await.unwrap()._0
+            synch_adapter.DECIMALS().call().await?._0
         }
     };
 
-    latest_answer / 10_f64.powi(decimals as i32)
+    Ok(latest_answer / 10_f64.powi(decimals as i32))
 }
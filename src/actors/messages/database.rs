@@ -4,16 +4,25 @@ use std::{
 };
 
 use actix::prelude::*;
-use alloy::primitives::Address;
+use alloy::primitives::{Address, TxHash, U256};
 pub use handlers::*;
-use sqlx::{types::time::PrimitiveDateTime, FromRow, PgPool, Row};
+use serde::Serialize;
+use sqlx::{
+    types::{time::PrimitiveDateTime, BigDecimal},
+    FromRow, PgPool, Row,
+};
 
-use crate::actors::Database;
+use crate::{
+    actors::Database, args::UserSort, client::UserReserve, error::LiquidatorError,
+    router::uniswap_v3_fee_tiers,
+};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct UserData {
     pub health_factor: f64,
     pub last_update: i64, // UTC EPOCH timestamp
+    // as of `last_update`, empty until the user's been evaluated
+    pub positions: Vec<UserReserve>,
 }
 
 impl Default for UserData {
@@ -21,10 +30,36 @@ impl Default for UserData {
         UserData {
             health_factor: -1.0,
             last_update: 0,
+            positions: Vec::new(),
         }
     }
 }
 
+/// A user's positions and health factor as last evaluated, replacing the stored ones.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UserWrite {
+    pub address: Address,
+    pub protocol_details_id: i32,
+    pub health_factor: f64,
+    pub positions: Vec<(Address, f64, f64, bool)>, // (token_address, supply amount, borrow amount, used as collateral)
+}
+
+impl UserWrite {
+    /// Users are told apart per protocol, see `aavev3_users`' primary key.
+    pub fn key(&self) -> (i32, Address) {
+        (self.protocol_details_id, self.address)
+    }
+}
+
+/// The fanatic's in-memory state, as of `block_number`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FanaticSnapshot {
+    pub block_number: u64,
+    pub users: HashMap<Address, UserData>,
+    // reserve => users with a position in it
+    pub reserves_users: HashMap<Address, HashSet<Address>>,
+}
+
 #[derive(Clone, Debug)]
 pub struct ReserveData {
     pub users: HashSet<Address>,
@@ -32,7 +67,7 @@ pub struct ReserveData {
 }
 
 #[derive(Message)]
-#[rtype(result = "Result<(), sqlx::Error>")]
+#[rtype(result = "Result<(), LiquidatorError>")]
 pub struct UpsertReserve {
     pub symbol: String,
     pub name: String,
@@ -44,81 +79,146 @@ pub struct UpsertReserve {
     pub flashloan_enabled: bool,
     pub oracle_addr: String,
     pub aggregator_addr: Option<String>,
+    pub flags: ReserveFlags,
     pub stats: UpsertReserveStats,
     pub price_usd: f64,
 }
 
-#[derive(Message)]
-#[rtype(result = "Result<(), sqlx::Error>")]
+#[derive(Clone, Debug, FromRow)]
+pub struct ReserveFlags {
+    pub is_active: bool,
+    pub is_frozen: bool,
+    pub is_paused: bool,
+    pub is_siloed_borrowing: bool,
+    pub borrowing_enabled: bool,
+    pub usage_as_collateral_enabled: bool,
+    pub borrowable_in_isolation: bool,
+    pub debt_ceiling: f64,
+}
+
+/// A single reserve configuration change, as emitted by the `PoolConfigurator`.
+#[derive(Clone, Debug)]
+pub enum ReserveFlagUpdate {
+    Active(bool),
+    Frozen(bool),
+    Paused(bool),
+    SiloedBorrowing(bool),
+    BorrowingEnabled(bool),
+    FlashloanEnabled(bool),
+    BorrowableInIsolation(bool),
+    DebtCeiling(f64),
+    CollateralConfiguration {
+        liquidation_threshold: f64,
+        liquidation_bonus: f64,
+    },
+}
+
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "Result<(), LiquidatorError>")]
 pub struct UpsertReserveStats {
     pub reserve: String,
     pub liquidity_rate: f64,
     pub variable_borrow_rate: f64,
     pub liquidity_index: f64,
     pub variable_borrow_index: f64,
+    // block of the `ReserveDataUpdated` log, `None` when read off the pool, i.e not recorded in
+    // the history
+    pub block_number: Option<u64>,
+}
+
+/// How a liquidation attempt ended up, see `liquidation_attempts`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttemptStatus {
+    // sent to a fork by `backtest`
+    Simulated,
+    // failed before being sent, i.e at gas estimation
+    Reverted,
+    MinedSuccess,
+    MinedFailed,
+    // failed because someone else liquidated the user first
+    LostRace,
+}
+
+impl AttemptStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AttemptStatus::Simulated => "simulated",
+            AttemptStatus::Reverted => "reverted",
+            AttemptStatus::MinedSuccess => "mined_success",
+            AttemptStatus::MinedFailed => "mined_failed",
+            AttemptStatus::LostRace => "lost_race",
+        }
+    }
 }
 
 pub mod handlers {
-    use crate::contracts;
+    use crate::{
+        actors::database::FLUSH_MAX_USERS,
+        client::{Liquidation, LiquidationReceipt},
+        contracts,
+        strategy::Strategy,
+    };
 
     use super::*;
 
     #[derive(Message)]
-    #[rtype(result = "Result<HashMap<String, Address>, sqlx::Error>")]
+    #[rtype(result = "Result<HashMap<String, Address>, LiquidatorError>")]
     pub struct GetProtocolContracts(pub String);
     impl Handler<GetProtocolContracts> for Database {
-        type Result = ResponseFuture<Result<HashMap<String, Address>, sqlx::Error>>;
+        type Result = ResponseFuture<Result<HashMap<String, Address>, LiquidatorError>>;
 
         fn handle(&mut self, msg: GetProtocolContracts, _: &mut Self::Context) -> Self::Result {
             let pool = self.pool.clone();
-            let (network, protocol) = msg.0.split_once('-').unwrap();
-            let (network, protocol) = (network.to_string(), protocol.to_string());
-
-            let fut = async move { get_protocol_contracts(&pool, &network, &protocol).await };
+            let fut = async move {
+                let (network, protocol) = split_target(&msg.0)?;
+                get_protocol_contracts(&pool, &network, &protocol).await
+            };
 
             Box::pin(fut)
         }
     }
 
     #[derive(Message)]
-    #[rtype(result = "Result<i32, sqlx::Error>")]
+    #[rtype(result = "Result<i32, LiquidatorError>")]
     pub struct GetProtocolDetailsId(pub String);
     impl Handler<GetProtocolDetailsId> for Database {
-        type Result = ResponseFuture<Result<i32, sqlx::Error>>;
+        type Result = ResponseFuture<Result<i32, LiquidatorError>>;
 
         fn handle(&mut self, msg: GetProtocolDetailsId, _: &mut Self::Context) -> Self::Result {
             let pool = self.pool.clone();
-            let (network, protocol) = msg.0.split_once('-').unwrap();
-            let (network, protocol) = (network.to_string(), protocol.to_string());
-
-            let fut = async move { get_protocol_details_id(&pool, &network, &protocol).await };
+            let fut = async move {
+                let (network, protocol) = split_target(&msg.0)?;
+                get_protocol_details_id(&pool, &network, &protocol).await
+            };
 
             Box::pin(fut)
         }
     }
 
     #[derive(Message)]
-    #[rtype(result = "Result<Option<i64>, sqlx::Error>")]
+    #[rtype(result = "Result<Option<i64>, LiquidatorError>")]
     pub struct GetProtocolDeployBlock(pub String);
     impl Handler<GetProtocolDeployBlock> for Database {
-        type Result = ResponseFuture<Result<Option<i64>, sqlx::Error>>;
+        type Result = ResponseFuture<Result<Option<i64>, LiquidatorError>>;
 
         fn handle(&mut self, msg: GetProtocolDeployBlock, _: &mut Self::Context) -> Self::Result {
             let pool = self.pool.clone();
-            let (network, protocol) = msg.0.split_once('-').unwrap();
-            let (network, protocol) = (network.to_string(), protocol.to_string());
-
-            let fut = async move { get_protocol_deploy_block(&pool, &network, &protocol).await };
+            let fut = async move {
+                let (network, protocol) = split_target(&msg.0)?;
+                get_protocol_deploy_block(&pool, &network, &protocol).await
+            };
 
             Box::pin(fut)
         }
     }
 
+    /// aggregator => reserves priced by it, e.g ETH/USD prices WETH and the LSTs
     #[derive(Message)]
-    #[rtype(result = "HashMap<Address, Address>")]
+    #[rtype(result = "Result<HashMap<Address, Vec<Address>>, LiquidatorError>")]
     pub struct GetAggregatorMapping(pub String);
     impl Handler<GetAggregatorMapping> for Database {
-        type Result = ResponseFuture<HashMap<Address, Address>>;
+        type Result = ResponseFuture<Result<HashMap<Address, Vec<Address>>, LiquidatorError>>;
 
         fn handle(&mut self, msg: GetAggregatorMapping, _ctx: &mut Self::Context) -> Self::Result {
             let pool = self.pool.clone();
@@ -129,16 +229,17 @@ pub mod handlers {
     }
 
     #[derive(Message)]
-    #[rtype(result = "Result<Vec<Reserve>, sqlx::Error>")]
+    #[rtype(result = "Result<Vec<Reserve>, LiquidatorError>")]
     pub struct GetReserves(pub String);
     impl Handler<GetReserves> for Database {
-        type Result = ResponseFuture<Result<Vec<Reserve>, sqlx::Error>>;
+        type Result = ResponseFuture<Result<Vec<Reserve>, LiquidatorError>>;
 
         fn handle(&mut self, msg: GetReserves, _: &mut Self::Context) -> Self::Result {
             let pool = self.pool.clone();
-            let (network, protocol) = msg.0.split_once('-').unwrap();
-            let (network, protocol) = (network.to_string(), protocol.to_string());
-            let fut = async move { get_reserves(&pool, &network, &protocol).await };
+            let fut = async move {
+                let (network, protocol) = split_target(&msg.0)?;
+                get_reserves(&pool, &network, &protocol).await
+            };
 
             Box::pin(fut)
         }
@@ -146,30 +247,65 @@ pub mod handlers {
 
     #[derive(Message)]
     #[rtype(
-        result = "Result<(HashMap<Address, UserData>, HashMap<Address, ReserveData>), sqlx::Error>"
+        result = "Result<(HashMap<Address, UserData>, HashMap<Address, ReserveData>), LiquidatorError>"
     )]
     pub struct GetReservesUsers(pub String);
     impl Handler<GetReservesUsers> for Database {
         type Result = ResponseFuture<
-            Result<(HashMap<Address, UserData>, HashMap<Address, ReserveData>), sqlx::Error>,
+            Result<(HashMap<Address, UserData>, HashMap<Address, ReserveData>), LiquidatorError>,
         >;
 
         fn handle(&mut self, msg: GetReservesUsers, _: &mut Self::Context) -> Self::Result {
             let pool = self.pool.clone();
-            let (network, protocol) = msg.0.split_once('-').unwrap();
-            let (network, protocol) = (network.to_string(), protocol.to_string());
+            let fut = async move {
+                let (network, protocol) = split_target(&msg.0)?;
+                get_reserves_users(&pool, &network, &protocol).await
+            };
+
+            Box::pin(fut)
+        }
+    }
+
+    /// Replaces the target's previous snapshot, all at once.
+    #[derive(Message)]
+    #[rtype(result = "Result<(), LiquidatorError>")]
+    pub struct SaveFanaticSnapshot {
+        pub protocol_details_id: i32,
+        pub snapshot: FanaticSnapshot,
+    }
+    impl Handler<SaveFanaticSnapshot> for Database {
+        type Result = ResponseFuture<Result<(), LiquidatorError>>;
+
+        fn handle(&mut self, msg: SaveFanaticSnapshot, _: &mut Self::Context) -> Self::Result {
+            let pool = self.pool.clone();
+            let fut = async move {
+                save_fanatic_snapshot(&pool, msg.protocol_details_id, &msg.snapshot).await
+            };
+
+            Box::pin(fut)
+        }
+    }
+
+    /// `protocol_details_id`'s latest snapshot, if any.
+    #[derive(Message)]
+    #[rtype(result = "Result<Option<FanaticSnapshot>, LiquidatorError>")]
+    pub struct GetFanaticSnapshot(pub i32);
+    impl Handler<GetFanaticSnapshot> for Database {
+        type Result = ResponseFuture<Result<Option<FanaticSnapshot>, LiquidatorError>>;
 
-            let fut = async move { get_reserves_users(&pool, &network, &protocol).await };
+        fn handle(&mut self, msg: GetFanaticSnapshot, _: &mut Self::Context) -> Self::Result {
+            let pool = self.pool.clone();
+            let fut = async move { get_fanatic_snapshot(&pool, msg.0).await };
 
             Box::pin(fut)
         }
     }
 
     #[derive(Message)]
-    #[rtype(result = "Result<HashMap<String, (f64, f64)>, sqlx::Error>")]
+    #[rtype(result = "Result<HashMap<String, (f64, f64)>, LiquidatorError>")]
     pub struct GetReservesLiquidityIndices(pub String);
     impl Handler<GetReservesLiquidityIndices> for Database {
-        type Result = ResponseFuture<Result<HashMap<String, (f64, f64)>, sqlx::Error>>;
+        type Result = ResponseFuture<Result<HashMap<String, (f64, f64)>, LiquidatorError>>;
 
         fn handle(
             &mut self,
@@ -177,38 +313,88 @@ pub mod handlers {
             _: &mut Self::Context,
         ) -> Self::Result {
             let pool = self.pool.clone();
-            let (network, protocol) = msg.0.split_once('-').unwrap();
-            let (network, protocol) = (network.to_string(), protocol.to_string());
-
-            let fut =
-                async move { get_reserves_liquidity_indices(&pool, &network, &protocol).await };
+            let fut = async move {
+                let (network, protocol) = split_target(&msg.0)?;
+                get_reserves_liquidity_indices(&pool, &network, &protocol).await
+            };
 
             Box::pin(fut)
         }
     }
 
     #[derive(Message)]
-    #[rtype(result = "Result<(), sqlx::Error>")]
+    #[rtype(result = "Result<(), LiquidatorError>")]
     pub struct UpdateOraclePrice {
         pub target: String,
         pub reserve: Address,
         pub price: f64,
+        // block of the `AnswerUpdated` log, if known
+        pub block_number: Option<u64>,
     }
     impl Handler<UpdateOraclePrice> for Database {
-        type Result = ResponseFuture<Result<(), sqlx::Error>>;
+        type Result = ResponseFuture<Result<(), LiquidatorError>>;
 
         fn handle(&mut self, msg: UpdateOraclePrice, _ctx: &mut Self::Context) -> Self::Result {
             let pool = self.pool.clone();
-            let (network, protocol) = msg.target.split_once('-').unwrap();
-            let (network, protocol) = (network.to_string(), protocol.to_string());
-
             let fut = async move {
+                let (network, protocol) = split_target(&msg.target)?;
                 update_oracle_price(
                     &pool,
                     msg.price,
+                    msg.block_number,
+                    &msg.reserve.to_string(),
+                    &network,
+                    &protocol,
+                )
+                .await
+            };
+
+            Box::pin(fut)
+        }
+    }
+
+    /// Flags the reserves whose aggregator is overdue, and clears the ones back on time.
+    #[derive(Message, Debug, Clone)]
+    #[rtype(result = "Result<(), LiquidatorError>")]
+    pub struct UpdatePriceStaleness {
+        pub target: String,
+        pub stale: Vec<Address>,
+        pub fresh: Vec<Address>,
+    }
+    impl Handler<UpdatePriceStaleness> for Database {
+        type Result = ResponseFuture<Result<(), LiquidatorError>>;
+
+        fn handle(&mut self, msg: UpdatePriceStaleness, _: &mut Self::Context) -> Self::Result {
+            let pool = self.pool.clone();
+            let fut = async move {
+                let (network, protocol) = split_target(&msg.target)?;
+                update_price_staleness(&pool, &network, &protocol, &msg.stale, &msg.fresh).await
+            };
+
+            Box::pin(fut)
+        }
+    }
+
+    #[derive(Message)]
+    #[rtype(result = "Result<(), LiquidatorError>")]
+    pub struct UpdateReserveFlag {
+        pub target: String,
+        pub reserve: Address,
+        pub update: ReserveFlagUpdate,
+    }
+    impl Handler<UpdateReserveFlag> for Database {
+        type Result = ResponseFuture<Result<(), LiquidatorError>>;
+
+        fn handle(&mut self, msg: UpdateReserveFlag, _ctx: &mut Self::Context) -> Self::Result {
+            let pool = self.pool.clone();
+            let fut = async move {
+                let (network, protocol) = split_target(&msg.target)?;
+                update_reserve_flag(
+                    &pool,
                     &msg.reserve.to_string(),
                     &network,
                     &protocol,
+                    msg.update,
                 )
                 .await
             };
@@ -218,13 +404,13 @@ pub mod handlers {
     }
 
     #[derive(Message)]
-    #[rtype(result = "Result<(), sqlx::Error>")]
+    #[rtype(result = "Result<(), LiquidatorError>")]
     pub struct UpsertReserves {
         pub network_id: String,
         pub reserves: Vec<UpsertReserve>,
     }
     impl Handler<UpsertReserves> for Database {
-        type Result = ResponseFuture<Result<(), sqlx::Error>>;
+        type Result = ResponseFuture<Result<(), LiquidatorError>>;
 
         fn handle(&mut self, msg: UpsertReserves, _: &mut Self::Context) -> Self::Result {
             let pool = self.pool.clone();
@@ -235,39 +421,77 @@ pub mod handlers {
     }
 
     #[derive(Message)]
-    #[rtype(result = "Result<(), sqlx::Error>")]
-    pub struct UpsertReservesStats(pub Vec<UpsertReserveStats>);
+    #[rtype(result = "Result<(), LiquidatorError>")]
+    pub struct UpsertReservesStats {
+        pub target: String,
+        pub reserves: Vec<UpsertReserveStats>,
+    }
     impl Handler<UpsertReservesStats> for Database {
-        type Result = ResponseFuture<Result<(), sqlx::Error>>;
+        type Result = ResponseFuture<Result<(), LiquidatorError>>;
 
         fn handle(&mut self, msg: UpsertReservesStats, _: &mut Self::Context) -> Self::Result {
             let pool = self.pool.clone();
-            let fut = async move { upsert_reserves_stats(&pool, msg.0).await };
+            let fut = async move {
+                let (network, protocol) = split_target(&msg.target)?;
+                upsert_reserves_stats(&pool, &network, &protocol, msg.reserves).await
+            };
+
+            Box::pin(fut)
+        }
+    }
+
+    /// Buffered, written by the next flush along with the other users' latest writes.
+    #[derive(Message)]
+    #[rtype(result = "()")]
+    pub struct UpsertUsersData(pub Vec<UserWrite>);
+    impl Handler<UpsertUsersData> for Database {
+        type Result = ();
+
+        fn handle(&mut self, msg: UpsertUsersData, ctx: &mut Self::Context) -> Self::Result {
+            if self.users.push(msg.0) >= FLUSH_MAX_USERS {
+                self.spawn_flush(ctx);
+            }
+        }
+    }
+
+    /// Writes the buffered users' data, e.g before shutting down.
+    #[derive(Message)]
+    #[rtype(result = "Result<(), LiquidatorError>")]
+    pub struct FlushUsersData;
+    impl Handler<FlushUsersData> for Database {
+        type Result = ResponseFuture<Result<(), LiquidatorError>>;
+
+        fn handle(&mut self, _: FlushUsersData, _: &mut Self::Context) -> Self::Result {
+            let pool = self.pool.clone();
+            let users = self.users.clone();
+            let fut = async move { users.flush(&pool).await.map(|_| ()) };
 
             Box::pin(fut)
         }
     }
 
     #[derive(Message)]
-    #[rtype(result = "Result<(), sqlx::Error>")]
-    pub struct UpsertUserData {
-        pub address: String,
-        pub protocol_details_id: i32,
-        pub health_factor: f64,
-        pub positions: Vec<(Address, f64, f64)>, // (token_address, supply amount, borrow amount)
+    #[rtype(result = "Result<(), LiquidatorError>")]
+    pub struct UpdateUserCollateral {
+        pub target: String,
+        pub user_address: Address,
+        pub reserve: Address,
+        pub enabled: bool,
     }
-    impl Handler<UpsertUserData> for Database {
-        type Result = ResponseFuture<Result<(), sqlx::Error>>;
+    impl Handler<UpdateUserCollateral> for Database {
+        type Result = ResponseFuture<Result<(), LiquidatorError>>;
 
-        fn handle(&mut self, msg: UpsertUserData, _: &mut Self::Context) -> Self::Result {
+        fn handle(&mut self, msg: UpdateUserCollateral, _: &mut Self::Context) -> Self::Result {
             let pool = self.pool.clone();
             let fut = async move {
-                upsert_user_data(
+                let (network, protocol) = split_target(&msg.target)?;
+                update_user_collateral(
                     &pool,
-                    &msg.address,
-                    msg.protocol_details_id,
-                    msg.positions,
-                    msg.health_factor,
+                    &network,
+                    &protocol,
+                    &msg.user_address.to_string(),
+                    &msg.reserve.to_string(),
+                    msg.enabled,
                 )
                 .await
             };
@@ -277,13 +501,13 @@ pub mod handlers {
     }
 
     #[derive(Message)]
-    #[rtype(result = "Result<(), sqlx::Error>")]
+    #[rtype(result = "Result<(), LiquidatorError>")]
     pub struct UpsertUsersStats {
         pub users: HashMap<Address, f64>,
         pub protocol_details_id: i32,
     }
     impl Handler<UpsertUsersStats> for Database {
-        type Result = ResponseFuture<Result<(), sqlx::Error>>;
+        type Result = ResponseFuture<Result<(), LiquidatorError>>;
 
         fn handle(&mut self, msg: UpsertUsersStats, _: &mut Self::Context) -> Self::Result {
             let pool = self.pool.clone();
@@ -294,24 +518,141 @@ pub mod handlers {
         }
     }
 
+    /// Health factors of users evaluated at `block_number`, appended to their history.
+    #[derive(Message, Debug, Clone)]
+    #[rtype(result = "Result<(), LiquidatorError>")]
+    pub struct InsertHealthFactors {
+        pub protocol_details_id: i32,
+        pub block_number: u64,
+        pub health_factors: Vec<(Address, f64)>,
+    }
+    impl Handler<InsertHealthFactors> for Database {
+        type Result = ResponseFuture<Result<(), LiquidatorError>>;
+
+        fn handle(&mut self, msg: InsertHealthFactors, _: &mut Self::Context) -> Self::Result {
+            let pool = self.pool.clone();
+            let fut = async move {
+                insert_health_factors(
+                    &pool,
+                    msg.protocol_details_id,
+                    msg.block_number,
+                    &msg.health_factors,
+                )
+                .await
+            };
+
+            Box::pin(fut)
+        }
+    }
+
+    #[derive(Message)]
+    #[rtype(result = "Result<Vec<HealthFactorPoint>, LiquidatorError>")]
+    pub struct GetHealthFactorHistory {
+        pub target: String,
+        pub user: Address,
+        pub range: HistoryRange,
+    }
+    impl Handler<GetHealthFactorHistory> for Database {
+        type Result = ResponseFuture<Result<Vec<HealthFactorPoint>, LiquidatorError>>;
+
+        fn handle(&mut self, msg: GetHealthFactorHistory, _: &mut Self::Context) -> Self::Result {
+            let pool = self.pool.clone();
+            let fut = async move {
+                let (network, protocol) = split_target(&msg.target)?;
+                get_health_factor_history(&pool, &network, &protocol, msg.user, &msg.range).await
+            };
+
+            Box::pin(fut)
+        }
+    }
+
+    #[derive(Message)]
+    #[rtype(result = "Result<Vec<PricePoint>, LiquidatorError>")]
+    pub struct GetPriceHistory {
+        pub target: String,
+        pub reserve: Address,
+        pub range: HistoryRange,
+    }
+    impl Handler<GetPriceHistory> for Database {
+        type Result = ResponseFuture<Result<Vec<PricePoint>, LiquidatorError>>;
+
+        fn handle(&mut self, msg: GetPriceHistory, _: &mut Self::Context) -> Self::Result {
+            let pool = self.pool.clone();
+            let fut = async move {
+                let (network, protocol) = split_target(&msg.target)?;
+                get_price_history(&pool, &network, &protocol, msg.reserve, &msg.range).await
+            };
+
+            Box::pin(fut)
+        }
+    }
+
+    #[derive(Message)]
+    #[rtype(result = "Result<Vec<ReserveStatsPoint>, LiquidatorError>")]
+    pub struct GetReserveStatsHistory {
+        pub target: String,
+        pub reserve: Address,
+        pub range: HistoryRange,
+    }
+    impl Handler<GetReserveStatsHistory> for Database {
+        type Result = ResponseFuture<Result<Vec<ReserveStatsPoint>, LiquidatorError>>;
+
+        fn handle(&mut self, msg: GetReserveStatsHistory, _: &mut Self::Context) -> Self::Result {
+            let pool = self.pool.clone();
+            let fut = async move {
+                let (network, protocol) = split_target(&msg.target)?;
+                get_reserve_stats_history(&pool, &network, &protocol, msg.reserve, &msg.range).await
+            };
+
+            Box::pin(fut)
+        }
+    }
+
     #[derive(Message)]
-    #[rtype(result = "Result<(), sqlx::Error>")]
+    #[rtype(result = "Result<(), LiquidatorError>")]
     pub struct InsertLiquidationCall {
         pub call: contracts::aave_v3::PoolContract::LiquidationCall,
         pub protocol_details_id: i32,
+        pub block_number: u64,
+        pub tx_hash: TxHash,
+        pub log_index: u64,
+        pub gas_price: Option<f64>, // gwei
+        pub ours: bool,
+        pub missed: bool,
     }
     impl Handler<InsertLiquidationCall> for Database {
-        type Result = ResponseFuture<Result<(), sqlx::Error>>;
+        type Result = ResponseFuture<Result<(), LiquidatorError>>;
 
         fn handle(&mut self, msg: InsertLiquidationCall, _: &mut Self::Context) -> Self::Result {
+            let pool = self.pool.clone();
+            let fut = async move { insert_liquidation_call(&pool, &msg).await };
+
+            Box::pin(fut)
+        }
+    }
+
+    #[derive(Message)]
+    #[rtype(result = "Result<(), LiquidatorError>")]
+    pub struct InsertSkippedLiquidation {
+        pub protocol_details_id: i32,
+        pub user_address: Address,
+        pub collateral_asset: Address,
+        pub debt_asset: Address,
+        pub reason: String,
+    }
+    impl Handler<InsertSkippedLiquidation> for Database {
+        type Result = ResponseFuture<Result<(), LiquidatorError>>;
+
+        fn handle(&mut self, msg: InsertSkippedLiquidation, _: &mut Self::Context) -> Self::Result {
             let pool = self.pool.clone();
             let fut = async move {
-                insert_liquidation_call(
+                insert_skipped_liquidation(
                     &pool,
                     msg.protocol_details_id,
-                    &msg.call.user.to_string(),
-                    &msg.call.collateralAsset.to_string(),
-                    &msg.call.debtAsset.to_string(),
+                    &msg.user_address.to_string(),
+                    &msg.collateral_asset.to_string(),
+                    &msg.debt_asset.to_string(),
+                    &msg.reason,
                 )
                 .await
             };
@@ -319,13 +660,65 @@ pub mod handlers {
             Box::pin(fut)
         }
     }
+
+    #[derive(Message, Debug, Clone)]
+    #[rtype(result = "Result<(), LiquidatorError>")]
+    pub struct InsertLiquidationAttempt {
+        pub protocol_details_id: i32,
+        pub liquidation: Liquidation,
+        pub strategy: Strategy,
+        // at decision time
+        pub health_factor: f64,
+        pub estimated_profit_usd: f64,
+        pub status: AttemptStatus,
+        // `None` unless the transaction was mined
+        pub receipt: Option<LiquidationReceipt>,
+        pub revert_reason: Option<String>,
+    }
+    impl Handler<InsertLiquidationAttempt> for Database {
+        type Result = ResponseFuture<Result<(), LiquidatorError>>;
+
+        fn handle(&mut self, msg: InsertLiquidationAttempt, _: &mut Self::Context) -> Self::Result {
+            let pool = self.pool.clone();
+            let fut = async move { insert_liquidation_attempt(&pool, &msg).await };
+
+            Box::pin(fut)
+        }
+    }
+}
+
+/// `<network>-<protocol>`, e.g `ethereum-aave_v3`.
+pub fn split_target(target: &str) -> Result<(String, String), LiquidatorError> {
+    target
+        .split_once('-')
+        .map(|(network, protocol)| (network.to_string(), protocol.to_string()))
+        .ok_or(LiquidatorError::Config(format!(
+            "invalid target {}, expected <network>-<protocol>",
+            target
+        )))
+}
+
+fn parse_address(address: &str) -> Result<Address, LiquidatorError> {
+    Address::from_str(address)
+        .map_err(|e| LiquidatorError::Decode(format!("invalid address {}: {}", address, e)))
+}
+
+fn to_big_decimal(value: impl ToString) -> Result<BigDecimal, LiquidatorError> {
+    let value = value.to_string();
+    BigDecimal::from_str(&value)
+        .map_err(|e| LiquidatorError::Decode(format!("invalid number {}: {}", value, e)))
+}
+
+fn parse_u256(value: &str) -> Result<U256, LiquidatorError> {
+    U256::from_str(value)
+        .map_err(|e| LiquidatorError::Decode(format!("invalid uint256 {}: {}", value, e)))
 }
 
 pub async fn get_protocol_contracts(
     pool: &PgPool,
     network: &str,
     protocol: &str,
-) -> Result<HashMap<String, Address>, sqlx::Error> {
+) -> Result<HashMap<String, Address>, LiquidatorError> {
     const QUERY: &str = r#"
                 SELECT pc.name, pc.address
                 FROM protocols_details pd
@@ -338,23 +731,21 @@ pub async fn get_protocol_contracts(
         .bind(protocol)
         .fetch_all(pool)
         .await?;
-    let mapping: HashMap<String, Address> = rows
-        .into_iter()
+    rows.into_iter()
         .map(|row| {
-            (
+            Ok((
                 row.get::<String, _>("name"),
-                Address::from_str(&row.get::<String, _>("address")).unwrap(),
-            )
+                parse_address(&row.get::<String, _>("address"))?,
+            ))
         })
-        .collect();
-    Ok(mapping)
+        .collect()
 }
 
 pub async fn get_protocol_details_id(
     pool: &PgPool,
     network: &str,
     protocol: &str,
-) -> Result<i32, sqlx::Error> {
+) -> Result<i32, LiquidatorError> {
     const QUERY: &str =
         "SELECT pd.id FROM protocols_details pd WHERE pd.network_id = $1 AND pd.protocol_id = $2";
     let row = sqlx::query(QUERY)
@@ -370,7 +761,7 @@ pub async fn get_protocol_deploy_block(
     pool: &PgPool,
     network: &str,
     protocol: &str,
-) -> Result<Option<i64>, sqlx::Error> {
+) -> Result<Option<i64>, LiquidatorError> {
     const QUERY: &str = r#"
             SELECT deployed_block
             FROM protocols_details
@@ -386,41 +777,68 @@ pub async fn get_protocol_deploy_block(
     Ok(row.map(|r| r.get("deployed_block")))
 }
 
-pub async fn get_aggregator_mapping(pool: &PgPool, target: String) -> HashMap<Address, Address> {
+pub async fn get_aggregator_mapping(
+    pool: &PgPool,
+    target: String,
+) -> Result<HashMap<Address, Vec<Address>>, LiquidatorError> {
     const QUERY: &str = r#"
             SELECT ar.aggregator_addr, ar.reserve
             FROM aavev3_reserves ar
             JOIN protocols_details pd ON pd.id = ar.protocol_details_id
             WHERE ar.aggregator_addr IS NOT NULL AND ar.reserve IS NOT NULL AND pd.network_id = $1 AND pd.protocol_id = $2"#;
 
-    let (network, protocol) = target.split_once('-').unwrap();
-    let (network, protocol) = (network.to_string(), protocol.to_string());
+    let (network, protocol) = split_target(&target)?;
 
     let r = sqlx::query(QUERY)
         .bind(network)
         .bind(protocol)
         .fetch_all(pool)
-        .await
-        .unwrap();
-    let mapping: HashMap<Address, Address> = r
-        .into_iter()
-        .map(|row| {
-            (
-                row.get::<String, _>("aggregator_addr"),
-                row.get::<String, _>("reserve"),
-            )
-        })
-        .map(|(aggregator_addr, reserve)| {
-            (
-                Address::from_str(&aggregator_addr).unwrap(),
-                Address::from_str(&reserve).unwrap(),
-            )
-        })
-        .collect();
+        .await?;
+    let mut mapping: HashMap<Address, Vec<Address>> = HashMap::new();
+    for row in r {
+        let aggregator_addr = parse_address(&row.get::<String, _>("aggregator_addr"))?;
+        let reserve = parse_address(&row.get::<String, _>("reserve"))?;
+        mapping.entry(aggregator_addr).or_default().push(reserve);
+    }
+
+    Ok(mapping)
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct ReserveAggregator {
+    pub reserve: String,
+    pub symbol: String,
+    pub aggregator_addr: Option<String>,
+}
 
-    mapping
+/// Finds a reserve by address or symbol, along with the aggregator its price is followed from.
+pub async fn get_reserve_aggregator(
+    pool: &PgPool,
+    network: &str,
+    protocol: &str,
+    reserve: &str,
+) -> Result<Option<ReserveAggregator>, LiquidatorError> {
+    const QUERY: &str = r#"
+        SELECT ar.reserve, TRIM(e.symbol) AS symbol, ar.aggregator_addr
+        FROM aavev3_reserves ar
+        JOIN protocols_details pd ON pd.id = ar.protocol_details_id
+        JOIN erc20_details ed ON ed.address = ar.reserve AND ed.network_id = pd.network_id
+        JOIN erc20 e ON e.id = ed.erc20_id
+        WHERE pd.network_id = $1
+          AND pd.protocol_id = $2
+          AND (LOWER(ar.reserve) = LOWER($3) OR UPPER(TRIM(e.symbol)) = UPPER($3))
+    "#;
+
+    sqlx::query_as::<_, ReserveAggregator>(QUERY)
+        .bind(network)
+        .bind(protocol)
+        .bind(reserve)
+        .fetch_optional(pool)
+        .await
+        .map_err(LiquidatorError::from)
 }
 
+#[allow(dead_code)]
 #[derive(Clone, Debug, FromRow)]
 pub struct Reserve {
     pub reserve: Address,
@@ -430,12 +848,20 @@ pub struct Reserve {
     pub flashloan_enabled: bool,
     pub oracle_addr: Address,
     pub aggregator_addr: Option<Address>,
+    // `None` falls back to `--oracle-heartbeat-secs` / `--oracle-deviation-bps`
+    pub oracle_heartbeat_secs: Option<i32>,
+    pub oracle_deviation_bps: Option<i32>,
+    pub symbol: String,
     pub decimals: i32,
 
+    #[sqlx(flatten)]
+    pub flags: ReserveFlags,
+
     #[sqlx(flatten)]
     pub stats: ReserveStats,
 }
 
+#[allow(dead_code)]
 #[derive(Clone, Debug, FromRow)]
 pub struct ReserveStats {
     pub liquidity_index: f64,
@@ -443,58 +869,275 @@ pub struct ReserveStats {
     pub variable_borrow_rate: f64,
     pub variable_borrow_index: f64,
     pub price_usd: f64,
+    // the aggregator missed its heartbeat, see `actors::Monitor`
+    pub price_stale: bool,
     pub updated_at: PrimitiveDateTime,
 }
 
 pub async fn update_oracle_price(
     pool: &PgPool,
     price: f64,
+    block_number: Option<u64>,
     reserve: &str,
     network: &str,
     protocol: &str,
-) -> Result<(), sqlx::Error> {
+) -> Result<(), LiquidatorError> {
     const QUERY: &str = r#"
         UPDATE aavev3_reserves_stats ars
-        SET price_usd = $1, updated_at = NOW()
+        SET price_usd = $1,
+            price_updated_block = COALESCE($5, ars.price_updated_block),
+            price_stale = FALSE,
+            price_stale_since = NULL,
+            updated_at = NOW()
         FROM aavev3_reserves ar
         JOIN protocols_details pd ON ar.protocol_details_id = pd.id
-        WHERE ars.reserve = ar.reserve
+        WHERE ars.protocol_details_id = ar.protocol_details_id
+          AND ars.reserve = ar.reserve
           AND ar.reserve = $2
           AND pd.network_id = $3
           AND pd.protocol_id = $4
     "#;
+    const INSERT_HISTORY: &str = r#"
+        INSERT INTO aavev3_prices_history (protocol_details_id, reserve, block_number, price_usd)
+        SELECT ar.protocol_details_id, ar.reserve, $5, $1
+        FROM aavev3_reserves ar
+        JOIN protocols_details pd ON ar.protocol_details_id = pd.id
+        WHERE ar.reserve = $2
+          AND pd.network_id = $3
+          AND pd.protocol_id = $4
+        -- replayed logs
+        ON CONFLICT (protocol_details_id, reserve, block_number) DO UPDATE SET
+            price_usd = EXCLUDED.price_usd
+    "#;
+
+    let mut tx = pool.begin().await?;
     sqlx::query(QUERY)
         .bind(price)
         .bind(reserve)
         .bind(network)
         .bind(protocol)
-        .execute(pool)
+        .bind(block_number.map(|n| n as i64))
+        .execute(&mut *tx)
         .await?;
+    if let Some(block_number) = block_number {
+        sqlx::query(INSERT_HISTORY)
+            .bind(price)
+            .bind(reserve)
+            .bind(network)
+            .bind(protocol)
+            .bind(block_number as i64)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
     Ok(())
 }
 
-pub async fn upsert_reserves_stats(
+pub async fn update_price_staleness(
     pool: &PgPool,
+    network: &str,
+    protocol: &str,
+    stale: &[Address],
+    fresh: &[Address],
+) -> Result<(), LiquidatorError> {
+    // keeps `price_stale_since` at the first check which found the feed overdue
+    const QUERY: &str = r#"
+        UPDATE aavev3_reserves_stats ars
+        SET price_stale = r.stale,
+            price_stale_since = CASE
+                WHEN NOT r.stale THEN NULL
+                ELSE COALESCE(ars.price_stale_since, NOW() AT TIME ZONE 'UTC')
+            END
+        FROM UNNEST($1::VARCHAR[], $2::BOOLEAN[]) AS r(reserve, stale),
+            aavev3_reserves ar
+        JOIN protocols_details pd ON ar.protocol_details_id = pd.id
+        WHERE ars.reserve = r.reserve
+          AND ar.protocol_details_id = ars.protocol_details_id
+          AND ar.reserve = ars.reserve
+          AND pd.network_id = $3
+          AND pd.protocol_id = $4
+    "#;
+
+    let (reserves, flags): (Vec<String>, Vec<bool>) = stale
+        .iter()
+        .map(|r| (r.to_string(), true))
+        .chain(fresh.iter().map(|r| (r.to_string(), false)))
+        .unzip();
+
+    sqlx::query(QUERY)
+        .bind(reserves)
+        .bind(flags)
+        .bind(network)
+        .bind(protocol)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn update_reserve_flag(
+    pool: &PgPool,
+    reserve: &str,
+    network: &str,
+    protocol: &str,
+    update: ReserveFlagUpdate,
+) -> Result<(), LiquidatorError> {
+    let (column, value) = match update {
+        ReserveFlagUpdate::Active(v) => ("is_active", v),
+        ReserveFlagUpdate::Frozen(v) => ("is_frozen", v),
+        ReserveFlagUpdate::Paused(v) => ("is_paused", v),
+        ReserveFlagUpdate::SiloedBorrowing(v) => ("is_siloed_borrowing", v),
+        ReserveFlagUpdate::BorrowingEnabled(v) => ("borrowing_enabled", v),
+        ReserveFlagUpdate::FlashloanEnabled(v) => ("flashloan_enabled", v),
+        ReserveFlagUpdate::BorrowableInIsolation(v) => ("borrowable_in_isolation", v),
+        ReserveFlagUpdate::DebtCeiling(v) => {
+            return update_reserve_column(pool, "debt_ceiling", v, reserve, network, protocol).await
+        }
+        ReserveFlagUpdate::CollateralConfiguration {
+            liquidation_threshold,
+            liquidation_bonus,
+        } => {
+            const QUERY: &str = r#"
+                UPDATE aavev3_reserves ar
+                SET liquidation_threshold = $1,
+                    liquidation_bonus = $2,
+                    usage_as_collateral_enabled = $1::DOUBLE PRECISION > 0
+                FROM protocols_details pd
+                WHERE ar.reserve = $3
+                  AND ar.protocol_details_id = pd.id
+                  AND pd.network_id = $4
+                  AND pd.protocol_id = $5
+            "#;
+            sqlx::query(QUERY)
+                .bind(liquidation_threshold)
+                .bind(liquidation_bonus)
+                .bind(reserve)
+                .bind(network)
+                .bind(protocol)
+                .execute(pool)
+                .await?;
+            return Ok(());
+        }
+    };
+
+    update_reserve_column(pool, column, value, reserve, network, protocol).await
+}
+
+async fn update_reserve_column<T>(
+    pool: &PgPool,
+    column: &str,
+    value: T,
+    reserve: &str,
+    network: &str,
+    protocol: &str,
+) -> Result<(), LiquidatorError>
+where
+    T: for<'q> sqlx::Encode<'q, sqlx::Postgres> + sqlx::Type<sqlx::Postgres> + Send,
+{
+    // `column` never comes from user input, see `update_reserve_flag`
+    let query = format!(
+        r#"
+        UPDATE aavev3_reserves ar
+        SET {column} = $1
+        FROM protocols_details pd
+        WHERE ar.reserve = $2
+          AND ar.protocol_details_id = pd.id
+          AND pd.network_id = $3
+          AND pd.protocol_id = $4
+    "#
+    );
+    sqlx::query(&query)
+        .bind(value)
+        .bind(reserve)
+        .bind(network)
+        .bind(protocol)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn upsert_reserves_stats(
+    pool: &PgPool,
+    network: &str,
+    protocol: &str,
     reserves: Vec<UpsertReserveStats>,
-) -> Result<(), sqlx::Error> {
+) -> Result<(), LiquidatorError> {
     const QUERY: &str = r#"
-        INSERT INTO aavev3_reserves_stats (reserve, liquidity_rate, variable_borrow_rate, liquidity_index, variable_borrow_index)
-        VALUES ($1, $2, $3, $4, $5)
-        ON CONFLICT (reserve) DO UPDATE SET
-            liquidity_rate = $2,
-            variable_borrow_rate = $3,
-            liquidity_index = $4,
-            variable_borrow_index = $5,
+        INSERT INTO aavev3_reserves_stats (
+            protocol_details_id, reserve, liquidity_rate, variable_borrow_rate, liquidity_index,
+            variable_borrow_index
+        )
+        SELECT pd.id, s.*
+        FROM UNNEST(
+            $1::VARCHAR[], $2::DOUBLE PRECISION[], $3::DOUBLE PRECISION[],
+            $4::DOUBLE PRECISION[], $5::DOUBLE PRECISION[]
+        ) AS s
+        JOIN protocols_details pd ON pd.network_id = $6 AND pd.protocol_id = $7
+        ON CONFLICT (protocol_details_id, reserve) DO UPDATE SET
+            liquidity_rate = EXCLUDED.liquidity_rate,
+            variable_borrow_rate = EXCLUDED.variable_borrow_rate,
+            liquidity_index = EXCLUDED.liquidity_index,
+            variable_borrow_index = EXCLUDED.variable_borrow_index,
             updated_at = NOW()
     "#;
+    const INSERT_HISTORY: &str = r#"
+        INSERT INTO aavev3_reserves_stats_history (
+            protocol_details_id, reserve, block_number, liquidity_rate, variable_borrow_rate,
+            liquidity_index, variable_borrow_index
+        )
+        SELECT ar.protocol_details_id, h.reserve, h.block_number, h.liquidity_rate,
+            h.variable_borrow_rate, h.liquidity_index, h.variable_borrow_index
+        FROM UNNEST(
+            $1::VARCHAR[], $2::DOUBLE PRECISION[], $3::DOUBLE PRECISION[],
+            $4::DOUBLE PRECISION[], $5::DOUBLE PRECISION[], $6::BIGINT[]
+        ) AS h (reserve, liquidity_rate, variable_borrow_rate, liquidity_index, variable_borrow_index, block_number)
+        JOIN protocols_details pd ON pd.network_id = $7 AND pd.protocol_id = $8
+        JOIN aavev3_reserves ar ON ar.protocol_details_id = pd.id AND ar.reserve = h.reserve
+        WHERE h.block_number IS NOT NULL
+        -- replayed logs
+        ON CONFLICT (protocol_details_id, reserve, block_number) DO UPDATE SET
+            liquidity_rate = EXCLUDED.liquidity_rate,
+            variable_borrow_rate = EXCLUDED.variable_borrow_rate,
+            liquidity_index = EXCLUDED.liquidity_index,
+            variable_borrow_index = EXCLUDED.variable_borrow_index
+    "#;
+    // a row can't be upserted twice by the same statement, the last update wins
+    let reserves = reserves
+        .into_iter()
+        .map(|reserve| (reserve.reserve.clone(), reserve))
+        .collect::<HashMap<_, _>>();
+
+    let mut columns = (Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    let mut block_numbers = Vec::new();
+    for (address, reserve) in reserves {
+        columns.0.push(address);
+        columns.1.push(reserve.liquidity_rate);
+        columns.2.push(reserve.variable_borrow_rate);
+        columns.3.push(reserve.liquidity_index);
+        columns.4.push(reserve.variable_borrow_index);
+        block_numbers.push(reserve.block_number.map(|n| n as i64));
+    }
+
     let mut tx = pool.begin().await?;
-    for reserve in reserves {
-        sqlx::query(QUERY)
-            .bind(&reserve.reserve)
-            .bind(reserve.liquidity_rate)
-            .bind(reserve.variable_borrow_rate)
-            .bind(reserve.liquidity_index)
-            .bind(reserve.variable_borrow_index)
+    sqlx::query(QUERY)
+        .bind(&columns.0)
+        .bind(&columns.1)
+        .bind(&columns.2)
+        .bind(&columns.3)
+        .bind(&columns.4)
+        .bind(network)
+        .bind(protocol)
+        .execute(&mut *tx)
+        .await?;
+    if block_numbers.iter().any(Option::is_some) {
+        sqlx::query(INSERT_HISTORY)
+            .bind(&columns.0)
+            .bind(&columns.1)
+            .bind(&columns.2)
+            .bind(&columns.3)
+            .bind(&columns.4)
+            .bind(&block_numbers)
+            .bind(network)
+            .bind(protocol)
             .execute(&mut *tx)
             .await?;
     }
@@ -506,11 +1149,12 @@ pub async fn get_reserves_liquidity_indices(
     pool: &PgPool,
     network: &str,
     protocol: &str,
-) -> Result<HashMap<String, (f64, f64)>, sqlx::Error> {
+) -> Result<HashMap<String, (f64, f64)>, LiquidatorError> {
     const QUERY: &str = r#"
         SELECT rs.reserve, rs.liquidity_index, rs.variable_borrow_index
         FROM aavev3_reserves_stats rs
-        JOIN aavev3_reserves r ON rs.reserve = r.reserve
+        JOIN aavev3_reserves r
+            ON rs.protocol_details_id = r.protocol_details_id AND rs.reserve = r.reserve
         JOIN protocols_details pd ON r.protocol_details_id = pd.id
         WHERE pd.network_id = $1 AND pd.protocol_id = $2
     "#;
@@ -538,7 +1182,7 @@ pub async fn upsert_reserves(
     pool: &PgPool,
     network_id: &str,
     reserves: Vec<UpsertReserve>,
-) -> Result<(), sqlx::Error> {
+) -> Result<(), LiquidatorError> {
     const INSERT_ERC20: &str = r#"
         INSERT INTO erc20 (symbol, name)
         VALUES ($1, $2)
@@ -548,30 +1192,40 @@ pub async fn upsert_reserves(
     const UPSERT_ERC20_DETAILS: &str = r#"
         INSERT INTO erc20_details (address, decimals, erc20_id, network_id)
         VALUES ($1, $2, $3, $4)
-        ON CONFLICT (address) DO UPDATE SET
+        ON CONFLICT (network_id, address) DO UPDATE SET
             decimals = $2,
-            erc20_id = $3,
-            network_id = $4
+            erc20_id = $3
     "#;
     const UPSERT_RESERVES: &str = r#"
         INSERT INTO aavev3_reserves (
             reserve, protocol_details_id, liquidation_threshold, liquidation_bonus,
-            flashloan_enabled, oracle_addr, aggregator_addr
+            flashloan_enabled, oracle_addr, aggregator_addr,
+            is_active, is_frozen, is_paused, is_siloed_borrowing, borrowing_enabled,
+            usage_as_collateral_enabled, borrowable_in_isolation, debt_ceiling
         )
-        VALUES ($1, $2, $3, $4, $5, $6, $7)
-        ON CONFLICT (reserve) DO UPDATE SET
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+        ON CONFLICT (protocol_details_id, reserve) DO UPDATE SET
             liquidation_threshold = $3,
             liquidation_bonus = $4,
             flashloan_enabled = $5,
             oracle_addr = $6,
-            aggregator_addr = $7
+            aggregator_addr = $7,
+            is_active = $8,
+            is_frozen = $9,
+            is_paused = $10,
+            is_siloed_borrowing = $11,
+            borrowing_enabled = $12,
+            usage_as_collateral_enabled = $13,
+            borrowable_in_isolation = $14,
+            debt_ceiling = $15
     "#;
     const UPSERT_STATS: &str = r#"
         INSERT INTO aavev3_reserves_stats (
-            reserve, liquidity_rate, variable_borrow_rate, liquidity_index, variable_borrow_index, price_usd
+            reserve, liquidity_rate, variable_borrow_rate, liquidity_index, variable_borrow_index, price_usd,
+            protocol_details_id
         )
-        VALUES ($1, $2, $3, $4, $5, $6)
-        ON CONFLICT (reserve) DO UPDATE SET
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (protocol_details_id, reserve) DO UPDATE SET
             liquidity_rate = $2,
             variable_borrow_rate = $3,
             liquidity_index = $4,
@@ -605,6 +1259,14 @@ pub async fn upsert_reserves(
             .bind(reserve.flashloan_enabled)
             .bind(&reserve.oracle_addr)
             .bind(&reserve.aggregator_addr)
+            .bind(reserve.flags.is_active)
+            .bind(reserve.flags.is_frozen)
+            .bind(reserve.flags.is_paused)
+            .bind(reserve.flags.is_siloed_borrowing)
+            .bind(reserve.flags.borrowing_enabled)
+            .bind(reserve.flags.usage_as_collateral_enabled)
+            .bind(reserve.flags.borrowable_in_isolation)
+            .bind(reserve.flags.debt_ceiling)
             .execute(&mut *tx)
             .await?;
 
@@ -615,6 +1277,7 @@ pub async fn upsert_reserves(
             .bind(reserve.stats.liquidity_index)
             .bind(reserve.stats.variable_borrow_index)
             .bind(reserve.price_usd)
+            .bind(reserve.protocol_details_id)
             .execute(&mut *tx)
             .await?;
     }
@@ -622,80 +1285,170 @@ pub async fn upsert_reserves(
     Ok(())
 }
 
-pub async fn upsert_user_data(
-    pool: &PgPool,
-    address: &str,
-    protocol_details_id: i32,
-    positions: Vec<(Address, f64, f64)>,
-    health_factor: f64,
-) -> Result<(), sqlx::Error> {
+/// Writes the users, their positions and health factors in a single transaction, one statement
+/// per table. Positions the users no longer have are deleted.
+pub async fn upsert_users_data(pool: &PgPool, users: &[UserWrite]) -> Result<(), LiquidatorError> {
     const UPSERT_USERS: &str = r#"
         INSERT INTO aavev3_users (address, protocol_details_id)
-        VALUES ($1, $2)
-        ON CONFLICT (address) DO UPDATE SET protocol_details_id = $2
+        SELECT * FROM UNNEST($1::VARCHAR[], $2::INTEGER[])
+        ON CONFLICT DO NOTHING
     "#;
     const DELETE_POSITIONS: &str = r#"
-        DELETE FROM aavev3_positions
-        WHERE user_address = $1 AND reserve != ANY($2::text[])
+        DELETE FROM aavev3_positions ap
+        USING UNNEST($1::VARCHAR[], $2::INTEGER[]) AS u (address, protocol_details_id)
+        WHERE ap.protocol_details_id = u.protocol_details_id
+        AND ap.user_address = u.address
+        AND NOT EXISTS (
+            SELECT 1
+            FROM UNNEST($3::VARCHAR[], $4::INTEGER[], $5::VARCHAR[])
+                AS p (user_address, protocol_details_id, reserve)
+            WHERE p.protocol_details_id = ap.protocol_details_id
+              AND p.user_address = ap.user_address
+              AND p.reserve = ap.reserve
+        )
     "#;
     const UPSERT_POSITIONS: &str = r#"
-        INSERT INTO aavev3_positions (user_address, reserve, supply_amount, borrow_amount)
-        VALUES ($1, $2, $3, $4)
-        ON CONFLICT (user_address, reserve)
-        DO UPDATE SET
-            supply_amount = $3,
-            borrow_amount = $4,
+        INSERT INTO aavev3_positions (
+            user_address, protocol_details_id, reserve, supply_amount, borrow_amount, collateral_enabled
+        )
+        SELECT * FROM UNNEST(
+            $1::VARCHAR[], $2::INTEGER[], $3::VARCHAR[], $4::DOUBLE PRECISION[],
+            $5::DOUBLE PRECISION[], $6::BOOLEAN[]
+        )
+        ON CONFLICT (protocol_details_id, user_address, reserve) DO UPDATE SET
+            supply_amount = EXCLUDED.supply_amount,
+            borrow_amount = EXCLUDED.borrow_amount,
+            collateral_enabled = EXCLUDED.collateral_enabled,
             updated_at = NOW()
     "#;
     const UPSERT_STATS: &str = r#"
-        INSERT INTO aavev3_users_stats (user_address, health_factor)
-        VALUES ($1, $2)
-        ON CONFLICT (user_address)
-        DO UPDATE SET health_factor = $2, updated_at = NOW()
+        INSERT INTO aavev3_users_stats (user_address, protocol_details_id, health_factor)
+        SELECT * FROM UNNEST($1::VARCHAR[], $2::INTEGER[], $3::DOUBLE PRECISION[])
+        ON CONFLICT (protocol_details_id, user_address) DO UPDATE SET
+            health_factor = EXCLUDED.health_factor,
+            updated_at = NOW()
     "#;
 
+    if users.is_empty() {
+        return Ok(());
+    }
+    let rows = UserRows::from(users);
+
     let mut tx = pool.begin().await?;
     sqlx::query(UPSERT_USERS)
-        .bind(address)
-        .bind(protocol_details_id)
+        .bind(&rows.users)
+        .bind(&rows.protocol_details_ids)
         .execute(&mut *tx)
         .await?;
-
-    let reserves: Vec<String> = positions
-        .iter()
-        .map(|(r, _, _)| r.to_string().clone())
-        .collect();
     sqlx::query(DELETE_POSITIONS)
-        .bind(address)
-        .bind(&reserves)
+        .bind(&rows.users)
+        .bind(&rows.protocol_details_ids)
+        .bind(&rows.position_users)
+        .bind(&rows.position_protocol_details_ids)
+        .bind(&rows.position_reserves)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(UPSERT_POSITIONS)
+        .bind(&rows.position_users)
+        .bind(&rows.position_protocol_details_ids)
+        .bind(&rows.position_reserves)
+        .bind(&rows.supply_amounts)
+        .bind(&rows.borrow_amounts)
+        .bind(&rows.collaterals)
         .execute(&mut *tx)
         .await?;
-
-    for (token_address, supply_amount, borrow_amount) in positions {
-        sqlx::query(UPSERT_POSITIONS)
-            .bind(address)
-            .bind(token_address.to_string())
-            .bind(supply_amount)
-            .bind(borrow_amount)
-            .execute(&mut *tx)
-            .await?;
-    }
-
     sqlx::query(UPSERT_STATS)
-        .bind(address)
-        .bind(health_factor)
+        .bind(&rows.users)
+        .bind(&rows.protocol_details_ids)
+        .bind(&rows.health_factors)
         .execute(&mut *tx)
         .await?;
-
     tx.commit().await?;
     Ok(())
 }
 
+/// Users' writes as columns bound to `UNNEST`, at most one row per user and per position.
+#[derive(Debug, Default, PartialEq)]
+struct UserRows {
+    users: Vec<String>,
+    protocol_details_ids: Vec<i32>,
+    health_factors: Vec<f64>,
+    position_users: Vec<String>,
+    position_protocol_details_ids: Vec<i32>,
+    position_reserves: Vec<String>,
+    supply_amounts: Vec<f64>,
+    borrow_amounts: Vec<f64>,
+    collaterals: Vec<bool>,
+}
+
+impl From<&[UserWrite]> for UserRows {
+    fn from(writes: &[UserWrite]) -> Self {
+        // the last write of a user wins, `ON CONFLICT` can't update a row twice
+        let mut latest: HashMap<(i32, Address), &UserWrite> = HashMap::new();
+        for write in writes {
+            latest.insert(write.key(), write);
+        }
+
+        let mut rows = UserRows::default();
+        for ((_, address), write) in latest {
+            let address = address.to_string();
+            rows.users.push(address.clone());
+            rows.protocol_details_ids.push(write.protocol_details_id);
+            rows.health_factors.push(write.health_factor);
+
+            let positions = write
+                .positions
+                .iter()
+                .map(|position| (position.0, position))
+                .collect::<HashMap<_, _>>();
+            for (reserve, (_, supply_amount, borrow_amount, collateral_enabled)) in positions {
+                rows.position_users.push(address.clone());
+                rows.position_protocol_details_ids
+                    .push(write.protocol_details_id);
+                rows.position_reserves.push(reserve.to_string());
+                rows.supply_amounts.push(*supply_amount);
+                rows.borrow_amounts.push(*borrow_amount);
+                rows.collaterals.push(*collateral_enabled);
+            }
+        }
+        rows
+    }
+}
+
+pub async fn update_user_collateral(
+    pool: &PgPool,
+    network: &str,
+    protocol: &str,
+    user_address: &str,
+    reserve: &str,
+    enabled: bool,
+) -> Result<(), LiquidatorError> {
+    const QUERY: &str = r#"
+        UPDATE aavev3_positions ap
+        SET collateral_enabled = $3, updated_at = NOW()
+        FROM protocols_details pd
+        WHERE ap.protocol_details_id = pd.id
+          AND ap.user_address = $1
+          AND ap.reserve = $2
+          AND pd.network_id = $4
+          AND pd.protocol_id = $5
+    "#;
+    sqlx::query(QUERY)
+        .bind(user_address)
+        .bind(reserve)
+        .bind(enabled)
+        .bind(network)
+        .bind(protocol)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
 pub async fn get_reserves(
     pool: &PgPool,
     network: &str,
     protocol: &str,
-) -> Result<Vec<Reserve>, sqlx::Error> {
+) -> Result<Vec<Reserve>, LiquidatorError> {
     const QUERY: &str = r#"
         SELECT
             ar.reserve,
@@ -705,125 +1458,524 @@ pub async fn get_reserves(
             ar.flashloan_enabled,
             ar.oracle_addr,
             ar.aggregator_addr,
+            ar.oracle_heartbeat_secs,
+            ar.oracle_deviation_bps,
+            ar.is_active,
+            ar.is_frozen,
+            ar.is_paused,
+            ar.is_siloed_borrowing,
+            ar.borrowing_enabled,
+            ar.usage_as_collateral_enabled,
+            ar.borrowable_in_isolation,
+            ar.debt_ceiling,
             ars.liquidity_index,
             ars.liquidity_rate,
             ars.variable_borrow_rate,
             ars.variable_borrow_index,
             ars.price_usd,
+            ars.price_stale,
+            e.symbol,
             ed.decimals,
             ars.updated_at
         FROM aavev3_reserves ar
         JOIN protocols_details pd ON ar.protocol_details_id = pd.id
-        JOIN erc20_details ed ON ar.reserve = ed.address
-        JOIN aavev3_reserves_stats ars ON ar.reserve = ars.reserve
-        WHERE ed.network_id = $1 AND pd.protocol_id = $2
+        JOIN erc20_details ed ON ar.reserve = ed.address AND ed.network_id = pd.network_id
+        JOIN erc20 e ON e.id = ed.erc20_id
+        JOIN aavev3_reserves_stats ars
+            ON ar.protocol_details_id = ars.protocol_details_id AND ar.reserve = ars.reserve
+        WHERE pd.network_id = $1 AND pd.protocol_id = $2
     "#;
     let rows = sqlx::query(QUERY)
         .bind(network)
         .bind(protocol)
         .fetch_all(pool)
         .await?;
-    let reserves: Vec<Reserve> = rows
-        .into_iter()
-        .map(|row| Reserve {
-            reserve: row.get::<String, _>("reserve").parse().unwrap(),
-            protocol_details_id: row.get("protocol_details_id"),
-            liquidation_threshold: row.get("liquidation_threshold"),
-            liquidation_bonus: row.get("liquidation_bonus"),
-            flashloan_enabled: row.get("flashloan_enabled"),
-            oracle_addr: row.get::<String, _>("oracle_addr").parse().unwrap(),
-            aggregator_addr: row
-                .get::<Option<String>, _>("aggregator_addr")
-                .map(|v| v.parse().unwrap()),
-            stats: ReserveStats {
-                liquidity_index: row.get("liquidity_index"),
-                liquidity_rate: row.get("liquidity_rate"),
-                variable_borrow_rate: row.get("variable_borrow_rate"),
-                variable_borrow_index: row.get("variable_borrow_index"),
-                price_usd: row.get::<Option<f64>, _>("price_usd").unwrap_or(0.0),
-                updated_at: row.get("updated_at"),
-            },
-            decimals: row.get("decimals"),
+    rows.into_iter()
+        .map(|row| {
+            Ok(Reserve {
+                reserve: parse_address(&row.get::<String, _>("reserve"))?,
+                protocol_details_id: row.get("protocol_details_id"),
+                liquidation_threshold: row.get("liquidation_threshold"),
+                liquidation_bonus: row.get("liquidation_bonus"),
+                flashloan_enabled: row.get("flashloan_enabled"),
+                oracle_addr: parse_address(&row.get::<String, _>("oracle_addr"))?,
+                aggregator_addr: row
+                    .get::<Option<String>, _>("aggregator_addr")
+                    .map(|v| parse_address(&v))
+                    .transpose()?,
+                oracle_heartbeat_secs: row.get("oracle_heartbeat_secs"),
+                oracle_deviation_bps: row.get("oracle_deviation_bps"),
+                flags: ReserveFlags {
+                    is_active: row.get("is_active"),
+                    is_frozen: row.get("is_frozen"),
+                    is_paused: row.get("is_paused"),
+                    is_siloed_borrowing: row.get("is_siloed_borrowing"),
+                    borrowing_enabled: row.get("borrowing_enabled"),
+                    usage_as_collateral_enabled: row.get("usage_as_collateral_enabled"),
+                    borrowable_in_isolation: row.get("borrowable_in_isolation"),
+                    debt_ceiling: row.get("debt_ceiling"),
+                },
+                stats: ReserveStats {
+                    liquidity_index: row.get("liquidity_index"),
+                    liquidity_rate: row.get("liquidity_rate"),
+                    variable_borrow_rate: row.get("variable_borrow_rate"),
+                    variable_borrow_index: row.get("variable_borrow_index"),
+                    price_usd: row.get::<Option<f64>, _>("price_usd").unwrap_or(0.0),
+                    price_stale: row.get("price_stale"),
+                    updated_at: row.get("updated_at"),
+                },
+                symbol: row.get("symbol"),
+                decimals: row.get("decimals"),
+            })
         })
-        .collect();
-    Ok(reserves)
+        .collect()
 }
 
 pub async fn get_reserves_users(
     pool: &PgPool,
     network: &str,
     protocol: &str,
-) -> Result<(HashMap<Address, UserData>, HashMap<Address, ReserveData>), sqlx::Error> {
-    const QUERY: &str = r#"
+) -> Result<(HashMap<Address, UserData>, HashMap<Address, ReserveData>), LiquidatorError> {
+    // every reserve, whether anyone's in it or not
+    const RESERVES: &str = r#"
+        SELECT ar.reserve, COALESCE(ars.price_usd, 0) AS price_usd
+        FROM aavev3_reserves ar
+        JOIN protocols_details pd ON pd.id = ar.protocol_details_id
+        LEFT JOIN aavev3_reserves_stats ars
+            ON ars.protocol_details_id = ar.protocol_details_id AND ars.reserve = ar.reserve
+        WHERE pd.network_id = $1 AND pd.protocol_id = $2
+    "#;
+    // users without stats yet are re-evaluated on their next update like any other
+    const USERS: &str = r#"
         SELECT
             au.address AS user_addr,
             aus.health_factor,
             EXTRACT(EPOCH FROM aus.updated_at)::NUMERIC::BIGINT as updated_at,
-            ap.reserve AS reserve_addr,
-            COALESCE(ars.price_usd, 0) AS price_usd
+            ap.reserve AS reserve_addr
         FROM aavev3_users au
-        JOIN aavev3_users_stats aus ON aus.user_address = au.address
         JOIN protocols_details pd ON pd.id = au.protocol_details_id
-        JOIN aavev3_positions ap ON ap.user_address = au.address
-        JOIN aavev3_reserves ar ON ar.reserve = ap.reserve
-        JOIN erc20_details ed ON ed.address = ar.reserve
-        JOIN erc20 e ON e.id = ed.erc20_id
-        JOIN aavev3_reserves_stats ars ON ar.reserve = ars.reserve
+        LEFT JOIN aavev3_users_stats aus
+            ON aus.protocol_details_id = au.protocol_details_id AND aus.user_address = au.address
+        LEFT JOIN aavev3_positions ap
+            ON ap.protocol_details_id = au.protocol_details_id AND ap.user_address = au.address
         WHERE pd.network_id = $1 AND pd.protocol_id = $2
     "#;
-    let rows = sqlx::query(QUERY)
+
+    let mut reserves: HashMap<Address, ReserveData> = HashMap::new();
+    for row in sqlx::query(RESERVES)
         .bind(network)
         .bind(protocol)
         .fetch_all(pool)
-        .await?;
+        .await?
+    {
+        reserves.insert(
+            parse_address(&row.get::<String, _>("reserve"))?,
+            ReserveData {
+                users: HashSet::new(),
+                price: row.get::<f64, _>("price_usd"),
+            },
+        );
+    }
 
     let mut users: HashMap<Address, UserData> = HashMap::new();
-    let mut reserves: HashMap<Address, ReserveData> = HashMap::new();
-
-    for row in rows {
-        let user_addr = Address::from_str(&row.get::<String, _>("user_addr")).unwrap();
-        let health_factor = row.get::<f64, _>("health_factor");
-        let updated_at = row.get::<i64, _>("updated_at");
-        let reserve_addr = Address::from_str(&row.get::<String, _>("reserve_addr")).unwrap();
-        let price_usd = row.get::<f64, _>("price_usd");
+    for row in sqlx::query(USERS)
+        .bind(network)
+        .bind(protocol)
+        .fetch_all(pool)
+        .await?
+    {
+        let user_addr = parse_address(&row.get::<String, _>("user_addr"))?;
+        let user_data = UserData::default();
 
         users.insert(
             user_addr,
             UserData {
-                health_factor,
-                last_update: updated_at,
+                health_factor: row
+                    .get::<Option<f64>, _>("health_factor")
+                    .unwrap_or(user_data.health_factor),
+                last_update: row
+                    .get::<Option<i64>, _>("updated_at")
+                    .unwrap_or(user_data.last_update),
+                ..user_data
             },
         );
 
-        reserves
-            .entry(reserve_addr)
-            .or_insert(ReserveData {
-                users: HashSet::new(),
-                price: price_usd,
-            })
-            .users
-            .insert(user_addr);
+        if let Some(reserve_addr) = row.get::<Option<String>, _>("reserve_addr") {
+            if let Some(reserve) = reserves.get_mut(&parse_address(&reserve_addr)?) {
+                reserve.users.insert(user_addr);
+            }
+        }
     }
 
     Ok((users, reserves))
 }
 
+pub async fn save_fanatic_snapshot(
+    pool: &PgPool,
+    protocol_details_id: i32,
+    snapshot: &FanaticSnapshot,
+) -> Result<(), LiquidatorError> {
+    // cascades to the users and positions
+    const DELETE_SNAPSHOT: &str = r#"
+        DELETE FROM fanatic_snapshots WHERE protocol_details_id = $1
+    "#;
+    const INSERT_SNAPSHOT: &str = r#"
+        INSERT INTO fanatic_snapshots (protocol_details_id, block_number)
+        VALUES ($1, $2)
+    "#;
+    const INSERT_USERS: &str = r#"
+        INSERT INTO fanatic_snapshot_users (protocol_details_id, user_address, health_factor, last_update)
+        SELECT $1, u.*
+        FROM UNNEST($2::VARCHAR[], $3::DOUBLE PRECISION[], $4::BIGINT[]) AS u
+    "#;
+    const INSERT_POSITIONS: &str = r#"
+        INSERT INTO fanatic_snapshot_positions (
+            protocol_details_id, user_address, reserve, scaled_a_token_balance,
+            scaled_variable_debt, usage_as_collateral_enabled
+        )
+        SELECT $1, p.*
+        FROM UNNEST($2::VARCHAR[], $3::VARCHAR[], $4::NUMERIC[], $5::NUMERIC[], $6::BOOLEAN[]) AS p
+    "#;
+
+    let rows = snapshot_rows(snapshot)?;
+
+    let mut tx = pool.begin().await?;
+    sqlx::query(DELETE_SNAPSHOT)
+        .bind(protocol_details_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(INSERT_SNAPSHOT)
+        .bind(protocol_details_id)
+        .bind(snapshot.block_number as i64)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(INSERT_USERS)
+        .bind(protocol_details_id)
+        .bind(rows.users)
+        .bind(rows.health_factors)
+        .bind(rows.last_updates)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(INSERT_POSITIONS)
+        .bind(protocol_details_id)
+        .bind(rows.position_users)
+        .bind(rows.position_reserves)
+        .bind(rows.scaled_a_token_balances)
+        .bind(rows.scaled_variable_debts)
+        .bind(rows.collaterals)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// A snapshot's columns, as bound to `UNNEST`.
+#[derive(Debug, Default)]
+struct SnapshotRows {
+    users: Vec<String>,
+    health_factors: Vec<f64>,
+    last_updates: Vec<i64>,
+    position_users: Vec<String>,
+    position_reserves: Vec<String>,
+    // `None` for users known to be in the reserve but not evaluated since, i.e restored from
+    // `aavev3_positions`
+    scaled_a_token_balances: Vec<Option<BigDecimal>>,
+    scaled_variable_debts: Vec<Option<BigDecimal>>,
+    collaterals: Vec<Option<bool>>,
+}
+
+fn snapshot_rows(snapshot: &FanaticSnapshot) -> Result<SnapshotRows, LiquidatorError> {
+    let mut rows = SnapshotRows::default();
+
+    // positions reference their user, reserves' members the fanatic hasn't evaluated yet are saved
+    // as such
+    let not_evaluated = UserData::default();
+    let missing = snapshot
+        .reserves_users
+        .values()
+        .flatten()
+        .filter(|user| !snapshot.users.contains_key(*user))
+        .collect::<HashSet<_>>();
+    let users = snapshot
+        .users
+        .iter()
+        .chain(missing.into_iter().map(|user| (user, &not_evaluated)));
+    for (user, data) in users {
+        rows.users.push(user.to_string());
+        rows.health_factors.push(data.health_factor);
+        rows.last_updates.push(data.last_update);
+    }
+
+    for (reserve, users) in &snapshot.reserves_users {
+        for user in users {
+            let position = snapshot.users.get(user).and_then(|data| {
+                data.positions
+                    .iter()
+                    .find(|p| p.underlying_asset == *reserve)
+            });
+
+            rows.position_users.push(user.to_string());
+            rows.position_reserves.push(reserve.to_string());
+            rows.scaled_a_token_balances.push(
+                position
+                    .map(|p| to_big_decimal(p.scaled_a_token_balance))
+                    .transpose()?,
+            );
+            rows.scaled_variable_debts.push(
+                position
+                    .map(|p| to_big_decimal(p.scaled_variable_debt))
+                    .transpose()?,
+            );
+            rows.collaterals
+                .push(position.map(|p| p.usage_as_collateral_enabled));
+        }
+    }
+
+    Ok(rows)
+}
+
+pub async fn get_fanatic_snapshot(
+    pool: &PgPool,
+    protocol_details_id: i32,
+) -> Result<Option<FanaticSnapshot>, LiquidatorError> {
+    const SNAPSHOT: &str = r#"
+        SELECT block_number FROM fanatic_snapshots WHERE protocol_details_id = $1
+    "#;
+    const USERS: &str = r#"
+        SELECT user_address, health_factor, last_update
+        FROM fanatic_snapshot_users
+        WHERE protocol_details_id = $1
+    "#;
+    const POSITIONS: &str = r#"
+        SELECT
+            user_address,
+            reserve,
+            scaled_a_token_balance::TEXT AS scaled_a_token_balance,
+            scaled_variable_debt::TEXT AS scaled_variable_debt,
+            usage_as_collateral_enabled
+        FROM fanatic_snapshot_positions
+        WHERE protocol_details_id = $1
+    "#;
+
+    // the three reads see the same snapshot, even if another one's being written
+    let mut tx = pool.begin().await?;
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ")
+        .execute(&mut *tx)
+        .await?;
+
+    let Some(block_number) = sqlx::query(SNAPSHOT)
+        .bind(protocol_details_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .map(|row| row.get::<i64, _>("block_number"))
+    else {
+        return Ok(None);
+    };
+
+    let mut snapshot = FanaticSnapshot {
+        block_number: block_number as u64,
+        ..Default::default()
+    };
+    for row in sqlx::query(USERS)
+        .bind(protocol_details_id)
+        .fetch_all(&mut *tx)
+        .await?
+    {
+        snapshot.users.insert(
+            parse_address(&row.get::<String, _>("user_address"))?,
+            UserData {
+                health_factor: row.get::<f64, _>("health_factor"),
+                last_update: row.get::<i64, _>("last_update"),
+                positions: Vec::new(),
+            },
+        );
+    }
+    for row in sqlx::query(POSITIONS)
+        .bind(protocol_details_id)
+        .fetch_all(&mut *tx)
+        .await?
+    {
+        let user = parse_address(&row.get::<String, _>("user_address"))?;
+        let reserve = parse_address(&row.get::<String, _>("reserve"))?;
+        snapshot
+            .reserves_users
+            .entry(reserve)
+            .or_default()
+            .insert(user);
+
+        let (Some(a_token_balance), Some(variable_debt), Some(collateral)) = (
+            row.get::<Option<String>, _>("scaled_a_token_balance"),
+            row.get::<Option<String>, _>("scaled_variable_debt"),
+            row.get::<Option<bool>, _>("usage_as_collateral_enabled"),
+        ) else {
+            continue;
+        };
+        if let Some(data) = snapshot.users.get_mut(&user) {
+            data.positions.push(UserReserve {
+                underlying_asset: reserve,
+                scaled_a_token_balance: parse_u256(&a_token_balance)?,
+                scaled_variable_debt: parse_u256(&variable_debt)?,
+                usage_as_collateral_enabled: collateral,
+            });
+        }
+    }
+    tx.commit().await?;
+
+    Ok(Some(snapshot))
+}
+
 pub async fn insert_liquidation_call(
+    pool: &PgPool,
+    msg: &InsertLiquidationCall,
+) -> Result<(), LiquidatorError> {
+    // USD values are derived from the prices as of the liquidation's block, and the trigger is the
+    // latest price update of either asset which landed by then. Both are read off the prices'
+    // history: the call is only inserted once confirmed, the reserves' stats may have moved on.
+    const QUERY: &str = r#"
+        INSERT INTO aavev3_liquidations (
+            protocol_details_id, user_address, collateral_asset, debt_asset, liquidator_address,
+            debt_to_cover, liquidated_collateral_amount, receive_a_token, block_number, tx_hash,
+            log_index, gas_price, ours, missed, debt_to_cover_usd, liquidated_collateral_usd,
+            trigger_block_number
+        )
+        VALUES (
+            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14,
+            (
+                SELECT $6::NUMERIC / POWER(10, ed.decimals) * COALESCE(
+                    (
+                        SELECT h.price_usd
+                        FROM aavev3_prices_history h
+                        WHERE h.protocol_details_id = $1 AND h.reserve = $4 AND h.block_number <= $9
+                        ORDER BY h.block_number DESC
+                        LIMIT 1
+                    ),
+                    ars.price_usd
+                )
+                FROM aavev3_reserves_stats ars
+                JOIN protocols_details pd ON pd.id = ars.protocol_details_id
+                JOIN erc20_details ed ON ed.address = ars.reserve AND ed.network_id = pd.network_id
+                WHERE ars.protocol_details_id = $1 AND ars.reserve = $4
+            ),
+            (
+                SELECT $7::NUMERIC / POWER(10, ed.decimals) * COALESCE(
+                    (
+                        SELECT h.price_usd
+                        FROM aavev3_prices_history h
+                        WHERE h.protocol_details_id = $1 AND h.reserve = $3 AND h.block_number <= $9
+                        ORDER BY h.block_number DESC
+                        LIMIT 1
+                    ),
+                    ars.price_usd
+                )
+                FROM aavev3_reserves_stats ars
+                JOIN protocols_details pd ON pd.id = ars.protocol_details_id
+                JOIN erc20_details ed ON ed.address = ars.reserve AND ed.network_id = pd.network_id
+                WHERE ars.protocol_details_id = $1 AND ars.reserve = $3
+            ),
+            (
+                SELECT MAX(h.block_number)
+                FROM aavev3_prices_history h
+                WHERE h.protocol_details_id = $1
+                  AND h.reserve IN ($3, $4)
+                  AND h.block_number <= $9
+            )
+        )
+        ON CONFLICT (tx_hash, log_index) DO NOTHING
+    "#;
+    sqlx::query(QUERY)
+        .bind(msg.protocol_details_id)
+        .bind(msg.call.user.to_string())
+        .bind(msg.call.collateralAsset.to_string())
+        .bind(msg.call.debtAsset.to_string())
+        .bind(msg.call.liquidator.to_string())
+        .bind(to_big_decimal(msg.call.debtToCover)?)
+        .bind(to_big_decimal(msg.call.liquidatedCollateralAmount)?)
+        .bind(msg.call.receiveAToken)
+        .bind(msg.block_number as i64)
+        .bind(msg.tx_hash.to_string())
+        .bind(msg.log_index as i32)
+        .bind(msg.gas_price)
+        .bind(msg.ours)
+        .bind(msg.missed)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn insert_skipped_liquidation(
     pool: &PgPool,
     protocol_details_id: i32,
     user_address: &str,
     collateral_asset: &str,
     debt_asset: &str,
-) -> Result<(), sqlx::Error> {
+    reason: &str,
+) -> Result<(), LiquidatorError> {
     const QUERY: &str = r#"
-        INSERT INTO aavev3_liquidations (protocol_details_id, user_address, collateral_asset, debt_asset)
-        VALUES ($1, $2, $3, $4)
+        INSERT INTO aavev3_skipped_liquidations (protocol_details_id, user_address, collateral_asset, debt_asset, reason)
+        VALUES ($1, $2, $3, $4, $5)
     "#;
     sqlx::query(QUERY)
         .bind(protocol_details_id)
         .bind(user_address)
         .bind(collateral_asset)
         .bind(debt_asset)
+        .bind(reason)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn insert_liquidation_attempt(
+    pool: &PgPool,
+    msg: &InsertLiquidationAttempt,
+) -> Result<(), LiquidatorError> {
+    const QUERY: &str = r#"
+        INSERT INTO liquidation_attempts (
+            protocol_details_id, user_address, collateral_asset, debt_asset, debt_to_cover,
+            strategy, flashloan_source, fee_tiers, swap_path, min_amount_out, health_factor,
+            estimated_profit_usd, gas_limit, max_fee_per_gas, max_priority_fee_per_gas, gas_used,
+            gas_price, tx_hash, block_number, status, revert_reason
+        )
+        VALUES (
+            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19,
+            $20, $21
+        )
+    "#;
+    let liquidation = &msg.liquidation;
+    let receipt = msg.receipt.as_ref();
+    let gwei = |wei: u128| wei as f64 / 1e9;
+
+    sqlx::query(QUERY)
+        .bind(msg.protocol_details_id)
+        .bind(liquidation.user.to_string())
+        .bind(liquidation.collateral_asset.to_string())
+        .bind(liquidation.debt_asset.to_string())
+        .bind(to_big_decimal(liquidation.debt_to_cover)?)
+        .bind(msg.strategy.as_str())
+        .bind(liquidation.flashloan.map(|f| f.source.as_str()))
+        .bind(
+            uniswap_v3_fee_tiers(&liquidation.path)
+                .into_iter()
+                .map(|fee| fee as i32)
+                .collect::<Vec<_>>(),
+        )
+        .bind(liquidation.path.to_string())
+        .bind(to_big_decimal(liquidation.min_amount_out)?)
+        .bind(msg.health_factor)
+        .bind(msg.estimated_profit_usd)
+        .bind(receipt.and_then(|r| r.gas_limit).map(|gas| gas as i64))
+        .bind(receipt.and_then(|r| r.max_fee_per_gas).map(gwei))
+        .bind(receipt.and_then(|r| r.max_priority_fee_per_gas).map(gwei))
+        .bind(receipt.map(|r| r.gas_used as i64))
+        .bind(receipt.map(|r| gwei(r.effective_gas_price)))
+        .bind(receipt.map(|r| r.tx_hash.to_string()))
+        .bind(
+            receipt
+                .and_then(|r| r.block_number)
+                .map(|block| block as i64),
+        )
+        .bind(msg.status.as_str())
+        .bind(msg.revert_reason.as_deref())
         .execute(pool)
         .await?;
     Ok(())
@@ -833,30 +1985,818 @@ pub async fn upsert_users_stats(
     pool: &PgPool,
     protocol_details_id: i32,
     users: HashMap<Address, f64>,
-) -> Result<(), sqlx::Error> {
+) -> Result<(), LiquidatorError> {
     const INSERT_USERS: &str = r#"
         INSERT INTO aavev3_users (address, protocol_details_id)
-        VALUES ($1, $2)
+        SELECT u, $2 FROM UNNEST($1::VARCHAR[]) AS u
         ON CONFLICT DO NOTHING
     "#;
     const UPSERT_STATS: &str = r#"
-        INSERT INTO aavev3_users_stats (user_address, health_factor)
-        VALUES ($1, $2)
-        ON CONFLICT (user_address) DO UPDATE SET health_factor = $2, updated_at = NOW()
+        INSERT INTO aavev3_users_stats (protocol_details_id, user_address, health_factor)
+        SELECT $3, s.* FROM UNNEST($1::VARCHAR[], $2::DOUBLE PRECISION[]) AS s
+        ON CONFLICT (protocol_details_id, user_address) DO UPDATE SET
+            health_factor = EXCLUDED.health_factor,
+            updated_at = NOW()
     "#;
 
-    for (addr, hf) in users {
-        let addr_str = addr.to_string();
-        sqlx::query(INSERT_USERS)
-            .bind(&addr_str)
-            .bind(protocol_details_id)
+    let (addresses, health_factors): (Vec<String>, Vec<f64>) = users
+        .into_iter()
+        .map(|(address, health_factor)| (address.to_string(), health_factor))
+        .unzip();
+
+    let mut tx = pool.begin().await?;
+    sqlx::query(INSERT_USERS)
+        .bind(&addresses)
+        .bind(protocol_details_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(UPSERT_STATS)
+        .bind(&addresses)
+        .bind(health_factors)
+        .bind(protocol_details_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Bounds of a history query, each one optional. `since_secs` goes by when the rows were recorded.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HistoryRange {
+    pub from_block: Option<u64>,
+    pub to_block: Option<u64>,
+    pub since_secs: Option<u64>,
+}
+
+#[derive(Clone, Debug, FromRow, Serialize)]
+pub struct HealthFactorPoint {
+    pub block_number: i64,
+    pub health_factor: f64,
+    pub recorded_at: String,
+}
+
+#[derive(Clone, Debug, FromRow, Serialize)]
+pub struct PricePoint {
+    pub block_number: i64,
+    pub price_usd: f64,
+    pub recorded_at: String,
+}
+
+#[derive(Clone, Debug, FromRow, Serialize)]
+pub struct ReserveStatsPoint {
+    pub block_number: i64,
+    pub liquidity_rate: f64,
+    pub variable_borrow_rate: f64,
+    pub liquidity_index: f64,
+    pub variable_borrow_index: f64,
+    pub recorded_at: String,
+}
+
+pub async fn insert_health_factors(
+    pool: &PgPool,
+    protocol_details_id: i32,
+    block_number: u64,
+    health_factors: &[(Address, f64)],
+) -> Result<(), LiquidatorError> {
+    const QUERY: &str = r#"
+        INSERT INTO aavev3_health_factors_history (protocol_details_id, user_address, block_number, health_factor)
+        SELECT $1, h.user_address, $2, h.health_factor
+        FROM UNNEST($3::VARCHAR[], $4::DOUBLE PRECISION[]) AS h (user_address, health_factor)
+        -- evaluated again within the same block
+        ON CONFLICT (protocol_details_id, user_address, block_number) DO UPDATE SET
+            health_factor = EXCLUDED.health_factor
+    "#;
+
+    // a row can't be upserted twice by the same statement, the last evaluation wins
+    let (users, values): (Vec<String>, Vec<f64>) = health_factors
+        .iter()
+        .copied()
+        .collect::<HashMap<_, _>>()
+        .into_iter()
+        .map(|(user, health_factor)| (user.to_string(), health_factor))
+        .unzip();
+    if users.is_empty() {
+        return Ok(());
+    }
+
+    sqlx::query(QUERY)
+        .bind(protocol_details_id)
+        .bind(block_number as i64)
+        .bind(users)
+        .bind(values)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn get_health_factor_history(
+    pool: &PgPool,
+    network: &str,
+    protocol: &str,
+    user: Address,
+    range: &HistoryRange,
+) -> Result<Vec<HealthFactorPoint>, LiquidatorError> {
+    const QUERY: &str = r#"
+        SELECT
+            h.block_number,
+            h.health_factor,
+            TO_CHAR(h.recorded_at, 'YYYY-MM-DD HH24:MI:SS') AS recorded_at
+        FROM aavev3_health_factors_history h
+        JOIN protocols_details pd ON h.protocol_details_id = pd.id
+        WHERE pd.network_id = $1
+          AND pd.protocol_id = $2
+          AND h.user_address = $3
+          AND ($4::BIGINT IS NULL OR h.block_number >= $4)
+          AND ($5::BIGINT IS NULL OR h.block_number <= $5)
+          AND ($6::DOUBLE PRECISION IS NULL
+            OR h.recorded_at >= (NOW() AT TIME ZONE 'UTC') - MAKE_INTERVAL(secs => $6))
+        ORDER BY h.block_number
+    "#;
+    sqlx::query_as::<_, HealthFactorPoint>(QUERY)
+        .bind(network)
+        .bind(protocol)
+        .bind(user.to_string())
+        .bind(range.from_block.map(|n| n as i64))
+        .bind(range.to_block.map(|n| n as i64))
+        .bind(range.since_secs.map(|secs| secs as f64))
+        .fetch_all(pool)
+        .await
+        .map_err(LiquidatorError::from)
+}
+
+pub async fn get_price_history(
+    pool: &PgPool,
+    network: &str,
+    protocol: &str,
+    reserve: Address,
+    range: &HistoryRange,
+) -> Result<Vec<PricePoint>, LiquidatorError> {
+    const QUERY: &str = r#"
+        SELECT
+            h.block_number,
+            h.price_usd,
+            TO_CHAR(h.recorded_at, 'YYYY-MM-DD HH24:MI:SS') AS recorded_at
+        FROM aavev3_prices_history h
+        JOIN protocols_details pd ON h.protocol_details_id = pd.id
+        WHERE pd.network_id = $1
+          AND pd.protocol_id = $2
+          AND h.reserve = $3
+          AND ($4::BIGINT IS NULL OR h.block_number >= $4)
+          AND ($5::BIGINT IS NULL OR h.block_number <= $5)
+          AND ($6::DOUBLE PRECISION IS NULL
+            OR h.recorded_at >= (NOW() AT TIME ZONE 'UTC') - MAKE_INTERVAL(secs => $6))
+        ORDER BY h.block_number
+    "#;
+    sqlx::query_as::<_, PricePoint>(QUERY)
+        .bind(network)
+        .bind(protocol)
+        .bind(reserve.to_string())
+        .bind(range.from_block.map(|n| n as i64))
+        .bind(range.to_block.map(|n| n as i64))
+        .bind(range.since_secs.map(|secs| secs as f64))
+        .fetch_all(pool)
+        .await
+        .map_err(LiquidatorError::from)
+}
+
+pub async fn get_reserve_stats_history(
+    pool: &PgPool,
+    network: &str,
+    protocol: &str,
+    reserve: Address,
+    range: &HistoryRange,
+) -> Result<Vec<ReserveStatsPoint>, LiquidatorError> {
+    const QUERY: &str = r#"
+        SELECT
+            h.block_number,
+            h.liquidity_rate,
+            h.variable_borrow_rate,
+            h.liquidity_index,
+            h.variable_borrow_index,
+            TO_CHAR(h.recorded_at, 'YYYY-MM-DD HH24:MI:SS') AS recorded_at
+        FROM aavev3_reserves_stats_history h
+        JOIN protocols_details pd ON h.protocol_details_id = pd.id
+        WHERE pd.network_id = $1
+          AND pd.protocol_id = $2
+          AND h.reserve = $3
+          AND ($4::BIGINT IS NULL OR h.block_number >= $4)
+          AND ($5::BIGINT IS NULL OR h.block_number <= $5)
+          AND ($6::DOUBLE PRECISION IS NULL
+            OR h.recorded_at >= (NOW() AT TIME ZONE 'UTC') - MAKE_INTERVAL(secs => $6))
+        ORDER BY h.block_number
+    "#;
+    sqlx::query_as::<_, ReserveStatsPoint>(QUERY)
+        .bind(network)
+        .bind(protocol)
+        .bind(reserve.to_string())
+        .bind(range.from_block.map(|n| n as i64))
+        .bind(range.to_block.map(|n| n as i64))
+        .bind(range.since_secs.map(|secs| secs as f64))
+        .fetch_all(pool)
+        .await
+        .map_err(LiquidatorError::from)
+}
+
+/// Thins out the history rows older than `downsample_after_secs` to the latest one per
+/// `bucket_secs` (per user or reserve), and deletes the ones older than `retention_secs`.
+/// Returns how many rows were deleted.
+pub async fn prune_history(
+    pool: &PgPool,
+    retention_secs: u64,
+    downsample_after_secs: u64,
+    bucket_secs: u64,
+) -> Result<u64, LiquidatorError> {
+    const EXPIRE_HEALTH_FACTORS: &str = r#"
+        DELETE FROM aavev3_health_factors_history
+        WHERE recorded_at < (NOW() AT TIME ZONE 'UTC') - MAKE_INTERVAL(secs => $1)
+    "#;
+    const EXPIRE_PRICES: &str = r#"
+        DELETE FROM aavev3_prices_history
+        WHERE recorded_at < (NOW() AT TIME ZONE 'UTC') - MAKE_INTERVAL(secs => $1)
+    "#;
+    const EXPIRE_RESERVES_STATS: &str = r#"
+        DELETE FROM aavev3_reserves_stats_history
+        WHERE recorded_at < (NOW() AT TIME ZONE 'UTC') - MAKE_INTERVAL(secs => $1)
+    "#;
+    const DOWNSAMPLE_HEALTH_FACTORS: &str = r#"
+        DELETE FROM aavev3_health_factors_history h
+        USING (
+            SELECT protocol_details_id, user_address, block_number, ROW_NUMBER() OVER (
+                PARTITION BY protocol_details_id, user_address,
+                    DATE_BIN(MAKE_INTERVAL(secs => $2), recorded_at, TIMESTAMP '2000-01-01')
+                ORDER BY block_number DESC
+            ) AS rank
+            FROM aavev3_health_factors_history
+            WHERE recorded_at < (NOW() AT TIME ZONE 'UTC') - MAKE_INTERVAL(secs => $1)
+        ) d
+        WHERE d.rank > 1
+          AND h.protocol_details_id = d.protocol_details_id
+          AND h.user_address = d.user_address
+          AND h.block_number = d.block_number
+    "#;
+    const DOWNSAMPLE_PRICES: &str = r#"
+        DELETE FROM aavev3_prices_history h
+        USING (
+            SELECT protocol_details_id, reserve, block_number, ROW_NUMBER() OVER (
+                PARTITION BY protocol_details_id, reserve,
+                    DATE_BIN(MAKE_INTERVAL(secs => $2), recorded_at, TIMESTAMP '2000-01-01')
+                ORDER BY block_number DESC
+            ) AS rank
+            FROM aavev3_prices_history
+            WHERE recorded_at < (NOW() AT TIME ZONE 'UTC') - MAKE_INTERVAL(secs => $1)
+        ) d
+        WHERE d.rank > 1
+          AND h.protocol_details_id = d.protocol_details_id
+          AND h.reserve = d.reserve
+          AND h.block_number = d.block_number
+    "#;
+    const DOWNSAMPLE_RESERVES_STATS: &str = r#"
+        DELETE FROM aavev3_reserves_stats_history h
+        USING (
+            SELECT protocol_details_id, reserve, block_number, ROW_NUMBER() OVER (
+                PARTITION BY protocol_details_id, reserve,
+                    DATE_BIN(MAKE_INTERVAL(secs => $2), recorded_at, TIMESTAMP '2000-01-01')
+                ORDER BY block_number DESC
+            ) AS rank
+            FROM aavev3_reserves_stats_history
+            WHERE recorded_at < (NOW() AT TIME ZONE 'UTC') - MAKE_INTERVAL(secs => $1)
+        ) d
+        WHERE d.rank > 1
+          AND h.protocol_details_id = d.protocol_details_id
+          AND h.reserve = d.reserve
+          AND h.block_number = d.block_number
+    "#;
+
+    let mut deleted = 0;
+    for query in [EXPIRE_HEALTH_FACTORS, EXPIRE_PRICES, EXPIRE_RESERVES_STATS] {
+        deleted += sqlx::query(query)
+            .bind(retention_secs as f64)
             .execute(pool)
-            .await?;
-        sqlx::query(UPSERT_STATS)
-            .bind(&addr_str)
-            .bind(hf)
+            .await?
+            .rows_affected();
+    }
+    for query in [
+        DOWNSAMPLE_HEALTH_FACTORS,
+        DOWNSAMPLE_PRICES,
+        DOWNSAMPLE_RESERVES_STATS,
+    ] {
+        deleted += sqlx::query(query)
+            .bind(downsample_after_secs as f64)
+            .bind(bucket_secs as f64)
             .execute(pool)
-            .await?;
+            .await?
+            .rows_affected();
+    }
+    Ok(deleted)
+}
+
+/// Per liquidator aggregates over a time window, see `get_top_liquidators`.
+/// Latencies are in blocks, from the latest oracle update of either asset to the liquidation.
+#[derive(Clone, Debug, FromRow, Serialize)]
+pub struct LiquidatorStats {
+    pub liquidator_address: String,
+    pub liquidations: i64,
+    pub volume_usd: f64,
+    pub median_latency_blocks: Option<f64>,
+    pub median_gas_price: Option<f64>,
+    pub max_gas_price: Option<f64>,
+    pub ours: bool,
+}
+
+/// How often a liquidator went after a (collateral, debt) pair, ranked per liquidator.
+#[derive(Clone, Debug, FromRow, Serialize)]
+pub struct LiquidatorFocus {
+    pub liquidator_address: String,
+    pub collateral_symbol: String,
+    pub debt_symbol: String,
+    pub liquidations: i64,
+    pub volume_usd: f64,
+    pub rank: i64,
+}
+
+/// A liquidation we had an attempt for, but someone else landed.
+#[derive(Clone, Debug, FromRow, Serialize)]
+pub struct MissedLiquidation {
+    pub user_address: String,
+    pub liquidator_address: Option<String>,
+    pub collateral_symbol: String,
+    pub debt_symbol: String,
+    pub debt_to_cover_usd: Option<f64>,
+    pub latency_blocks: Option<i64>,
+    pub gas_price: Option<f64>,
+    pub block_number: Option<i64>,
+    pub tx_hash: Option<String>,
+    pub timestamp: String,
+}
+
+pub async fn get_top_liquidators(
+    pool: &PgPool,
+    network: &str,
+    protocol: &str,
+    since_secs: u64,
+    limit: i64,
+) -> Result<Vec<LiquidatorStats>, LiquidatorError> {
+    const QUERY: &str = r#"
+        SELECT
+            TRIM(l.liquidator_address) AS liquidator_address,
+            COUNT(*) AS liquidations,
+            COALESCE(SUM(l.debt_to_cover_usd), 0) AS volume_usd,
+            PERCENTILE_CONT(0.5) WITHIN GROUP (
+                ORDER BY l.block_number - l.trigger_block_number
+            ) AS median_latency_blocks,
+            PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY l.gas_price) AS median_gas_price,
+            MAX(l.gas_price) AS max_gas_price,
+            BOOL_OR(l.ours) AS ours
+        FROM aavev3_liquidations l
+        JOIN protocols_details pd ON l.protocol_details_id = pd.id
+        WHERE pd.network_id = $1
+          AND pd.protocol_id = $2
+          AND l.timestamp >= (NOW() AT TIME ZONE 'UTC') - MAKE_INTERVAL(secs => $3)
+          AND l.liquidator_address IS NOT NULL
+        GROUP BY l.liquidator_address
+        ORDER BY liquidations DESC, volume_usd DESC
+        LIMIT $4
+    "#;
+    sqlx::query_as::<_, LiquidatorStats>(QUERY)
+        .bind(network)
+        .bind(protocol)
+        .bind(since_secs as f64)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+        .map_err(LiquidatorError::from)
+}
+
+/// The `per_liquidator` most frequent (collateral, debt) pairs of every liquidator in the window.
+pub async fn get_liquidators_focus(
+    pool: &PgPool,
+    network: &str,
+    protocol: &str,
+    since_secs: u64,
+    per_liquidator: i64,
+) -> Result<Vec<LiquidatorFocus>, LiquidatorError> {
+    const QUERY: &str = r#"
+        SELECT * FROM (
+            SELECT
+                TRIM(l.liquidator_address) AS liquidator_address,
+                ce.symbol AS collateral_symbol,
+                de.symbol AS debt_symbol,
+                COUNT(*) AS liquidations,
+                COALESCE(SUM(l.debt_to_cover_usd), 0) AS volume_usd,
+                ROW_NUMBER() OVER (
+                    PARTITION BY l.liquidator_address
+                    ORDER BY COUNT(*) DESC, SUM(l.debt_to_cover_usd) DESC NULLS LAST
+                ) AS rank
+            FROM aavev3_liquidations l
+            JOIN protocols_details pd ON l.protocol_details_id = pd.id
+            JOIN erc20_details ced
+                ON ced.address = l.collateral_asset AND ced.network_id = pd.network_id
+            JOIN erc20 ce ON ce.id = ced.erc20_id
+            JOIN erc20_details ded
+                ON ded.address = l.debt_asset AND ded.network_id = pd.network_id
+            JOIN erc20 de ON de.id = ded.erc20_id
+            WHERE pd.network_id = $1
+              AND pd.protocol_id = $2
+              AND l.timestamp >= (NOW() AT TIME ZONE 'UTC') - MAKE_INTERVAL(secs => $3)
+              AND l.liquidator_address IS NOT NULL
+            GROUP BY l.liquidator_address, ce.symbol, de.symbol
+        ) focus
+        WHERE rank <= $4
+        ORDER BY liquidator_address, rank
+    "#;
+    sqlx::query_as::<_, LiquidatorFocus>(QUERY)
+        .bind(network)
+        .bind(protocol)
+        .bind(since_secs as f64)
+        .bind(per_liquidator)
+        .fetch_all(pool)
+        .await
+        .map_err(LiquidatorError::from)
+}
+
+pub async fn get_missed_liquidations(
+    pool: &PgPool,
+    network: &str,
+    protocol: &str,
+    since_secs: u64,
+) -> Result<Vec<MissedLiquidation>, LiquidatorError> {
+    const QUERY: &str = r#"
+        SELECT
+            TRIM(l.user_address) AS user_address,
+            TRIM(l.liquidator_address) AS liquidator_address,
+            ce.symbol AS collateral_symbol,
+            de.symbol AS debt_symbol,
+            l.debt_to_cover_usd,
+            l.block_number - l.trigger_block_number AS latency_blocks,
+            l.gas_price,
+            l.block_number,
+            TRIM(l.tx_hash) AS tx_hash,
+            TO_CHAR(l.timestamp, 'YYYY-MM-DD HH24:MI:SS') AS timestamp
+        FROM aavev3_liquidations l
+        JOIN protocols_details pd ON l.protocol_details_id = pd.id
+        JOIN erc20_details ced
+            ON ced.address = l.collateral_asset AND ced.network_id = pd.network_id
+        JOIN erc20 ce ON ce.id = ced.erc20_id
+        JOIN erc20_details ded
+            ON ded.address = l.debt_asset AND ded.network_id = pd.network_id
+        JOIN erc20 de ON de.id = ded.erc20_id
+        WHERE pd.network_id = $1
+          AND pd.protocol_id = $2
+          AND l.timestamp >= (NOW() AT TIME ZONE 'UTC') - MAKE_INTERVAL(secs => $3)
+          AND l.missed
+        ORDER BY l.timestamp DESC
+    "#;
+    sqlx::query_as::<_, MissedLiquidation>(QUERY)
+        .bind(network)
+        .bind(protocol)
+        .bind(since_secs as f64)
+        .fetch_all(pool)
+        .await
+        .map_err(LiquidatorError::from)
+}
+
+/// A user's health factor as last evaluated, and the USD value of its positions at the current
+/// prices.
+#[derive(Clone, Debug, FromRow, Serialize)]
+pub struct UserSummary {
+    pub user_address: String,
+    pub health_factor: f64,
+    // supplied as collateral only
+    pub collateral_usd: f64,
+    pub debt_usd: f64,
+    pub updated_at: String,
+}
+
+/// Filters of `get_users`, each one optional.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UsersQuery {
+    pub user: Option<Address>,
+    pub max_health_factor: Option<f64>,
+    pub sort: UserSort,
+    pub limit: Option<i64>,
+}
+
+/// A user's position, amounts in token units.
+#[derive(Clone, Debug, FromRow, Serialize)]
+pub struct UserPosition {
+    pub reserve: String,
+    pub symbol: String,
+    pub supply_amount: f64,
+    pub borrow_amount: f64,
+    pub collateral_enabled: bool,
+    pub price_usd: Option<f64>,
+    pub supply_usd: Option<f64>,
+    pub borrow_usd: Option<f64>,
+}
+
+#[derive(Clone, Debug, FromRow, Serialize)]
+pub struct Liquidation {
+    pub user_address: String,
+    pub liquidator_address: Option<String>,
+    pub collateral_symbol: String,
+    pub debt_symbol: String,
+    pub debt_to_cover_usd: Option<f64>,
+    pub liquidated_collateral_usd: Option<f64>,
+    pub ours: bool,
+    pub missed: bool,
+    pub block_number: Option<i64>,
+    pub tx_hash: Option<String>,
+    pub timestamp: String,
+}
+
+/// Evaluated users, i.e with stats, riskiest first unless sorted otherwise.
+pub async fn get_users(
+    pool: &PgPool,
+    network: &str,
+    protocol: &str,
+    query: &UsersQuery,
+) -> Result<Vec<UserSummary>, LiquidatorError> {
+    const QUERY: &str = r#"
+        SELECT * FROM (
+            SELECT
+                TRIM(aus.user_address) AS user_address,
+                aus.health_factor,
+                COALESCE(SUM(ap.supply_amount / POWER(10, ed.decimals) * ars.price_usd)
+                    FILTER (WHERE ap.collateral_enabled), 0) AS collateral_usd,
+                COALESCE(SUM(ap.borrow_amount / POWER(10, ed.decimals) * ars.price_usd), 0) AS debt_usd,
+                TO_CHAR(aus.updated_at, 'YYYY-MM-DD HH24:MI:SS') AS updated_at
+            FROM aavev3_users_stats aus
+            JOIN protocols_details pd ON aus.protocol_details_id = pd.id
+            LEFT JOIN aavev3_positions ap
+                ON ap.protocol_details_id = aus.protocol_details_id
+                AND ap.user_address = aus.user_address
+            LEFT JOIN aavev3_reserves_stats ars
+                ON ars.protocol_details_id = ap.protocol_details_id AND ars.reserve = ap.reserve
+            LEFT JOIN erc20_details ed ON ed.address = ap.reserve AND ed.network_id = pd.network_id
+            WHERE pd.network_id = $1
+              AND pd.protocol_id = $2
+              AND ($3::VARCHAR IS NULL OR aus.user_address = $3)
+              AND ($4::DOUBLE PRECISION IS NULL OR aus.health_factor <= $4)
+            GROUP BY aus.protocol_details_id, aus.user_address, aus.health_factor, aus.updated_at
+        ) users
+        ORDER BY
+            CASE $5::VARCHAR WHEN 'debt' THEN debt_usd WHEN 'collateral' THEN collateral_usd END DESC,
+            health_factor
+        LIMIT $6
+    "#;
+    sqlx::query_as::<_, UserSummary>(QUERY)
+        .bind(network)
+        .bind(protocol)
+        .bind(query.user.map(|user| user.to_string()))
+        .bind(query.max_health_factor)
+        .bind(query.sort.as_str())
+        .bind(query.limit)
+        .fetch_all(pool)
+        .await
+        .map_err(LiquidatorError::from)
+}
+
+pub async fn get_user_positions(
+    pool: &PgPool,
+    network: &str,
+    protocol: &str,
+    user: Address,
+) -> Result<Vec<UserPosition>, LiquidatorError> {
+    const QUERY: &str = r#"
+        SELECT
+            TRIM(ap.reserve) AS reserve,
+            e.symbol,
+            ap.supply_amount / POWER(10, ed.decimals) AS supply_amount,
+            ap.borrow_amount / POWER(10, ed.decimals) AS borrow_amount,
+            ap.collateral_enabled,
+            ars.price_usd,
+            ap.supply_amount / POWER(10, ed.decimals) * ars.price_usd AS supply_usd,
+            ap.borrow_amount / POWER(10, ed.decimals) * ars.price_usd AS borrow_usd
+        FROM aavev3_positions ap
+        JOIN protocols_details pd ON ap.protocol_details_id = pd.id
+        JOIN erc20_details ed ON ed.address = ap.reserve AND ed.network_id = pd.network_id
+        JOIN erc20 e ON e.id = ed.erc20_id
+        LEFT JOIN aavev3_reserves_stats ars
+            ON ars.protocol_details_id = ap.protocol_details_id AND ars.reserve = ap.reserve
+        WHERE pd.network_id = $1 AND pd.protocol_id = $2 AND ap.user_address = $3
+        ORDER BY borrow_usd DESC NULLS LAST, supply_usd DESC NULLS LAST
+    "#;
+    sqlx::query_as::<_, UserPosition>(QUERY)
+        .bind(network)
+        .bind(protocol)
+        .bind(user.to_string())
+        .fetch_all(pool)
+        .await
+        .map_err(LiquidatorError::from)
+}
+
+/// Every liquidation recorded in the window, ours, missed or anyone else's.
+pub async fn get_liquidations(
+    pool: &PgPool,
+    network: &str,
+    protocol: &str,
+    since_secs: u64,
+) -> Result<Vec<Liquidation>, LiquidatorError> {
+    const QUERY: &str = r#"
+        SELECT
+            TRIM(l.user_address) AS user_address,
+            TRIM(l.liquidator_address) AS liquidator_address,
+            ce.symbol AS collateral_symbol,
+            de.symbol AS debt_symbol,
+            l.debt_to_cover_usd,
+            l.liquidated_collateral_usd,
+            l.ours,
+            l.missed,
+            l.block_number,
+            TRIM(l.tx_hash) AS tx_hash,
+            TO_CHAR(l.timestamp, 'YYYY-MM-DD HH24:MI:SS') AS timestamp
+        FROM aavev3_liquidations l
+        JOIN protocols_details pd ON l.protocol_details_id = pd.id
+        JOIN erc20_details ced
+            ON ced.address = l.collateral_asset AND ced.network_id = pd.network_id
+        JOIN erc20 ce ON ce.id = ced.erc20_id
+        JOIN erc20_details ded
+            ON ded.address = l.debt_asset AND ded.network_id = pd.network_id
+        JOIN erc20 de ON de.id = ded.erc20_id
+        WHERE pd.network_id = $1
+          AND pd.protocol_id = $2
+          AND l.timestamp >= (NOW() AT TIME ZONE 'UTC') - MAKE_INTERVAL(secs => $3)
+        ORDER BY l.timestamp DESC
+    "#;
+    sqlx::query_as::<_, Liquidation>(QUERY)
+        .bind(network)
+        .bind(protocol)
+        .bind(since_secs as f64)
+        .fetch_all(pool)
+        .await
+        .map_err(LiquidatorError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_targets_and_addresses_are_errors() {
+        assert_eq!(
+            split_target("ethereum-aave_v3").unwrap(),
+            ("ethereum".to_string(), "aave_v3".to_string())
+        );
+        assert!(matches!(
+            split_target("ethereum"),
+            Err(LiquidatorError::Config(_))
+        ));
+        assert!(matches!(
+            parse_address("0xnot-an-address"),
+            Err(LiquidatorError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn users_rows_hold_each_user_and_position_once() {
+        let (weth, usdc) = (Address::with_last_byte(1), Address::with_last_byte(2));
+        let user = Address::with_last_byte(10);
+        let write = |health_factor, positions| UserWrite {
+            address: user,
+            protocol_details_id: 1,
+            health_factor,
+            positions,
+        };
+
+        let rows = UserRows::from(
+            &[
+                write(1.5, vec![(weth, 1.0, 0.0, true)]),
+                write(
+                    0.9,
+                    vec![
+                        (weth, 2.0, 0.0, true),
+                        (usdc, 0.0, 1.0, false),
+                        (weth, 3.0, 0.0, false),
+                    ],
+                ),
+            ][..],
+        );
+
+        assert_eq!(rows.users, vec![user.to_string()]);
+        assert_eq!(rows.health_factors, vec![0.9]);
+        assert_eq!(rows.position_users, vec![user.to_string(); 2]);
+        let mut positions = rows
+            .position_reserves
+            .into_iter()
+            .zip(rows.supply_amounts)
+            .zip(rows.collaterals)
+            .collect::<Vec<_>>();
+        positions.sort_by(|a, b| a.0 .0.cmp(&b.0 .0));
+        assert_eq!(
+            positions,
+            vec![
+                ((weth.to_string(), 3.0), false),
+                ((usdc.to_string(), 0.0), false)
+            ]
+        );
+    }
+
+    #[test]
+    fn users_rows_tell_protocols_apart() {
+        let weth = Address::with_last_byte(1);
+        let user = Address::with_last_byte(10);
+        let write = |protocol_details_id, health_factor| UserWrite {
+            address: user,
+            protocol_details_id,
+            health_factor,
+            positions: vec![(weth, 1.0, 0.0, true)],
+        };
+
+        // same address on aave and spark
+        let rows = UserRows::from(&[write(1, 1.5), write(2, 0.9)][..]);
+
+        let mut users = rows
+            .protocol_details_ids
+            .into_iter()
+            .zip(rows.health_factors)
+            .collect::<Vec<_>>();
+        users.sort_by_key(|user| user.0);
+        assert_eq!(users, vec![(1, 1.5), (2, 0.9)]);
+        let mut positions = rows.position_protocol_details_ids;
+        positions.sort();
+        assert_eq!(positions, vec![1, 2]);
+    }
+
+    #[test]
+    fn snapshot_keeps_reserves_of_users_not_evaluated_yet() {
+        let weth = Address::with_last_byte(1);
+        let usdc = Address::with_last_byte(2);
+        let (evaluated, restored) = (Address::with_last_byte(10), Address::with_last_byte(11));
+
+        let snapshot = FanaticSnapshot {
+            block_number: 21_000_000,
+            users: HashMap::from([
+                (
+                    evaluated,
+                    UserData {
+                        health_factor: 1.1,
+                        last_update: 1_700_000_000,
+                        positions: vec![UserReserve {
+                            underlying_asset: weth,
+                            scaled_a_token_balance: U256::from(10).pow(U256::from(18)),
+                            scaled_variable_debt: U256::ZERO,
+                            usage_as_collateral_enabled: true,
+                        }],
+                    },
+                ),
+                (restored, UserData::default()),
+            ]),
+            reserves_users: HashMap::from([
+                (weth, HashSet::from([evaluated])),
+                (usdc, HashSet::from([restored])),
+            ]),
+        };
+
+        let mut rows = snapshot_rows(&snapshot).unwrap();
+        assert_eq!(rows.users.len(), 2);
+        assert_eq!(rows.position_users.len(), 2);
+
+        let weth_position = rows
+            .position_reserves
+            .iter()
+            .position(|r| *r == weth.to_string())
+            .unwrap();
+        assert_eq!(
+            rows.scaled_a_token_balances.remove(weth_position),
+            Some(BigDecimal::from_str("1000000000000000000").unwrap())
+        );
+        assert_eq!(rows.collaterals.remove(weth_position), Some(true));
+        // known to be in usdc, balances unknown
+        assert_eq!(rows.scaled_a_token_balances, vec![None]);
+        assert_eq!(rows.collaterals, vec![None]);
+    }
+
+    #[test]
+    fn snapshot_keeps_reserves_members_missing_from_users() {
+        let usdc = Address::with_last_byte(2);
+        // digits only, their checksummed form sorts like the addresses
+        let (restored, unknown) = (Address::with_last_byte(0x21), Address::with_last_byte(0x22));
+
+        let snapshot = FanaticSnapshot {
+            block_number: 21_000_000,
+            users: HashMap::from([(restored, UserData::default())]),
+            // `unknown` was added to the reserve, the snapshot was taken before its evaluation
+            reserves_users: HashMap::from([(usdc, HashSet::from([restored, unknown]))]),
+        };
+
+        let rows = snapshot_rows(&snapshot).unwrap();
+        let mut users = rows
+            .users
+            .into_iter()
+            .zip(rows.health_factors)
+            .collect::<Vec<_>>();
+        users.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            users,
+            vec![(restored.to_string(), -1.0), (unknown.to_string(), -1.0)]
+        );
+
+        let mut position_users = rows.position_users;
+        position_users.sort();
+        assert_eq!(
+            position_users,
+            vec![restored.to_string(), unknown.to_string()]
+        );
+        assert_eq!(rows.scaled_a_token_balances, vec![None, None]);
     }
-    Ok(())
 }
@@ -1,7 +1,8 @@
 use actix::prelude::*;
-use alloy::{primitives::Address, providers::Provider};
+use alloy::primitives::{Address, TxHash};
+use serde::Serialize;
 
-use crate::actors::Fanatic;
+use crate::{flashloan::FlashloanSource, strategy::Strategy};
 
 #[derive(Message, Debug, Clone)]
 #[rtype(result = "eyre::Result<()>")]
@@ -10,3 +11,33 @@ pub struct LiquidationRequest {
     pub network: String,
     pub protocol: String,
 }
+
+/// Outcome of a liquidation executed against a fork, reported by the executor in simulation mode.
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulatedLiquidation {
+    // block the fork was at when the liquidation was sent
+    pub block_number: u64,
+    pub user_address: Address,
+    pub collateral_asset: Address,
+    pub debt_asset: Address,
+    pub debt_to_cover: f64,
+    pub strategy: Strategy,
+    pub flashloan_source: Option<FlashloanSource>,
+    pub flashloan_fee: f64,
+    pub success: bool,
+    pub tx_hash: Option<TxHash>,
+    pub gas_used: Option<u64>,
+    // debt asset left in the bot once the flashloan's repaid
+    pub profit: f64,
+    pub profit_usd: f64,
+    // as expected by the chosen strategy, before sending
+    pub estimated_profit_usd: f64,
+}
+
+/// Pauses the executor while aave's `PriceOracleSentinel` disallows liquidations, requests being
+/// queued until they're allowed again.
+#[derive(Message, Debug, Clone)]
+#[rtype(result = "()")]
+pub struct SetLiquidationsAllowed {
+    pub allowed: bool,
+}
@@ -1,6 +1,6 @@
 use crate::{actors::Executor, contracts};
 use actix::prelude::*;
-use alloy::primitives::Address;
+use alloy::primitives::{Address, TxHash};
 use alloy::providers::Provider;
 
 #[derive(Message, Debug, Clone)]
@@ -8,6 +8,8 @@ use alloy::providers::Provider;
 pub struct UpdateReservePrice {
     pub reserve: Address,
     pub new_price: f64,
+    // block of the `AnswerUpdated` log, the users' health factors are recorded as of it
+    pub block_number: Option<u64>,
 }
 
 #[derive(Message, Debug, Clone)]
@@ -15,11 +17,18 @@ pub struct UpdateReservePrice {
 pub struct UpdateReserveUser {
     pub reserve: Address,
     pub user_addr: Address,
+    // block of the log which touched the user, the health factor is recorded as of it
+    pub block_number: Option<u64>,
 }
 
 #[derive(Message)]
 #[rtype(result = "eyre::Result<()>")]
-pub struct DoSmthWithLiquidationCall(pub contracts::aave_v3::PoolContract::LiquidationCall);
+pub struct DoSmthWithLiquidationCall {
+    pub call: contracts::aave_v3::PoolContract::LiquidationCall,
+    pub block_number: u64,
+    pub tx_hash: TxHash,
+    pub log_index: u64,
+}
 
 #[derive(Message)]
 #[rtype(result = "eyre::Result<()>")]
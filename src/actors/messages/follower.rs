@@ -1,5 +1,8 @@
 use actix::prelude::*;
-use alloy::providers::Provider;
+use alloy::{
+    providers::Provider,
+    rpc::types::{Filter, Log},
+};
 
 use crate::actors::Fanatic;
 
@@ -14,3 +17,31 @@ pub struct StartListeningForEvents;
 #[derive(Message, Debug, Clone)]
 #[rtype(result = "()")]
 pub struct SendFanaticAddr<P: Provider + Unpin + Clone + 'static>(pub Addr<Fanatic<P>>);
+
+/// Filter matching every log the follower listens to, pool's and aggregators' alike.
+#[derive(Message, Debug, Clone)]
+#[rtype(result = "eyre::Result<Filter>")]
+pub struct GetReplayFilter;
+
+/// Replays the logs of a past block, in order, as if they were just received.
+/// Only handled in replay mode, writes are applied right away as the block is final.
+#[derive(Message, Debug, Clone)]
+#[rtype(result = "()")]
+pub struct ReplayBlock {
+    pub number: u64,
+    pub logs: Vec<Log>,
+}
+
+/// Highest block the live subscriptions got to, `None` before the first one or while
+/// backfilling, i.e when it doesn't tell what's been processed.
+#[derive(Message, Debug, Clone)]
+#[rtype(result = "Option<u64>")]
+pub struct GetLastBlock;
+
+/// Replays the logs from `from` to the head, i.e those emitted while the bot was down. Resolves to
+/// the last block replayed.
+#[derive(Message, Debug, Clone)]
+#[rtype(result = "eyre::Result<u64>")]
+pub struct Backfill {
+    pub from: u64,
+}
@@ -2,3 +2,4 @@ pub mod database;
 pub mod executor;
 pub mod fanatic;
 pub mod follower;
+pub mod watchdog;
@@ -0,0 +1,8 @@
+use actix::prelude::*;
+
+/// Sent by a supervised actor as it's being restarted.
+#[derive(Message, Debug, Clone)]
+#[rtype(result = "()")]
+pub struct Restarted {
+    pub actor: &'static str,
+}
@@ -4,8 +4,12 @@ pub mod database;
 pub mod executor;
 pub mod fanatic;
 pub mod follower;
+pub mod monitor;
+pub mod watchdog;
 
 pub use database::Database;
 pub use executor::Executor;
 pub use fanatic::Fanatic;
 pub use follower::Follower;
+pub use monitor::Monitor;
+pub use watchdog::Watchdog;
@@ -0,0 +1,306 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+
+use actix::prelude::*;
+use alloy::{
+    eips::BlockNumberOrTag, primitives::Address, providers::Provider,
+    rpc::types::BlockTransactionsKind,
+};
+use serde::Serialize;
+use tokio::time::Duration;
+use tracing::{error, info, warn};
+
+use super::{
+    messages::{
+        database::{GetReserves, Reserve, UpdatePriceStaleness},
+        watchdog::Restarted,
+    },
+    watchdog::catch_panics,
+    Database, Watchdog,
+};
+use crate::{configs::MonitorConfig, contracts, metrics::METRICS};
+
+// aggregators update within a few blocks of their heartbeat elapsing, not on the dot
+const HEARTBEAT_SLACK_SECS: u64 = 60;
+
+/// Last known state of each aggregator, by address. Shared with the HTTP API's `/oracles`.
+pub type Feeds = Arc<RwLock<BTreeMap<Address, FeedStatus>>>;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FeedStatus {
+    pub aggregator: Address,
+    // reserves priced by the aggregator
+    pub reserves: Vec<Address>,
+    // `latestRoundData().updatedAt`
+    pub updated_at: u64,
+    pub age_secs: u64,
+    pub heartbeat_secs: u64,
+    pub deviation_bps: u32,
+    pub stale: bool,
+}
+
+/// Watches the Chainlink aggregators behind the reserves' oracles, flagging the reserves whose
+/// aggregator went past its heartbeat so that the executor leaves them alone.
+#[derive(Debug, Clone)]
+pub struct Monitor<P: Provider + Unpin + Clone + 'static> {
+    provider: P,
+    db_addr: Addr<Database>,
+    target: String,
+
+    // defaults, overridden per reserve
+    heartbeat_secs: u64,
+    deviation_bps: u32,
+    check_interval: Duration,
+
+    feeds: Feeds,
+}
+
+impl<P: Provider + Unpin + Clone + 'static> Actor for Monitor<P> {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(self.check_interval, |actor, ctx| {
+            let monitor = actor.clone();
+
+            let fut = async move {
+                if let Err(e) = monitor.check_feeds().await {
+                    error!("Failed to check oracle feeds: {}", e);
+                }
+                Ok(())
+            };
+
+            ctx.spawn(catch_panics(fut).into_actor(actor).map(|res, _, ctx| {
+                if let Err(e) = res {
+                    error!("Oracle feeds check failed: {:?}", e);
+                    ctx.stop();
+                }
+            }));
+        });
+    }
+}
+
+impl<P: Provider + Unpin + Clone + 'static> Supervised for Monitor<P> {
+    // `feeds` survive, stale/recovered transitions are still told apart after a restart
+    fn restarting(&mut self, _: &mut Self::Context) {
+        warn!("[restarting] Monitor");
+        Watchdog::from_registry().do_send(Restarted { actor: "monitor" });
+    }
+}
+
+impl<P: Provider + Unpin + Clone + 'static> Monitor<P> {
+    pub fn new(config: MonitorConfig<P>) -> Self {
+        Monitor {
+            provider: config.provider,
+            db_addr: config.db_addr,
+            target: config.target,
+            heartbeat_secs: config.heartbeat_secs,
+            deviation_bps: config.deviation_bps,
+            check_interval: config.check_interval,
+            feeds: config.feeds,
+        }
+    }
+
+    async fn check_feeds(&self) -> eyre::Result<()> {
+        let reserves = self
+            .db_addr
+            .send(GetReserves(self.target.clone()))
+            .await??;
+
+        let mut by_aggregator: BTreeMap<Address, Vec<&Reserve>> = BTreeMap::new();
+        for reserve in &reserves {
+            if let Some(aggregator) = reserve.aggregator_addr {
+                by_aggregator.entry(aggregator).or_default().push(reserve);
+            }
+        }
+
+        // the chain's clock rather than ours, `updatedAt` is a block timestamp
+        let now = self
+            .provider
+            .get_block_by_number(BlockNumberOrTag::Latest, BlockTransactionsKind::Hashes)
+            .await?
+            .ok_or(eyre::eyre!("No latest block"))?
+            .header
+            .timestamp;
+
+        let (mut stale, mut fresh) = (Vec::new(), Vec::new());
+        for (aggregator, reserves) in by_aggregator {
+            let round = match contracts::chainlink::OffchainAggregatorContract::new(
+                aggregator,
+                self.provider.clone(),
+            )
+            .latestRoundData()
+            .call()
+            .await
+            {
+                Ok(round) => round,
+                Err(e) => {
+                    warn!(?aggregator, error = ?e, "Failed to fetch latest round");
+                    continue;
+                }
+            };
+
+            let overrides = reserves
+                .iter()
+                .map(|r| (r.oracle_heartbeat_secs, r.oracle_deviation_bps))
+                .collect::<Vec<_>>();
+            let (heartbeat_secs, deviation_bps) =
+                feed_parameters(&overrides, self.heartbeat_secs, self.deviation_bps);
+            let updated_at = round.updatedAt.saturating_to::<u64>();
+            let status = FeedStatus {
+                aggregator,
+                reserves: reserves.iter().map(|r| r.reserve).collect(),
+                updated_at,
+                age_secs: now.saturating_sub(updated_at),
+                heartbeat_secs,
+                deviation_bps,
+                stale: is_overdue(updated_at, now, heartbeat_secs),
+            };
+
+            let was_stale = self
+                .feeds
+                .read()
+                .unwrap()
+                .get(&aggregator)
+                .is_some_and(|previous| previous.stale);
+            match (was_stale, status.stale) {
+                (false, true) => warn!(
+                    ?aggregator,
+                    reserves = ?status.reserves,
+                    age_secs = status.age_secs,
+                    heartbeat_secs,
+                    "oracle feed overdue, prices are stale"
+                ),
+                (true, false) => info!(?aggregator, "oracle feed updated again"),
+                _ => {}
+            }
+
+            record_metrics(&status);
+            if status.stale {
+                stale.extend(&status.reserves);
+            } else {
+                fresh.extend(&status.reserves);
+            }
+            self.feeds.write().unwrap().insert(aggregator, status);
+        }
+
+        self.db_addr
+            .send(UpdatePriceStaleness {
+                target: self.target.clone(),
+                stale,
+                fresh,
+            })
+            .await??;
+
+        Ok(())
+    }
+}
+
+/// Heartbeat and deviation threshold of an aggregator, the strictest of its reserves'
+/// `(heartbeat_secs, deviation_bps)` overrides or the defaults.
+pub fn feed_parameters(
+    overrides: &[(Option<i32>, Option<i32>)],
+    default_heartbeat_secs: u64,
+    default_deviation_bps: u32,
+) -> (u64, u32) {
+    let heartbeat_secs = overrides
+        .iter()
+        .filter_map(|(secs, _)| *secs)
+        .min()
+        .map_or(default_heartbeat_secs, |secs| secs as u64);
+    let deviation_bps = overrides
+        .iter()
+        .filter_map(|(_, bps)| *bps)
+        .min()
+        .map_or(default_deviation_bps, |bps| bps as u32);
+
+    (heartbeat_secs, deviation_bps)
+}
+
+pub fn is_overdue(updated_at: u64, now: u64, heartbeat_secs: u64) -> bool {
+    now.saturating_sub(updated_at) > heartbeat_secs + HEARTBEAT_SLACK_SECS
+}
+
+fn record_metrics(status: &FeedStatus) {
+    let labels = [("aggregator", status.aggregator.to_string())];
+
+    METRICS.set_gauge(
+        "oracle_feed_age_seconds",
+        "Seconds since the aggregator's last update",
+        &labels,
+        status.age_secs as f64,
+    );
+    METRICS.set_gauge(
+        "oracle_feed_heartbeat_seconds",
+        "Heartbeat the aggregator is expected to update within",
+        &labels,
+        status.heartbeat_secs as f64,
+    );
+    METRICS.set_gauge(
+        "oracle_feed_deviation_threshold_bps",
+        "Price move triggering an update of the aggregator",
+        &labels,
+        status.deviation_bps as f64,
+    );
+    METRICS.set_gauge(
+        "oracle_feed_stale",
+        "1 while the aggregator is overdue",
+        &labels,
+        if status.stale { 1.0 } else { 0.0 },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feeds_are_overdue_past_their_heartbeat_and_some_slack() {
+        assert!(!is_overdue(1_000, 1_000 + 3600, 3600));
+        assert!(!is_overdue(
+            1_000,
+            1_000 + 3600 + HEARTBEAT_SLACK_SECS,
+            3600
+        ));
+        assert!(is_overdue(
+            1_000,
+            1_000 + 3600 + HEARTBEAT_SLACK_SECS + 1,
+            3600
+        ));
+        // answered in a block ahead of ours
+        assert!(!is_overdue(1_000, 900, 3600));
+    }
+
+    #[test]
+    fn stablecoin_feeds_arent_stale_between_daily_updates() {
+        #[derive(clap::Parser)]
+        struct Cli {
+            #[command(flatten)]
+            monitor: crate::args::MonitorArgs,
+        }
+        let args = <Cli as clap::Parser>::parse_from(["liquidator"]).monitor;
+
+        // USDC/USD has no override, its 24h heartbeat is the default one
+        let (heartbeat_secs, _) = feed_parameters(
+            &[(None, None)],
+            args.oracle_heartbeat_secs,
+            args.oracle_deviation_bps,
+        );
+        assert!(!is_overdue(1_000, 1_000 + 2 * 3600, heartbeat_secs));
+        assert!(is_overdue(1_000, 1_000 + 25 * 3600, heartbeat_secs));
+    }
+
+    #[test]
+    fn strictest_override_wins_over_the_defaults() {
+        assert_eq!(feed_parameters(&[(None, None)], 1200, 50), (1200, 50));
+        assert_eq!(
+            feed_parameters(
+                &[(None, None), (Some(86400), Some(200)), (Some(3600), None)],
+                1200,
+                50
+            ),
+            (3600, 200)
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use std::{collections::VecDeque, future::Future, panic::AssertUnwindSafe};
+
+use actix::prelude::*;
+use futures_util::FutureExt;
+use tokio::time::{Duration, Instant};
+use tracing::{error, warn};
+
+use super::messages::watchdog::Restarted;
+
+// more restarts than this within the window and we give up
+const MAX_RESTARTS: usize = 5;
+const RESTART_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// Counts the supervised actors' restarts, exits the process once they keep failing.
+///
+/// Reached through the system registry, i.e `Watchdog::from_registry()`, so the actors don't need
+/// its address.
+#[derive(Debug)]
+pub struct Watchdog {
+    restarts: RestartBudget,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Watchdog {
+            restarts: RestartBudget::new(MAX_RESTARTS, RESTART_WINDOW),
+        }
+    }
+}
+
+impl Actor for Watchdog {
+    type Context = Context<Self>;
+}
+
+impl Supervised for Watchdog {}
+
+impl SystemService for Watchdog {}
+
+impl Handler<Restarted> for Watchdog {
+    type Result = ();
+
+    fn handle(&mut self, msg: Restarted, _: &mut Context<Self>) -> Self::Result {
+        warn!(actor = msg.actor, "actor restarted");
+
+        if self.restarts.exhausted(Instant::now()) {
+            error!(
+                actor = msg.actor,
+                max = MAX_RESTARTS,
+                window = ?RESTART_WINDOW,
+                "actors keep failing, exiting"
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Sliding window of restarts.
+#[derive(Debug)]
+pub struct RestartBudget {
+    max: usize,
+    window: Duration,
+    restarts: VecDeque<Instant>,
+}
+
+impl RestartBudget {
+    pub fn new(max: usize, window: Duration) -> Self {
+        RestartBudget {
+            max,
+            window,
+            restarts: VecDeque::new(),
+        }
+    }
+
+    /// Records a restart at `now`, true once there's been more than `max` within the window.
+    pub fn exhausted(&mut self, now: Instant) -> bool {
+        while self
+            .restarts
+            .front()
+            .is_some_and(|at| now.duration_since(*at) > self.window)
+        {
+            self.restarts.pop_front();
+        }
+        self.restarts.push_back(now);
+
+        self.restarts.len() > self.max
+    }
+}
+
+/// Turns a panic of `fut` into an error, so that the actor running it can stop and be restarted
+/// by its supervisor rather than silently lose the future.
+pub async fn catch_panics<F, T>(fut: F) -> eyre::Result<T>
+where
+    F: Future<Output = eyre::Result<T>>,
+{
+    match AssertUnwindSafe(fut).catch_unwind().await {
+        Ok(res) => res,
+        Err(panic) => {
+            let reason = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(eyre::eyre!("panicked: {}", reason))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restarts_outside_the_window_are_forgiven() {
+        let mut budget = RestartBudget::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+
+        assert!(!budget.exhausted(start));
+        assert!(!budget.exhausted(start + Duration::from_secs(10)));
+        assert!(budget.exhausted(start + Duration::from_secs(20)));
+
+        // the first two fell out of the window
+        assert!(!budget.exhausted(start + Duration::from_secs(75)));
+    }
+
+    #[tokio::test]
+    async fn panics_become_errors() {
+        let res = catch_panics::<_, ()>(async { panic!("no fanatic_addr found") }).await;
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "panicked: no fanatic_addr found"
+        );
+
+        assert!(catch_panics(async { Ok(()) }).await.is_ok());
+
+        // a handler's own error isn't a panic
+        let res = catch_panics(async { Ok(Err::<(), _>(eyre::eyre!("reverted"))) }).await;
+        assert_eq!(res.unwrap().unwrap_err().to_string(), "reverted");
+    }
+}
@@ -0,0 +1,105 @@
+//! HTTP API, for probes and dashboards:
+//! - `GET /health`
+//! - `GET /metrics`, in Prometheus' text format
+//! - `GET /oracles`, the monitored aggregators
+
+use std::net::SocketAddr;
+
+use actix_web::{web, App, HttpResponse, HttpServer, Responder};
+
+use crate::{actors::monitor::Feeds, metrics::METRICS};
+
+#[derive(Debug, Clone)]
+pub struct ApiState {
+    pub feeds: Feeds,
+}
+
+pub async fn serve(addr: SocketAddr, state: ApiState) -> std::io::Result<()> {
+    HttpServer::new(move || app(state.clone()))
+        .workers(1)
+        // `run` handles SIGINT/SIGTERM
+        .disable_signals()
+        .bind(addr)?
+        .run()
+        .await
+}
+
+fn app(
+    state: ApiState,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    App::new()
+        .app_data(web::Data::new(state))
+        .route("/health", web::get().to(health))
+        .route("/metrics", web::get().to(metrics))
+        .route("/oracles", web::get().to(oracles))
+}
+
+async fn health() -> impl Responder {
+    HttpResponse::Ok().body("ok")
+}
+
+async fn metrics() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(METRICS.render())
+}
+
+async fn oracles(state: web::Data<ApiState>) -> impl Responder {
+    let feeds = state
+        .feeds
+        .read()
+        .unwrap()
+        .values()
+        .cloned()
+        .collect::<Vec<_>>();
+    HttpResponse::Ok().json(feeds)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, sync::RwLock};
+
+    use actix_web::test;
+    use alloy::primitives::address;
+
+    use super::*;
+    use crate::actors::monitor::FeedStatus;
+
+    #[actix_web::test]
+    async fn oracles_lists_the_monitored_feeds() {
+        let aggregator = address!("0xE62B71cf983019BFf55bC83B48601ce8419650CC");
+        let feeds = Feeds::new(RwLock::new(BTreeMap::from([(
+            aggregator,
+            FeedStatus {
+                aggregator,
+                reserves: vec![address!("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")],
+                updated_at: 1_700_000_000,
+                age_secs: 7200,
+                heartbeat_secs: 3600,
+                deviation_bps: 50,
+                stale: true,
+            },
+        )])));
+        let app = test::init_service(app(ApiState { feeds })).await;
+
+        let health =
+            test::call_service(&app, test::TestRequest::get().uri("/health").to_request()).await;
+        assert!(health.status().is_success());
+
+        let oracles: serde_json::Value = test::call_and_read_body_json(
+            &app,
+            test::TestRequest::get().uri("/oracles").to_request(),
+        )
+        .await;
+        assert_eq!(oracles[0]["stale"], true);
+        assert_eq!(oracles[0]["age_secs"], 7200);
+    }
+}
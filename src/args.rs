@@ -1,10 +1,17 @@
+use std::{net::SocketAddr, path::PathBuf};
+
 use alloy::primitives::Address;
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use secrecy::SecretString;
 
+use crate::output::Format;
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     #[arg(long, env = "WS_URL", default_value = "ws://localhost:8545")]
     pub ws_url: SecretString,
 
@@ -29,12 +36,407 @@ pub struct Args {
     )]
     pub protocol: String,
 
+    // required to run the bot, not by the read-only subcommands
     #[arg(long, env = "ACCOUNT_PUBKEY")]
-    pub account_pubkey: Address,
+    pub account_pubkey: Option<Address>,
 
     #[arg(long, env = "ACCOUNT_PRIVKEY")]
-    pub account_privkey: SecretString,
+    pub account_privkey: Option<SecretString>,
 
     #[arg(long, env = "BOT_ADDR")]
-    pub bot_addr: Address,
+    pub bot_addr: Option<Address>,
+
+    #[arg(
+        long,
+        env = "CONFIRMATIONS",
+        default_value_t = 2,
+        help = "Blocks a log must be buried under before its db writes are applied"
+    )]
+    pub confirmations: u64,
+
+    #[arg(
+        long,
+        env = "HTTP_ADDR",
+        default_value = "127.0.0.1:8080",
+        help = "Address the HTTP API (/health, /metrics, /oracles) listens on"
+    )]
+    pub http_addr: SocketAddr,
+
+    #[command(flatten)]
+    pub execution: ExecutionArgs,
+
+    #[command(flatten)]
+    pub monitor: MonitorArgs,
+
+    #[command(flatten)]
+    pub history: HistoryArgs,
+}
+
+/// Bounds of the liquidations sent, by the bot as well as `backtest`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct ExecutionArgs {
+    #[arg(
+        long,
+        env = "SLIPPAGE_BPS",
+        default_value_t = 50,
+        value_parser = clap::value_parser!(u32).range(0..=10_000),
+        help = "Slippage tolerated on the collateral swap, off the quoted output"
+    )]
+    pub slippage_bps: u32,
+
+    #[arg(
+        long,
+        env = "MIN_PROFIT_USD",
+        default_value_t = 0.0,
+        help = "Liquidations bringing back less than the flashloan plus this are skipped, and revert"
+    )]
+    pub min_profit_usd: f64,
+
+    #[arg(
+        long,
+        env = "HOLD_ATOKENS",
+        value_delimiter = ',',
+        help = "Collaterals whose aToken the bot keeps, repaying out of its own balance, when that's the most profitable"
+    )]
+    pub hold_atokens: Vec<Address>,
+}
+
+/// Oracle feeds' expected cadence, overridden per reserve by `aavev3_reserves.oracle_heartbeat_secs`
+/// and `oracle_deviation_bps`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct MonitorArgs {
+    #[arg(
+        long,
+        env = "ORACLE_HEARTBEAT_SECS",
+        default_value_t = 86400,
+        help = "Aggregators not updated for longer than this are considered stale, the USD stablecoins' \
+                feeds only update daily"
+    )]
+    pub oracle_heartbeat_secs: u64,
+
+    #[arg(
+        long,
+        env = "ORACLE_DEVIATION_BPS",
+        default_value_t = 50,
+        help = "Price move triggering an aggregator update, i.e how far off a fresh price may be"
+    )]
+    pub oracle_deviation_bps: u32,
+
+    #[arg(
+        long,
+        env = "ORACLE_CHECK_SECS",
+        default_value_t = 30,
+        help = "Interval between two checks of the aggregators' latestRoundData"
+    )]
+    pub oracle_check_secs: u64,
+}
+
+/// Policies of the health factors, prices and reserve rates history tables.
+#[derive(clap::Args, Debug, Clone)]
+pub struct HistoryArgs {
+    #[arg(
+        long,
+        env = "HISTORY_RETENTION",
+        default_value = "90d",
+        value_parser = parse_duration,
+        help = "History rows older than this are deleted (e.g., 30d, 12w)"
+    )]
+    pub history_retention: u64,
+
+    #[arg(
+        long,
+        env = "HISTORY_DOWNSAMPLE_AFTER",
+        default_value = "7d",
+        value_parser = parse_duration,
+        help = "History rows older than this are thinned out to one per bucket"
+    )]
+    pub history_downsample_after: u64,
+
+    #[arg(
+        long,
+        env = "HISTORY_DOWNSAMPLE_BUCKET",
+        default_value = "1h",
+        value_parser = parse_duration,
+        help = "Downsampled history keeps the latest row of each user or reserve per bucket"
+    )]
+    pub history_downsample_bucket: u64,
+}
+
+/// Runs the bot when none is given.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Competitor analytics over the recorded liquidations
+    Report(ReportArgs),
+    /// Replays a block range against an anvil fork and compares with the actual liquidations
+    Backtest(BacktestArgs),
+    /// Moves prices on an anvil node (`--ws-url`) through mocked aggregators
+    Sim(SimArgs),
+    /// Evaluated users, riskiest first
+    Users(UsersArgs),
+    /// A user's positions and health factor history
+    User(UserArgs),
+    /// Reserves' price, liquidation parameters and indices
+    Reserves(ReservesArgs),
+    /// Liquidations recorded on the protocol, whoever sent them
+    Liquidations(LiquidationsArgs),
+    /// Checks a user and liquidates them through the bot, without following the protocol
+    Liquidate(LiquidateArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ReportArgs {
+    #[arg(
+        long,
+        default_value = "24h",
+        value_parser = parse_duration,
+        help = "Time window to report on (e.g., 90m, 24h, 7d)"
+    )]
+    pub since: u64,
+
+    #[arg(
+        long,
+        default_value_t = 10,
+        help = "Number of liquidators to report on"
+    )]
+    pub limit: i64,
+
+    #[arg(
+        long,
+        default_value_t = 3,
+        help = "Number of (collateral, debt) pairs to show per liquidator"
+    )]
+    pub focus: i64,
+
+    #[arg(long, value_enum, default_value_t = Format::Table)]
+    pub format: Format,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct BacktestArgs {
+    #[arg(long)]
+    pub from_block: u64,
+
+    #[arg(long)]
+    pub to_block: u64,
+
+    #[arg(
+        long,
+        env = "FORK_URL",
+        help = "Archive node to fork from and fetch the historical logs from"
+    )]
+    pub fork_url: String,
+
+    #[arg(
+        long,
+        env = "SWAP_ROUTER",
+        default_value = "0xE592427A0AEce92De3Edee1F18E0157C05861564",
+        help = "Uniswap's SwapRouter the bot is deployed with on the fork"
+    )]
+    pub swap_router: Address,
+
+    #[arg(long, value_enum, default_value_t = Format::Table)]
+    pub format: Format,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct SimArgs {
+    #[command(subcommand)]
+    pub action: SimAction,
+
+    #[arg(long, value_enum, default_value_t = Format::Table, global = true)]
+    pub format: Format,
+}
+
+/// Prices are the aggregator's answer, i.e the reserve's USD price unless it goes through an
+/// adapter (e.g the LSTs are priced off ETH/USD).
+#[derive(Subcommand, Debug)]
+pub enum SimAction {
+    /// Replaces reserves' aggregators with the mock, keeping their latest answer
+    Mock {
+        #[arg(required = true, help = "Reserves, by address or symbol")]
+        reserves: Vec<String>,
+    },
+    /// Sets a reserve's price, optionally walking there over several blocks
+    SetPrice {
+        #[arg(help = "Reserve, by address or symbol")]
+        reserve: String,
+
+        #[arg(long, required_unless_present = "change", conflicts_with = "change")]
+        price: Option<f64>,
+
+        #[arg(
+            long,
+            allow_hyphen_values = true,
+            help = "Change relative to the current price, in percent (e.g., -20)"
+        )]
+        change: Option<f64>,
+
+        #[arg(
+            long,
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u64).range(1..),
+            help = "Blocks to get there in, one update per block"
+        )]
+        blocks: u64,
+    },
+    /// Plays a price path from a CSV of `block,reserve,price` rows, blocks counted from the start
+    Path { csv: PathBuf },
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum UserSort {
+    /// Lowest health factor first
+    #[default]
+    Hf,
+    /// Largest debt first
+    Debt,
+    /// Largest collateral first
+    Collateral,
+}
+
+impl UserSort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserSort::Hf => "hf",
+            UserSort::Debt => "debt",
+            UserSort::Collateral => "collateral",
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+pub struct UsersArgs {
+    #[arg(long, help = "Only users with a health factor at or below this")]
+    pub max_hf: Option<f64>,
+
+    #[arg(long, value_enum, default_value_t = UserSort::Hf)]
+    pub sort: UserSort,
+
+    #[arg(long, default_value_t = 50)]
+    pub limit: i64,
+
+    #[arg(long, value_enum, default_value_t = Format::Table)]
+    pub format: Format,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct UserArgs {
+    pub address: Address,
+
+    #[arg(
+        long,
+        default_value = "7d",
+        value_parser = parse_duration,
+        help = "Health factor history to show (e.g., 24h, 30d)"
+    )]
+    pub since: u64,
+
+    #[arg(long, value_enum, default_value_t = Format::Table)]
+    pub format: Format,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ReservesArgs {
+    #[arg(long, value_enum, default_value_t = Format::Table)]
+    pub format: Format,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct LiquidationsArgs {
+    #[arg(
+        long,
+        default_value = "24h",
+        value_parser = parse_duration,
+        help = "Time window to list (e.g., 90m, 24h, 7d)"
+    )]
+    pub since: u64,
+
+    #[arg(long, value_enum, default_value_t = Format::Table)]
+    pub format: Format,
+}
+
+/// The pair and amount default to what the executor would pick.
+#[derive(clap::Args, Debug)]
+pub struct LiquidateArgs {
+    pub user: Address,
+
+    #[arg(long, help = "Debt asset to repay, by address or symbol")]
+    pub debt_asset: Option<String>,
+
+    #[arg(long, help = "Collateral asset to seize, by address or symbol")]
+    pub collateral_asset: Option<String>,
+
+    #[arg(
+        long,
+        help = "Debt to cover, in units of the debt asset (e.g., 1500.5), half the debt by default"
+    )]
+    pub amount: Option<f64>,
+
+    #[arg(long, help = "Only print the liquidation, without sending it")]
+    pub dry_run: bool,
+
+    #[arg(long, value_enum, default_value_t = Format::Table)]
+    pub format: Format,
+}
+
+/// Parses `<n><unit>` with unit one of `s`, `m`, `h`, `d`, `w` into seconds.
+pub fn parse_duration(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let value: u64 = value
+        .parse()
+        .map_err(|_| format!("invalid duration `{s}`, expected e.g 24h"))?;
+
+    let multiplier = match unit {
+        "s" | "" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "invalid duration unit `{unit}`, expected s, m, h, d or w"
+            ))
+        }
+    };
+    Ok(value * multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_are_parsed_into_seconds() {
+        assert_eq!(parse_duration("90"), Ok(90));
+        assert_eq!(parse_duration("30s"), Ok(30));
+        assert_eq!(parse_duration("15m"), Ok(15 * 60));
+        assert_eq!(parse_duration(" 24h "), Ok(24 * 60 * 60));
+        assert_eq!(parse_duration("7d"), Ok(7 * 24 * 60 * 60));
+        assert_eq!(parse_duration("2w"), Ok(14 * 24 * 60 * 60));
+
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("-1d").is_err());
+        assert!(parse_duration("1.5h").is_err());
+        assert!(parse_duration("3y").is_err());
+    }
+
+    #[test]
+    fn slippage_is_bounded_by_the_whole_quote() {
+        #[derive(clap::Parser)]
+        struct Cli {
+            #[command(flatten)]
+            execution: ExecutionArgs,
+        }
+        let parse = |bps: &str| {
+            <Cli as clap::Parser>::try_parse_from(["liquidator", "--slippage-bps", bps])
+                .map(|cli| cli.execution.slippage_bps)
+        };
+
+        assert_eq!(parse("0").unwrap(), 0);
+        assert_eq!(parse("10000").unwrap(), 10_000);
+        assert!(parse("10001").is_err());
+        assert!(parse("-1").is_err());
+    }
 }
@@ -0,0 +1,440 @@
+//! `backtest` subcommand, replays a block range through the same `Follower` → `Fanatic` →
+//! `Executor` flow against an anvil fork, and compares what the bot would've done with the
+//! `LiquidationCall`s which actually happened.
+//!
+//! The fork is reset to every block holding logs before they're replayed, so the actors observe
+//! the chain as it was right after that block. The executor sends its liquidations to the fork
+//! and reports their outcome instead of simulating them with `eth_call`.
+//!
+//! Actors write as they do live, but to a throwaway schema of `--database-url` which is dropped
+//! once done, leaving the bot's own tables untouched.
+
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use actix::prelude::*;
+use alloy::{
+    network::EthereumWallet,
+    node_bindings::Anvil,
+    primitives::{Address, TxHash, U256},
+    providers::{ext::AnvilApi, Provider, ProviderBuilder, WsConnect},
+    rpc::types::{anvil::Forking, Log},
+    signers::local::PrivateKeySigner,
+    sol_types::SolEvent,
+};
+use serde::Serialize;
+use sqlx::{postgres::PgPoolOptions, PgPool};
+use tokio::sync::mpsc::unbounded_channel;
+use tracing::info;
+
+use crate::{
+    actors::{
+        messages::{
+            database,
+            executor::SimulatedLiquidation,
+            fanatic::SendExecutorAddr,
+            follower::{GetReplayFilter, ReplayBlock, SendFanaticAddr},
+        },
+        Database, Executor, Fanatic, Follower,
+    },
+    args::{BacktestArgs, ExecutionArgs},
+    client::{AaveV3Client, LendingProtocolClient},
+    configs::{DatabaseConfig, ExecutorConfig, FanaticConfig, FollowerConfig},
+    contracts,
+    output::{self, Table},
+};
+
+// blocks per `eth_getLogs` request
+const LOGS_CHUNK_SIZE: u64 = 1_000;
+
+#[derive(Debug, Serialize)]
+pub struct ActualLiquidation {
+    pub block_number: u64,
+    pub tx_hash: Option<TxHash>,
+    pub user_address: Address,
+    pub liquidator_address: Address,
+    pub collateral_asset: Address,
+    pub debt_asset: Address,
+    pub debt_to_cover: String,
+    // we'd have successfully liquidated that user in an earlier block
+    pub caught: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub from_block: u64,
+    pub to_block: u64,
+    pub blocks_replayed: usize,
+    pub logs_replayed: usize,
+    pub attempts: usize,
+    pub successful: usize,
+    pub failed: usize,
+    pub profit_usd: f64,
+    pub actual_liquidations: usize,
+    pub caught: usize,
+    pub missed: usize,
+    // successful attempts on users nobody liquidated afterwards within the range
+    pub unmatched: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Backtest {
+    pub summary: Summary,
+    pub simulated: Vec<SimulatedLiquidation>,
+    pub actual: Vec<ActualLiquidation>,
+}
+
+/// Schema the backtest's actors write to, with the migrations applied.
+struct ScratchSchema {
+    pool: PgPool,
+    name: String,
+}
+
+impl ScratchSchema {
+    async fn create(admin: &PgPool) -> eyre::Result<Self> {
+        let name = format!(
+            "liquidator_backtest_{}_{}",
+            std::process::id(),
+            SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos()
+        );
+        sqlx::query(&format!("CREATE SCHEMA {name}"))
+            .execute(admin)
+            .await?;
+
+        let options = (*admin.connect_options())
+            .clone()
+            .options([("search_path", name.as_str())]);
+        let pool = PgPoolOptions::new().connect_with(options).await?;
+        let schema = ScratchSchema { pool, name };
+        if let Err(e) = sqlx::migrate!("./migrations").run(&schema.pool).await {
+            schema.drop(admin).await?;
+            return Err(e.into());
+        }
+
+        Ok(schema)
+    }
+
+    async fn drop(self, admin: &PgPool) -> eyre::Result<()> {
+        self.pool.close().await;
+        sqlx::query(&format!("DROP SCHEMA {} CASCADE", self.name))
+            .execute(admin)
+            .await?;
+
+        Ok(())
+    }
+}
+
+pub async fn backtest(
+    pool: &PgPool,
+    target: &str,
+    args: &BacktestArgs,
+    execution: &ExecutionArgs,
+) -> eyre::Result<()> {
+    if args.from_block > args.to_block {
+        return Err(eyre::eyre!("--from-block must be lower than --to-block"));
+    }
+
+    let scratch = ScratchSchema::create(pool).await?;
+    info!(schema = scratch.name, "replaying into a scratch schema");
+    let backtest = replay(&scratch.pool, target, args, execution).await;
+    scratch.drop(pool).await?;
+
+    let backtest = backtest?;
+    output::print(args.format, &backtest, &tables(&backtest))
+}
+
+async fn replay(
+    pool: &PgPool,
+    target: &str,
+    args: &BacktestArgs,
+    execution: &ExecutionArgs,
+) -> eyre::Result<Backtest> {
+    let anvil = Anvil::new()
+        .fork(args.fork_url.clone())
+        .args(["--fork-block-number", &args.from_block.to_string()])
+        .try_spawn()?;
+    let signer: PrivateKeySigner = anvil.keys()[0].clone().into();
+    let owner = signer.address();
+    let provider = ProviderBuilder::new()
+        .wallet(EthereumWallet::from(signer))
+        .on_ws(WsConnect::new(anvil.ws_endpoint()))
+        .await?;
+    // historical logs come from the archive node, the fork only knows about its own blocks
+    let source = ProviderBuilder::new().on_builtin(&args.fork_url).await?;
+
+    let db_addr = Database::new(DatabaseConfig {
+        pool: Arc::new(pool.clone()),
+        history: None,
+    })
+    .await
+    .start();
+
+    // the bot is deployed once, its code and owner are restored after every reset
+    let contracts = db_addr
+        .send(database::GetProtocolContracts(target.to_string()))
+        .await??;
+    let addresses_provider = contracts
+        .get("PoolAddressesProvider")
+        .ok_or(eyre::eyre!("Missing PoolAddressesProvider address"))?;
+    contracts::liquidator::ensure_bytecode_matches_abi()?;
+    let bot = contracts::liquidator::LiquidatoorContract::deploy(
+        provider.clone(),
+        *addresses_provider,
+        args.swap_router,
+    )
+    .await?;
+    let bot_addr = *bot.address();
+    let bot_code = provider.get_code_at(bot_addr).await?;
+    let bot_owner_slot = provider.get_storage_at(bot_addr, U256::ZERO).await?;
+    info!(?bot_addr, ?owner, "deployed bot on the fork");
+
+    let client: Arc<dyn LendingProtocolClient> =
+        Arc::new(AaveV3Client::new(provider.clone(), &contracts, bot_addr)?);
+    let (simulations_tx, mut simulations_rx) = unbounded_channel();
+
+    let follower_addr = Follower::new(FollowerConfig {
+        provider: provider.clone(),
+        db_addr: db_addr.clone(),
+        client: client.clone(),
+        target: target.to_string(),
+        confirmations: 0,
+        replay: true,
+    })
+    .await?
+    .start();
+
+    let fanatic_addr = Fanatic::new(FanaticConfig {
+        provider: provider.clone(),
+        db_addr: db_addr.clone(),
+        client: client.clone(),
+        follower_addr: follower_addr.clone(),
+        bot_addr,
+        target: target.to_string(),
+        // the replay starts from `--from-block`, not from where the live bot was
+        snapshots: false,
+    })
+    .await?
+    .init()
+    .await?
+    .start();
+
+    let executor_addr = Executor::new(ExecutorConfig {
+        provider: provider.clone(),
+        db_addr: db_addr.clone(),
+        client: client.clone(),
+        fanatic_addr: fanatic_addr.clone(),
+        bot_addr,
+        target: target.to_string(),
+        slippage_bps: execution.slippage_bps,
+        min_profit_usd: execution.min_profit_usd,
+        hold_atokens: execution.hold_atokens.clone(),
+        simulations: Some(simulations_tx),
+    })
+    .await?
+    .start();
+
+    // the actors exchange their addresses once started, make sure it happened before replaying
+    follower_addr
+        .send(SendFanaticAddr(fanatic_addr.clone()))
+        .await?;
+    fanatic_addr.send(SendExecutorAddr(executor_addr)).await?;
+
+    let filter = follower_addr.send(GetReplayFilter).await??;
+    let mut blocks: BTreeMap<u64, Vec<Log>> = BTreeMap::new();
+    let mut from = args.from_block;
+    while from <= args.to_block {
+        let to = (from + LOGS_CHUNK_SIZE - 1).min(args.to_block);
+        let logs = source
+            .get_logs(&filter.clone().from_block(from).to_block(to))
+            .await?;
+        info!(from, to, logs = logs.len(), "fetched logs");
+
+        for log in logs {
+            if let Some(number) = log.block_number {
+                blocks.entry(number).or_default().push(log);
+            }
+        }
+        from = to + 1;
+    }
+
+    let logs_replayed = blocks.values().map(Vec::len).sum();
+    let actual_calls: Vec<(Log, contracts::aave_v3::PoolContract::LiquidationCall)> = blocks
+        .values()
+        .flatten()
+        .filter_map(|log| {
+            contracts::aave_v3::PoolContract::LiquidationCall::decode_log(&log.inner, true)
+                .ok()
+                .map(|event| (log.clone(), event.data))
+        })
+        .collect();
+
+    let blocks_replayed = blocks.len();
+    for (number, mut logs) in blocks {
+        logs.sort_by_key(|log| log.log_index);
+
+        provider
+            .anvil_reset(Some(Forking {
+                json_rpc_url: Some(args.fork_url.clone()),
+                block_number: Some(number),
+            }))
+            .await?;
+        provider.anvil_set_code(bot_addr, bot_code.clone()).await?;
+        provider
+            .anvil_set_storage_at(bot_addr, U256::ZERO, bot_owner_slot.into())
+            .await?;
+        provider
+            .anvil_set_balance(owner, U256::from(100) * U256::from(10).pow(U256::from(18)))
+            .await?;
+
+        info!(number, logs = logs.len(), "replaying block");
+        follower_addr.send(ReplayBlock { number, logs }).await?;
+    }
+
+    // every request is awaited down the flow, all outcomes are in by now
+    let mut simulated = Vec::new();
+    while let Ok(simulation) = simulations_rx.try_recv() {
+        simulated.push(simulation);
+    }
+
+    let actual: Vec<ActualLiquidation> = actual_calls
+        .into_iter()
+        .map(|(log, call)| {
+            let block_number = log.block_number.unwrap_or_default();
+            let caught = simulated
+                .iter()
+                .any(|s| s.success && s.user_address == call.user && s.block_number < block_number);
+
+            ActualLiquidation {
+                block_number,
+                tx_hash: log.transaction_hash,
+                user_address: call.user,
+                liquidator_address: call.liquidator,
+                collateral_asset: call.collateralAsset,
+                debt_asset: call.debtAsset,
+                debt_to_cover: call.debtToCover.to_string(),
+                caught,
+            }
+        })
+        .collect();
+
+    let successful = simulated.iter().filter(|s| s.success).count();
+    let caught = actual.iter().filter(|a| a.caught).count();
+    let summary = Summary {
+        from_block: args.from_block,
+        to_block: args.to_block,
+        blocks_replayed,
+        logs_replayed,
+        attempts: simulated.len(),
+        successful,
+        failed: simulated.len() - successful,
+        profit_usd: simulated
+            .iter()
+            .filter(|s| s.success)
+            .map(|s| s.profit_usd)
+            .sum(),
+        actual_liquidations: actual.len(),
+        caught,
+        missed: actual.len() - caught,
+        unmatched: simulated
+            .iter()
+            .filter(|s| {
+                s.success
+                    && !actual.iter().any(|a| {
+                        a.user_address == s.user_address && a.block_number > s.block_number
+                    })
+            })
+            .count(),
+    };
+
+    Ok(Backtest {
+        summary,
+        simulated,
+        actual,
+    })
+}
+
+fn tables(backtest: &Backtest) -> Vec<Table> {
+    let s = &backtest.summary;
+    let mut summary = Table::new(
+        format!("backtest, blocks {} to {}", s.from_block, s.to_block),
+        vec!["metric", "value"],
+    );
+    for (metric, value) in [
+        ("blocks_replayed", s.blocks_replayed.to_string()),
+        ("logs_replayed", s.logs_replayed.to_string()),
+        ("attempts", s.attempts.to_string()),
+        ("successful", s.successful.to_string()),
+        ("failed", s.failed.to_string()),
+        ("profit_usd", format!("{:.2}", s.profit_usd)),
+        ("actual_liquidations", s.actual_liquidations.to_string()),
+        ("caught", s.caught.to_string()),
+        ("missed", s.missed.to_string()),
+        ("unmatched", s.unmatched.to_string()),
+    ] {
+        summary.push(vec![metric.to_string(), value]);
+    }
+
+    let mut simulated = Table::new(
+        "simulated liquidations",
+        vec![
+            "block",
+            "user",
+            "collateral",
+            "debt",
+            "debt_to_cover",
+            "strategy",
+            "flashloan",
+            "success",
+            "gas_used",
+            "profit",
+            "profit_usd",
+            "estimated_profit_usd",
+        ],
+    );
+    for l in &backtest.simulated {
+        simulated.push(vec![
+            l.block_number.to_string(),
+            l.user_address.to_string(),
+            l.collateral_asset.to_string(),
+            l.debt_asset.to_string(),
+            format!("{:.6}", l.debt_to_cover),
+            l.strategy.to_string(),
+            l.flashloan_source
+                .map_or("-".to_string(), |s| s.to_string()),
+            l.success.to_string(),
+            l.gas_used.map_or("-".to_string(), |g| g.to_string()),
+            format!("{:.6}", l.profit),
+            format!("{:.2}", l.profit_usd),
+            format!("{:.2}", l.estimated_profit_usd),
+        ]);
+    }
+
+    let mut actual = Table::new(
+        "actual liquidations",
+        vec![
+            "block",
+            "user",
+            "liquidator",
+            "collateral",
+            "debt",
+            "debt_to_cover",
+            "caught",
+        ],
+    );
+    for l in &backtest.actual {
+        actual.push(vec![
+            l.block_number.to_string(),
+            l.user_address.to_string(),
+            l.liquidator_address.to_string(),
+            l.collateral_asset.to_string(),
+            l.debt_asset.to_string(),
+            l.debt_to_cover.clone(),
+            l.caught.to_string(),
+        ]);
+    }
+
+    vec![summary, simulated, actual]
+}
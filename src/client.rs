@@ -0,0 +1,647 @@
+//! Chain access of the lending protocol, behind a trait so the actors' decisions can be exercised
+//! without a node.
+//!
+//! `AaveV3Client` is the alloy implementation, `fake::InMemoryClient` keeps everything in memory
+//! for tests.
+
+use std::{collections::HashMap, fmt::Debug};
+
+use alloy::{
+    consensus::Transaction,
+    primitives::{utils::format_ether, Address, Bytes, TxHash, U256},
+    providers::Provider,
+    rpc::types::TransactionRequest,
+};
+use async_trait::async_trait;
+
+use crate::{
+    actors::{follower::oracle_price, messages::database::ReserveFlags},
+    consts::{LIQUIDATION_PROTOCOL_FEE_START_BIT_POSITION, RAY},
+    contracts::{self, liquidator::Liquidatoor::LiquidationParams},
+    flashloan::{Flashloan, FlashloanSource},
+    utils::{liquidation_bonus, liquidation_threshold, norm},
+};
+
+// aave's base currency is USD with 8 decimals
+const BASE_CURRENCY_UNIT: f64 = 1e8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountData {
+    pub total_collateral_base: f64,
+    pub total_debt_base: f64,
+    pub health_factor: f64,
+}
+
+/// A user's position in a reserve, balances are scaled, i.e to be multiplied by the reserve's
+/// liquidity/variable borrow index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserReserve {
+    pub underlying_asset: Address,
+    pub scaled_a_token_balance: U256,
+    pub scaled_variable_debt: U256,
+    pub usage_as_collateral_enabled: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReserveInfo {
+    pub underlying_asset: Address,
+    pub a_token_address: Address,
+    pub symbol: String,
+    pub name: String,
+    pub decimals: i32,
+    pub liquidation_threshold: f64,
+    pub liquidation_bonus: f64,
+    pub flashloan_enabled: bool,
+    pub price_oracle: Address,
+    pub flags: ReserveFlags,
+    pub liquidity_rate: f64,
+    pub variable_borrow_rate: f64,
+    pub liquidity_index: f64,
+    pub variable_borrow_index: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Liquidation {
+    pub debt_asset: Address,
+    pub collateral_asset: Address,
+    pub user: Address,
+    pub debt_to_cover: U256,
+    // uniswap_v3 path the seized collateral is swapped through, see `router`
+    pub path: Bytes,
+    // the bot reverts if the liquidation brings back less, see `strategy`
+    pub min_amount_out: U256,
+    // `None` to repay out of the bot's own balance and keep the collateral's aToken
+    pub flashloan: Option<Flashloan>,
+}
+
+impl From<&Liquidation> for LiquidationParams {
+    fn from(liquidation: &Liquidation) -> Self {
+        LiquidationParams {
+            debtAsset: liquidation.debt_asset,
+            collateralAsset: liquidation.collateral_asset,
+            user: liquidation.user,
+            debtToCover: liquidation.debt_to_cover,
+            path: liquidation.path.clone(),
+            minAmountOut: liquidation.min_amount_out,
+        }
+    }
+}
+
+/// A liquidation transaction once mined, whether it succeeded or not.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiquidationReceipt {
+    pub tx_hash: TxHash,
+    pub block_number: Option<u64>,
+    pub success: bool,
+    pub gas_used: u64,
+    // wei
+    pub effective_gas_price: u128,
+    // as sent, filled in by the provider
+    pub gas_limit: Option<u64>,
+    pub max_fee_per_gas: Option<u128>,
+    pub max_priority_fee_per_gas: Option<u128>,
+}
+
+/// Aave's `PriceOracleSentinel`, which disallows liquidations while the L2's sequencer is down and
+/// for a grace period once it's back up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SentinelStatus {
+    pub sentinel: Address,
+    pub liquidation_allowed: bool,
+    // Chainlink's sequencer uptime feed, answering 0 when up and 1 when down
+    pub sequencer_up: bool,
+    // `startedAt` of the feed's latest round, i.e when the sequencer last went up or down
+    pub sequencer_since: u64,
+    pub grace_period_secs: u64,
+}
+
+impl SentinelStatus {
+    /// Timestamp from which liquidations are allowed again, `None` while the sequencer's down.
+    pub fn resumes_at(&self) -> Option<u64> {
+        self.sequencer_up
+            .then_some(self.sequencer_since + self.grace_period_secs + 1)
+    }
+}
+
+/// Sends the liquidation and waits for it to be mined. A reverting liquidation fails at gas
+/// estimation, before anything is sent.
+pub async fn send_liquidation<P: Provider + Unpin + Clone + 'static>(
+    provider: &P,
+    bot_contract: &contracts::liquidator::LiquidatoorContract::LiquidatoorContractInstance<(), P>,
+    liquidation: &Liquidation,
+) -> eyre::Result<LiquidationReceipt> {
+    let receipt = provider
+        .send_transaction(liquidation_tx(bot_contract, liquidation))
+        .await?
+        .get_receipt()
+        .await?;
+    let tx = provider
+        .get_transaction_by_hash(receipt.transaction_hash)
+        .await?;
+
+    Ok(LiquidationReceipt {
+        tx_hash: receipt.transaction_hash,
+        block_number: receipt.block_number,
+        success: receipt.status(),
+        gas_used: receipt.gas_used,
+        effective_gas_price: receipt.effective_gas_price,
+        gas_limit: tx.as_ref().map(|tx| tx.gas_limit()),
+        max_fee_per_gas: tx.as_ref().map(|tx| tx.max_fee_per_gas()),
+        max_priority_fee_per_gas: tx.as_ref().and_then(|tx| tx.max_priority_fee_per_gas()),
+    })
+}
+
+/// Call to the bot's entrypoint matching the liquidation's flashloan source, if any.
+pub fn liquidation_tx<P: Provider + Unpin + Clone + 'static>(
+    bot_contract: &contracts::liquidator::LiquidatoorContract::LiquidatoorContractInstance<(), P>,
+    liquidation: &Liquidation,
+) -> TransactionRequest {
+    let params = LiquidationParams::from(liquidation);
+    let Some(flashloan) = liquidation.flashloan else {
+        return bot_contract
+            .liquidatoorHold(params)
+            .into_transaction_request();
+    };
+
+    match flashloan.source {
+        FlashloanSource::Aave => bot_contract.liquidatoor(params).into_transaction_request(),
+        FlashloanSource::Balancer => bot_contract
+            .liquidatoorBalancer(flashloan.lender, params)
+            .into_transaction_request(),
+        FlashloanSource::Morpho => bot_contract
+            .liquidatoorMorpho(flashloan.lender, params)
+            .into_transaction_request(),
+    }
+}
+
+#[async_trait]
+pub trait LendingProtocolClient: Debug + Send + Sync {
+    async fn account_data(&self, user: Address) -> eyre::Result<AccountData>;
+
+    async fn user_reserves(&self, user: Address) -> eyre::Result<Vec<UserReserve>>;
+
+    async fn reserves_data(&self) -> eyre::Result<Vec<ReserveInfo>>;
+
+    /// USD price reported by an aggregator or price adapter.
+    async fn price(&self, oracle: Address) -> eyre::Result<f64>;
+
+    /// Aave's `flashLoanSimple` premium, in bps.
+    async fn flashloan_premium(&self) -> eyre::Result<u128>;
+
+    /// Share of the liquidation bonus the protocol keeps off the reserve's collateral, in percent.
+    async fn liquidation_protocol_fee(&self, asset: Address) -> eyre::Result<f64>;
+
+    /// `None` on deployments without a `PriceOracleSentinel`, i.e L1s.
+    async fn sentinel(&self) -> eyre::Result<Option<SentinelStatus>>;
+
+    /// Triggers the bot, which flashloans the debt asset to liquidate `user`. Errors when the
+    /// liquidation reverts before being sent.
+    async fn liquidate(&self, liquidation: Liquidation) -> eyre::Result<LiquidationReceipt>;
+}
+
+#[derive(Clone)]
+pub struct AaveV3Client<P: Provider + Unpin + Clone + 'static> {
+    provider: P,
+    addresses_provider: Address,
+
+    pool_contract: contracts::aave_v3::PoolContract::PoolContractInstance<(), P>,
+    datap_contract: contracts::aave_v3::DataProviderContract::DataProviderContractInstance<(), P>,
+    bot_contract: contracts::liquidator::LiquidatoorContract::LiquidatoorContractInstance<(), P>,
+}
+
+impl<P: Provider + Unpin + Clone + 'static> AaveV3Client<P> {
+    /// `contracts` as returned by `GetProtocolContracts`.
+    pub fn new(
+        provider: P,
+        contracts: &HashMap<String, Address>,
+        bot_addr: Address,
+    ) -> eyre::Result<Self> {
+        match (
+            contracts.get("Pool"),
+            contracts.get("UiPoolDataProviderV3"),
+            contracts.get("PoolAddressesProvider"),
+        ) {
+            (Some(pool_addr), Some(datap_addr), Some(addresses_provider)) => Ok(AaveV3Client {
+                addresses_provider: *addresses_provider,
+                pool_contract: contracts::aave_v3::PoolContract::new(*pool_addr, provider.clone()),
+                datap_contract: contracts::aave_v3::DataProviderContract::new(
+                    *datap_addr,
+                    provider.clone(),
+                ),
+                bot_contract: contracts::liquidator::LiquidatoorContract::new(
+                    bot_addr,
+                    provider.clone(),
+                ),
+                provider,
+            }),
+            _ => Err(eyre::eyre!("Missing required contract addresses")),
+        }
+    }
+}
+
+// providers aren't `Debug`
+impl<P: Provider + Unpin + Clone + 'static> Debug for AaveV3Client<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AaveV3Client")
+            .field("addresses_provider", &self.addresses_provider)
+            .field("pool", self.pool_contract.address())
+            .field("data_provider", self.datap_contract.address())
+            .field("bot", self.bot_contract.address())
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<P: Provider + Unpin + Clone + 'static> LendingProtocolClient for AaveV3Client<P> {
+    async fn account_data(&self, user: Address) -> eyre::Result<AccountData> {
+        let data = self.pool_contract.getUserAccountData(user).call().await?;
+
+        Ok(AccountData {
+            total_collateral_base: norm(data.totalCollateralBase, Some(1.0 / BASE_CURRENCY_UNIT))?,
+            total_debt_base: norm(data.totalDebtBase, Some(1.0 / BASE_CURRENCY_UNIT))?,
+            health_factor: format_ether(data.healthFactor).parse::<f64>()?,
+        })
+    }
+
+    async fn user_reserves(&self, user: Address) -> eyre::Result<Vec<UserReserve>> {
+        let user_reserves = self
+            .datap_contract
+            .getUserReservesData(self.addresses_provider, user)
+            .call()
+            .await?;
+
+        Ok(user_reserves
+            ._0
+            .into_iter()
+            .map(|r| UserReserve {
+                underlying_asset: r.underlyingAsset,
+                scaled_a_token_balance: r.scaledATokenBalance,
+                scaled_variable_debt: r.scaledVariableDebt,
+                usage_as_collateral_enabled: r.usageAsCollateralEnabledOnUser,
+            })
+            .collect())
+    }
+
+    async fn reserves_data(&self) -> eyre::Result<Vec<ReserveInfo>> {
+        let reserves = self
+            .datap_contract
+            .getReservesData(self.addresses_provider)
+            .call()
+            .await?;
+
+        reserves
+            ._0
+            .into_iter()
+            .map(|r| {
+                Ok(ReserveInfo {
+                    underlying_asset: r.underlyingAsset,
+                    a_token_address: r.aTokenAddress,
+                    symbol: r.symbol,
+                    name: r.name,
+                    decimals: r.decimals.to::<u32>() as i32,
+                    liquidation_threshold: liquidation_threshold(r.reserveLiquidationThreshold)?,
+                    liquidation_bonus: liquidation_bonus(r.reserveLiquidationBonus)?,
+                    flashloan_enabled: r.flashLoanEnabled,
+                    price_oracle: r.priceOracle,
+                    flags: ReserveFlags {
+                        is_active: r.isActive,
+                        is_frozen: r.isFrozen,
+                        is_paused: r.isPaused,
+                        is_siloed_borrowing: r.isSiloedBorrowing,
+                        borrowing_enabled: r.borrowingEnabled,
+                        usage_as_collateral_enabled: r.usageAsCollateralEnabled,
+                        borrowable_in_isolation: r.borrowableInIsolation,
+                        debt_ceiling: norm(
+                            r.debtCeiling,
+                            Some(10.0_f64.powi(-r.debtCeilingDecimals.to::<i32>())),
+                        )?,
+                    },
+                    liquidity_rate: norm(r.liquidityRate, Some(100.0 / RAY))?,
+                    variable_borrow_rate: norm(r.variableBorrowRate, Some(100.0 / RAY))?,
+                    liquidity_index: norm(r.liquidityIndex, Some(1.0 / RAY))?,
+                    variable_borrow_index: norm(r.variableBorrowIndex, Some(1.0 / RAY))?,
+                })
+            })
+            .collect()
+    }
+
+    async fn price(&self, oracle: Address) -> eyre::Result<f64> {
+        oracle_price(&self.provider, oracle).await
+    }
+
+    async fn flashloan_premium(&self) -> eyre::Result<u128> {
+        Ok(self
+            .pool_contract
+            .FLASHLOAN_PREMIUM_TOTAL()
+            .call()
+            .await?
+            ._0)
+    }
+
+    async fn liquidation_protocol_fee(&self, asset: Address) -> eyre::Result<f64> {
+        let configuration = self
+            .pool_contract
+            .getConfiguration(asset)
+            .call()
+            .await?
+            ._0
+            .data;
+        let bps =
+            (configuration >> LIQUIDATION_PROTOCOL_FEE_START_BIT_POSITION) & U256::from(0xFFFF);
+        norm(bps, Some(0.01))
+    }
+
+    async fn sentinel(&self) -> eyre::Result<Option<SentinelStatus>> {
+        // read every time, governance may replace it
+        let sentinel = contracts::aave_v3::AddressProviderContract::new(
+            self.addresses_provider,
+            self.provider.clone(),
+        )
+        .getPriceOracleSentinel()
+        .call()
+        .await?
+        ._0;
+        if sentinel == Address::ZERO {
+            return Ok(None);
+        }
+
+        let sentinel_contract =
+            contracts::aave_v3::PriceOracleSentinelContract::new(sentinel, self.provider.clone());
+        let liquidation_allowed = sentinel_contract.isLiquidationAllowed().call().await?._0;
+        let grace_period = sentinel_contract.getGracePeriod().call().await?._0;
+        let sequencer_oracle = sentinel_contract.getSequencerOracle().call().await?._0;
+        let round = contracts::chainlink::EACAggregatorProxyContract::new(
+            sequencer_oracle,
+            self.provider.clone(),
+        )
+        .latestRoundData()
+        .call()
+        .await?;
+
+        Ok(Some(SentinelStatus {
+            sentinel,
+            liquidation_allowed,
+            sequencer_up: round.answer.is_zero(),
+            sequencer_since: round.startedAt.saturating_to::<u64>(),
+            grace_period_secs: grace_period.saturating_to::<u64>(),
+        }))
+    }
+
+    async fn liquidate(&self, liquidation: Liquidation) -> eyre::Result<LiquidationReceipt> {
+        send_liquidation(&self.provider, &self.bot_contract, &liquidation).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::{
+        primitives::{address, hex},
+        sol_types::{SolCall, SolValue},
+    };
+
+    use super::*;
+    use crate::{
+        contracts::liquidator::{
+            missing_selectors,
+            LiquidatoorContract::{
+                self, liquidatoorBalancerCall, liquidatoorCall, liquidatoorHoldCall,
+                liquidatoorMorphoCall, onMorphoFlashLoanCall, receiveBalancerFlashLoanCall,
+            },
+        },
+        router::encode_uniswap_v3_path,
+    };
+
+    const WETH: Address = address!("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+    const USDC: Address = address!("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+    const VAULT: Address = address!("0xbA1333333333a1BA1108E8412f11850A5C319bA9");
+
+    fn liquidation() -> Liquidation {
+        Liquidation {
+            debt_asset: USDC,
+            collateral_asset: WETH,
+            user: address!("0x1111111111111111111111111111111111111111"),
+            debt_to_cover: U256::from(1_000_000_000u64),
+            path: encode_uniswap_v3_path(&[WETH, USDC], &[500]),
+            min_amount_out: U256::from(1_004_975_000u64),
+            flashloan: Some(Flashloan {
+                source: FlashloanSource::Aave,
+                lender: Address::ZERO,
+                fee: U256::from(500_000),
+            }),
+        }
+    }
+
+    fn assert_params(params: &LiquidationParams, liquidation: &Liquidation) {
+        assert_eq!(params.debtAsset, liquidation.debt_asset);
+        assert_eq!(params.collateralAsset, liquidation.collateral_asset);
+        assert_eq!(params.user, liquidation.user);
+        assert_eq!(params.debtToCover, liquidation.debt_to_cover);
+        assert_eq!(params.path, liquidation.path);
+        assert_eq!(params.minAmountOut, liquidation.min_amount_out);
+    }
+
+    #[test]
+    fn liquidation_params_round_trip_through_the_abi() {
+        let liquidation = liquidation();
+        let params = LiquidationParams::from(&liquidation);
+
+        let calldata = liquidatoorCall {
+            params: params.clone(),
+        }
+        .abi_encode();
+        let decoded = liquidatoorCall::abi_decode(&calldata, true).unwrap();
+        assert_params(&decoded.params, &liquidation);
+
+        // what `executeOperation` and `onMorphoFlashLoan` decode, i.e `abi.encode(params)`
+        let encoded = params.abi_encode();
+        assert_eq!(&calldata[4..], &encoded[..]);
+        assert_params(
+            &LiquidationParams::abi_decode(&encoded, true).unwrap(),
+            &liquidation,
+        );
+    }
+
+    #[test]
+    fn zero_fee_entrypoints_take_the_lender_first() {
+        let liquidation = liquidation();
+        let params = LiquidationParams::from(&liquidation);
+
+        let calldata = liquidatoorBalancerCall {
+            vault: VAULT,
+            params: params.clone(),
+        }
+        .abi_encode();
+        let decoded = liquidatoorBalancerCall::abi_decode(&calldata, true).unwrap();
+        assert_eq!(decoded.vault, VAULT);
+        assert_params(&decoded.params, &liquidation);
+
+        let calldata = liquidatoorMorphoCall {
+            morpho: VAULT,
+            params,
+        }
+        .abi_encode();
+        let decoded = liquidatoorMorphoCall::abi_decode(&calldata, true).unwrap();
+        assert_eq!(decoded.morpho, VAULT);
+        assert_params(&decoded.params, &liquidation);
+    }
+
+    #[test]
+    #[ignore = "abis/Liquidator.json's bytecode predates these entrypoints, rebuild it with `forge build`"]
+    fn entrypoints_are_dispatched_by_the_deployed_bytecode() {
+        let missing = missing_selectors(&LiquidatoorContract::DEPLOYED_BYTECODE);
+        for selector in [
+            liquidatoorCall::SELECTOR,
+            liquidatoorHoldCall::SELECTOR,
+            liquidatoorBalancerCall::SELECTOR,
+            liquidatoorMorphoCall::SELECTOR,
+            receiveBalancerFlashLoanCall::SELECTOR,
+            onMorphoFlashLoanCall::SELECTOR,
+        ] {
+            assert!(
+                !missing.contains(&selector),
+                "{} isn't dispatched",
+                hex::encode(selector)
+            );
+        }
+    }
+
+    #[test]
+    fn liquidations_resume_once_the_grace_period_elapsed() {
+        let mut status = SentinelStatus {
+            sentinel: address!("0xF6Bb9E6D9A2ed12c1ba2D92F1Eb4E9f6A2F2D7bC"),
+            liquidation_allowed: false,
+            sequencer_up: true,
+            sequencer_since: 1_700_000_000,
+            grace_period_secs: 3600,
+        };
+        // aave's `block.timestamp - startedAt > gracePeriod`
+        assert_eq!(status.resumes_at(), Some(1_700_003_601));
+
+        status.sequencer_up = false;
+        assert_eq!(status.resumes_at(), None);
+    }
+}
+
+#[cfg(test)]
+pub mod fake {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Protocol state set by hand, liquidations are recorded rather than executed.
+    #[derive(Debug, Default)]
+    pub struct InMemoryClient {
+        pub accounts: Mutex<HashMap<Address, AccountData>>,
+        pub user_reserves: Mutex<HashMap<Address, Vec<UserReserve>>>,
+        pub reserves: Mutex<Vec<ReserveInfo>>,
+        pub prices: Mutex<HashMap<Address, f64>>,
+        pub liquidations: Mutex<Vec<Liquidation>>,
+        pub flashloan_premium: Mutex<u128>,
+        // percent, none unless set
+        pub liquidation_protocol_fees: Mutex<HashMap<Address, f64>>,
+        pub sentinel: Mutex<Option<SentinelStatus>>,
+        // makes `liquidate` fail, i.e the bot's transaction reverting
+        pub revert_liquidations: Mutex<bool>,
+    }
+
+    impl InMemoryClient {
+        pub fn set_health_factor(&self, user: Address, health_factor: f64) {
+            self.accounts
+                .lock()
+                .unwrap()
+                .entry(user)
+                .or_insert(AccountData {
+                    total_collateral_base: 0.0,
+                    total_debt_base: 0.0,
+                    health_factor,
+                })
+                .health_factor = health_factor;
+        }
+
+        pub fn set_user_reserves(&self, user: Address, reserves: Vec<UserReserve>) {
+            self.user_reserves.lock().unwrap().insert(user, reserves);
+        }
+
+        pub fn liquidations(&self) -> Vec<Liquidation> {
+            self.liquidations.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl LendingProtocolClient for InMemoryClient {
+        async fn account_data(&self, user: Address) -> eyre::Result<AccountData> {
+            // aave reports `type(uint256).max` for users without debt
+            Ok(self
+                .accounts
+                .lock()
+                .unwrap()
+                .get(&user)
+                .cloned()
+                .unwrap_or(AccountData {
+                    total_collateral_base: 0.0,
+                    total_debt_base: 0.0,
+                    health_factor: f64::MAX,
+                }))
+        }
+
+        async fn user_reserves(&self, user: Address) -> eyre::Result<Vec<UserReserve>> {
+            Ok(self
+                .user_reserves
+                .lock()
+                .unwrap()
+                .get(&user)
+                .cloned()
+                .unwrap_or_default())
+        }
+
+        async fn reserves_data(&self) -> eyre::Result<Vec<ReserveInfo>> {
+            Ok(self.reserves.lock().unwrap().clone())
+        }
+
+        async fn price(&self, oracle: Address) -> eyre::Result<f64> {
+            self.prices
+                .lock()
+                .unwrap()
+                .get(&oracle)
+                .copied()
+                .ok_or(eyre::eyre!("no price for oracle {}", oracle))
+        }
+
+        async fn flashloan_premium(&self) -> eyre::Result<u128> {
+            Ok(*self.flashloan_premium.lock().unwrap())
+        }
+
+        async fn liquidation_protocol_fee(&self, asset: Address) -> eyre::Result<f64> {
+            Ok(self
+                .liquidation_protocol_fees
+                .lock()
+                .unwrap()
+                .get(&asset)
+                .copied()
+                .unwrap_or_default())
+        }
+
+        async fn sentinel(&self) -> eyre::Result<Option<SentinelStatus>> {
+            Ok(self.sentinel.lock().unwrap().clone())
+        }
+
+        async fn liquidate(&self, liquidation: Liquidation) -> eyre::Result<LiquidationReceipt> {
+            if *self.revert_liquidations.lock().unwrap() {
+                return Err(eyre::eyre!("execution reverted"));
+            }
+
+            let mut liquidations = self.liquidations.lock().unwrap();
+            liquidations.push(liquidation);
+            Ok(LiquidationReceipt {
+                tx_hash: TxHash::with_last_byte(liquidations.len() as u8),
+                block_number: None,
+                success: true,
+                gas_used: 0,
+                effective_gas_price: 0,
+                gas_limit: None,
+                max_fee_per_gas: None,
+                max_priority_fee_per_gas: None,
+            })
+        }
+    }
+}
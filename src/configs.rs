@@ -1,46 +1,86 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use actix::Addr;
 use alloy::{primitives::Address, providers::Provider};
 use sqlx::PgPool;
+use tokio::{sync::mpsc::UnboundedSender, time::Duration};
 
-use crate::actors::{Database, Fanatic, Follower};
+use crate::{
+    actors::{
+        messages::executor::SimulatedLiquidation, monitor::Feeds, Database, Fanatic, Follower,
+    },
+    args::{ExecutionArgs, HistoryArgs, MonitorArgs},
+    client::LendingProtocolClient,
+};
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub ws_url: String,
     pub database_url: String,
     pub target: String,
+    #[allow(dead_code)]
     pub account_pubkey: Address,
     pub account_privkey: String,
     pub bot_addr: Address,
+    pub confirmations: u64,
+    pub http_addr: SocketAddr,
+    pub execution: ExecutionArgs,
+    pub monitor: MonitorArgs,
+    pub history: HistoryArgs,
 }
 
 #[derive(Debug, Clone)]
 pub struct FollowerConfig<P: Provider + Unpin + Clone + 'static> {
     pub provider: P,
     pub db_addr: Addr<Database>,
+    pub client: Arc<dyn LendingProtocolClient>,
     pub target: String,
+    pub confirmations: u64,
+    pub replay: bool,
 }
 
 #[derive(Debug, Clone)]
 pub struct ExecutorConfig<P: Provider + Unpin + Clone + 'static> {
     pub provider: P,
     pub db_addr: Addr<Database>,
+    pub client: Arc<dyn LendingProtocolClient>,
     pub fanatic_addr: Addr<Fanatic<P>>,
     pub bot_addr: Address,
     pub target: String,
+    pub slippage_bps: u32,
+    pub min_profit_usd: f64,
+    pub hold_atokens: Vec<Address>,
+    // when set, liquidations are sent (to a fork) and their outcome reported here
+    pub simulations: Option<UnboundedSender<SimulatedLiquidation>>,
 }
 
 #[derive(Debug, Clone)]
 pub struct FanaticConfig<P: Provider + Unpin + Clone + 'static> {
     pub provider: P,
     pub db_addr: Addr<Database>,
+    pub client: Arc<dyn LendingProtocolClient>,
     pub follower_addr: Addr<Follower<P>>,
+    pub bot_addr: Address,
+    pub target: String,
+    // restore the latest snapshot on startup, take one periodically and on shutdown
+    pub snapshots: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct MonitorConfig<P: Provider + Unpin + Clone + 'static> {
+    pub provider: P,
+    pub db_addr: Addr<Database>,
     pub target: String,
+    pub heartbeat_secs: u64,
+    pub deviation_bps: u32,
+    pub check_interval: Duration,
+    // shared with the HTTP API
+    pub feeds: Feeds,
 }
 
 #[derive(Debug, Clone)]
 pub struct DatabaseConfig {
     pub pool: Arc<PgPool>,
+    // history retention and downsampling, left alone when `None`
+    pub history: Option<HistoryArgs>,
 }
@@ -1,3 +1,9 @@
 //! https://aave.com/docs/resources/parameters
 
 pub const RAY: f64 = 1e27;
+
+// `DebtCeilingChanged` reports the ceiling with 2 decimals, i.e 1_000_000_00 is $1M
+pub const DEBT_CEILING_DECIMALS: i32 = 2;
+
+// bits 152-167 of a reserve's configuration, in bps
+pub const LIQUIDATION_PROTOCOL_FEE_START_BIT_POSITION: usize = 152;
@@ -9,6 +9,88 @@ pub mod liquidator {
         LiquidatoorContract,
         "./abis/Liquidator.json"
     }
+
+    /// Selectors of the ABI's functions `deployed_bytecode` doesn't dispatch to, i.e never pushes.
+    pub fn missing_selectors(deployed_bytecode: &[u8]) -> Vec<[u8; 4]> {
+        LiquidatoorContract::LiquidatoorContractCalls::SELECTORS
+            .iter()
+            .filter(|selector| {
+                let push = push_instruction(selector);
+                !deployed_bytecode
+                    .windows(push.len())
+                    .any(|w| w == push.as_slice())
+            })
+            .copied()
+            .collect()
+    }
+
+    // solc drops the selector's leading zeros, i.e 0x00abcdef is `PUSH3 abcdef`
+    fn push_instruction(selector: &[u8; 4]) -> Vec<u8> {
+        let value = &selector[selector.iter().take_while(|b| **b == 0).count()..];
+        // PUSH1 is 0x60
+        [&[0x5f + value.len() as u8], value].concat()
+    }
+
+    /// Errors when `abis/Liquidator.json`'s bytecode wasn't rebuilt along with its ABI, deploying
+    /// it would have the missing functions revert.
+    pub fn ensure_bytecode_matches_abi() -> eyre::Result<()> {
+        let missing = missing_selectors(&LiquidatoorContract::DEPLOYED_BYTECODE);
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        Err(eyre::eyre!(
+            "abis/Liquidator.json is out of date, its bytecode lacks {}: run `forge build` in \
+             contracts/ and copy out/Liquidator.sol/Liquidator.json over it",
+            missing
+                .iter()
+                .map(alloy::primitives::hex::encode)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn selectors_missing_from_the_bytecode_are_reported() {
+            let selectors = LiquidatoorContract::LiquidatoorContractCalls::SELECTORS;
+            let bytecode = selectors
+                .iter()
+                .flat_map(push_instruction)
+                .collect::<Vec<_>>();
+            assert!(missing_selectors(&bytecode).is_empty());
+
+            let stale = selectors[1..]
+                .iter()
+                .flat_map(push_instruction)
+                .collect::<Vec<_>>();
+            assert_eq!(missing_selectors(&stale), vec![selectors[0]]);
+            // not pushed, i.e some other instruction's data
+            assert_eq!(
+                missing_selectors(&selectors.concat()).len(),
+                selectors.len()
+            );
+
+            assert_eq!(
+                push_instruction(&[0, 0xab, 0xcd, 0xef]),
+                vec![0x62, 0xab, 0xcd, 0xef]
+            );
+        }
+    }
+}
+
+pub mod erc20 {
+    use super::sol;
+
+    sol! {
+        #[sol(rpc)]
+        #[derive(Debug)]
+        ERC20Contract,
+        "./abis/chainlink/ERC20.json"
+    }
 }
 
 pub mod aave_v3 {
@@ -31,6 +113,20 @@ pub mod aave_v3 {
         "./abis/aave_v3/PoolAddressesProvider.json"
     }
 
+    sol! {
+        #[sol(rpc)]
+        #[derive(Debug)]
+        ATokenContract,
+        "./abis/aave_v3/AToken.json"
+    }
+
+    sol! {
+        #[sol(rpc)]
+        #[derive(Debug)]
+        PoolConfiguratorContract,
+        "./abis/aave_v3/PoolConfigurator.json"
+    }
+
     // workaround of err
     // `previous definition of the module `DataTypes``
     mod tmp {
@@ -45,6 +141,32 @@ pub mod aave_v3 {
     }
 
     pub use tmp::DataProviderContract;
+
+    // v3.3 replaced `Errors.sol`'s string codes with custom errors, only the ones a liquidation
+    // may hit
+    sol! {
+        #[derive(Debug, PartialEq, Eq)]
+        interface PoolErrors {
+            error HealthFactorNotBelowThreshold();
+            error CollateralCannotBeLiquidated();
+            error SpecifiedCurrencyNotBorrowedByUser();
+            error ReserveInactive();
+            error ReservePaused();
+            error PriceOracleSentinelCheckFailed();
+            error FlashloanDisabled();
+        }
+    }
+
+    // L2 deployments only, `PoolAddressesProvider.getPriceOracleSentinel()` is zero elsewhere
+    sol! {
+        #[sol(rpc)]
+        #[derive(Debug)]
+        interface PriceOracleSentinelContract {
+            function isLiquidationAllowed() external view returns (bool);
+            function getSequencerOracle() external view returns (address);
+            function getGracePeriod() external view returns (uint256);
+        }
+    }
 }
 
 pub mod chainlink {
@@ -0,0 +1,155 @@
+//! Crate-level error, telling transient failures (worth retrying) apart from fatal ones.
+
+use std::fmt;
+
+use actix::MailboxError;
+use alloy::transports::{TransportError, TransportErrorKind};
+
+use crate::revert::{LiquidationError, RetryPolicy};
+
+#[derive(Debug)]
+pub enum LiquidatorError {
+    // the node, i.e transport or JSON-RPC error
+    Rpc(TransportError),
+    Db(sqlx::Error),
+    // an actor which couldn't be reached, or didn't answer in time
+    Mailbox(MailboxError),
+    // data which isn't what it should be, e.g an address stored in postgres or an ABI payload
+    Decode(String),
+    // e.g a target which isn't `<network>-<protocol>`
+    Config(String),
+    ContractRevert(LiquidationError),
+    // our view of the protocol lags behind the chain
+    StaleState(String),
+}
+
+impl LiquidatorError {
+    /// Whether the same operation may succeed if tried again later.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            LiquidatorError::Rpc(e) => !e.is_error_resp(),
+            LiquidatorError::Db(e) => matches!(
+                e,
+                sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut | sqlx::Error::WorkerCrashed
+            ),
+            LiquidatorError::Mailbox(e) => matches!(e, MailboxError::Timeout),
+            LiquidatorError::ContractRevert(e) => e.retry_policy() == RetryPolicy::RetryNextBlock,
+            LiquidatorError::StaleState(_) => true,
+            LiquidatorError::Decode(_) | LiquidatorError::Config(_) => false,
+        }
+    }
+
+    /// Types a liquidation which didn't go through: the pool's or the bot's revert, or else the
+    /// node's error.
+    pub fn from_liquidation_report(e: eyre::Report) -> Self {
+        if let Some(revert) = LiquidationError::from_report(&e) {
+            return LiquidatorError::ContractRevert(revert);
+        }
+
+        match e.downcast::<TransportError>() {
+            Ok(e) => LiquidatorError::Rpc(e),
+            Err(e) => match e.downcast::<alloy::contract::Error>() {
+                Ok(e) => LiquidatorError::from(e),
+                Err(e) => LiquidatorError::Rpc(TransportErrorKind::custom_str(&e.to_string())),
+            },
+        }
+    }
+}
+
+impl fmt::Display for LiquidatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LiquidatorError::Rpc(e) => write!(f, "rpc error: {}", e),
+            LiquidatorError::Db(e) => write!(f, "database error: {}", e),
+            LiquidatorError::Mailbox(e) => write!(f, "mailbox error: {}", e),
+            LiquidatorError::Decode(e) => write!(f, "decode error: {}", e),
+            LiquidatorError::Config(e) => write!(f, "config error: {}", e),
+            LiquidatorError::ContractRevert(e) => write!(f, "contract reverted: {}", e),
+            LiquidatorError::StaleState(e) => write!(f, "stale state: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LiquidatorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LiquidatorError::Rpc(e) => Some(e),
+            LiquidatorError::Db(e) => Some(e),
+            LiquidatorError::Mailbox(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for LiquidatorError {
+    fn from(e: sqlx::Error) -> Self {
+        LiquidatorError::Db(e)
+    }
+}
+
+impl From<MailboxError> for LiquidatorError {
+    fn from(e: MailboxError) -> Self {
+        LiquidatorError::Mailbox(e)
+    }
+}
+
+impl From<TransportError> for LiquidatorError {
+    fn from(e: TransportError) -> Self {
+        LiquidatorError::Rpc(e)
+    }
+}
+
+impl From<alloy::contract::Error> for LiquidatorError {
+    fn from(e: alloy::contract::Error) -> Self {
+        match e {
+            alloy::contract::Error::TransportError(e) => LiquidatorError::Rpc(e),
+            e => LiquidatorError::Decode(e.to_string()),
+        }
+    }
+}
+
+impl From<LiquidationError> for LiquidatorError {
+    fn from(e: LiquidationError) -> Self {
+        LiquidatorError::ContractRevert(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::{rpc::json_rpc::ErrorPayload, transports::RpcError};
+
+    use super::*;
+
+    #[test]
+    fn only_transient_failures_are_worth_retrying() {
+        assert!(LiquidatorError::from(sqlx::Error::PoolTimedOut).is_transient());
+        assert!(!LiquidatorError::from(sqlx::Error::RowNotFound).is_transient());
+        assert!(LiquidatorError::from(TransportErrorKind::backend_gone()).is_transient());
+        assert!(LiquidatorError::from(LiquidationError::TooLittleReceived).is_transient());
+        assert!(
+            !LiquidatorError::from(LiquidationError::CollateralCannotBeLiquidated).is_transient()
+        );
+        assert!(LiquidatorError::from(MailboxError::Timeout).is_transient());
+        assert!(!LiquidatorError::from(MailboxError::Closed).is_transient());
+        assert!(!LiquidatorError::Config("ethereum".to_string()).is_transient());
+    }
+
+    #[test]
+    fn failed_liquidations_are_typed() {
+        let reverted: TransportError = RpcError::ErrorResp(ErrorPayload {
+            code: 3,
+            message: "execution reverted: 46".into(),
+            data: None,
+        });
+        assert!(matches!(
+            LiquidatorError::from_liquidation_report(eyre::Report::new(reverted)),
+            LiquidatorError::ContractRevert(LiquidationError::CollateralCannotBeLiquidated)
+        ));
+
+        let gone = LiquidatorError::from_liquidation_report(eyre::Report::new(
+            TransportErrorKind::backend_gone(),
+        ));
+        assert!(matches!(gone, LiquidatorError::Rpc(_)));
+        assert!(gone.is_transient());
+    }
+}
@@ -0,0 +1,210 @@
+//! Where `Liquidatoor` borrows the debt asset from. Aave charges `FLASHLOAN_PREMIUM_TOTAL` on
+//! `flashLoanSimple`, whereas the Balancer V3 vault and Morpho Blue lend for free, as long as they
+//! hold enough of the asset.
+
+use std::fmt;
+
+use alloy::{
+    primitives::{Address, U256},
+    providers::Provider,
+};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::contracts;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlashloanSource {
+    Aave,
+    Balancer,
+    Morpho,
+}
+
+impl FlashloanSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FlashloanSource::Aave => "aave",
+            FlashloanSource::Balancer => "balancer",
+            FlashloanSource::Morpho => "morpho",
+        }
+    }
+}
+
+impl fmt::Display for FlashloanSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Flashloan {
+    pub source: FlashloanSource,
+    // aave pool, balancer vault or morpho
+    pub lender: Address,
+    // in the debt asset's smallest unit
+    pub fee: U256,
+}
+
+/// What a lender would charge to lend `liquidity` at most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlashloanOffer {
+    pub source: FlashloanSource,
+    pub lender: Address,
+    pub fee: U256,
+    pub liquidity: U256,
+}
+
+/// Flashloan providers of the network, the zero-fee ones are optional as they may not be
+/// deployed everywhere.
+#[derive(Debug, Clone)]
+pub struct FlashloanLenders {
+    pub aave_pool: Address,
+    pub balancer_vault: Option<Address>,
+    pub morpho: Option<Address>,
+}
+
+impl FlashloanLenders {
+    /// The cheapest lender able to lend `amount` of `asset`, `None` when there's none. The aave
+    /// pool only lends reserves whose flashloans are enabled.
+    pub async fn cheapest<P: Provider + Unpin + Clone + 'static>(
+        &self,
+        provider: &P,
+        asset: Address,
+        amount: U256,
+        aave_enabled: bool,
+        aave_premium_bps: u128,
+    ) -> Option<Flashloan> {
+        let token = contracts::erc20::ERC20Contract::new(asset, provider.clone());
+
+        let mut offers = Vec::new();
+        for (source, lender) in [
+            (FlashloanSource::Balancer, self.balancer_vault),
+            (FlashloanSource::Morpho, self.morpho),
+        ] {
+            let Some(lender) = lender else { continue };
+            // both hold what they lend, i.e their balance is what's available
+            let liquidity = match token.balanceOf(lender).call().await {
+                Ok(balance) => balance._0,
+                Err(e) => {
+                    warn!(%source, ?lender, ?asset, error = ?e, "failed to fetch flashloan liquidity");
+                    continue;
+                }
+            };
+            offers.push(FlashloanOffer {
+                source,
+                lender,
+                fee: U256::ZERO,
+                liquidity,
+            });
+        }
+        if aave_enabled {
+            match self.aave_liquidity(provider, &token).await {
+                Ok(liquidity) => offers.push(FlashloanOffer {
+                    source: FlashloanSource::Aave,
+                    lender: self.aave_pool,
+                    fee: aave_flashloan_fee(amount, aave_premium_bps),
+                    liquidity,
+                }),
+                Err(e) => {
+                    warn!(source = %FlashloanSource::Aave, lender = ?self.aave_pool, ?asset, error = ?e, "failed to fetch flashloan liquidity");
+                }
+            }
+        }
+
+        cheapest_offer(&offers, amount)
+    }
+
+    /// The pool lends what the reserve's aToken holds of the asset.
+    async fn aave_liquidity<P: Provider + Unpin + Clone + 'static>(
+        &self,
+        provider: &P,
+        token: &contracts::erc20::ERC20Contract::ERC20ContractInstance<(), P>,
+    ) -> eyre::Result<U256> {
+        let a_token = contracts::aave_v3::PoolContract::new(self.aave_pool, provider.clone())
+            .getReserveData(*token.address())
+            .call()
+            .await?
+            ._0
+            .aTokenAddress;
+        Ok(token.balanceOf(a_token).call().await?._0)
+    }
+}
+
+/// `FLASHLOAN_PREMIUM_TOTAL` is expressed in bps, i.e 5 is 0.05%
+pub fn aave_flashloan_fee(amount: U256, premium_bps: u128) -> U256 {
+    amount * U256::from(premium_bps) / U256::from(10_000)
+}
+
+/// Lowest fee among the offers covering `amount`, the first one wins ties.
+pub fn cheapest_offer(offers: &[FlashloanOffer], amount: U256) -> Option<Flashloan> {
+    offers
+        .iter()
+        .filter(|offer| offer.liquidity >= amount)
+        .min_by_key(|offer| offer.fee)
+        .map(|offer| Flashloan {
+            source: offer.source,
+            lender: offer.lender,
+            fee: offer.fee,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::address;
+
+    use super::*;
+
+    const POOL: Address = address!("0x87870Bca3F3fD6335C3F4ce8392D69350B4fA4E2");
+    const VAULT: Address = address!("0xbA1333333333a1BA1108E8412f11850A5C319bA9");
+    const MORPHO: Address = address!("0xBBBBBbbBBb9cC5e90e3b3Af64bdAF62C37EEFFCb");
+
+    fn offers(balancer_liquidity: u64, morpho_liquidity: u64) -> Vec<FlashloanOffer> {
+        vec![
+            FlashloanOffer {
+                source: FlashloanSource::Balancer,
+                lender: VAULT,
+                fee: U256::ZERO,
+                liquidity: U256::from(balancer_liquidity),
+            },
+            FlashloanOffer {
+                source: FlashloanSource::Morpho,
+                lender: MORPHO,
+                fee: U256::ZERO,
+                liquidity: U256::from(morpho_liquidity),
+            },
+            FlashloanOffer {
+                source: FlashloanSource::Aave,
+                lender: POOL,
+                fee: aave_flashloan_fee(U256::from(1_000_000u64), 5),
+                liquidity: U256::MAX,
+            },
+        ]
+    }
+
+    #[test]
+    fn aave_premium_is_in_bps() {
+        assert_eq!(
+            aave_flashloan_fee(U256::from(1_000_000u64), 5),
+            U256::from(500)
+        );
+        assert_eq!(aave_flashloan_fee(U256::from(1_000u64), 0), U256::ZERO);
+    }
+
+    #[test]
+    fn zero_fee_lenders_are_preferred_when_liquid_enough() {
+        let amount = U256::from(1_000_000u64);
+
+        let flashloan = cheapest_offer(&offers(2_000_000, 2_000_000), amount).unwrap();
+        assert_eq!(flashloan.source, FlashloanSource::Balancer);
+        assert_eq!(flashloan.fee, U256::ZERO);
+
+        let flashloan = cheapest_offer(&offers(10, 1_000_000), amount).unwrap();
+        assert_eq!(flashloan.source, FlashloanSource::Morpho);
+        assert_eq!(flashloan.lender, MORPHO);
+
+        let flashloan = cheapest_offer(&offers(10, 10), amount).unwrap();
+        assert_eq!(flashloan.source, FlashloanSource::Aave);
+        assert_eq!(flashloan.fee, U256::from(500));
+    }
+}
@@ -0,0 +1,295 @@
+//! `users`, `user`, `reserves` and `liquidations` subcommands, day-to-day views of what the bot
+//! keeps in the database.
+
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::{
+    actors::messages::database::{
+        get_health_factor_history, get_liquidations, get_reserves, get_user_positions, get_users,
+        HealthFactorPoint, HistoryRange, Liquidation, UserPosition, UserSummary, UsersQuery,
+    },
+    args::{LiquidationsArgs, ReservesArgs, UserArgs, UsersArgs},
+    output::{self, cell, Table},
+};
+
+#[derive(Debug, Serialize)]
+pub struct UserDetails {
+    // `None` until the user's been evaluated
+    pub summary: Option<UserSummary>,
+    pub positions: Vec<UserPosition>,
+    pub health_factor_history: Vec<HealthFactorPoint>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReserveOverview {
+    pub reserve: String,
+    pub symbol: String,
+    pub price_usd: f64,
+    pub price_stale: bool,
+    pub liquidation_threshold: f64,
+    pub liquidation_bonus: f64,
+    pub liquidity_index: f64,
+    pub variable_borrow_index: f64,
+    pub liquidity_rate: f64,
+    pub variable_borrow_rate: f64,
+    pub is_active: bool,
+    pub is_frozen: bool,
+    pub is_paused: bool,
+}
+
+pub async fn users(pool: &PgPool, target: &str, args: &UsersArgs) -> eyre::Result<()> {
+    let (network, protocol) = target.split_once('-').unwrap();
+
+    let users = get_users(
+        pool,
+        network,
+        protocol,
+        &UsersQuery {
+            max_health_factor: args.max_hf,
+            sort: args.sort,
+            limit: Some(args.limit),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    let mut table = Table::new(
+        format!("users, by {}", args.sort.as_str()),
+        vec![
+            "user",
+            "health_factor",
+            "collateral_usd",
+            "debt_usd",
+            "updated_at",
+        ],
+    );
+    for u in &users {
+        table.push(user_row(u));
+    }
+    output::print(args.format, &users, &[table])
+}
+
+pub async fn user(pool: &PgPool, target: &str, args: &UserArgs) -> eyre::Result<()> {
+    let (network, protocol) = target.split_once('-').unwrap();
+
+    let summary = get_users(
+        pool,
+        network,
+        protocol,
+        &UsersQuery {
+            user: Some(args.address),
+            ..Default::default()
+        },
+    )
+    .await?
+    .pop();
+    let positions = get_user_positions(pool, network, protocol, args.address).await?;
+    let health_factor_history = get_health_factor_history(
+        pool,
+        network,
+        protocol,
+        args.address,
+        &HistoryRange {
+            since_secs: Some(args.since),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    let details = UserDetails {
+        summary,
+        positions,
+        health_factor_history,
+    };
+    output::print(args.format, &details, &user_tables(&details, args.since))
+}
+
+pub async fn reserves(pool: &PgPool, target: &str, args: &ReservesArgs) -> eyre::Result<()> {
+    let (network, protocol) = target.split_once('-').unwrap();
+
+    let mut reserves = get_reserves(pool, network, protocol)
+        .await?
+        .into_iter()
+        .map(|r| ReserveOverview {
+            reserve: r.reserve.to_string(),
+            symbol: r.symbol,
+            price_usd: r.stats.price_usd,
+            price_stale: r.stats.price_stale,
+            liquidation_threshold: r.liquidation_threshold,
+            liquidation_bonus: r.liquidation_bonus,
+            liquidity_index: r.stats.liquidity_index,
+            variable_borrow_index: r.stats.variable_borrow_index,
+            liquidity_rate: r.stats.liquidity_rate,
+            variable_borrow_rate: r.stats.variable_borrow_rate,
+            is_active: r.flags.is_active,
+            is_frozen: r.flags.is_frozen,
+            is_paused: r.flags.is_paused,
+        })
+        .collect::<Vec<_>>();
+    reserves.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+
+    let mut table = Table::new(
+        "reserves",
+        vec![
+            "symbol",
+            "reserve",
+            "price_usd",
+            "lt_%",
+            "bonus_%",
+            "liquidity_index",
+            "borrow_index",
+            "supply_apr_%",
+            "borrow_apr_%",
+            "status",
+        ],
+    );
+    for r in &reserves {
+        table.push(vec![
+            r.symbol.clone(),
+            r.reserve.clone(),
+            format!(
+                "{:.4}{}",
+                r.price_usd,
+                if r.price_stale { " (stale)" } else { "" }
+            ),
+            format!("{:.2}", r.liquidation_threshold),
+            format!("{:.2}", r.liquidation_bonus),
+            format!("{:.6}", r.liquidity_index),
+            format!("{:.6}", r.variable_borrow_index),
+            format!("{:.2}", r.liquidity_rate),
+            format!("{:.2}", r.variable_borrow_rate),
+            reserve_status(r).to_string(),
+        ]);
+    }
+    output::print(args.format, &reserves, &[table])
+}
+
+pub async fn liquidations(
+    pool: &PgPool,
+    target: &str,
+    args: &LiquidationsArgs,
+) -> eyre::Result<()> {
+    let (network, protocol) = target.split_once('-').unwrap();
+
+    let liquidations = get_liquidations(pool, network, protocol, args.since).await?;
+
+    let mut table = Table::new(
+        format!("liquidations, last {}s", args.since),
+        vec![
+            "timestamp",
+            "user",
+            "collateral",
+            "debt",
+            "debt_usd",
+            "collateral_usd",
+            "liquidator",
+            "by",
+            "block",
+            "tx_hash",
+        ],
+    );
+    for l in &liquidations {
+        table.push(liquidation_row(l));
+    }
+    output::print(args.format, &liquidations, &[table])
+}
+
+fn user_row(u: &UserSummary) -> Vec<String> {
+    vec![
+        u.user_address.clone(),
+        format!("{:.4}", u.health_factor),
+        format!("{:.2}", u.collateral_usd),
+        format!("{:.2}", u.debt_usd),
+        u.updated_at.clone(),
+    ]
+}
+
+fn user_tables(details: &UserDetails, since_secs: u64) -> Vec<Table> {
+    let mut summary = Table::new(
+        "user",
+        vec![
+            "user",
+            "health_factor",
+            "collateral_usd",
+            "debt_usd",
+            "updated_at",
+        ],
+    );
+    if let Some(u) = &details.summary {
+        summary.push(user_row(u));
+    }
+
+    let mut positions = Table::new(
+        "positions",
+        vec![
+            "symbol",
+            "reserve",
+            "supplied",
+            "borrowed",
+            "collateral",
+            "price_usd",
+            "supplied_usd",
+            "borrowed_usd",
+        ],
+    );
+    for p in &details.positions {
+        positions.push(vec![
+            p.symbol.clone(),
+            p.reserve.clone(),
+            format!("{:.6}", p.supply_amount),
+            format!("{:.6}", p.borrow_amount),
+            p.collateral_enabled.to_string(),
+            cell(p.price_usd, 4),
+            cell(p.supply_usd, 2),
+            cell(p.borrow_usd, 2),
+        ]);
+    }
+
+    let mut history = Table::new(
+        format!("health factor history, last {since_secs}s"),
+        vec!["block", "health_factor", "recorded_at"],
+    );
+    for h in &details.health_factor_history {
+        history.push(vec![
+            h.block_number.to_string(),
+            format!("{:.4}", h.health_factor),
+            h.recorded_at.clone(),
+        ]);
+    }
+
+    vec![summary, positions, history]
+}
+
+fn liquidation_row(l: &Liquidation) -> Vec<String> {
+    let by = match (l.ours, l.missed) {
+        (true, _) => "us",
+        // we had an attempt for it
+        (false, true) => "missed",
+        (false, false) => "other",
+    };
+    vec![
+        l.timestamp.clone(),
+        l.user_address.clone(),
+        l.collateral_symbol.clone(),
+        l.debt_symbol.clone(),
+        cell(l.debt_to_cover_usd, 2),
+        cell(l.liquidated_collateral_usd, 2),
+        l.liquidator_address.clone().unwrap_or("-".to_string()),
+        by.to_string(),
+        l.block_number.map_or("-".to_string(), |b| b.to_string()),
+        l.tx_hash.clone().unwrap_or("-".to_string()),
+    ]
+}
+
+fn reserve_status(r: &ReserveOverview) -> &'static str {
+    if r.is_paused {
+        "paused"
+    } else if r.is_frozen {
+        "frozen"
+    } else if !r.is_active {
+        "inactive"
+    } else {
+        "active"
+    }
+}
@@ -0,0 +1,24 @@
+pub mod actors;
+pub mod api;
+pub mod args;
+pub mod backtest;
+pub mod client;
+pub mod configs;
+pub mod consts;
+pub mod contracts;
+pub mod database;
+pub mod error;
+pub mod flashloan;
+pub mod inspect;
+pub mod liquidate;
+pub mod metrics;
+pub mod output;
+pub mod reorg;
+pub mod report;
+pub mod revert;
+pub mod router;
+pub mod run;
+pub mod sim;
+pub mod strategy;
+pub mod utils;
+pub mod validator;
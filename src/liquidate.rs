@@ -0,0 +1,476 @@
+//! `liquidate` subcommand, checks a user and liquidates them through the bot on the spot, e.g
+//! during an incident.
+//!
+//! It goes through the executor's steps: health factor, pair, swap route, flashloan and strategy,
+//! off our view of the reserves. Only the database actor is started, the protocol isn't followed.
+
+use std::sync::Arc;
+
+use actix::prelude::*;
+use alloy::{
+    network::EthereumWallet,
+    primitives::{Address, TxHash, U256},
+    providers::{Provider, ProviderBuilder},
+    signers::local::PrivateKeySigner,
+};
+use serde::Serialize;
+use sqlx::PgPool;
+use tracing::{error, info, warn};
+
+use crate::{
+    actors::{
+        executor::{attempt_status, plan_liquidation, router_and_lenders},
+        messages::database::{self, Reserve},
+        Database,
+    },
+    args::{ExecutionArgs, LiquidateArgs},
+    client::{AaveV3Client, LendingProtocolClient, Liquidation},
+    configs::DatabaseConfig,
+    flashloan::FlashloanSource,
+    output::{self, cell, Table},
+    revert::revert_reason,
+    strategy::Strategy,
+    utils::{health_factor, liquidation_pair, norm},
+    validator::{validate_liquidation, SkipReason},
+};
+
+#[derive(Debug, Default, Serialize)]
+pub struct LiquidateReport {
+    pub user: Address,
+    // `None` without debt
+    pub health_factor: Option<f64>,
+    pub collateral_asset: Address,
+    pub collateral_symbol: String,
+    pub debt_asset: Address,
+    pub debt_symbol: String,
+    // amounts in token units, the seized collateral in the collateral's, the rest in the debt's
+    pub debt_to_cover: f64,
+    pub seized: f64,
+    pub quote: Option<f64>,
+    pub route: Vec<Address>,
+    pub flashloan_source: Option<FlashloanSource>,
+    pub flashloan_fee: f64,
+    pub strategy: Option<Strategy>,
+    pub estimated_profit_usd: Option<f64>,
+    // why the liquidation wasn't sent
+    pub skipped: Option<String>,
+    pub tx_hash: Option<TxHash>,
+    pub success: Option<bool>,
+    pub revert_reason: Option<String>,
+}
+
+/// Sends from `account_privkey`'s account, which is only needed unless `args.dry_run`.
+pub async fn liquidate(
+    pool: &PgPool,
+    rpc_url: &str,
+    account_privkey: Option<&str>,
+    bot_addr: Address,
+    target: &str,
+    execution: &ExecutionArgs,
+    args: &LiquidateArgs,
+) -> eyre::Result<()> {
+    let report = match account_privkey {
+        Some(privkey) => {
+            let provider = ProviderBuilder::new()
+                .wallet(EthereumWallet::from(privkey.parse::<PrivateKeySigner>()?))
+                .on_builtin(rpc_url)
+                .await?;
+            liquidate_user(provider, pool, bot_addr, target, execution, args).await?
+        }
+        None => {
+            let provider = ProviderBuilder::new().on_builtin(rpc_url).await?;
+            liquidate_user(provider, pool, bot_addr, target, execution, args).await?
+        }
+    };
+
+    output::print(args.format, &report, &[table(&report)])
+}
+
+async fn liquidate_user<P: Provider + Unpin + Clone + 'static>(
+    provider: P,
+    pool: &PgPool,
+    bot_addr: Address,
+    target: &str,
+    execution: &ExecutionArgs,
+    args: &LiquidateArgs,
+) -> eyre::Result<LiquidateReport> {
+    let db_addr = Database::new(DatabaseConfig {
+        pool: Arc::new(pool.clone()),
+        history: None,
+    })
+    .await
+    .start();
+
+    let contracts = db_addr
+        .send(database::GetProtocolContracts(target.to_string()))
+        .await??;
+    let client = AaveV3Client::new(provider.clone(), &contracts, bot_addr)?;
+    let protocol_details_id = db_addr
+        .send(database::GetProtocolDetailsId(target.to_string()))
+        .await??;
+    let (router, flashloan_lenders) = router_and_lenders(&provider, &db_addr, target).await?;
+    let indices = db_addr
+        .send(database::GetReservesLiquidityIndices(target.to_string()))
+        .await??;
+    let reserves = db_addr
+        .send(database::GetReserves(target.to_string()))
+        .await??;
+
+    let hf = health_factor(&client, args.user).await?;
+    let debt_asset = args
+        .debt_asset
+        .as_deref()
+        .map(|asset| find_reserve(&reserves, asset))
+        .transpose()?
+        .map(|r| r.reserve);
+    let collateral_asset = args
+        .collateral_asset
+        .as_deref()
+        .map(|asset| find_reserve(&reserves, asset))
+        .transpose()?
+        .map(|r| r.reserve);
+    let (debt_asset, collateral_asset, debt_to_cover, collateral_balance) = liquidation_pair(
+        &client.user_reserves(args.user).await?,
+        &indices,
+        debt_asset,
+        collateral_asset,
+    )?;
+
+    let collateral_reserve = find_reserve(&reserves, &collateral_asset.to_string())?;
+    let debt_reserve = find_reserve(&reserves, &debt_asset.to_string())?;
+    let debt_to_cover = match args.amount {
+        Some(amount) => to_amount(debt_reserve, amount),
+        None => debt_to_cover,
+    };
+    let debt_scale = Some(10f64.powi(-debt_reserve.decimals));
+
+    let plan = plan_liquidation(
+        &provider,
+        &client,
+        &router,
+        &flashloan_lenders,
+        bot_addr,
+        collateral_reserve,
+        debt_reserve,
+        debt_to_cover,
+        collateral_balance,
+        &execution.hold_atokens,
+        execution.slippage_bps,
+        execution.min_profit_usd,
+    )
+    .await?;
+
+    let mut report = LiquidateReport {
+        user: args.user,
+        health_factor: hf,
+        collateral_asset,
+        collateral_symbol: collateral_reserve.symbol.clone(),
+        debt_asset,
+        debt_symbol: debt_reserve.symbol.clone(),
+        debt_to_cover: norm(debt_to_cover, debt_scale)?,
+        seized: norm(plan.seized, Some(10f64.powi(-collateral_reserve.decimals)))?,
+        quote: plan
+            .route
+            .as_ref()
+            .map(|route| norm(route.amount_out, debt_scale))
+            .transpose()?,
+        route: plan
+            .route
+            .as_ref()
+            .map_or(Vec::new(), |route| route.tokens.clone()),
+        flashloan_source: plan
+            .candidate
+            .as_ref()
+            .and_then(|c| c.flashloan)
+            .map(|f| f.source),
+        flashloan_fee: norm(plan.flashloan.map_or(U256::ZERO, |f| f.fee), debt_scale)?,
+        strategy: plan.candidate.as_ref().map(|c| c.strategy),
+        estimated_profit_usd: plan.candidate.as_ref().map(|c| c.expected_profit_usd),
+        ..Default::default()
+    };
+
+    let liquidation_allowed = client
+        .sentinel()
+        .await?
+        .is_none_or(|status| status.liquidation_allowed);
+    report.skipped = skip_reason(
+        hf,
+        validate_liquidation(collateral_reserve, debt_reserve),
+        liquidation_allowed,
+        plan.candidate().err(),
+        args.dry_run,
+    );
+    let (None, Some(hf), Some(candidate)) = (&report.skipped, hf, plan.candidate) else {
+        return Ok(report);
+    };
+
+    let liquidation = Liquidation {
+        debt_asset,
+        collateral_asset,
+        user: args.user,
+        debt_to_cover,
+        path: candidate.path,
+        min_amount_out: candidate.min_amount_out,
+        flashloan: candidate.flashloan,
+    };
+    info!(user = ?args.user, strategy = %candidate.strategy, flashloan = ?candidate.flashloan.map(|f| f.source), estimated_profit_usd = candidate.expected_profit_usd, "liquidating");
+
+    let sent = client.liquidate(liquidation.clone()).await;
+    let liquidated = sent.as_ref().is_ok_and(|receipt| receipt.success);
+    let still_liquidatable = liquidated
+        || match health_factor(&client, args.user).await {
+            Ok(hf) => hf.is_some_and(|hf| hf < 1.0),
+            // can't tell, don't blame it on someone else
+            Err(e) => {
+                warn!(user = ?args.user, "failed to fetch health factor: {}", e);
+                true
+            }
+        };
+    let status = attempt_status(false, &sent, still_liquidatable);
+    let (receipt, revert_reason) = match sent {
+        Ok(receipt) => (Some(receipt), None),
+        Err(e) => {
+            error!(user = ?args.user, "Liquidation failed: {}", e);
+            (None, Some(revert_reason(&e).unwrap_or(e.to_string())))
+        }
+    };
+    report.tx_hash = receipt.as_ref().map(|r| r.tx_hash);
+    report.success = Some(liquidated);
+    report.revert_reason = revert_reason.clone();
+
+    db_addr
+        .send(database::InsertLiquidationAttempt {
+            protocol_details_id,
+            liquidation,
+            strategy: candidate.strategy,
+            health_factor: hf,
+            estimated_profit_usd: candidate.expected_profit_usd,
+            status,
+            receipt,
+            revert_reason,
+        })
+        .await??;
+
+    Ok(report)
+}
+
+/// Why the liquidation isn't sent, `None` when it is.
+pub fn skip_reason(
+    health_factor: Option<f64>,
+    validation: Result<(), SkipReason>,
+    liquidation_allowed: bool,
+    // why the plan has no strategy
+    unplanned: Option<SkipReason>,
+    dry_run: bool,
+) -> Option<String> {
+    if !health_factor.is_some_and(|hf| hf < 1.0) {
+        return Some("healthy".to_string());
+    }
+    if let Err(reason) = validation {
+        return Some(reason.to_string());
+    }
+    if !liquidation_allowed {
+        return Some("disallowed by the price oracle sentinel".to_string());
+    }
+    if let Some(reason) = unplanned {
+        return Some(reason.to_string());
+    }
+    dry_run.then(|| "dry run".to_string())
+}
+
+/// Reserve by address or symbol, case insensitive.
+pub fn find_reserve<'a>(reserves: &'a [Reserve], asset: &str) -> eyre::Result<&'a Reserve> {
+    reserves
+        .iter()
+        .find(|r| {
+            r.reserve.to_string().eq_ignore_ascii_case(asset)
+                || r.symbol.eq_ignore_ascii_case(asset)
+        })
+        .ok_or(eyre::eyre!("unknown reserve {}", asset))
+}
+
+/// `amount` in token units, in the reserve's smallest unit.
+pub fn to_amount(reserve: &Reserve, amount: f64) -> U256 {
+    U256::from((amount * 10f64.powi(reserve.decimals)) as u128)
+}
+
+fn table(report: &LiquidateReport) -> Table {
+    let mut table = Table::new(
+        format!("liquidation of {}", report.user),
+        vec!["field", "value"],
+    );
+    let rows = [
+        ("health_factor", cell(report.health_factor, 4)),
+        (
+            "collateral",
+            format!("{} ({})", report.collateral_symbol, report.collateral_asset),
+        ),
+        (
+            "debt",
+            format!("{} ({})", report.debt_symbol, report.debt_asset),
+        ),
+        (
+            "debt_to_cover",
+            format!("{:.6} {}", report.debt_to_cover, report.debt_symbol),
+        ),
+        (
+            "seized",
+            format!("{:.6} {}", report.seized, report.collateral_symbol),
+        ),
+        (
+            "quote",
+            report.quote.map_or("-".to_string(), |quote| {
+                format!("{:.6} {}", quote, report.debt_symbol)
+            }),
+        ),
+        (
+            "route",
+            if report.route.is_empty() {
+                "-".to_string()
+            } else {
+                report
+                    .route
+                    .iter()
+                    .map(|token| token.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" > ")
+            },
+        ),
+        (
+            "flashloan",
+            report.flashloan_source.map_or("-".to_string(), |source| {
+                format!(
+                    "{} (fee {:.6} {})",
+                    source, report.flashloan_fee, report.debt_symbol
+                )
+            }),
+        ),
+        (
+            "strategy",
+            report
+                .strategy
+                .map_or("-".to_string(), |strategy| strategy.to_string()),
+        ),
+        ("estimated_profit_usd", cell(report.estimated_profit_usd, 2)),
+        ("skipped", report.skipped.clone().unwrap_or("-".to_string())),
+        (
+            "tx_hash",
+            report
+                .tx_hash
+                .map_or("-".to_string(), |hash| hash.to_string()),
+        ),
+        (
+            "success",
+            report
+                .success
+                .map_or("-".to_string(), |success| success.to_string()),
+        ),
+        (
+            "revert_reason",
+            report.revert_reason.clone().unwrap_or("-".to_string()),
+        ),
+    ];
+    for (field, value) in rows {
+        table.push(vec![field.to_string(), value]);
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::client::UserReserve;
+
+    #[test]
+    fn only_liquidatable_and_profitable_users_are_sent() {
+        assert_eq!(skip_reason(Some(0.9), Ok(()), true, None, false), None);
+        assert_eq!(
+            skip_reason(Some(0.9), Ok(()), true, None, true).as_deref(),
+            Some("dry run")
+        );
+        // without debt
+        assert_eq!(
+            skip_reason(None, Ok(()), true, None, false).as_deref(),
+            Some("healthy")
+        );
+        assert_eq!(
+            skip_reason(Some(1.0), Ok(()), true, None, false).as_deref(),
+            Some("healthy")
+        );
+        assert_eq!(
+            skip_reason(
+                Some(0.9),
+                Err(SkipReason::DebtReservePaused),
+                true,
+                None,
+                false
+            )
+            .as_deref(),
+            Some("debt_reserve_paused")
+        );
+        assert_eq!(
+            skip_reason(Some(0.9), Ok(()), false, None, false).as_deref(),
+            Some("disallowed by the price oracle sentinel")
+        );
+        assert_eq!(
+            skip_reason(
+                Some(0.9),
+                Ok(()),
+                true,
+                Some(SkipReason::Unprofitable),
+                false
+            )
+            .as_deref(),
+            Some("unprofitable")
+        );
+        // no lender for the debt asset
+        assert_eq!(
+            skip_reason(
+                Some(0.9),
+                Ok(()),
+                true,
+                Some(SkipReason::FlashloanDisabled),
+                false
+            )
+            .as_deref(),
+            Some("flashloan_disabled")
+        );
+    }
+
+    #[test]
+    fn pair_defaults_to_the_first_debt_and_collateral() {
+        let (weth, wbtc, usdc, dai) = (
+            Address::with_last_byte(1),
+            Address::with_last_byte(2),
+            Address::with_last_byte(3),
+            Address::with_last_byte(4),
+        );
+        let position = |asset, supplied: u64, borrowed: u64| UserReserve {
+            underlying_asset: asset,
+            scaled_a_token_balance: U256::from(supplied),
+            scaled_variable_debt: U256::from(borrowed),
+            usage_as_collateral_enabled: true,
+        };
+        let positions = [
+            position(weth, 10, 0),
+            position(wbtc, 5, 0),
+            position(usdc, 0, 1_000),
+            position(dai, 0, 400),
+        ];
+        let indices = HashMap::from([(dai.to_string(), (1.0, 1.5))]);
+
+        assert_eq!(
+            liquidation_pair(&positions, &indices, None, None).unwrap(),
+            (usdc, weth, U256::from(500), U256::from(10))
+        );
+        // half the chosen debt, accrued
+        assert_eq!(
+            liquidation_pair(&positions, &indices, Some(dai), Some(wbtc)).unwrap(),
+            (dai, wbtc, U256::from(300), U256::from(5))
+        );
+        // nothing borrowed in it
+        assert!(liquidation_pair(&positions, &indices, Some(weth), None).is_err());
+    }
+}
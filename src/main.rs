@@ -1,42 +1,117 @@
-mod actors;
-mod args;
-mod configs;
-mod consts;
-mod contracts;
-mod database;
-mod run;
-mod utils;
-
-use clap::Parser;
+use clap::{error::ErrorKind, CommandFactory, Parser};
+use liquidator::{
+    args::{Args, Command},
+    backtest::backtest,
+    configs::Config,
+    inspect::{liquidations, reserves, user, users},
+    liquidate::liquidate,
+    report::report,
+    run::run,
+    sim::sim,
+};
 use secrecy::ExposeSecret;
 use tracing::{debug, info};
 
-use crate::{args::Args, configs::Config, run::run};
-
-pub async fn run_migrations(config: &Config) {
+pub async fn run_migrations(database_url: &str) {
     sqlx::migrate!("./migrations")
-        .run(&sqlx::PgPool::connect(&config.database_url).await.unwrap())
+        .run(&sqlx::PgPool::connect(database_url).await.unwrap())
         .await
         .unwrap();
 }
 
-#[actix_rt::main]
-async fn main() {
-    tracing_subscriber::fmt().init();
+/// Exits with a usage error if an argument needed `to` do something is missing.
+fn required<T>(value: Option<T>, name: &str, to: &str) -> T {
+    value.unwrap_or_else(|| {
+        Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                format!("--{name} is required to {to}"),
+            )
+            .exit()
+    })
+}
 
+#[actix_rt::main]
+async fn main() -> eyre::Result<()> {
     let args = Args::parse();
-    info!(?args);
-
-    let config = Config {
-        ws_url: args.ws_url.expose_secret().into(),
-        database_url: args.database_url.expose_secret().into(),
-        account_pubkey: args.account_pubkey,
-        account_privkey: args.account_privkey.expose_secret().into(),
-        bot_addr: args.bot_addr,
-        target: format!("{}-{}", args.network, args.protocol),
-    };
-    debug!(?config);
-
-    run_migrations(&config).await;
-    run(config).await
+    let database_url: String = args.database_url.expose_secret().into();
+    let target = format!("{}-{}", args.network, args.protocol);
+
+    match &args.command {
+        None => {
+            tracing_subscriber::fmt().init();
+            info!(?args);
+
+            let config = Config {
+                ws_url: args.ws_url.expose_secret().into(),
+                database_url,
+                account_pubkey: required(args.account_pubkey, "account-pubkey", "run the bot"),
+                account_privkey: required(
+                    args.account_privkey.as_ref(),
+                    "account-privkey",
+                    "run the bot",
+                )
+                .expose_secret()
+                .into(),
+                bot_addr: required(args.bot_addr, "bot-addr", "run the bot"),
+                confirmations: args.confirmations,
+                http_addr: args.http_addr,
+                execution: args.execution.clone(),
+                monitor: args.monitor.clone(),
+                history: args.history.clone(),
+                target,
+            };
+            debug!(?config);
+
+            run_migrations(&config.database_url).await;
+            run(config).await;
+        }
+        // keep stdout for the subcommands' output
+        Some(command) => {
+            tracing_subscriber::fmt()
+                .with_writer(std::io::stderr)
+                .init();
+            run_migrations(&database_url).await;
+            let pool = sqlx::PgPool::connect(&database_url).await?;
+
+            match command {
+                Command::Report(report_args) => report(&pool, &target, report_args).await?,
+                Command::Backtest(backtest_args) => {
+                    backtest(&pool, &target, backtest_args, &args.execution).await?
+                }
+                Command::Sim(sim_args) => {
+                    sim(&pool, args.ws_url.expose_secret(), &target, sim_args).await?
+                }
+                Command::Users(users_args) => users(&pool, &target, users_args).await?,
+                Command::User(user_args) => user(&pool, &target, user_args).await?,
+                Command::Reserves(reserves_args) => reserves(&pool, &target, reserves_args).await?,
+                Command::Liquidations(liquidations_args) => {
+                    liquidations(&pool, &target, liquidations_args).await?
+                }
+                Command::Liquidate(liquidate_args) => {
+                    // a dry run doesn't send anything
+                    let account_privkey = (!liquidate_args.dry_run).then(|| {
+                        required(
+                            args.account_privkey.as_ref(),
+                            "account-privkey",
+                            "liquidate",
+                        )
+                        .expose_secret()
+                    });
+                    liquidate(
+                        &pool,
+                        args.ws_url.expose_secret(),
+                        account_privkey,
+                        required(args.bot_addr, "bot-addr", "liquidate"),
+                        &target,
+                        &args.execution,
+                        liquidate_args,
+                    )
+                    .await?
+                }
+            }
+        }
+    }
+
+    Ok(())
 }
@@ -0,0 +1,144 @@
+//! Process-wide metrics, rendered in Prometheus' text exposition format by the HTTP API's
+//! `/metrics`.
+//!
+//! https://prometheus.io/docs/instrumenting/exposition_formats/
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{LazyLock, Mutex},
+};
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Gauge,
+    Counter,
+}
+
+impl Kind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Gauge => "gauge",
+            Kind::Counter => "counter",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Family {
+    kind: Kind,
+    help: &'static str,
+    // labels => value
+    series: BTreeMap<Vec<(&'static str, String)>, f64>,
+}
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    families: Mutex<BTreeMap<&'static str, Family>>,
+}
+
+impl Metrics {
+    pub fn set_gauge(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, String)],
+        value: f64,
+    ) {
+        self.update(name, help, Kind::Gauge, labels, |v| *v = value);
+    }
+
+    pub fn inc_counter(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, String)],
+        by: f64,
+    ) {
+        self.update(name, help, Kind::Counter, labels, |v| *v += by);
+    }
+
+    fn update(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        kind: Kind,
+        labels: &[(&'static str, String)],
+        f: impl FnOnce(&mut f64),
+    ) {
+        let mut families = self.families.lock().unwrap();
+        let family = families.entry(name).or_insert(Family {
+            kind,
+            help,
+            series: BTreeMap::new(),
+        });
+        debug_assert_eq!(
+            family.kind, kind,
+            "{} registered as a {:?}",
+            name, family.kind
+        );
+        f(family.series.entry(labels.to_vec()).or_insert(0.0));
+    }
+
+    pub fn render(&self) -> String {
+        let families = self.families.lock().unwrap();
+
+        let mut out = String::new();
+        for (name, family) in families.iter() {
+            let _ = writeln!(out, "# HELP {} {}", name, family.help);
+            let _ = writeln!(out, "# TYPE {} {}", name, family.kind.as_str());
+            for (labels, value) in &family.series {
+                let _ = writeln!(out, "{}{} {}", name, render_labels(labels), value);
+            }
+        }
+        out
+    }
+}
+
+fn render_labels(labels: &[(&'static str, String)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+
+    let labels = labels
+        .iter()
+        .map(|(k, v)| {
+            let v = v
+                .replace('\\', r"\\")
+                .replace('"', r#"\""#)
+                .replace('\n', r"\n");
+            format!(r#"{}="{}""#, k, v)
+        })
+        .collect::<Vec<_>>();
+    format!("{{{}}}", labels.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn series_render_in_the_exposition_format() {
+        let metrics = Metrics::default();
+        metrics.set_gauge(
+            "oracle_feed_age_seconds",
+            "Seconds since the feed's last update",
+            &[("aggregator", "0xabc".to_string())],
+            12.0,
+        );
+        metrics.inc_counter("restarts_total", "Actor restarts", &[], 1.0);
+        metrics.inc_counter("restarts_total", "Actor restarts", &[], 2.0);
+
+        assert_eq!(
+            metrics.render(),
+            "# HELP oracle_feed_age_seconds Seconds since the feed's last update\n\
+             # TYPE oracle_feed_age_seconds gauge\n\
+             oracle_feed_age_seconds{aggregator=\"0xabc\"} 12\n\
+             # HELP restarts_total Actor restarts\n\
+             # TYPE restarts_total counter\n\
+             restarts_total 3\n"
+        );
+    }
+}
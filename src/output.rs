@@ -0,0 +1,156 @@
+//! Rendering of the read-only subcommands' results, as an aligned table, JSON or CSV.
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    #[default]
+    Table,
+    Json,
+    Csv,
+}
+
+#[derive(Debug)]
+pub struct Table {
+    pub title: String,
+    pub headers: Vec<&'static str>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(title: impl Into<String>, headers: Vec<&'static str>) -> Self {
+        Table {
+            title: title.into(),
+            headers,
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    fn to_text(&self) -> String {
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.len()).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let line = |cells: &[String]| {
+            cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        };
+
+        let headers: Vec<String> = self.headers.iter().map(|h| h.to_string()).collect();
+        let separator: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+        let mut out = vec![
+            format!("# {}", self.title),
+            line(&headers),
+            line(&separator),
+        ];
+        if self.rows.is_empty() {
+            out.push("(none)".to_string());
+        }
+        out.extend(self.rows.iter().map(|row| line(row)));
+        out.join("\n")
+    }
+
+    fn to_csv(&self) -> String {
+        let escape = |cell: &str| {
+            if cell.contains([',', '"', '\n']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell.to_string()
+            }
+        };
+
+        let mut out = vec![self
+            .headers
+            .iter()
+            .map(|h| escape(h))
+            .collect::<Vec<_>>()
+            .join(",")];
+        for row in &self.rows {
+            out.push(row.iter().map(|c| escape(c)).collect::<Vec<_>>().join(","));
+        }
+        out.join("\n")
+    }
+}
+
+/// Prints `tables` in `format`, JSON output serializes `value` instead.
+/// CSV sections are separated by a blank line and prefixed with their title as a comment.
+pub fn print<T: Serialize>(format: Format, value: &T, tables: &[Table]) -> eyre::Result<()> {
+    match format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(value)?),
+        Format::Table => println!(
+            "{}",
+            tables
+                .iter()
+                .map(Table::to_text)
+                .collect::<Vec<_>>()
+                .join("\n\n")
+        ),
+        Format::Csv => println!(
+            "{}",
+            tables
+                .iter()
+                .map(|t| format!("# {}\n{}", t.title, t.to_csv()))
+                .collect::<Vec<_>>()
+                .join("\n\n")
+        ),
+    }
+    Ok(())
+}
+
+/// Formats an optional float cell, `-` when unknown.
+pub fn cell(value: Option<f64>, decimals: usize) -> String {
+    value.map_or("-".to_string(), |v| format!("{v:.decimals$}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> Table {
+        let mut table = Table::new("users", vec!["user", "health_factor"]);
+        table.push(vec!["0xabc".to_string(), cell(Some(0.98765), 2)]);
+        table.push(vec!["0xdef, \"2\"".to_string(), cell(None, 2)]);
+        table
+    }
+
+    #[test]
+    fn columns_are_aligned_on_their_widest_cell() {
+        assert_eq!(
+            table().to_text(),
+            [
+                "# users",
+                "user        health_factor",
+                "----------  -------------",
+                "0xabc       0.99",
+                "0xdef, \"2\"  -",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            Table::new("users", vec!["user"]).to_text(),
+            "# users\nuser\n----\n(none)"
+        );
+    }
+
+    #[test]
+    fn csv_cells_are_escaped() {
+        assert_eq!(
+            table().to_csv(),
+            "user,health_factor\n0xabc,0.99\n\"0xdef, \"\"2\"\"\",-"
+        );
+    }
+}
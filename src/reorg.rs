@@ -0,0 +1,170 @@
+//! Keeps track of the blocks we've processed logs from, so orphaned blocks can be rolled back.
+//!
+//! Writes derived from logs are held until they're `confirmations` blocks deep, a reorg within
+//! that window simply drops them. In-memory state isn't buffered, it follows the tip and gets
+//! re-derived for the affected users instead.
+//!
+//! Reserve stats are writes too, so the liquidity/borrow indices the executor sizes `debt_to_cover`
+//! with lag the tip by `confirmations` blocks. They only accrue interest in between, the lag is
+//! negligible next to the close factor, and applying them right away would let the delayed write
+//! of an older block move them backwards.
+
+use std::collections::BTreeMap;
+
+use alloy::primitives::B256;
+use tracing::{error, warn};
+
+#[derive(Debug)]
+pub struct ReorgTracker<T> {
+    confirmations: u64,
+    // block number => (block hash, writes pending confirmation)
+    blocks: BTreeMap<u64, (B256, Vec<T>)>,
+    // highest block whose writes were flushed
+    confirmed_up_to: Option<u64>,
+}
+
+impl<T> ReorgTracker<T> {
+    pub fn new(confirmations: u64) -> Self {
+        ReorgTracker {
+            confirmations,
+            blocks: BTreeMap::new(),
+            confirmed_up_to: None,
+        }
+    }
+
+    /// Records `hash` as the canonical block at `number`.
+    /// Returns the writes of every block orphaned by it, i.e any block at or above `number`
+    /// which was recorded with a different hash.
+    pub fn observe(&mut self, number: u64, hash: B256) -> Vec<T> {
+        let orphaned = match self.blocks.get(&number) {
+            Some((known, _)) if *known != hash => self.rollback(number),
+            _ => Vec::new(),
+        };
+
+        self.blocks
+            .entry(number)
+            .or_insert_with(|| (hash, Vec::new()));
+        orphaned
+    }
+
+    /// Queues a write derived from a log of block (`number`, `hash`).
+    pub fn push(&mut self, number: u64, hash: B256, write: T) {
+        let _ = self.observe(number, hash);
+        if let Some((_, writes)) = self.blocks.get_mut(&number) {
+            writes.push(write);
+        }
+    }
+
+    /// Handles a log delivered with `removed: true`, the block it belonged to is no longer canonical.
+    /// Returns the dropped writes of that block.
+    pub fn remove(&mut self, number: u64, hash: B256) -> Vec<T> {
+        match self.blocks.get(&number) {
+            Some((known, _)) if *known == hash => self.rollback(number),
+            Some(_) => Vec::new(), // already replaced by the canonical block
+            None => {
+                if self.confirmed_up_to.is_some_and(|c| number <= c) {
+                    error!(
+                        number,
+                        ?hash,
+                        "removed log for an already confirmed block, its writes can't be rolled back"
+                    );
+                } else {
+                    warn!(number, ?hash, "removed log for an unknown block");
+                }
+                Vec::new()
+            }
+        }
+    }
+
+    /// Drains the writes which are now at least `confirmations` blocks deep, given the current head.
+    pub fn confirmed(&mut self, head: u64) -> Vec<T> {
+        let Some(up_to) = head.checked_sub(self.confirmations) else {
+            return Vec::new();
+        };
+
+        let pending = self.blocks.split_off(&(up_to + 1));
+        let confirmed = std::mem::replace(&mut self.blocks, pending);
+        if let Some((&last, _)) = confirmed.last_key_value() {
+            self.confirmed_up_to = Some(self.confirmed_up_to.map_or(last, |c| c.max(last)));
+        }
+
+        confirmed
+            .into_values()
+            .flat_map(|(_, writes)| writes)
+            .collect()
+    }
+
+    fn rollback(&mut self, from: u64) -> Vec<T> {
+        self.blocks
+            .split_off(&from)
+            .into_values()
+            .flat_map(|(_, writes)| writes)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(n: u8) -> B256 {
+        B256::repeat_byte(n)
+    }
+
+    #[test]
+    fn same_height_with_another_hash_is_a_reorg() {
+        let mut tracker = ReorgTracker::new(3);
+        tracker.push(10, hash(1), "a");
+        tracker.push(11, hash(1), "b");
+
+        assert!(tracker.observe(10, hash(1)).is_empty());
+        assert_eq!(tracker.observe(10, hash(2)), vec!["a", "b"]);
+        // the new block is canonical now
+        assert!(tracker.observe(10, hash(2)).is_empty());
+    }
+
+    #[test]
+    fn removed_log_drops_its_block() {
+        let mut tracker = ReorgTracker::new(3);
+        tracker.push(10, hash(1), "a");
+        tracker.push(11, hash(1), "b");
+
+        // already replaced by the canonical block
+        assert!(tracker.remove(11, hash(2)).is_empty());
+        assert_eq!(tracker.remove(11, hash(1)), vec!["b"]);
+        assert!(tracker.remove(12, hash(1)).is_empty());
+
+        assert_eq!(tracker.confirmed(13), vec!["a"]);
+    }
+
+    #[test]
+    fn writes_are_confirmed_exactly_confirmations_deep() {
+        let mut tracker = ReorgTracker::new(2);
+        assert!(tracker.confirmed(1).is_empty());
+
+        tracker.push(10, hash(1), "reserve stats");
+        // the executor keeps reading the previous indices meanwhile
+        assert!(tracker.confirmed(10).is_empty());
+        assert!(tracker.confirmed(11).is_empty());
+        assert_eq!(tracker.confirmed(12), vec!["reserve stats"]);
+        assert!(tracker.confirmed(12).is_empty());
+
+        // too late to roll it back
+        assert!(tracker.remove(10, hash(1)).is_empty());
+    }
+
+    #[test]
+    fn rollback_keeps_the_blocks_below() {
+        let mut tracker = ReorgTracker::new(2);
+        tracker.push(10, hash(1), "a");
+        tracker.push(11, hash(1), "b");
+        tracker.push(12, hash(1), "c");
+        tracker.push(12, hash(1), "d");
+
+        assert_eq!(tracker.rollback(11), vec!["b", "c", "d"]);
+        tracker.push(11, hash(2), "e");
+
+        assert_eq!(tracker.confirmed(13), vec!["a", "e"]);
+        assert!(tracker.blocks.is_empty());
+    }
+}
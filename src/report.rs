@@ -0,0 +1,127 @@
+//! `report` subcommand, competitor analytics built on `aavev3_liquidations`: who liquidates the
+//! most, how fast after the triggering oracle update, at which gas price, on which reserves, and
+//! which of our opportunities they took.
+
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::{
+    actors::messages::database::{
+        get_liquidators_focus, get_missed_liquidations, get_top_liquidators, LiquidatorFocus,
+        LiquidatorStats, MissedLiquidation,
+    },
+    args::ReportArgs,
+    output::{self, cell, Table},
+};
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub since_secs: u64,
+    pub top_liquidators: Vec<LiquidatorStats>,
+    pub focus: Vec<LiquidatorFocus>,
+    pub missed: Vec<MissedLiquidation>,
+}
+
+pub async fn report(pool: &PgPool, target: &str, args: &ReportArgs) -> eyre::Result<()> {
+    let (network, protocol) = target.split_once('-').unwrap();
+
+    let top_liquidators =
+        get_top_liquidators(pool, network, protocol, args.since, args.limit).await?;
+    // only keep the focus of the liquidators we report on
+    let focus = get_liquidators_focus(pool, network, protocol, args.since, args.focus)
+        .await?
+        .into_iter()
+        .filter(|f| {
+            top_liquidators
+                .iter()
+                .any(|l| l.liquidator_address == f.liquidator_address)
+        })
+        .collect();
+    let missed = get_missed_liquidations(pool, network, protocol, args.since).await?;
+
+    let report = Report {
+        since_secs: args.since,
+        top_liquidators,
+        focus,
+        missed,
+    };
+    output::print(args.format, &report, &tables(&report))
+}
+
+fn tables(report: &Report) -> Vec<Table> {
+    let mut top = Table::new(
+        format!("top liquidators, last {}s", report.since_secs),
+        vec![
+            "liquidator",
+            "count",
+            "volume_usd",
+            "median_latency_blocks",
+            "median_gas_gwei",
+            "max_gas_gwei",
+            "ours",
+        ],
+    );
+    for l in &report.top_liquidators {
+        top.push(vec![
+            l.liquidator_address.clone(),
+            l.liquidations.to_string(),
+            format!("{:.2}", l.volume_usd),
+            cell(l.median_latency_blocks, 1),
+            cell(l.median_gas_price, 3),
+            cell(l.max_gas_price, 3),
+            l.ours.to_string(),
+        ]);
+    }
+
+    let mut focus = Table::new(
+        "reserves focus",
+        vec![
+            "liquidator",
+            "rank",
+            "collateral",
+            "debt",
+            "count",
+            "volume_usd",
+        ],
+    );
+    for f in &report.focus {
+        focus.push(vec![
+            f.liquidator_address.clone(),
+            f.rank.to_string(),
+            f.collateral_symbol.clone(),
+            f.debt_symbol.clone(),
+            f.liquidations.to_string(),
+            format!("{:.2}", f.volume_usd),
+        ]);
+    }
+
+    let mut missed = Table::new(
+        "lost opportunities",
+        vec![
+            "timestamp",
+            "user",
+            "collateral",
+            "debt",
+            "debt_usd",
+            "winner",
+            "latency_blocks",
+            "gas_gwei",
+            "tx_hash",
+        ],
+    );
+    for m in &report.missed {
+        missed.push(vec![
+            m.timestamp.clone(),
+            m.user_address.clone(),
+            m.collateral_symbol.clone(),
+            m.debt_symbol.clone(),
+            cell(m.debt_to_cover_usd, 2),
+            m.liquidator_address.clone().unwrap_or("-".to_string()),
+            m.latency_blocks.map_or("-".to_string(), |b| b.to_string()),
+            cell(m.gas_price, 3),
+            m.tx_hash.clone().unwrap_or("-".to_string()),
+        ]);
+    }
+
+    vec![top, focus, missed]
+}
@@ -0,0 +1,251 @@
+//! Why a liquidation reverted, and what to do about it.
+//!
+//! Aave reverts with `Errors.sol`'s numeric codes up to v3.2 and with custom errors since v3.3,
+//! uniswap's `SwapRouter` and `Liquidatoor` with plain `require` messages.
+//!
+//! https://github.com/aave-dao/aave-v3-origin/blob/main/src/contracts/protocol/libraries/helpers/Errors.sol
+
+use std::fmt;
+
+use alloy::{
+    rpc::json_rpc::ErrorPayload,
+    sol_types::{decode_revert_reason, SolInterface},
+    transports::TransportError,
+};
+
+use crate::contracts::aave_v3::PoolErrors::PoolErrorsErrors;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LiquidationError {
+    // aave
+    HealthFactorNotBelowThreshold,
+    CollateralCannotBeLiquidated,
+    SpecifiedCurrencyNotBorrowedByUser,
+    ReserveInactive,
+    ReservePaused,
+    // L2s' grace period after a sequencer outage
+    PriceOracleSentinelCheckFailed,
+    FlashloanDisabled,
+    // uniswap, `TransferHelper.safeTransferFrom` of the collateral failed
+    SwapTransferFailed,
+    // uniswap, `amountOutMinimum` not met
+    TooLittleReceived,
+    // `Liquidatoor`
+    InsufficientProceeds,
+    UnauthorizedFlashloan,
+    Other(String),
+}
+
+/// What the executor does with a user whose liquidation reverted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryPolicy {
+    // wait for the user's next update
+    Drop,
+    // prices moved against us, the same liquidation may go through on the next block
+    RetryNextBlock,
+    // bound to revert again, stop liquidating this collateral/debt pair
+    Blacklist,
+}
+
+impl LiquidationError {
+    /// Decodes the revert behind `error`, `None` when it didn't come from the node.
+    pub fn from_report(error: &eyre::Report) -> Option<Self> {
+        let payload = error_payload(error)?;
+
+        if let Some(data) = payload.as_revert_data() {
+            if let Ok(error) = PoolErrorsErrors::abi_decode(&data, true) {
+                return Some(Self::from(error));
+            }
+            if let Some(reason) = decode_revert_reason(&data) {
+                return Some(Self::from_reason(&reason));
+            }
+        }
+        Some(Self::from_reason(&payload.message))
+    }
+
+    /// Parses a `revert("..")` reason, nodes may prefix it with `execution reverted: `.
+    pub fn from_reason(reason: &str) -> Self {
+        let reason = reason.trim();
+        let reason = reason
+            .strip_prefix("execution reverted:")
+            .or_else(|| reason.strip_prefix("revert:"))
+            .map(str::trim)
+            .unwrap_or(reason);
+
+        match reason {
+            "45" => Self::HealthFactorNotBelowThreshold,
+            "46" => Self::CollateralCannotBeLiquidated,
+            "47" => Self::SpecifiedCurrencyNotBorrowedByUser,
+            "27" => Self::ReserveInactive,
+            "29" => Self::ReservePaused,
+            "59" => Self::PriceOracleSentinelCheckFailed,
+            "91" => Self::FlashloanDisabled,
+            "STF" => Self::SwapTransferFailed,
+            "Too little received" => Self::TooLittleReceived,
+            "insufficient proceeds" => Self::InsufficientProceeds,
+            "unauthorized flashloan" => Self::UnauthorizedFlashloan,
+            other => Self::Other(other.to_string()),
+        }
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        match self {
+            // liquidated by someone else, or repaid/topped up in the meantime
+            Self::HealthFactorNotBelowThreshold | Self::SpecifiedCurrencyNotBorrowedByUser => {
+                RetryPolicy::Drop
+            }
+            // flags are followed through `PoolConfigurator`, the next update sees them
+            Self::ReserveInactive | Self::ReservePaused => RetryPolicy::Drop,
+            Self::PriceOracleSentinelCheckFailed
+            | Self::TooLittleReceived
+            | Self::InsufficientProceeds => RetryPolicy::RetryNextBlock,
+            Self::CollateralCannotBeLiquidated
+            | Self::FlashloanDisabled
+            | Self::SwapTransferFailed => RetryPolicy::Blacklist,
+            // our own misconfiguration, retrying won't help
+            Self::UnauthorizedFlashloan | Self::Other(_) => RetryPolicy::Drop,
+        }
+    }
+}
+
+impl From<PoolErrorsErrors> for LiquidationError {
+    fn from(error: PoolErrorsErrors) -> Self {
+        match error {
+            PoolErrorsErrors::HealthFactorNotBelowThreshold(_) => {
+                Self::HealthFactorNotBelowThreshold
+            }
+            PoolErrorsErrors::CollateralCannotBeLiquidated(_) => Self::CollateralCannotBeLiquidated,
+            PoolErrorsErrors::SpecifiedCurrencyNotBorrowedByUser(_) => {
+                Self::SpecifiedCurrencyNotBorrowedByUser
+            }
+            PoolErrorsErrors::ReserveInactive(_) => Self::ReserveInactive,
+            PoolErrorsErrors::ReservePaused(_) => Self::ReservePaused,
+            PoolErrorsErrors::PriceOracleSentinelCheckFailed(_) => {
+                Self::PriceOracleSentinelCheckFailed
+            }
+            PoolErrorsErrors::FlashloanDisabled(_) => Self::FlashloanDisabled,
+        }
+    }
+}
+
+impl fmt::Display for LiquidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HealthFactorNotBelowThreshold => f.write_str("health_factor_not_below_threshold"),
+            Self::CollateralCannotBeLiquidated => f.write_str("collateral_cannot_be_liquidated"),
+            Self::SpecifiedCurrencyNotBorrowedByUser => {
+                f.write_str("specified_currency_not_borrowed_by_user")
+            }
+            Self::ReserveInactive => f.write_str("reserve_inactive"),
+            Self::ReservePaused => f.write_str("reserve_paused"),
+            Self::PriceOracleSentinelCheckFailed => {
+                f.write_str("price_oracle_sentinel_check_failed")
+            }
+            Self::FlashloanDisabled => f.write_str("flashloan_disabled"),
+            Self::SwapTransferFailed => f.write_str("swap_transfer_failed"),
+            Self::TooLittleReceived => f.write_str("too_little_received"),
+            Self::InsufficientProceeds => f.write_str("insufficient_proceeds"),
+            Self::UnauthorizedFlashloan => f.write_str("unauthorized_flashloan"),
+            Self::Other(reason) => f.write_str(reason),
+        }
+    }
+}
+
+/// Reason of a revert reported by the node, decoded when it's a `revert("..")` or a panic.
+pub fn revert_reason(error: &eyre::Report) -> Option<String> {
+    let payload = error_payload(error)?;
+
+    Some(
+        payload
+            .as_revert_data()
+            .and_then(|data| decode_revert_reason(&data))
+            .unwrap_or_else(|| payload.message.to_string()),
+    )
+}
+
+fn error_payload(error: &eyre::Report) -> Option<&ErrorPayload> {
+    error
+        .chain()
+        .find_map(|e| e.downcast_ref::<TransportError>())?
+        .as_error_resp()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::{
+        rpc::json_rpc::ErrorPayload,
+        sol_types::{Revert, SolError},
+        transports::RpcError,
+    };
+    use serde_json::value::RawValue;
+
+    use super::*;
+    use crate::contracts::aave_v3::PoolErrors;
+
+    fn reverted_with(data: &[u8]) -> eyre::Report {
+        let data = format!("\"0x{}\"", alloy::primitives::hex::encode(data));
+        let error: TransportError = RpcError::ErrorResp(ErrorPayload {
+            code: 3,
+            message: "execution reverted".into(),
+            data: Some(RawValue::from_string(data).unwrap()),
+        });
+        eyre::Report::new(error)
+    }
+
+    #[test]
+    fn aave_codes_and_custom_errors_decode_alike() {
+        let coded = reverted_with(&Revert::from("45").abi_encode());
+        let custom = reverted_with(&PoolErrors::HealthFactorNotBelowThreshold {}.abi_encode());
+
+        assert_eq!(
+            LiquidationError::from_report(&coded),
+            Some(LiquidationError::HealthFactorNotBelowThreshold)
+        );
+        assert_eq!(
+            LiquidationError::from_report(&custom),
+            Some(LiquidationError::HealthFactorNotBelowThreshold)
+        );
+        assert_eq!(revert_reason(&coded).as_deref(), Some("revert: 45"));
+        assert_eq!(LiquidationError::from_report(&eyre::eyre!("timeout")), None);
+    }
+
+    #[test]
+    fn swap_and_bot_reverts_are_recognized() {
+        assert_eq!(
+            LiquidationError::from_reason("execution reverted: Too little received"),
+            LiquidationError::TooLittleReceived
+        );
+        assert_eq!(
+            LiquidationError::from_reason("STF"),
+            LiquidationError::SwapTransferFailed
+        );
+        assert_eq!(
+            LiquidationError::from_reason("insufficient proceeds"),
+            LiquidationError::InsufficientProceeds
+        );
+        assert_eq!(
+            LiquidationError::from_reason("ERC20: transfer amount exceeds balance"),
+            LiquidationError::Other("ERC20: transfer amount exceeds balance".to_string())
+        );
+    }
+
+    #[test]
+    fn each_error_maps_to_a_retry_policy() {
+        assert_eq!(
+            LiquidationError::HealthFactorNotBelowThreshold.retry_policy(),
+            RetryPolicy::Drop
+        );
+        assert_eq!(
+            LiquidationError::TooLittleReceived.retry_policy(),
+            RetryPolicy::RetryNextBlock
+        );
+        assert_eq!(
+            LiquidationError::CollateralCannotBeLiquidated.retry_policy(),
+            RetryPolicy::Blacklist
+        );
+        assert_eq!(
+            LiquidationError::Other("?".to_string()).retry_policy(),
+            RetryPolicy::Drop
+        );
+    }
+}
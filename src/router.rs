@@ -0,0 +1,311 @@
+//! Routing of the seized collateral back to the debt asset. Every `Dex` quotes its best route for
+//! the amount actually swapped, the `Router` keeps whichever returns the most.
+//!
+//! `Liquidatoor` only swaps through uniswap_v3's `SwapRouter` for now, other dexes need their own
+//! entrypoint before being plugged in.
+
+use std::{fmt::Debug, sync::Arc};
+
+use alloy::{
+    primitives::{address, Address, Bytes, Uint, U256},
+    providers::Provider,
+};
+use async_trait::async_trait;
+use tracing::{debug, info};
+
+use crate::contracts;
+
+// every uniswap_v3 fee tier, in hundredths of a bip
+const UNISWAP_V3_FEES: [u32; 4] = [100, 500, 3000, 10000];
+
+/// Tokens worth hopping through when there's no (good) direct pool.
+pub fn intermediate_tokens(network: &str) -> Vec<Address> {
+    match network {
+        "ethereum" => vec![
+            address!("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"), // WETH
+            address!("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"), // USDC
+        ],
+        _ => vec![],
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    pub dex: &'static str,
+    // token_in, hops.., token_out
+    pub tokens: Vec<Address>,
+    // encoded the way the dex expects it, passed as is to `Liquidatoor`
+    pub path: Bytes,
+    pub amount_out: U256,
+}
+
+#[async_trait]
+pub trait Dex: Debug + Send + Sync {
+    /// Best route swapping `amount_in` of `token_in` for `token_out`, `None` if there's no pool to
+    /// go through.
+    async fn best_route(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> eyre::Result<Option<Route>>;
+}
+
+#[derive(Debug, Clone)]
+pub struct Router {
+    dexes: Vec<Arc<dyn Dex>>,
+}
+
+impl Router {
+    pub fn new(dexes: Vec<Arc<dyn Dex>>) -> Self {
+        Router { dexes }
+    }
+
+    /// Route maximizing what's received for `amount_in`, swapping an asset for itself is a no-op.
+    pub async fn best_route(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> eyre::Result<Route> {
+        if token_in == token_out {
+            return Ok(Route {
+                dex: "none",
+                tokens: vec![token_in],
+                path: Bytes::new(),
+                amount_out: amount_in,
+            });
+        }
+
+        let mut routes = Vec::new();
+        for dex in &self.dexes {
+            if let Some(route) = dex.best_route(token_in, token_out, amount_in).await? {
+                routes.push(route);
+            }
+        }
+
+        let best = routes.into_iter().max_by_key(|route| route.amount_out);
+        info!(?token_in, ?token_out, %amount_in, route = ?best.as_ref().map(|r| (r.dex, &r.tokens, r.amount_out)), "best swap route");
+        best.ok_or(eyre::eyre!("No route from {} to {}", token_in, token_out))
+    }
+}
+
+/// `token | fee | token | fee | token ...` packed, as `SwapRouter.exactInput` expects it.
+pub fn encode_uniswap_v3_path(tokens: &[Address], fees: &[u32]) -> Bytes {
+    debug_assert_eq!(tokens.len(), fees.len() + 1);
+
+    let mut path = Vec::with_capacity(tokens.len() * 20 + fees.len() * 3);
+    for (i, token) in tokens.iter().enumerate() {
+        path.extend_from_slice(token.as_slice());
+        if let Some(fee) = fees.get(i) {
+            path.extend_from_slice(&fee.to_be_bytes()[1..]);
+        }
+    }
+    path.into()
+}
+
+/// Fee tiers of a path encoded by `encode_uniswap_v3_path`, one per hop.
+pub fn uniswap_v3_fee_tiers(path: &[u8]) -> Vec<u32> {
+    path.chunks(23)
+        .filter(|chunk| chunk.len() == 23)
+        .map(|chunk| u32::from_be_bytes([0, chunk[20], chunk[21], chunk[22]]))
+        .collect()
+}
+
+#[derive(Clone)]
+pub struct UniswapV3<P: Provider + Unpin + Clone + 'static> {
+    factory_contract: contracts::uniswap_v3::FactoryContract::FactoryContractInstance<(), P>,
+    quoter_contract: contracts::uniswap_v3::QuoterContract::QuoterContractInstance<(), P>,
+    intermediates: Vec<Address>,
+}
+
+impl<P: Provider + Unpin + Clone + 'static> UniswapV3<P> {
+    pub fn new(
+        provider: P,
+        factory_addr: Address,
+        quoter_addr: Address,
+        intermediates: Vec<Address>,
+    ) -> Self {
+        UniswapV3 {
+            factory_contract: contracts::uniswap_v3::FactoryContract::new(
+                factory_addr,
+                provider.clone(),
+            ),
+            quoter_contract: contracts::uniswap_v3::QuoterContract::new(quoter_addr, provider),
+            intermediates,
+        }
+    }
+
+    /// Fee tier of the pool returning the most for `amount_in`, with what it returns.
+    async fn best_hop(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> eyre::Result<Option<(u32, U256)>> {
+        let mut best: Option<(u32, U256)> = None;
+
+        for fee in UNISWAP_V3_FEES {
+            let pool = self
+                .factory_contract
+                .getPool(token_in, token_out, Uint::from(fee))
+                .call()
+                .await?
+                ._0;
+            if pool == Address::ZERO {
+                continue;
+            }
+
+            let path = encode_uniswap_v3_path(&[token_in, token_out], &[fee]);
+            match self
+                .quoter_contract
+                .quoteExactInput(path, amount_in)
+                .call()
+                .await
+            {
+                Ok(quote) if best.is_none_or(|(_, out)| quote.amountOut > out) => {
+                    best = Some((fee, quote.amountOut))
+                }
+                Ok(_) => {}
+                // i.e not enough liquidity in range
+                Err(e) => debug!(?pool, fee, error = ?e, "quote failed"),
+            }
+        }
+
+        Ok(best)
+    }
+}
+
+// providers aren't `Debug`
+impl<P: Provider + Unpin + Clone + 'static> Debug for UniswapV3<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UniswapV3")
+            .field("factory", self.factory_contract.address())
+            .field("quoter", self.quoter_contract.address())
+            .field("intermediates", &self.intermediates)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<P: Provider + Unpin + Clone + 'static> Dex for UniswapV3<P> {
+    // a hop's output only grows with its input, so picking the best pool of each hop in turn
+    // gives the best path through a given intermediate
+    async fn best_route(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> eyre::Result<Option<Route>> {
+        let mut candidates = Vec::new();
+
+        if let Some((fee, amount_out)) = self.best_hop(token_in, token_out, amount_in).await? {
+            candidates.push((vec![token_in, token_out], vec![fee], amount_out));
+        }
+        for &hop in &self.intermediates {
+            if hop == token_in || hop == token_out {
+                continue;
+            }
+            let Some((fee_in, amount_hop)) = self.best_hop(token_in, hop, amount_in).await? else {
+                continue;
+            };
+            if let Some((fee_out, amount_out)) = self.best_hop(hop, token_out, amount_hop).await? {
+                candidates.push((
+                    vec![token_in, hop, token_out],
+                    vec![fee_in, fee_out],
+                    amount_out,
+                ));
+            }
+        }
+
+        Ok(candidates
+            .into_iter()
+            .max_by_key(|(_, _, amount_out)| *amount_out)
+            .map(|(tokens, fees, amount_out)| Route {
+                dex: "uniswap_v3",
+                path: encode_uniswap_v3_path(&tokens, &fees),
+                tokens,
+                amount_out,
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::hex;
+
+    use super::*;
+
+    const WETH: Address = address!("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+    const USDC: Address = address!("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+    const WBTC: Address = address!("0x2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599");
+
+    /// Quotes a fixed amount for any pair.
+    #[derive(Debug)]
+    struct FixedDex(&'static str, Option<u64>);
+
+    #[async_trait]
+    impl Dex for FixedDex {
+        async fn best_route(
+            &self,
+            token_in: Address,
+            token_out: Address,
+            _amount_in: U256,
+        ) -> eyre::Result<Option<Route>> {
+            Ok(self.1.map(|amount_out| Route {
+                dex: self.0,
+                tokens: vec![token_in, token_out],
+                path: Bytes::new(),
+                amount_out: U256::from(amount_out),
+            }))
+        }
+    }
+
+    #[test]
+    fn paths_are_packed_with_3_bytes_fees() {
+        let path = encode_uniswap_v3_path(&[WBTC, WETH, USDC], &[3000, 500]);
+
+        assert_eq!(path.len(), 20 * 3 + 3 * 2);
+        assert_eq!(uniswap_v3_fee_tiers(&path), vec![3000, 500]);
+        assert!(uniswap_v3_fee_tiers(&[]).is_empty());
+        assert_eq!(
+            hex::encode(&path),
+            "2260fac5e5542a773aa44fbcfedf7c193bc2c599000bb8\
+             c02aaa39b223fe8d0a0e5c4f27ead9083c756cc20001f4\
+             a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"
+        );
+    }
+
+    #[tokio::test]
+    async fn router_keeps_the_dex_returning_the_most() {
+        let router = Router::new(vec![
+            Arc::new(FixedDex("low", Some(90))),
+            Arc::new(FixedDex("without_pool", None)),
+            Arc::new(FixedDex("high", Some(110))),
+        ]);
+
+        let route = router
+            .best_route(WETH, USDC, U256::from(100))
+            .await
+            .unwrap();
+        assert_eq!(route.dex, "high");
+        assert_eq!(route.amount_out, U256::from(110));
+
+        let router = Router::new(vec![Arc::new(FixedDex("without_pool", None))]);
+        assert!(router
+            .best_route(WETH, USDC, U256::from(100))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn same_asset_needs_no_swap() {
+        let route = Router::new(vec![])
+            .best_route(WETH, WETH, U256::from(100))
+            .await
+            .unwrap();
+
+        assert!(route.path.is_empty());
+        assert_eq!(route.amount_out, U256::from(100));
+    }
+}
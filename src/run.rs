@@ -1,4 +1,7 @@
-use std::sync::Arc;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
 
 use actix::prelude::*;
 use alloy::{
@@ -7,11 +10,22 @@ use alloy::{
     signers::local::PrivateKeySigner,
 };
 use sqlx::postgres::PgPoolOptions;
-use tokio::signal::unix::{signal, SignalKind};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    time::Duration,
+};
+use tracing::error;
 
 use crate::{
-    actors::{Database, Executor, Fanatic, Follower},
-    configs::{Config, DatabaseConfig, ExecutorConfig, FanaticConfig, FollowerConfig},
+    actors::{
+        messages::database::GetProtocolContracts, monitor::Feeds, Database, Executor, Fanatic,
+        Follower, Monitor, Watchdog,
+    },
+    api::{self, ApiState},
+    client::{AaveV3Client, LendingProtocolClient},
+    configs::{
+        Config, DatabaseConfig, ExecutorConfig, FanaticConfig, FollowerConfig, MonitorConfig,
+    },
 };
 
 #[derive(Message)]
@@ -43,48 +57,98 @@ pub async fn run(config: Config) {
         )
     };
 
+    /* Actors run supervised, restarted when they stop and given up on by the watchdog once they
+    keep failing */
+    let _ = Watchdog::from_registry();
+
     /* Spin up the database actor */
-    let db_addr = Database::new(DatabaseConfig {
+    let database = Database::new(DatabaseConfig {
         pool: db_pool.clone(),
+        history: Some(config.history.clone()),
     })
-    .await
-    .start();
+    .await;
+    let db_addr = Supervisor::start(|_| database);
+
+    /* Chain access shared by the actors */
+    let client: Arc<dyn LendingProtocolClient> = Arc::new(
+        AaveV3Client::new(
+            provider_with_wallet.clone(),
+            &db_addr
+                .send(GetProtocolContracts(config.target.clone()))
+                .await
+                .unwrap()
+                .expect("Unable to fetch protocol contracts"),
+            config.bot_addr,
+        )
+        .expect("Unable to initialise lending protocol client"),
+    );
 
     /* Spin up the follower actor */
-    let follower_addr = Follower::new(FollowerConfig {
+    let follower = Follower::new(FollowerConfig {
         provider: provider_with_wallet.clone(),
         db_addr: db_addr.clone(),
+        client: client.clone(),
         target: config.target.clone(),
+        confirmations: config.confirmations,
+        replay: false,
     })
     .await
-    .expect("Unable to initialise follower actor")
-    .start();
+    .expect("Unable to initialise follower actor");
+    let follower_addr = Supervisor::start(|_| follower);
 
     /* Spin up the fanatic actor */
-    let fanatic_addr = Fanatic::new(FanaticConfig {
+    let fanatic = Fanatic::new(FanaticConfig {
         provider: provider_with_wallet.clone(),
         db_addr: db_addr.clone(),
+        client: client.clone(),
         follower_addr: follower_addr.clone(),
+        bot_addr: config.bot_addr,
         target: config.target.clone(),
+        snapshots: true,
     })
     .await
     .expect("Unable to initialise Fanatic actor")
     .init()
     .await
-    .expect("Unable to initialise Fanatic actor")
-    .start();
+    .expect("Unable to initialise Fanatic actor");
+    let fanatic_addr = Supervisor::start(|_| fanatic);
 
     /* Spin up the alpha executor actor */
-    let _ = Executor::new(ExecutorConfig {
+    let executor = Executor::new(ExecutorConfig {
         provider: provider_with_wallet.clone(),
         db_addr: db_addr.clone(),
+        client: client.clone(),
         fanatic_addr: fanatic_addr.clone(),
         bot_addr: config.bot_addr,
-        target: config.target,
+        target: config.target.clone(),
+        slippage_bps: config.execution.slippage_bps,
+        min_profit_usd: config.execution.min_profit_usd,
+        hold_atokens: config.execution.hold_atokens,
+        simulations: None,
     })
     .await
-    .expect("Unable to initialise Executor actor")
-    .start();
+    .expect("Unable to initialise Executor actor");
+    let _ = Supervisor::start(|_| executor);
+
+    /* Spin up the oracle monitor actor */
+    let feeds: Feeds = Arc::new(RwLock::new(BTreeMap::new()));
+    let monitor = Monitor::new(MonitorConfig {
+        provider: provider_with_wallet.clone(),
+        db_addr: db_addr.clone(),
+        target: config.target,
+        heartbeat_secs: config.monitor.oracle_heartbeat_secs,
+        deviation_bps: config.monitor.oracle_deviation_bps,
+        check_interval: Duration::from_secs(config.monitor.oracle_check_secs),
+        feeds: feeds.clone(),
+    });
+    let _ = Supervisor::start(|_| monitor);
+
+    /* Serve the HTTP API */
+    actix::spawn(async move {
+        if let Err(e) = api::serve(config.http_addr, ApiState { feeds }).await {
+            error!("HTTP API stopped: {}", e);
+        }
+    });
 
     tokio::select! {
         _ = interrupt.recv() => {
@@ -0,0 +1,426 @@
+//! `sim` subcommand, moves prices on an anvil node for local liquidation scenarios.
+//!
+//! The aggregator a reserve's price is followed from gets its code replaced with a mock, carrying
+//! over its latest round. Answers are then set by hand, the mock emitting `AnswerUpdated` as
+//! chainlink's aggregators do, so the follower picks them up like any other price update.
+//! Whatever else is priced through that aggregator moves along, e.g ETH/USD moves WETH and the LSTs.
+
+use std::{collections::HashMap, fs};
+
+use alloy::{
+    primitives::{bytes, Address, Bytes, TxHash, I256, U256},
+    providers::{ext::AnvilApi, Provider, ProviderBuilder},
+    rpc::types::TransactionReceipt,
+    sol,
+};
+use serde::Serialize;
+use sqlx::PgPool;
+use tracing::info;
+
+use crate::{
+    actors::messages::database::get_reserve_aggregator,
+    args::{SimAction, SimArgs},
+    contracts,
+    output::{self, Table},
+};
+
+sol! {
+    #[sol(rpc)]
+    interface IMockAggregator {
+        function latestAnswer() external view returns (int256);
+        function updateAnswer(int256 answer) external;
+    }
+}
+
+/// Runtime bytecode of the mock, assembled by hand. It's the equivalent of
+///
+/// ```solidity
+/// contract MockAggregator {
+///     int256 answer;
+///     uint256 round;
+///     uint256 updatedAt;
+///     uint8 decimals;
+///
+///     event AnswerUpdated(int256 indexed current, uint256 indexed roundId, uint256 updatedAt);
+///
+///     function latestAnswer() external view returns (int256) { return answer; }
+///     function latestRoundData() external view returns (uint80, int256, uint256, uint256, uint80) {
+///         return (uint80(round), answer, updatedAt, updatedAt, uint80(round));
+///     }
+///     function decimals() external view returns (uint8) { return decimals; }
+///     // resolves to itself in `Fanatic::aggregator_addr`
+///     function aggregator() external view returns (address) { return address(this); }
+///
+///     function updateAnswer(int256 _answer) external {
+///         answer = _answer;
+///         round += 1;
+///         updatedAt = block.timestamp;
+///         emit AnswerUpdated(_answer, round, block.timestamp);
+///     }
+/// }
+/// ```
+///
+/// with any other calldata reverting.
+pub const MOCK_AGGREGATOR_RUNTIME: Bytes = bytes!(
+    "60003560e01c806350d25bcd14610041578063feaf968c1461004d578063313ce5671461006f578063245a7bfc"
+    "1461007b578063a87a20ce1461008557600080fd5b60005460005260206000f35b600154600052600054602052"
+    "6002548060405260605260015460805260a06000f35b60035460005260206000f35b3060005260206000f35b60"
+    "043580600055600154600101806001554280600255600052907f0559884fd3a460db3073b7fc896cc77986f16e"
+    "378210ded43186175bf646fc5f60206000a300"
+);
+
+// the mock's storage layout
+const ANSWER_SLOT: u64 = 0;
+const ROUND_SLOT: u64 = 1;
+const UPDATED_AT_SLOT: u64 = 2;
+const DECIMALS_SLOT: u64 = 3;
+
+pub struct MockAggregator<P: Provider + Clone> {
+    pub address: Address,
+    pub decimals: u8,
+    // account the updates are sent from
+    sender: Address,
+    contract: IMockAggregator::IMockAggregatorInstance<(), P>,
+}
+
+impl<P: Provider + Clone> MockAggregator<P> {
+    /// Replaces the aggregator at `address` with the mock, carrying over its latest round and
+    /// decimals, replacing a mock is a no-op. `sender` has to be the provider's signer or an
+    /// account unlocked on the node.
+    pub async fn replace(provider: P, address: Address, sender: Address) -> eyre::Result<Self> {
+        let aggregator = contracts::chainlink::OffchainAggregatorContract::new(address, &provider);
+        let decimals = aggregator.decimals().call().await?._0;
+
+        if provider.get_code_at(address).await? != MOCK_AGGREGATOR_RUNTIME {
+            let round = aggregator.latestRoundData().call().await?;
+
+            provider
+                .anvil_set_code(address, MOCK_AGGREGATOR_RUNTIME)
+                .await?;
+            for (slot, value) in [
+                (ANSWER_SLOT, round.answer.into_raw()),
+                (ROUND_SLOT, U256::from(round.roundId)),
+                (UPDATED_AT_SLOT, round.updatedAt),
+                (DECIMALS_SLOT, U256::from(decimals)),
+            ] {
+                provider
+                    .anvil_set_storage_at(address, U256::from(slot), value.into())
+                    .await?;
+            }
+            info!(?address, answer = ?round.answer, "replaced aggregator with the mock");
+        }
+
+        Ok(MockAggregator {
+            address,
+            decimals,
+            sender,
+            contract: IMockAggregator::new(address, provider),
+        })
+    }
+
+    pub async fn answer(&self) -> eyre::Result<I256> {
+        Ok(self.contract.latestAnswer().call().await?._0)
+    }
+
+    /// Sets the answer, emitting `AnswerUpdated`.
+    pub async fn set_answer(&self, answer: I256) -> eyre::Result<TransactionReceipt> {
+        Ok(self
+            .contract
+            .updateAnswer(answer)
+            .from(self.sender)
+            .send()
+            .await?
+            .get_receipt()
+            .await?)
+    }
+
+    pub async fn price(&self) -> eyre::Result<f64> {
+        Ok(self.answer().await?.to_string().parse::<f64>()? / 10f64.powi(self.decimals.into()))
+    }
+
+    pub async fn set_price(&self, price: f64) -> eyre::Result<TransactionReceipt> {
+        let answer = (price * 10f64.powi(self.decimals.into())).round() as i128;
+        self.set_answer(I256::try_from(answer)?).await
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PriceUpdate {
+    pub reserve: Address,
+    pub symbol: String,
+    pub aggregator: Address,
+    pub price: f64,
+    // none when only mocked
+    pub block_number: Option<u64>,
+    pub tx_hash: Option<TxHash>,
+}
+
+/// A row of a price path, `block` being counted from the start of the path.
+#[derive(Debug, PartialEq)]
+pub struct PathStep {
+    pub block: u64,
+    pub reserve: String,
+    pub price: f64,
+}
+
+struct Mocked<P: Provider + Clone> {
+    reserve: Address,
+    symbol: String,
+    aggregator: MockAggregator<P>,
+}
+
+impl<P: Provider + Clone> Mocked<P> {
+    async fn update(&self, price: f64) -> eyre::Result<PriceUpdate> {
+        let receipt = self.aggregator.set_price(price).await?;
+        info!(symbol = self.symbol, price, block = ?receipt.block_number, "price updated");
+
+        Ok(PriceUpdate {
+            reserve: self.reserve,
+            symbol: self.symbol.clone(),
+            aggregator: self.aggregator.address,
+            price,
+            block_number: receipt.block_number,
+            tx_hash: Some(receipt.transaction_hash),
+        })
+    }
+}
+
+struct Sim<'a, P: Provider + Clone> {
+    pool: &'a PgPool,
+    target: &'a str,
+    provider: P,
+    sender: Address,
+    // by reserve, as given on the command line
+    mocks: HashMap<String, Mocked<P>>,
+}
+
+impl<P: Provider + Clone> Sim<'_, P> {
+    async fn mock(&mut self, reserve: &str) -> eyre::Result<&Mocked<P>> {
+        let key = reserve.to_lowercase();
+        if !self.mocks.contains_key(&key) {
+            let (network, protocol) = self.target.split_once('-').unwrap();
+            let found = get_reserve_aggregator(self.pool, network, protocol, reserve)
+                .await?
+                .ok_or(eyre::eyre!("unknown reserve {}", reserve))?;
+            let aggregator_addr = found.aggregator_addr.ok_or(eyre::eyre!(
+                "no aggregator is followed for {}",
+                found.symbol
+            ))?;
+
+            let aggregator = MockAggregator::replace(
+                self.provider.clone(),
+                aggregator_addr.trim().parse()?,
+                self.sender,
+            )
+            .await?;
+            self.mocks.insert(
+                key.clone(),
+                Mocked {
+                    reserve: found.reserve.trim().parse()?,
+                    symbol: found.symbol,
+                    aggregator,
+                },
+            );
+        }
+
+        Ok(&self.mocks[&key])
+    }
+
+    /// Walks the price to `to` linearly, one update per block.
+    async fn walk(
+        &mut self,
+        reserve: &str,
+        to: f64,
+        blocks: u64,
+    ) -> eyre::Result<Vec<PriceUpdate>> {
+        let mocked = self.mock(reserve).await?;
+        let from = mocked.aggregator.price().await?;
+
+        let mut updates = Vec::new();
+        for step in 1..=blocks {
+            let price = from + (to - from) * step as f64 / blocks as f64;
+            updates.push(mocked.update(price).await?);
+        }
+        Ok(updates)
+    }
+
+    /// Plays the steps in order, none earlier than its block. Blocks in between are mined, while
+    /// automine puts every update in a block of its own.
+    async fn play(&mut self, steps: &[PathStep]) -> eyre::Result<Vec<PriceUpdate>> {
+        let start = self.provider.get_block_number().await?;
+
+        let mut updates = Vec::new();
+        for step in steps {
+            let head = self.provider.get_block_number().await?;
+            if head < start + step.block {
+                self.provider
+                    .anvil_mine(Some(start + step.block - head), None)
+                    .await?;
+            }
+
+            let mocked = self.mock(&step.reserve).await?;
+            updates.push(mocked.update(step.price).await?);
+        }
+        Ok(updates)
+    }
+}
+
+pub async fn sim(pool: &PgPool, rpc_url: &str, target: &str, args: &SimArgs) -> eyre::Result<()> {
+    let provider = ProviderBuilder::new().on_builtin(rpc_url).await?;
+    let sender = *provider.get_accounts().await?.first().ok_or(eyre::eyre!(
+        "the node has no unlocked account, is it anvil?"
+    ))?;
+    let mut sim = Sim {
+        pool,
+        target,
+        provider,
+        sender,
+        mocks: HashMap::new(),
+    };
+
+    let updates = match &args.action {
+        SimAction::Mock { reserves } => {
+            let mut updates = Vec::new();
+            for reserve in reserves {
+                let mocked = sim.mock(reserve).await?;
+                updates.push(PriceUpdate {
+                    reserve: mocked.reserve,
+                    symbol: mocked.symbol.clone(),
+                    aggregator: mocked.aggregator.address,
+                    price: mocked.aggregator.price().await?,
+                    block_number: None,
+                    tx_hash: None,
+                });
+            }
+            updates
+        }
+        SimAction::SetPrice {
+            reserve,
+            price,
+            change,
+            blocks,
+        } => {
+            let to = match (price, change) {
+                (Some(price), _) => *price,
+                (None, Some(change)) => {
+                    sim.mock(reserve).await?.aggregator.price().await? * (1.0 + change / 100.0)
+                }
+                (None, None) => unreachable!("enforced by clap"),
+            };
+            sim.walk(reserve, to, *blocks).await?
+        }
+        SimAction::Path { csv } => sim.play(&parse_path(&fs::read_to_string(csv)?)?).await?,
+    };
+
+    output::print(args.format, &updates, &[table(&updates)])
+}
+
+/// Parses `block,reserve,price` rows, with an optional header.
+pub fn parse_path(csv: &str) -> eyre::Result<Vec<PathStep>> {
+    let mut steps = Vec::new();
+    let mut first = true;
+    for (i, line) in csv.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let is_header = std::mem::replace(&mut first, false);
+
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let [block, reserve, price] = fields[..] else {
+            return Err(eyre::eyre!(
+                "line {}: expected `block,reserve,price`, got `{}`",
+                i + 1,
+                line
+            ));
+        };
+        let Ok(block) = block.parse::<u64>() else {
+            if is_header {
+                continue;
+            }
+            return Err(eyre::eyre!("line {}: invalid block `{}`", i + 1, block));
+        };
+
+        steps.push(PathStep {
+            block,
+            reserve: reserve.to_string(),
+            price: price
+                .parse()
+                .map_err(|_| eyre::eyre!("line {}: invalid price `{}`", i + 1, price))?,
+        });
+    }
+
+    if steps.windows(2).any(|w| w[0].block > w[1].block) {
+        return Err(eyre::eyre!("the path's blocks must be in ascending order"));
+    }
+    Ok(steps)
+}
+
+fn table(updates: &[PriceUpdate]) -> Table {
+    let mut table = Table::new(
+        "price updates",
+        vec![
+            "block",
+            "reserve",
+            "symbol",
+            "aggregator",
+            "price",
+            "tx_hash",
+        ],
+    );
+    for u in updates {
+        table.push(vec![
+            u.block_number.map_or("-".to_string(), |b| b.to_string()),
+            u.reserve.to_string(),
+            u.symbol.clone(),
+            u.aggregator.to_string(),
+            format!("{:.6}", u.price),
+            u.tx_hash.map_or("-".to_string(), |h| h.to_string()),
+        ]);
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_price_paths() {
+        let csv = "block,reserve,price\n\
+                   # ETH dropping 20% over 10 blocks\n\
+                   0, WETH, 2500\n\
+                   10, WETH, 2000\n\
+                   \n\
+                   10,0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48,0.98\n";
+
+        assert_eq!(
+            parse_path(csv).unwrap(),
+            vec![
+                PathStep {
+                    block: 0,
+                    reserve: "WETH".to_string(),
+                    price: 2500.0
+                },
+                PathStep {
+                    block: 10,
+                    reserve: "WETH".to_string(),
+                    price: 2000.0
+                },
+                PathStep {
+                    block: 10,
+                    reserve: "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48".to_string(),
+                    price: 0.98
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_paths() {
+        assert!(parse_path("0,WETH").is_err());
+        assert!(parse_path("0,WETH,2500\nnext,WETH,2000").is_err());
+        assert!(parse_path("0,WETH,cheap").is_err());
+        assert!(parse_path("10,WETH,2500\n0,WETH,2000").is_err());
+    }
+}
@@ -0,0 +1,82 @@
+//! How a liquidation gets its debt asset and what's done with the seized collateral. The executor
+//! evaluates every strategy valid for the position and sends the most profitable one.
+
+use std::fmt;
+
+use alloy::primitives::{Bytes, U256};
+use serde::Serialize;
+
+use crate::flashloan::Flashloan;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    // flashloan the debt asset, swap the collateral back to repay it
+    SwapAndRepay,
+    // collateral and debt are the same asset, the seized collateral repays the flashloan as is
+    SameAsset,
+    // repay out of the bot's own balance and keep the collateral's aToken, see `--hold-atokens`
+    ReceiveAToken,
+}
+
+impl Strategy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Strategy::SwapAndRepay => "swap_and_repay",
+            Strategy::SameAsset => "same_asset",
+            Strategy::ReceiveAToken => "receive_atoken",
+        }
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A strategy valid for the position, i.e expected to clear the minimum profit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub strategy: Strategy,
+    // empty unless swapping
+    pub path: Bytes,
+    // debt asset brought back, or aTokens received when holding them
+    pub min_amount_out: U256,
+    // `None` when liquidating out of the bot's own balance
+    pub flashloan: Option<Flashloan>,
+    pub expected_profit_usd: f64,
+}
+
+pub fn most_profitable(candidates: Vec<Candidate>) -> Option<Candidate> {
+    candidates
+        .into_iter()
+        .max_by(|a, b| a.expected_profit_usd.total_cmp(&b.expected_profit_usd))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(strategy: Strategy, expected_profit_usd: f64) -> Candidate {
+        Candidate {
+            strategy,
+            path: Bytes::new(),
+            min_amount_out: U256::ZERO,
+            flashloan: None,
+            expected_profit_usd,
+        }
+    }
+
+    #[test]
+    fn the_most_profitable_strategy_wins() {
+        let best = most_profitable(vec![
+            candidate(Strategy::SwapAndRepay, 12.5),
+            candidate(Strategy::ReceiveAToken, 40.0),
+        ])
+        .unwrap();
+
+        assert_eq!(best.strategy, Strategy::ReceiveAToken);
+        assert_eq!(most_profitable(vec![]), None);
+    }
+}
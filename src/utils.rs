@@ -1,10 +1,7 @@
 use std::collections::HashMap;
 
-use crate::contracts;
-use alloy::{
-    primitives::{utils::format_ether, Address, Uint, U256},
-    providers::Provider,
-};
+use crate::client::{LendingProtocolClient, UserReserve};
+use alloy::primitives::{Address, U256};
 use tracing::info;
 
 // Liquidators can only close a certain amount of collateral defined by a close factor.
@@ -20,122 +17,120 @@ where
     Ok(val.to_string().parse::<f64>()? * factor.unwrap_or(1.0))
 }
 
-pub async fn health_factor<P: Provider + Clone>(
-    contract: &contracts::aave_v3::PoolContract::PoolContractInstance<(), P>,
+// https://aave.com/docs/developers/smart-contracts/pool-configurator#only-risk-or-pool-admins-methods-configurereserveascollateral
+// All the values are expressed in bps. A value of 10000 results in 100.00%.
+pub fn liquidation_threshold<T>(bps: T) -> eyre::Result<f64>
+where
+    T: ToString,
+{
+    norm(bps, Some(10.0_f64.powf(-2.0)))
+}
+
+// The liquidationBonus is always above 100%.
+// A value of 105% means the liquidator will receive a 5% bonus.
+pub fn liquidation_bonus<T>(bps: T) -> eyre::Result<f64>
+where
+    T: ToString,
+{
+    Ok(((norm(bps, None)? - 10_000.0) / 100.0).max(0.0))
+}
+
+pub async fn health_factor(
+    client: &dyn LendingProtocolClient,
     user: Address,
-) -> Option<f64> {
-    let data = contract.getUserAccountData(user).call().await.unwrap();
-    let health_factor = format_ether(data.healthFactor).parse::<f64>().unwrap();
+) -> eyre::Result<Option<f64>> {
+    let health_factor = client.account_data(user).await?.health_factor;
 
     // sanity check — ensure health factor is within a reasonable range
     if health_factor > 10_000.0 {
-        return None;
+        return Ok(None);
     }
 
-    Some(health_factor)
+    Ok(Some(health_factor))
 }
 
-pub async fn user_liquidation_data<P: Provider + Clone>(
-    datap_contract: &contracts::aave_v3::DataProviderContract::DataProviderContractInstance<(), P>,
-    provider_addr: Address,
+pub async fn user_liquidation_data(
+    client: &dyn LendingProtocolClient,
     user: Address,
     indices: &HashMap<String, (f64, f64)>,
-) -> eyre::Result<(Address, Address, U256)> {
-    let user_reserves = datap_contract
-        .getUserReservesData(provider_addr, user)
-        .call()
-        .await?;
+) -> eyre::Result<(Address, Address, U256, U256)> {
+    let user_reserves = client.user_reserves(user).await?;
+    liquidation_pair(&user_reserves, indices, None, None)
+}
 
+/// `(debt_asset, collateral_asset, debt_to_cover, collateral_balance)` of a position, the first
+/// debt and collateral found unless given. Balances are accrued.
+pub fn liquidation_pair(
+    user_reserves: &[UserReserve],
+    indices: &HashMap<String, (f64, f64)>,
+    debt_asset: Option<Address>,
+    collateral_asset: Option<Address>,
+) -> eyre::Result<(Address, Address, U256, U256)> {
     let debt = user_reserves
-        ._0
         .iter()
-        .find(|v| v.scaledVariableDebt > U256::from(0))
+        .filter(|v| debt_asset.is_none_or(|asset| v.underlying_asset == asset))
+        .find(|v| v.scaled_variable_debt > U256::from(0))
         .ok_or(eyre::eyre!("No debt asset found"))?;
 
     let (_, var_idx) = indices
-        .get(&debt.underlyingAsset.to_string())
+        .get(&debt.underlying_asset.to_string())
         .unwrap_or(&(1.0, 1.0));
-    let normalized_debt = norm(debt.scaledVariableDebt, Some(*var_idx))?;
+    let normalized_debt = norm(debt.scaled_variable_debt, Some(*var_idx))?;
     let debt_to_cover = debt_to_cover(U256::from(normalized_debt));
 
+    // a reserve disabled as collateral by the user can't be seized
     let collateral = user_reserves
-        ._0
         .iter()
-        .find(|v| v.scaledATokenBalance > U256::from(0))
+        .filter(|v| collateral_asset.is_none_or(|asset| v.underlying_asset == asset))
+        .find(|v| v.scaled_a_token_balance > U256::from(0) && v.usage_as_collateral_enabled)
         .ok_or(eyre::eyre!("No collateral asset found"))?;
 
+    let (liq_idx, _) = indices
+        .get(&collateral.underlying_asset.to_string())
+        .unwrap_or(&(1.0, 1.0));
+    let collateral_balance = U256::from(norm(collateral.scaled_a_token_balance, Some(*liq_idx))?);
+
     info!(
-        debt_asset = ?debt.underlyingAsset,
-        debt = ?debt.scaledVariableDebt,
-        collateral = ?collateral.scaledATokenBalance,
-        collateral_asset = ?collateral.underlyingAsset,
+        debt_asset = ?debt.underlying_asset,
+        debt = ?debt.scaled_variable_debt,
+        collateral = ?collateral.scaled_a_token_balance,
+        collateral_asset = ?collateral.underlying_asset,
         debt_to_cover = ?debt_to_cover,
     );
 
-    let debt_asset = debt.underlyingAsset;
-    let collateral_asset = collateral.underlyingAsset;
+    let debt_asset = debt.underlying_asset;
+    let collateral_asset = collateral.underlying_asset;
 
-    Ok((debt_asset, collateral_asset, debt_to_cover))
+    Ok((
+        debt_asset,
+        collateral_asset,
+        debt_to_cover,
+        collateral_balance,
+    ))
 }
 
 pub fn debt_to_cover(variable_debt: U256) -> U256 {
-    variable_debt * Uint::from(CLOSE_FACTOR)
-}
-
-pub async fn find_most_liquid_uniswap_pool<P: Provider + Clone>(
-    provider: &P,
-    factory_contract: &contracts::uniswap_v3::FactoryContract::FactoryContractInstance<(), P>,
-    collateral_asset: Address,
-    debt_asset: Address,
-) -> eyre::Result<(Address, u16)> {
-    const FEES: [u16; 3] = [500, 3000, 10000];
-
-    let mut max_liquidity = U256::ZERO;
-    let mut best_pool = None;
-
-    for fee in FEES {
-        let pool = factory_contract
-            .getPool(collateral_asset, debt_asset, Uint::from(fee))
-            .call()
-            .await?;
-
-        if pool._0 != Address::ZERO {
-            let contract = contracts::uniswap_v3::PoolContract::new(pool._0, provider.clone());
-            let liquidity = U256::from(contract.liquidity().call().await?._0);
-
-            if liquidity > max_liquidity {
-                max_liquidity = liquidity;
-                best_pool = Some((pool._0, fee));
-            }
-        }
-    }
-
-    info!(?best_pool, "most liquid uniswap_v3 pool");
-    best_pool.ok_or(eyre::eyre!("No valid pool found"))
+    // `Uint::from(0.5)` rounds to 1, scale the close factor to bps instead
+    variable_debt * U256::from(CLOSE_FACTOR * 10_000.0) / U256::from(10_000)
 }
 
-pub async fn user_positions<P: Provider + Clone>(
-    datap_contract: &contracts::aave_v3::DataProviderContract::DataProviderContractInstance<(), P>,
-    addressp_addr: &Address,
+pub async fn user_positions(
+    client: &dyn LendingProtocolClient,
     user: &Address,
     indices: &HashMap<String, (f64, f64)>,
-) -> eyre::Result<Vec<(Address, f64, f64)>> {
-    let user_data = datap_contract
-        .getUserReservesData(*addressp_addr, *user)
-        .call()
-        .await?;
+) -> eyre::Result<Vec<(Address, f64, f64, bool)>> {
+    let user_data = client.user_reserves(*user).await?;
 
     Ok(user_data
-        ._0
         .iter()
-        .filter(|r| !r.scaledATokenBalance.is_zero() || !r.scaledVariableDebt.is_zero())
+        .filter(|r| !r.scaled_a_token_balance.is_zero() || !r.scaled_variable_debt.is_zero())
         .map(|r| {
-            let addr = r.underlyingAsset;
+            let addr = r.underlying_asset;
             let (liq_idx, var_idx) = indices.get(&addr.to_string()).unwrap_or(&(1.0, 1.0));
             let collateral =
-                norm(r.scaledATokenBalance, Some(*liq_idx)).expect("supply calc failed");
-            let debt = norm(r.scaledVariableDebt, Some(*var_idx)).expect("borrow calc failed");
-            (addr, collateral, debt)
+                norm(r.scaled_a_token_balance, Some(*liq_idx)).expect("supply calc failed");
+            let debt = norm(r.scaled_variable_debt, Some(*var_idx)).expect("borrow calc failed");
+            (addr, collateral, debt, r.usage_as_collateral_enabled)
         })
         .collect())
 }
@@ -0,0 +1,76 @@
+//! Pre-execution checks mirroring `ValidationLogic.validateLiquidationCall` and
+//! `ValidationLogic.validateFlashloanSimple`, so we don't fire transactions which are bound to revert.
+//!
+//! https://github.com/aave-dao/aave-v3-origin/blob/main/src/contracts/protocol/libraries/logic/ValidationLogic.sol
+
+use std::fmt;
+
+use crate::actors::messages::database::Reserve;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    CollateralReserveInactive,
+    DebtReserveInactive,
+    CollateralReservePaused,
+    DebtReservePaused,
+    // a liquidation threshold of 0 means the asset can't be used as collateral, hence can't be seized
+    CollateralCannotBeLiquidated,
+    // no lender can flashloan the debt asset, e.g aave's flashloans are disabled for it and the
+    // zero-fee lenders lack liquidity, nor do we hold enough of it
+    FlashloanDisabled,
+    // the quoted swap doesn't bring back the flashloan plus `--min-profit-usd`
+    Unprofitable,
+    // an earlier attempt reverted in a way bound to repeat, see `RetryPolicy::Blacklist`
+    Blacklisted,
+    // the collateral's or debt's aggregator missed its heartbeat, the profit can't be trusted
+    StalePrice,
+}
+
+impl SkipReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SkipReason::CollateralReserveInactive => "collateral_reserve_inactive",
+            SkipReason::DebtReserveInactive => "debt_reserve_inactive",
+            SkipReason::CollateralReservePaused => "collateral_reserve_paused",
+            SkipReason::DebtReservePaused => "debt_reserve_paused",
+            SkipReason::CollateralCannotBeLiquidated => "collateral_cannot_be_liquidated",
+            SkipReason::FlashloanDisabled => "flashloan_disabled",
+            SkipReason::Unprofitable => "unprofitable",
+            SkipReason::Blacklisted => "blacklisted",
+            SkipReason::StalePrice => "stale_price",
+        }
+    }
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Frozen reserves, siloed borrowing and isolation mode don't prevent liquidations, they only
+/// restrict supplying/borrowing. They're tracked so the strategy can account for them, but never
+/// cause a skip on their own. Neither do disabled flashloans, another lender may step in, see
+/// `FlashloanLenders::cheapest`.
+pub fn validate_liquidation(collateral: &Reserve, debt: &Reserve) -> Result<(), SkipReason> {
+    if !collateral.flags.is_active {
+        return Err(SkipReason::CollateralReserveInactive);
+    }
+    if !debt.flags.is_active {
+        return Err(SkipReason::DebtReserveInactive);
+    }
+    if collateral.flags.is_paused {
+        return Err(SkipReason::CollateralReservePaused);
+    }
+    if debt.flags.is_paused {
+        return Err(SkipReason::DebtReservePaused);
+    }
+    if collateral.liquidation_threshold == 0.0 {
+        return Err(SkipReason::CollateralCannotBeLiquidated);
+    }
+    if collateral.stats.price_stale || debt.stats.price_stale {
+        return Err(SkipReason::StalePrice);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,406 @@
+//! Fixtures shared by the integration tests: an anvil fork, a throwaway postgres schema with the
+//! migrations applied, the deployed `Liquidatoor` and the actors running on top of them.
+//!
+//! They need `anvil` in `PATH`, an archive node to fork from (`FORK_URL`, optionally pinned with
+//! `FORK_BLOCK_NUMBER`) and a postgres database to create the schemas in (`TEST_DATABASE_URL`),
+//! hence the `#[ignore]`s. Run them with `cargo test -- --ignored`.
+
+// every test binary compiles the fixtures, none of them uses all of it
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use actix::prelude::*;
+use alloy::{
+    network::EthereumWallet,
+    node_bindings::{Anvil, AnvilInstance},
+    primitives::{address, Address, U256},
+    providers::{Provider, ProviderBuilder, WsConnect},
+    rpc::types::Filter,
+    signers::local::PrivateKeySigner,
+    sol,
+    sol_types::SolEvent,
+};
+use liquidator::{
+    actors::{
+        messages::{
+            database::{get_protocol_contracts, get_reserve_aggregator},
+            fanatic::SendExecutorAddr,
+            follower::SendFanaticAddr,
+        },
+        Database, Executor, Fanatic, Follower,
+    },
+    client::{AaveV3Client, LendingProtocolClient},
+    configs::{DatabaseConfig, ExecutorConfig, FanaticConfig, FollowerConfig},
+    contracts::{self, aave_v3::PoolContract::LiquidationCall},
+    sim::MockAggregator,
+};
+use sqlx::{
+    postgres::{PgConnectOptions, PgPoolOptions},
+    PgPool,
+};
+
+pub const TARGET: &str = "ethereum-aave_v3";
+
+pub const WETH: Address = address!("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+pub const USDC: Address = address!("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+// uniswap_v3's `SwapRouter`
+pub const SWAP_ROUTER: Address = address!("0xE592427A0AEce92De3Edee1F18E0157C05861564");
+
+sol! {
+    #[sol(rpc)]
+    interface IWETH {
+        function deposit() external payable;
+    }
+
+    #[sol(rpc)]
+    interface IAaveOracle {
+        function getAssetPrice(address asset) external view returns (uint256);
+    }
+}
+
+pub fn env(name: &str) -> String {
+    std::env::var(name)
+        .unwrap_or_else(|_| panic!("{name} must be set to run the integration tests"))
+}
+
+pub fn ether(amount: u64) -> U256 {
+    U256::from(amount) * U256::from(10).pow(U256::from(18))
+}
+
+/// Forks `FORK_URL`, at `FORK_BLOCK_NUMBER` if set.
+pub fn fork() -> AnvilInstance {
+    let mut anvil = Anvil::new().fork(env("FORK_URL")).timeout(30_000);
+    if let Ok(number) = std::env::var("FORK_BLOCK_NUMBER") {
+        anvil = anvil.fork_block_number(number.parse().expect("invalid FORK_BLOCK_NUMBER"));
+    }
+
+    anvil.spawn()
+}
+
+/// `asset`'s price in the protocol's base currency, as the pool sees it.
+pub async fn asset_price<P: Provider>(
+    provider: &P,
+    contracts: &HashMap<String, Address>,
+    asset: Address,
+) -> eyre::Result<U256> {
+    let oracle_addr = contracts::aave_v3::AddressProviderContract::new(
+        contracts["PoolAddressesProvider"],
+        provider,
+    )
+    .getPriceOracle()
+    .call()
+    .await?
+    ._0;
+
+    Ok(IAaveOracle::new(oracle_addr, provider)
+        .getAssetPrice(asset)
+        .call()
+        .await?
+        ._0)
+}
+
+/// Provider signing with anvil's `index`th dev account.
+pub async fn wallet_provider(
+    anvil: &AnvilInstance,
+    index: usize,
+) -> eyre::Result<impl Provider + Unpin + Clone + 'static> {
+    let signer: PrivateKeySigner = anvil.keys()[index].clone().into();
+
+    Ok(ProviderBuilder::new()
+        .wallet(EthereumWallet::from(signer))
+        .on_ws(WsConnect::new(anvil.ws_endpoint()))
+        .await?)
+}
+
+/// Schema created for a single test, dropped by `cleanup`.
+pub struct TestDatabase {
+    pub pool: PgPool,
+    admin: PgPool,
+    schema: String,
+}
+
+impl TestDatabase {
+    pub async fn create() -> eyre::Result<Self> {
+        let database_url = env("TEST_DATABASE_URL");
+        let schema = format!(
+            "liquidator_test_{}_{}",
+            std::process::id(),
+            SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos()
+        );
+
+        let admin = PgPool::connect(&database_url).await?;
+        sqlx::query(&format!("CREATE SCHEMA {schema}"))
+            .execute(&admin)
+            .await?;
+
+        let pool = PgPoolOptions::new()
+            .connect_with(
+                PgConnectOptions::from_str(&database_url)?
+                    .options([("search_path", schema.as_str())]),
+            )
+            .await?;
+        sqlx::migrate!("./migrations").run(&pool).await?;
+
+        Ok(TestDatabase {
+            pool,
+            admin,
+            schema,
+        })
+    }
+
+    pub async fn cleanup(self) -> eyre::Result<()> {
+        self.pool.close().await;
+        sqlx::query(&format!("DROP SCHEMA {} CASCADE", self.schema))
+            .execute(&self.admin)
+            .await?;
+
+        Ok(())
+    }
+}
+
+pub async fn protocol_contracts(pool: &PgPool) -> eyre::Result<HashMap<String, Address>> {
+    let (network, protocol) = TARGET.split_once('-').unwrap();
+    Ok(get_protocol_contracts(pool, network, protocol).await?)
+}
+
+/// Replaces the aggregator `asset`'s price is followed from with the mock, as `sim` does. The
+/// reserves have to be stored, i.e the actors started.
+pub async fn mock_aggregator<P: Provider + Clone>(
+    provider: P,
+    pool: &PgPool,
+    asset: Address,
+    sender: Address,
+) -> eyre::Result<MockAggregator<P>> {
+    let (network, protocol) = TARGET.split_once('-').unwrap();
+    let aggregator_addr = get_reserve_aggregator(pool, network, protocol, &asset.to_string())
+        .await?
+        .and_then(|reserve| reserve.aggregator_addr)
+        .ok_or(eyre::eyre!("no aggregator is followed for {}", asset))?;
+
+    MockAggregator::replace(provider, aggregator_addr.trim().parse()?, sender).await
+}
+
+/// Deploys `Liquidatoor`, owned by the provider's signer.
+pub async fn deploy_bot<P: Provider + Clone>(
+    provider: P,
+    contracts: &HashMap<String, Address>,
+) -> eyre::Result<Address> {
+    let addresses_provider = contracts
+        .get("PoolAddressesProvider")
+        .ok_or(eyre::eyre!("Missing PoolAddressesProvider address"))?;
+    contracts::liquidator::ensure_bytecode_matches_abi()?;
+    let bot = contracts::liquidator::LiquidatoorContract::deploy(
+        provider,
+        *addresses_provider,
+        SWAP_ROUTER,
+    )
+    .await?;
+
+    Ok(*bot.address())
+}
+
+pub struct Actors<P: Provider + Unpin + Clone + 'static> {
+    pub db: Addr<Database>,
+    pub follower: Addr<Follower<P>>,
+    pub fanatic: Addr<Fanatic<P>>,
+    pub executor: Addr<Executor<P>>,
+}
+
+/// Starts the actors the way `run` does, `provider` being the bot's owner.
+pub async fn start_actors<P: Provider + Unpin + Clone + 'static>(
+    provider: P,
+    pool: &PgPool,
+    bot_addr: Address,
+) -> eyre::Result<Actors<P>> {
+    let db = Database::new(DatabaseConfig {
+        pool: Arc::new(pool.clone()),
+        history: None,
+    })
+    .await
+    .start();
+
+    let client: Arc<dyn LendingProtocolClient> = Arc::new(AaveV3Client::new(
+        provider.clone(),
+        &protocol_contracts(pool).await?,
+        bot_addr,
+    )?);
+
+    let follower = Follower::new(FollowerConfig {
+        provider: provider.clone(),
+        db_addr: db.clone(),
+        client: client.clone(),
+        target: TARGET.to_string(),
+        confirmations: 0,
+        replay: false,
+    })
+    .await?
+    .start();
+
+    let fanatic = Fanatic::new(FanaticConfig {
+        provider: provider.clone(),
+        db_addr: db.clone(),
+        client: client.clone(),
+        follower_addr: follower.clone(),
+        bot_addr,
+        target: TARGET.to_string(),
+        snapshots: false,
+    })
+    .await?
+    .init()
+    .await?
+    .start();
+
+    let executor = Executor::new(ExecutorConfig {
+        provider,
+        db_addr: db.clone(),
+        client,
+        fanatic_addr: fanatic.clone(),
+        bot_addr,
+        target: TARGET.to_string(),
+        slippage_bps: 50,
+        min_profit_usd: 0.0,
+        hold_atokens: vec![],
+        simulations: None,
+    })
+    .await?
+    .start();
+
+    // the actors exchange their addresses once started, don't let the test race them
+    follower.send(SendFanaticAddr(fanatic.clone())).await?;
+    fanatic.send(SendExecutorAddr(executor.clone())).await?;
+
+    Ok(Actors {
+        db,
+        follower,
+        fanatic,
+        executor,
+    })
+}
+
+/// An account borrowing from the pool.
+pub struct Borrower<P: Provider + Clone> {
+    pub address: Address,
+    provider: P,
+    contracts: HashMap<String, Address>,
+    pool: contracts::aave_v3::PoolContract::PoolContractInstance<(), P>,
+}
+
+impl<P: Provider + Clone> Borrower<P> {
+    pub fn new(provider: P, address: Address, contracts: &HashMap<String, Address>) -> Self {
+        let pool = contracts::aave_v3::PoolContract::new(contracts["Pool"], provider.clone());
+
+        Borrower {
+            address,
+            provider,
+            contracts: contracts.clone(),
+            pool,
+        }
+    }
+
+    /// Wraps `amount` of ETH and supplies it as collateral.
+    pub async fn supply_eth(&self, amount: U256) -> eyre::Result<()> {
+        IWETH::new(WETH, &self.provider)
+            .deposit()
+            .value(amount)
+            .send()
+            .await?
+            .get_receipt()
+            .await?;
+        contracts::erc20::ERC20Contract::new(WETH, &self.provider)
+            .approve(*self.pool.address(), amount)
+            .send()
+            .await?
+            .get_receipt()
+            .await?;
+        self.pool
+            .supply(WETH, amount, self.address, 0)
+            .send()
+            .await?
+            .get_receipt()
+            .await?;
+
+        Ok(())
+    }
+
+    /// Borrows `share` of what the position allows, at variable rate. Returns the borrowed amount.
+    pub async fn borrow(&self, asset: Address, share: f64) -> eyre::Result<U256> {
+        let price = asset_price(&self.provider, &self.contracts, asset).await?;
+        let available = self
+            .pool
+            .getUserAccountData(self.address)
+            .call()
+            .await?
+            .availableBorrowsBase;
+        let decimals = contracts::erc20::ERC20Contract::new(asset, &self.provider)
+            .decimals()
+            .call()
+            .await?
+            ._0;
+
+        let amount =
+            available * U256::from(share * 10_000.0) * U256::from(10).pow(U256::from(decimals))
+                / U256::from(10_000)
+                / price;
+        self.pool
+            .borrow(asset, amount, U256::from(2), 0, self.address)
+            .send()
+            .await?
+            .get_receipt()
+            .await?;
+
+        Ok(amount)
+    }
+
+    pub async fn health_factor(&self) -> eyre::Result<f64> {
+        let data = self.pool.getUserAccountData(self.address).call().await?;
+        Ok(data.healthFactor.to_string().parse::<f64>()? / 1e18)
+    }
+}
+
+/// Polls `check` until it returns something, or fails once `timeout` has elapsed.
+pub async fn eventually<T, F, Fut>(timeout: Duration, mut check: F) -> eyre::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = eyre::Result<Option<T>>>,
+{
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        if let Some(value) = check().await? {
+            return Ok(value);
+        }
+        if tokio::time::Instant::now() > deadline {
+            return Err(eyre::eyre!("timed out after {:?}", timeout));
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+}
+
+/// `LiquidationCall`s of `user` emitted by the pool since `from_block`, which should be after the
+/// fork's, anything older is fetched from the forked node.
+pub async fn liquidation_calls<P: Provider>(
+    provider: &P,
+    contracts: &HashMap<String, Address>,
+    user: Address,
+    from_block: u64,
+) -> eyre::Result<Vec<LiquidationCall>> {
+    let filter = Filter::new()
+        .address(contracts["Pool"])
+        .event_signature(LiquidationCall::SIGNATURE_HASH)
+        .topic3(user.into_word())
+        .from_block(from_block);
+
+    Ok(provider
+        .get_logs(&filter)
+        .await?
+        .iter()
+        .filter_map(|log| LiquidationCall::decode_log(&log.inner, true).ok())
+        .map(|event| event.data)
+        .collect())
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use std::time::Duration;
+
+use alloy::providers::Provider;
+use common::{
+    deploy_bot, ether, eventually, fork, liquidation_calls, mock_aggregator, protocol_contracts,
+    start_actors, wallet_provider, Borrower, TestDatabase, USDC, WETH,
+};
+
+#[actix_rt::test]
+#[ignore = "needs anvil, FORK_URL and TEST_DATABASE_URL"]
+async fn bot_liquidates_borrower_once_collateral_price_drops() -> eyre::Result<()> {
+    let anvil = fork();
+    let owner = wallet_provider(&anvil, 0).await?;
+    let db = TestDatabase::create().await?;
+    let contracts = protocol_contracts(&db.pool).await?;
+
+    let bot_addr = deploy_bot(owner.clone(), &contracts).await?;
+    let _actors = start_actors(owner.clone(), &db.pool, bot_addr).await?;
+    // ETH/USD, which the LSTs are priced off as well
+    let aggregator = mock_aggregator(owner.clone(), &db.pool, WETH, anvil.addresses()[0]).await?;
+
+    let borrower = Borrower::new(
+        wallet_provider(&anvil, 1).await?,
+        anvil.addresses()[1],
+        &contracts,
+    );
+    borrower.supply_eth(ether(10)).await?;
+    borrower.borrow(USDC, 0.99).await?;
+    assert!(borrower.health_factor().await? > 1.0);
+
+    let from_block = owner.get_block_number().await?;
+    aggregator
+        .set_price(aggregator.price().await? * 0.85)
+        .await?;
+    assert!(borrower.health_factor().await? < 1.0);
+
+    let calls = eventually(Duration::from_secs(60), || async {
+        let calls = liquidation_calls(&owner, &contracts, borrower.address, from_block).await?;
+        Ok((!calls.is_empty()).then_some(calls))
+    })
+    .await?;
+    assert!(calls.iter().all(|call| call.liquidator == bot_addr));
+    assert_eq!(calls[0].collateralAsset, WETH);
+    assert_eq!(calls[0].debtAsset, USDC);
+
+    // and the follower flagged it as ours
+    eventually(Duration::from_secs(30), || async {
+        let ours: Option<bool> = sqlx::query_scalar(
+            "SELECT ours FROM aavev3_liquidations WHERE user_address = $1 LIMIT 1",
+        )
+        .bind(borrower.address.to_string())
+        .fetch_optional(&db.pool)
+        .await?;
+        Ok(ours.filter(|ours| *ours))
+    })
+    .await?;
+
+    db.cleanup().await
+}